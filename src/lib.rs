@@ -12,15 +12,15 @@ pub use modules::scanner::{
 pub use modules::lnurl;
 pub use modules::onchain;
 pub use modules::activity;
-use crate::activity::{ActivityError, ActivityDB, OnchainActivity, LightningActivity, Activity, ActivityFilter, SortDirection, PaymentType, DbError};
+use crate::activity::{ActivityError, ActivityDB, OnchainActivity, LightningActivity, Activity, ActivityFilter, SortDirection, DbError, ActivityCursor, ActivityPage, ActivityQuery, ActivityTotals, ActivityTypeTotals, ActivityBucket, ActivityBucketInterval, TagTotals, ActivityExportFormat, ActivityFiatRate, ActivityChangeListener, ActivityUpsertResult};
 use crate::modules::blocktank::{BlocktankDB, BlocktankError, IBtInfo, IBtOrder, CreateOrderOptions, BtOrderState2, IBt0ConfMinTxFeeWindow, IBtEstimateFeeResponse, IBtEstimateFeeResponse2, CreateCjitOptions, ICJitEntry, CJitStateEnum, IBtBolt11Invoice};
-use crate::onchain::{AddressError, ValidationResult, WordCount, GetAddressResponse, Network, GetAddressesResponse};
+use crate::onchain::{AddressError, AddressType, ValidationResult, WordCount, GetAddressResponse, Network, GetAddressesResponse, ChainBackend, AddressDiscoveryResult, DiscoveryError, Utxo, TargetOutput, CoinSelectionStrategy, CoinSelectionResult, TransactionError, ChangeOutput, SignPsbtResult, FeeBumpResult, MessageSignatureFormat, SignedMessage, MessageSigningError, DecodedTransaction, Slip39Error, Slip39Group, Slip39GroupShares, Slip39ShareInfo, SeedEnvelopeContents, SeedEnvelopeError, MultisigScriptType, MultisigDescriptors, MultisigAddress, PayjoinParams, PayjoinError};
 
 use std::sync::Mutex as StdMutex;
 use tokio::runtime::Runtime;
 use tokio::sync::Mutex as TokioMutex;
 use crate::modules::trezor;
-use crate::modules::trezor::{AccountInfoDetails, AmountUnit, CommonParams, ComposeAccount, ComposeOutput, ComposeTransactionParams, DeepLinkResult, DefaultAccountType, FeeLevel, GetAccountInfoParams, GetAddressParams, MultisigRedeemScriptType, RefTransaction, SignMessageParams, SignTransactionParams, TokenFilter, TrezorConnectError, TrezorEnvironment, TrezorResponsePayload, TxAckPaymentRequest, TxInputType, TxOutputType, UnlockPath, VerifyMessageParams, XrpMarker};
+use crate::modules::trezor::{AccountInfoDetails, AmountUnit, CommonParams, ComposeAccount, ComposeOutput, ComposeTransactionParams, DeepLinkResult, DefaultAccountType, FeeLevel, GetAccountInfoParams, GetAddressParams, HDNodeType, MultisigRedeemScriptType, RefTransaction, SignMessageParams, SignTransactionParams, TokenFilter, TrezorConnectError, TrezorEnvironment, TrezorResponsePayload, TxAckPaymentRequest, TxInputType, TxOutputType, UnlockPath, VerifyMessageParams, XrpMarker};
 
 pub struct DatabaseConnections {
     activity_db: Option<ActivityDB>,
@@ -61,6 +61,185 @@ pub fn generate_mnemonic(word_count: Option<WordCount>) -> Result<String, Addres
     onchain::BitcoinAddressValidator::genenerate_mnemonic(external_word_count)
 }
 
+#[uniffi::export]
+pub fn derive_bip85_mnemonic(
+    mnemonic_phrase: String,
+    word_count: Option<WordCount>,
+    index: u32,
+    bip39_passphrase: Option<String>,
+) -> Result<String, AddressError> {
+    onchain::derive_bip85_mnemonic(
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        word_count.unwrap_or(WordCount::Words12),
+        index,
+    )
+}
+
+#[uniffi::export]
+pub fn derive_bip85_wif(
+    mnemonic_phrase: String,
+    index: u32,
+    network: Option<Network>,
+    bip39_passphrase: Option<String>,
+) -> Result<String, AddressError> {
+    onchain::derive_bip85_wif(
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        index,
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+    )
+}
+
+#[uniffi::export]
+pub fn derive_bip85_xprv(
+    mnemonic_phrase: String,
+    index: u32,
+    network: Option<Network>,
+    bip39_passphrase: Option<String>,
+) -> Result<String, AddressError> {
+    onchain::derive_bip85_xprv(
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        index,
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+    )
+}
+
+#[uniffi::export]
+pub fn derive_bip85_hex(
+    mnemonic_phrase: String,
+    num_bytes: u32,
+    index: u32,
+    bip39_passphrase: Option<String>,
+) -> Result<String, AddressError> {
+    onchain::derive_bip85_hex(&mnemonic_phrase, bip39_passphrase.as_deref(), num_bytes, index)
+}
+
+#[uniffi::export]
+pub fn get_master_fingerprint(mnemonic_phrase: String, bip39_passphrase: Option<String>) -> Result<String, AddressError> {
+    onchain::get_master_fingerprint(&mnemonic_phrase, bip39_passphrase.as_deref())
+}
+
+#[uniffi::export]
+pub fn get_key_origin(
+    mnemonic_phrase: String,
+    bip39_passphrase: Option<String>,
+    derivation_path: String,
+    network: Option<Network>,
+) -> Result<String, AddressError> {
+    onchain::get_key_origin(
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        &derivation_path,
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+    )
+}
+
+#[uniffi::export]
+pub fn create_multisig_descriptors(
+    threshold: u32,
+    keys: Vec<String>,
+    script_type: MultisigScriptType,
+    network: Option<Network>,
+) -> Result<MultisigDescriptors, AddressError> {
+    onchain::create_multisig_descriptors(
+        threshold,
+        &keys,
+        script_type,
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+    )
+}
+
+#[uniffi::export]
+pub fn derive_multisig_addresses(
+    threshold: u32,
+    keys: Vec<String>,
+    script_type: MultisigScriptType,
+    network: Option<Network>,
+    is_change: Option<bool>,
+    start_index: Option<u32>,
+    count: Option<u32>,
+) -> Result<Vec<MultisigAddress>, AddressError> {
+    onchain::derive_multisig_addresses(
+        threshold,
+        &keys,
+        script_type,
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+        is_change.unwrap_or(false),
+        start_index.unwrap_or(0),
+        count.unwrap_or(1),
+    )
+}
+
+#[uniffi::export]
+pub fn get_multisig_redeem_script(
+    threshold: u32,
+    keys: Vec<String>,
+    script_type: MultisigScriptType,
+    network: Option<Network>,
+    is_change: bool,
+    index: u32,
+) -> Result<MultisigRedeemScriptType, AddressError> {
+    onchain::get_multisig_redeem_script(
+        threshold,
+        &keys,
+        script_type,
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+        is_change,
+        index,
+    )
+}
+
+#[uniffi::export]
+pub fn split_slip39_mnemonic(
+    mnemonic_phrase: String,
+    group_threshold: u8,
+    groups: Vec<Slip39Group>,
+    passphrase: Option<String>,
+    iteration_exponent: Option<u8>,
+    extendable: Option<bool>,
+) -> Result<Vec<Slip39GroupShares>, Slip39Error> {
+    onchain::split_slip39_mnemonic(
+        &mnemonic_phrase,
+        group_threshold,
+        &groups,
+        passphrase.as_deref(),
+        iteration_exponent.unwrap_or(onchain::DEFAULT_ITERATION_EXPONENT),
+        extendable.unwrap_or(true),
+    )
+}
+
+#[uniffi::export]
+pub fn validate_slip39_share(share: String) -> Result<Slip39ShareInfo, Slip39Error> {
+    onchain::validate_slip39_share(&share)
+}
+
+#[uniffi::export]
+pub fn combine_slip39_shares(shares: Vec<String>, passphrase: Option<String>) -> Result<String, Slip39Error> {
+    onchain::combine_slip39_shares(&shares, passphrase.as_deref())
+}
+
+#[uniffi::export]
+pub fn encrypt_seed_envelope(
+    mnemonic_phrase: String,
+    bip39_passphrase: Option<String>,
+    pin: String,
+    network: Option<Network>,
+) -> Result<String, SeedEnvelopeError> {
+    onchain::encrypt_seed_envelope(
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        &pin,
+        network.unwrap_or(Network::Bitcoin),
+    )
+}
+
+#[uniffi::export]
+pub fn decrypt_seed_envelope(envelope: String, pin: String) -> Result<SeedEnvelopeContents, SeedEnvelopeError> {
+    onchain::decrypt_seed_envelope(&envelope, &pin)
+}
+
 #[uniffi::export]
 pub fn derive_bitcoin_address(
     mnemonic_phrase: String,
@@ -112,6 +291,342 @@ pub fn derive_private_key(
     )
 }
 
+#[uniffi::export]
+pub fn get_derivation_path(
+    address_type: AddressType,
+    network: Option<Network>,
+    account: Option<u32>,
+    is_change: Option<bool>,
+    index: Option<u32>,
+) -> Result<String, AddressError> {
+    onchain::BitcoinAddressValidator::get_derivation_path(
+        address_type,
+        network.map(|n| n.into()),
+        account,
+        is_change,
+        index,
+    )
+}
+
+#[uniffi::export]
+pub fn derive_address_for_type(
+    mnemonic_phrase: String,
+    address_type: AddressType,
+    network: Option<Network>,
+    bip39_passphrase: Option<String>,
+    account: Option<u32>,
+    is_change: Option<bool>,
+    index: Option<u32>,
+) -> Result<GetAddressResponse, AddressError> {
+    onchain::BitcoinAddressValidator::derive_address_for_type(
+        &mnemonic_phrase,
+        address_type,
+        network.map(|n| n.into()),
+        bip39_passphrase.as_deref(),
+        account,
+        is_change,
+        index,
+    )
+}
+
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn derive_addresses_for_type(
+    mnemonic_phrase: String,
+    address_type: AddressType,
+    network: Option<Network>,
+    bip39_passphrase: Option<String>,
+    account: Option<u32>,
+    is_change: Option<bool>,
+    start_index: Option<u32>,
+    count: Option<u32>,
+) -> Result<GetAddressesResponse, AddressError> {
+    onchain::BitcoinAddressValidator::derive_addresses_for_type(
+        &mnemonic_phrase,
+        address_type,
+        network.map(|n| n.into()),
+        bip39_passphrase.as_deref(),
+        account,
+        is_change,
+        start_index,
+        count,
+    )
+}
+
+#[uniffi::export]
+pub fn derive_address_at_path(
+    mnemonic_phrase: String,
+    derivation_path: String,
+    address_type: AddressType,
+    network: Option<Network>,
+    bip39_passphrase: Option<String>,
+) -> Result<GetAddressResponse, AddressError> {
+    onchain::BitcoinAddressValidator::derive_address_at_path(
+        &mnemonic_phrase,
+        &derivation_path,
+        address_type,
+        network.map(|n| n.into()),
+        bip39_passphrase.as_deref(),
+    )
+}
+
+#[uniffi::export]
+pub fn derive_addresses_from_descriptor(
+    descriptor: String,
+    is_change: Option<bool>,
+    start_index: Option<u32>,
+    count: Option<u32>,
+    network: Option<Network>,
+) -> Result<GetAddressesResponse, AddressError> {
+    onchain::BitcoinAddressValidator::derive_addresses_from_descriptor(
+        &descriptor,
+        network.map(|n| n.into()),
+        is_change,
+        start_index,
+        count,
+    )
+}
+
+#[uniffi::export]
+pub async fn discover_addresses(
+    mnemonic_phrase: String,
+    derivation_path_str: Option<String>,
+    network: Option<Network>,
+    bip39_passphrase: Option<String>,
+    backend: ChainBackend,
+    gap_limit: Option<u32>,
+) -> Result<AddressDiscoveryResult, DiscoveryError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let source = backend.into_source()?;
+        onchain::BitcoinAddressValidator::discover_addresses(
+            source.as_ref(),
+            &mnemonic_phrase,
+            derivation_path_str.as_deref(),
+            network.map(|n| n.into()),
+            bip39_passphrase.as_deref(),
+            gap_limit,
+        ).await
+    }).await.unwrap_or_else(|e| Err(DiscoveryError::BackendError {
+        error_details: format!("Runtime error: {}", e)
+    }))
+}
+
+#[uniffi::export]
+pub async fn discover_descriptor_addresses(
+    descriptor: String,
+    network: Option<Network>,
+    backend: ChainBackend,
+    gap_limit: Option<u32>,
+) -> Result<AddressDiscoveryResult, DiscoveryError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let source = backend.into_source()?;
+        onchain::BitcoinAddressValidator::discover_descriptor_addresses(
+            source.as_ref(),
+            &descriptor,
+            network.map(|n| n.into()),
+            gap_limit,
+        ).await
+    }).await.unwrap_or_else(|e| Err(DiscoveryError::BackendError {
+        error_details: format!("Runtime error: {}", e)
+    }))
+}
+
+#[uniffi::export]
+pub fn select_coins(
+    utxos: Vec<Utxo>,
+    outputs: Vec<TargetOutput>,
+    fee_rate_sat_per_vb: f64,
+    change_address_type: AddressType,
+    strategy: Option<CoinSelectionStrategy>,
+    long_term_fee_rate_sat_per_vb: Option<f64>,
+) -> Result<CoinSelectionResult, TransactionError> {
+    onchain::select_coins(
+        utxos,
+        outputs,
+        fee_rate_sat_per_vb,
+        change_address_type,
+        strategy.unwrap_or(CoinSelectionStrategy::BranchAndBound),
+        long_term_fee_rate_sat_per_vb,
+    )
+}
+
+#[uniffi::export]
+pub fn estimate_tx_vsize(
+    inputs: Vec<AddressType>,
+    outputs: Vec<AddressType>,
+) -> Result<u64, TransactionError> {
+    onchain::estimate_tx_vsize(&inputs, &outputs)
+}
+
+#[uniffi::export]
+pub fn estimate_fee(
+    inputs: Vec<AddressType>,
+    outputs: Vec<AddressType>,
+    fee_rate_sat_per_vb: f64,
+) -> Result<u64, TransactionError> {
+    onchain::estimate_fee(&inputs, &outputs, fee_rate_sat_per_vb)
+}
+
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn build_psbt(
+    utxos: Vec<Utxo>,
+    outputs: Vec<TargetOutput>,
+    change_output: Option<ChangeOutput>,
+    mnemonic_phrase: String,
+    bip39_passphrase: Option<String>,
+    network: Option<Network>,
+    enable_rbf: Option<bool>,
+    max_fee_rate_sat_per_vb: Option<f64>,
+) -> Result<String, TransactionError> {
+    let psbt = onchain::build_psbt(
+        utxos,
+        outputs,
+        change_output,
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+        enable_rbf.unwrap_or(true),
+        max_fee_rate_sat_per_vb,
+    )?;
+    Ok(psbt.to_string())
+}
+
+#[uniffi::export]
+pub fn sign_psbt(
+    psbt: String,
+    mnemonic_phrase: String,
+    bip39_passphrase: Option<String>,
+    network: Option<Network>,
+) -> Result<SignPsbtResult, TransactionError> {
+    onchain::sign_psbt(
+        &psbt,
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+    )
+}
+
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn build_rbf_psbt(
+    tx_hex: String,
+    utxos: Vec<Utxo>,
+    change_output_index: u32,
+    change_derivation_path: Option<String>,
+    target_fee_rate_sat_per_vb: f64,
+    mnemonic_phrase: String,
+    bip39_passphrase: Option<String>,
+    network: Option<Network>,
+    incremental_relay_fee_sat_per_vb: Option<f64>,
+) -> Result<FeeBumpResult, TransactionError> {
+    onchain::build_rbf_psbt(
+        &tx_hex,
+        utxos,
+        change_output_index,
+        change_derivation_path.as_deref(),
+        target_fee_rate_sat_per_vb,
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+        incremental_relay_fee_sat_per_vb,
+    )
+}
+
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn build_cpfp_psbt(
+    parent_tx_hex: String,
+    parent_utxos: Vec<Utxo>,
+    output_index: u32,
+    derivation_path: String,
+    destination_address: String,
+    target_fee_rate_sat_per_vb: f64,
+    mnemonic_phrase: String,
+    bip39_passphrase: Option<String>,
+    network: Option<Network>,
+) -> Result<FeeBumpResult, TransactionError> {
+    onchain::build_cpfp_psbt(
+        &parent_tx_hex,
+        parent_utxos,
+        output_index,
+        &derivation_path,
+        &destination_address,
+        target_fee_rate_sat_per_vb,
+        &mnemonic_phrase,
+        bip39_passphrase.as_deref(),
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+    )
+}
+
+#[uniffi::export]
+pub async fn send_payjoin_request(
+    original_psbt: String,
+    unsigned_psbt: String,
+    payjoin_url: String,
+    payee_address: String,
+    params: Option<PayjoinParams>,
+    network: Option<Network>,
+) -> Result<String, PayjoinError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let transport = onchain::HttpPayjoinTransport::new()?;
+        let proposal = onchain::send_payjoin_request(
+            &transport,
+            &original_psbt,
+            &unsigned_psbt,
+            &payjoin_url,
+            &payee_address,
+            &params.unwrap_or_default(),
+            network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+        ).await?;
+        Ok(proposal.to_string())
+    }).await.unwrap_or_else(|e| Err(PayjoinError::TransportError {
+        error_details: format!("Runtime error: {}", e)
+    }))
+}
+
+#[uniffi::export]
+pub fn sign_message(
+    mnemonic_phrase: String,
+    derivation_path: String,
+    message: String,
+    format: MessageSignatureFormat,
+    bip39_passphrase: Option<String>,
+    network: Option<Network>,
+) -> Result<SignedMessage, MessageSigningError> {
+    onchain::sign_message(
+        &mnemonic_phrase,
+        &derivation_path,
+        &message,
+        format,
+        bip39_passphrase.as_deref(),
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+    )
+}
+
+#[uniffi::export]
+pub fn verify_message(
+    address: String,
+    message: String,
+    signature: String,
+) -> Result<bool, MessageSigningError> {
+    onchain::verify_message(&address, &message, &signature)
+}
+
+#[uniffi::export]
+pub fn decode_transaction(
+    tx_hex: String,
+    network: Option<Network>,
+) -> Result<DecodedTransaction, TransactionError> {
+    onchain::decode_transaction(
+        &tx_hex,
+        network.map(|n| n.into()).unwrap_or(bitcoin::Network::Bitcoin),
+    )
+}
+
 #[uniffi::export]
 pub fn init_db(base_path: String) -> Result<String, DbError> {
     // Initialize sync database state
@@ -155,16 +670,7 @@ pub fn init_db(base_path: String) -> Result<String, DbError> {
 }
 
 #[uniffi::export]
-pub fn get_activities(
-    filter: Option<ActivityFilter>,
-    tx_type: Option<PaymentType>,
-    tags: Option<Vec<String>>,
-    search: Option<String>,
-    min_date: Option<u64>,
-    max_date: Option<u64>,
-    limit: Option<u32>,
-    sort_direction: Option<SortDirection>
-) -> Result<Vec<Activity>, ActivityError> {
+pub fn get_activities(query: ActivityQuery) -> Result<ActivityPage, ActivityError> {
     let cell = DB.get().ok_or(ActivityError::ConnectionError {
         error_details: "Database not initialized. Call init_db first.".to_string()
     })?;
@@ -172,7 +678,7 @@ pub fn get_activities(
     let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
         error_details: "Database not initialized. Call init_db first.".to_string()
     })?;
-    db.get_activities(filter, tx_type, tags, search, min_date, max_date, limit, sort_direction)
+    db.get_activities(query)
 }
 
 #[uniffi::export]
@@ -187,6 +693,18 @@ pub fn upsert_activity(activity: Activity) -> Result<(), ActivityError> {
     db.upsert_activity(&activity)
 }
 
+#[uniffi::export]
+pub fn upsert_activities(activities: Vec<Activity>, abort_on_failure: bool) -> Result<ActivityUpsertResult, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let mut guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_mut().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.upsert_activities(&activities, abort_on_failure)
+}
+
 #[uniffi::export]
 pub fn insert_activity(activity: Activity) -> Result<(), ActivityError> {
     let cell = DB.get().ok_or(ActivityError::ConnectionError {
@@ -278,7 +796,61 @@ pub fn get_tags(activity_id: String) -> Result<Vec<String>, ActivityError> {
 }
 
 #[uniffi::export]
-pub fn get_activities_by_tag(tag: String, limit: Option<u32>, sort_direction: Option<SortDirection>) -> Result<Vec<Activity>, ActivityError> {
+pub fn get_activities_by_tag(
+    tag: String,
+    limit: Option<u32>,
+    sort_direction: Option<SortDirection>,
+    after_cursor: Option<ActivityCursor>,
+    before_cursor: Option<ActivityCursor>
+) -> Result<ActivityPage, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.get_activities_by_tag(&tag, limit, sort_direction, after_cursor, before_cursor)
+}
+
+#[uniffi::export]
+pub fn set_activity_fiat_rate(activity_id: String, rate: ActivityFiatRate) -> Result<(), ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let mut guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_mut().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.set_activity_fiat_rate(&activity_id, &rate)
+}
+
+#[uniffi::export]
+pub fn get_activity_fiat_rates(activity_id: String) -> Result<Vec<ActivityFiatRate>, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.get_activity_fiat_rates(&activity_id)
+}
+
+#[uniffi::export]
+pub fn remove_activity_fiat_rate(activity_id: String, currency: String) -> Result<bool, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let mut guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_mut().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.remove_activity_fiat_rate(&activity_id, &currency)
+}
+
+#[uniffi::export]
+pub fn get_activity_ids_missing_fiat_rate(currency: String, limit: Option<u32>) -> Result<Vec<String>, ActivityError> {
     let cell = DB.get().ok_or(ActivityError::ConnectionError {
         error_details: "Database not initialized. Call init_db first.".to_string()
     })?;
@@ -286,7 +858,130 @@ pub fn get_activities_by_tag(tag: String, limit: Option<u32>, sort_direction: Op
     let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
         error_details: "Database not initialized. Call init_db first.".to_string()
     })?;
-    db.get_activities_by_tag(&tag, limit, sort_direction)
+    db.get_activity_ids_missing_fiat_rate(&currency, limit)
+}
+
+#[uniffi::export]
+pub fn get_activity_totals(
+    min_date: Option<u64>,
+    max_date: Option<u64>,
+    fiat_currency: Option<String>
+) -> Result<ActivityTypeTotals, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.get_activity_totals(min_date, max_date, fiat_currency.as_deref())
+}
+
+#[uniffi::export]
+pub fn get_activity_buckets(
+    interval: ActivityBucketInterval,
+    min_date: u64,
+    max_date: u64,
+    filter: Option<ActivityFilter>,
+    utc_offset_seconds: Option<i32>,
+    fiat_currency: Option<String>
+) -> Result<Vec<ActivityBucket>, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.get_activity_buckets(interval, min_date, max_date, filter, utc_offset_seconds, fiat_currency.as_deref())
+}
+
+#[uniffi::export]
+pub fn get_pending_lightning_totals(fiat_currency: Option<String>) -> Result<ActivityTotals, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.get_pending_lightning_totals(fiat_currency.as_deref())
+}
+
+#[uniffi::export]
+pub fn get_tag_totals(
+    min_date: Option<u64>,
+    max_date: Option<u64>,
+    fiat_currency: Option<String>
+) -> Result<Vec<TagTotals>, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.get_tag_totals(min_date, max_date, fiat_currency.as_deref())
+}
+
+#[uniffi::export]
+pub fn search_activities(query: String, limit: Option<u32>) -> Result<Vec<Activity>, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.search_activities(&query, limit)
+}
+
+#[uniffi::export]
+pub fn subscribe_activity_changes(listener: Box<dyn ActivityChangeListener>) -> Result<u64, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let mut guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_mut().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.subscribe_activity_changes(listener)
+}
+
+#[uniffi::export]
+pub fn unsubscribe_activity_changes(subscription_id: u64) -> Result<bool, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let mut guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_mut().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    Ok(db.unsubscribe_activity_changes(subscription_id))
+}
+
+#[uniffi::export]
+pub fn export_activities(format: ActivityExportFormat, query: ActivityQuery) -> Result<String, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_ref().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.export_activities(format, query)
+}
+
+#[uniffi::export]
+pub fn import_activities(data: String) -> Result<u32, ActivityError> {
+    let cell = DB.get().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    let mut guard = cell.lock().unwrap();
+    let db = guard.activity_db.as_mut().ok_or(ActivityError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string()
+    })?;
+    db.import_activities(&data)
 }
 
 #[uniffi::export]
@@ -1047,4 +1742,14 @@ pub fn trezor_compose_transaction(
         Ok(result) => Ok(result),
         Err(e) => Err(TrezorConnectError::ClientError { error_details: e.to_string() }),
     }
-}
\ No newline at end of file
+}
+
+#[uniffi::export]
+pub fn trezor_hd_node_from_key_origin(key_origin: String) -> Result<HDNodeType, TrezorConnectError> {
+    HDNodeType::from_key_origin(&key_origin)
+}
+
+#[uniffi::export]
+pub fn trezor_hd_node_matches_key_origin(node: HDNodeType, key_origin: String) -> Result<bool, TrezorConnectError> {
+    node.matches_key_origin(&key_origin)
+}
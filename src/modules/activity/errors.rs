@@ -1,4 +1,5 @@
 use thiserror::Error;
+use crate::modules::migrations::MigrationError;
 
 #[derive(uniffi::Error, Debug, Error)]
 pub enum ActivityError {
@@ -35,5 +36,23 @@ pub enum ActivityError {
     #[error("Serialization error: {error_details}")]
     SerializationError {
         error_details: String,
+    },
+
+    #[error("Unsupported database version: {error_details}")]
+    UnsupportedSchemaVersion {
+        error_details: String,
+    }
+}
+
+impl From<MigrationError> for ActivityError {
+    fn from(error: MigrationError) -> Self {
+        match error {
+            MigrationError::NewerVersion { .. } => ActivityError::UnsupportedSchemaVersion {
+                error_details: error.to_string(),
+            },
+            MigrationError::Failed { .. } => ActivityError::InitializationError {
+                error_details: error.to_string(),
+            },
+        }
     }
 }
\ No newline at end of file
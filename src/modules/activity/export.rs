@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use chrono::{DateTime, SecondsFormat};
+use rusqlite::Transaction;
+use serde::{Deserialize, Serialize};
+use crate::activity::{
+    Activity, ActivityChangeEvent, ActivityDB, ActivityError, ActivityExportColumn, ActivityExportFormat, ActivityFiatRate,
+    ActivityQuery, LightningActivity, OnchainActivity, PaymentState, PaymentType,
+};
+
+/// Columns of a CSV export when none are chosen.
+pub const DEFAULT_CSV_COLUMNS: &[ActivityExportColumn] = &[
+    ActivityExportColumn::Timestamp,
+    ActivityExportColumn::ActivityType,
+    ActivityExportColumn::TxType,
+    ActivityExportColumn::Status,
+    ActivityExportColumn::Value,
+    ActivityExportColumn::Fee,
+    ActivityExportColumn::Id,
+    ActivityExportColumn::TxId,
+    ActivityExportColumn::Address,
+    ActivityExportColumn::Invoice,
+    ActivityExportColumn::Message,
+    ActivityExportColumn::ConfirmTimestamp,
+    ActivityExportColumn::IsTransfer,
+    ActivityExportColumn::TransferTxId,
+    ActivityExportColumn::ChannelId,
+    ActivityExportColumn::Tags,
+];
+
+/// Columns added to the default CSV columns when a fiat currency is chosen.
+pub const DEFAULT_CSV_FIAT_COLUMNS: &[ActivityExportColumn] = &[
+    ActivityExportColumn::FiatCurrency,
+    ActivityExportColumn::FiatRate,
+    ActivityExportColumn::FiatValue,
+    ActivityExportColumn::FiatFee,
+];
+
+/// One line of the JSON Lines export.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "activity_type", rename_all = "snake_case")]
+enum ExportedActivity {
+    Onchain {
+        #[serde(flatten)]
+        activity: OnchainActivity,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        fiat_rates: Vec<ActivityFiatRate>,
+    },
+    Lightning {
+        #[serde(flatten)]
+        activity: LightningActivity,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        fiat_rates: Vec<ActivityFiatRate>,
+    },
+}
+
+impl ActivityDB {
+    /// Exports the activities matching `query`, in its order, as CSV or JSON Lines.
+    pub fn export_activities(&self, format: ActivityExportFormat, query: ActivityQuery) -> Result<String, ActivityError> {
+        let activities = self.get_activities(query)?.activities;
+        let mut tags = self.tags_by_activity()?;
+        let mut tags_of = |activity: &Activity| tags.remove(activity.get_id()).unwrap_or_default();
+
+        let mut output = String::new();
+        match format {
+            ActivityExportFormat::Csv { columns, fiat_currency } => {
+                let fiat_currency = fiat_currency.as_deref().map(Self::normalize_currency).transpose()?;
+                let columns = columns.unwrap_or_else(|| match fiat_currency {
+                    Some(_) => [DEFAULT_CSV_COLUMNS, DEFAULT_CSV_FIAT_COLUMNS].concat(),
+                    None => DEFAULT_CSV_COLUMNS.to_vec(),
+                });
+                if fiat_currency.is_none() && columns.iter().any(|c| is_fiat_column(*c)) {
+                    return Err(ActivityError::DataError {
+                        error_details: "Fiat columns need a fiat_currency".to_string(),
+                    });
+                }
+                let mut fiat_rates = self.fiat_rates_by_activity(fiat_currency.as_deref())?;
+
+                let header: Vec<String> = columns.iter().map(|c| csv_escape(column_name(*c))).collect();
+                output.push_str(&header.join(","));
+                output.push_str("\r\n");
+                for activity in &activities {
+                    let tags = tags_of(activity);
+                    let fiat = fiat_rates.remove(activity.get_id()).and_then(|rates| rates.into_iter().next());
+                    let row: Vec<String> = columns.iter()
+                        .map(|c| csv_escape(&csv_value(*c, activity, &tags, fiat.as_ref())))
+                        .collect();
+                    output.push_str(&row.join(","));
+                    output.push_str("\r\n");
+                }
+            }
+            ActivityExportFormat::JsonLines => {
+                let mut fiat_rates = self.fiat_rates_by_activity(None)?;
+                for activity in activities {
+                    let tags = tags_of(&activity);
+                    let fiat_rates = fiat_rates.remove(activity.get_id()).unwrap_or_default();
+                    let exported = match activity {
+                        Activity::Onchain(activity) => ExportedActivity::Onchain { activity, tags, fiat_rates },
+                        Activity::Lightning(activity) => ExportedActivity::Lightning { activity, tags, fiat_rates },
+                    };
+                    let line = serde_json::to_string(&exported).map_err(|e| ActivityError::DataError {
+                        error_details: format!("Failed to serialize activity: {}", e),
+                    })?;
+                    output.push_str(&line);
+                    output.push('\n');
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    /// Inserts the activities of a JSON Lines export, keeping their tags, fiat rates and
+    /// `created_at`/`updated_at`. Either every activity is imported or none is.
+    /// Returns the number of imported activities.
+    pub fn import_activities(&mut self, data: &str) -> Result<u32, ActivityError> {
+        let tx = self.conn.transaction().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to start transaction: {}", e),
+        })?;
+
+        let mut events = Vec::new();
+        for (index, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exported: ExportedActivity = serde_json::from_str(line).map_err(|e| ActivityError::DataError {
+                error_details: format!("Invalid activity on line {}: {}", index + 1, e),
+            })?;
+            let (id, tags, fiat_rates) = match &exported {
+                ExportedActivity::Onchain { activity, tags, fiat_rates } => (&activity.id, tags, fiat_rates),
+                ExportedActivity::Lightning { activity, tags, fiat_rates } => (&activity.id, tags, fiat_rates),
+            };
+            if id.is_empty() {
+                return Err(ActivityError::DataError {
+                    error_details: format!("Activity ID cannot be empty on line {}", index + 1),
+                });
+            }
+
+            match &exported {
+                ExportedActivity::Onchain { activity, .. } => Self::insert_onchain_in(&tx, activity, true)?,
+                ExportedActivity::Lightning { activity, .. } => Self::insert_lightning_in(&tx, activity, true)?,
+            }
+            insert_tags(&tx, id, tags)?;
+            for rate in fiat_rates {
+                Self::insert_fiat_rate_in(&tx, id, rate)?;
+            }
+            events.push(ActivityChangeEvent::Inserted { activity_id: id.clone() });
+        }
+
+        tx.commit().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        let imported = events.len() as u32;
+        self.notifier.notify(events);
+        Ok(imported)
+    }
+
+    /// Tags of every activity, each list sorted.
+    fn tags_by_activity(&self) -> Result<HashMap<String, Vec<String>>, ActivityError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT activity_id, tag FROM activity_tags ORDER BY activity_id, tag"
+        ).map_err(|e| ActivityError::RetrievalError {
+            error_details: format!("Failed to prepare statement: {}", e),
+        })?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?;
+
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (activity_id, tag) = row.map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to process row: {}", e),
+            })?;
+            tags.entry(activity_id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+
+    /// Fiat rates of every activity, only those in `currency` when it is set.
+    fn fiat_rates_by_activity(&self, currency: Option<&str>) -> Result<HashMap<String, Vec<ActivityFiatRate>>, ActivityError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT activity_id, currency, rate, source FROM activity_fiat
+             WHERE ?1 IS NULL OR currency = ?1
+             ORDER BY activity_id, currency"
+        ).map_err(|e| ActivityError::RetrievalError {
+            error_details: format!("Failed to prepare statement: {}", e),
+        })?;
+        let rows = stmt.query_map([currency], |row| {
+            Ok((row.get::<_, String>(0)?, ActivityFiatRate {
+                currency: row.get(1)?,
+                rate: row.get(2)?,
+                source: row.get(3)?,
+            }))
+        })
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?;
+
+        let mut rates: HashMap<String, Vec<ActivityFiatRate>> = HashMap::new();
+        for row in rows {
+            let (activity_id, rate) = row.map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to process row: {}", e),
+            })?;
+            rates.entry(activity_id).or_default().push(rate);
+        }
+        Ok(rates)
+    }
+}
+
+fn insert_tags(tx: &Transaction, activity_id: &str, tags: &[String]) -> Result<(), ActivityError> {
+    for tag in tags {
+        tx.execute(
+            "INSERT OR IGNORE INTO activity_tags (activity_id, tag) VALUES (?1, ?2)",
+            [activity_id, tag],
+        ).map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to insert tag: {}", e),
+        })?;
+    }
+    Ok(())
+}
+
+fn column_name(column: ActivityExportColumn) -> &'static str {
+    match column {
+        ActivityExportColumn::Id => "id",
+        ActivityExportColumn::ActivityType => "activity_type",
+        ActivityExportColumn::TxType => "tx_type",
+        ActivityExportColumn::Status => "status",
+        ActivityExportColumn::Timestamp => "timestamp",
+        ActivityExportColumn::Value => "value",
+        ActivityExportColumn::Fee => "fee",
+        ActivityExportColumn::FeeRate => "fee_rate",
+        ActivityExportColumn::TxId => "tx_id",
+        ActivityExportColumn::Address => "address",
+        ActivityExportColumn::Invoice => "invoice",
+        ActivityExportColumn::Message => "message",
+        ActivityExportColumn::Preimage => "preimage",
+        ActivityExportColumn::Confirmed => "confirmed",
+        ActivityExportColumn::ConfirmTimestamp => "confirm_timestamp",
+        ActivityExportColumn::IsBoosted => "is_boosted",
+        ActivityExportColumn::IsTransfer => "is_transfer",
+        ActivityExportColumn::DoesExist => "does_exist",
+        ActivityExportColumn::ChannelId => "channel_id",
+        ActivityExportColumn::TransferTxId => "transfer_tx_id",
+        ActivityExportColumn::Tags => "tags",
+        ActivityExportColumn::CreatedAt => "created_at",
+        ActivityExportColumn::UpdatedAt => "updated_at",
+        ActivityExportColumn::FiatCurrency => "fiat_currency",
+        ActivityExportColumn::FiatRate => "fiat_rate",
+        ActivityExportColumn::FiatSource => "fiat_source",
+        ActivityExportColumn::FiatValue => "fiat_value",
+        ActivityExportColumn::FiatFee => "fiat_fee",
+    }
+}
+
+fn is_fiat_column(column: ActivityExportColumn) -> bool {
+    matches!(
+        column,
+        ActivityExportColumn::FiatCurrency
+            | ActivityExportColumn::FiatRate
+            | ActivityExportColumn::FiatSource
+            | ActivityExportColumn::FiatValue
+            | ActivityExportColumn::FiatFee
+    )
+}
+
+/// Value of `column` for `activity`; empty when it doesn't apply to the activity type
+/// or the activity has no `fiat` rate.
+fn csv_value(column: ActivityExportColumn, activity: &Activity, tags: &[String], fiat: Option<&ActivityFiatRate>) -> String {
+    let (onchain, lightning) = match activity {
+        Activity::Onchain(o) => (Some(o), None),
+        Activity::Lightning(l) => (None, Some(l)),
+    };
+    let text = |value: Option<&str>| value.unwrap_or_default().to_string();
+    let flag = |value: Option<bool>| value.map(|v| v.to_string()).unwrap_or_default();
+    let fee = match (onchain, lightning) {
+        (Some(o), _) => Some(o.fee),
+        (_, Some(l)) => l.fee,
+        (None, None) => None,
+    };
+
+    match column {
+        ActivityExportColumn::Id => activity.get_id().to_string(),
+        ActivityExportColumn::ActivityType => match activity {
+            Activity::Onchain(_) => "onchain".to_string(),
+            Activity::Lightning(_) => "lightning".to_string(),
+        },
+        ActivityExportColumn::TxType => match onchain.map(|o| &o.tx_type).or(lightning.map(|l| &l.tx_type)) {
+            Some(PaymentType::Sent) => "sent".to_string(),
+            Some(PaymentType::Received) => "received".to_string(),
+            None => String::new(),
+        },
+        ActivityExportColumn::Status => match (onchain, lightning) {
+            (Some(o), _) if o.confirmed => "confirmed".to_string(),
+            (Some(_), _) => "unconfirmed".to_string(),
+            (_, Some(l)) => match l.status {
+                PaymentState::Pending => "pending".to_string(),
+                PaymentState::Succeeded => "succeeded".to_string(),
+                PaymentState::Failed => "failed".to_string(),
+            },
+            (None, None) => String::new(),
+        },
+        ActivityExportColumn::Timestamp => format_time(Some(activity.get_timestamp())),
+        ActivityExportColumn::Value => activity.get_value().to_string(),
+        ActivityExportColumn::Fee => fee.map(|f| f.to_string()).unwrap_or_default(),
+        ActivityExportColumn::FeeRate => onchain.map(|o| o.fee_rate.to_string()).unwrap_or_default(),
+        ActivityExportColumn::TxId => text(onchain.map(|o| o.tx_id.as_str())),
+        ActivityExportColumn::Address => text(onchain.map(|o| o.address.as_str())),
+        ActivityExportColumn::Invoice => text(lightning.map(|l| l.invoice.as_str())),
+        ActivityExportColumn::Message => text(lightning.map(|l| l.message.as_str())),
+        ActivityExportColumn::Preimage => text(lightning.and_then(|l| l.preimage.as_deref())),
+        ActivityExportColumn::Confirmed => flag(onchain.map(|o| o.confirmed)),
+        ActivityExportColumn::ConfirmTimestamp => format_time(onchain.and_then(|o| o.confirm_timestamp)),
+        ActivityExportColumn::IsBoosted => flag(onchain.map(|o| o.is_boosted)),
+        ActivityExportColumn::IsTransfer => flag(onchain.map(|o| o.is_transfer)),
+        ActivityExportColumn::DoesExist => flag(onchain.map(|o| o.does_exist)),
+        ActivityExportColumn::ChannelId => text(onchain.and_then(|o| o.channel_id.as_deref())),
+        ActivityExportColumn::TransferTxId => text(onchain.and_then(|o| o.transfer_tx_id.as_deref())),
+        ActivityExportColumn::Tags => tags.join(";"),
+        ActivityExportColumn::CreatedAt => format_time(activity.get_created_at()),
+        ActivityExportColumn::UpdatedAt => format_time(activity.get_updated_at()),
+        ActivityExportColumn::FiatCurrency => text(fiat.map(|f| f.currency.as_str())),
+        ActivityExportColumn::FiatRate => fiat.map(|f| f.rate.to_string()).unwrap_or_default(),
+        ActivityExportColumn::FiatSource => text(fiat.map(|f| f.source.as_str())),
+        ActivityExportColumn::FiatValue => fiat
+            .map(|f| format!("{:.2}", f.convert(activity.get_value())))
+            .unwrap_or_default(),
+        ActivityExportColumn::FiatFee => fiat
+            .zip(fee)
+            .map(|(f, fee)| format!("{:.2}", f.convert(fee)))
+            .unwrap_or_default(),
+    }
+}
+
+/// RFC 3339 in UTC, empty when unset or out of range.
+fn format_time(timestamp: Option<u64>) -> String {
+    timestamp
+        .and_then(|t| i64::try_from(t).ok())
+        .and_then(|t| DateTime::from_timestamp(t, 0))
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Quotes fields as RFC 4180 requires. Text a spreadsheet would read as a formula
+/// gets a leading `'`, since messages and tags can come from other people.
+fn csv_escape(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
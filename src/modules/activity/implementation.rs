@@ -1,8 +1,12 @@
-use rusqlite::{Connection, OptionalExtension};
-use crate::activity::{Activity, ActivityError, ActivityFilter, LightningActivity, OnchainActivity, PaymentState, PaymentType, SortDirection};
+use std::collections::HashSet;
+use rusqlite::{params_from_iter, Connection, OptionalExtension, ToSql, Transaction};
+use crate::activity::{Activity, ActivityBucket, ActivityBucketInterval, ActivityChangeEvent, ActivityCursor, ActivityError, ActivityFiatRate, ActivityFiatTotals, ActivityFilter, ActivityPage, ActivityQuery, ActivitySortField, ActivityTotals, ActivityTypeTotals, ActivityUpsertFailure, ActivityUpsertResult, LightningActivity, OnchainActivity, PaymentState, PaymentType, SortDirection, TagMatch, TagTotals};
+use crate::activity::notifications::ActivityNotifier;
+use crate::modules::migrations::{run_migrations, Migration};
 
 pub struct ActivityDB {
     pub conn: Connection,
+    pub(crate) notifier: ActivityNotifier,
 }
 const CREATE_ACTIVITIES_TABLE: &str = "
     CREATE TABLE IF NOT EXISTS activities (
@@ -116,6 +120,201 @@ const TRIGGER_STATEMENTS: &[&str] = &[
      END"
 ];
 
+/// Activities joined with their onchain or lightning details and their fiat rate in
+/// the currency bound to `?1`, for aggregates.
+const AGGREGATE_SOURCE: &str = "
+    FROM activities a
+    LEFT JOIN onchain_activity o ON a.id = o.id AND a.activity_type = 'onchain'
+    LEFT JOIN lightning_activity l ON a.id = l.id AND a.activity_type = 'lightning'
+    LEFT JOIN activity_fiat f ON f.activity_id = a.id AND f.currency = ?1";
+
+/// Activities that moved funds: existing onchain transactions and succeeded lightning payments.
+const SETTLED_ACTIVITY: &str = "
+    ((a.activity_type = 'onchain' AND o.does_exist = 1)
+     OR (a.activity_type = 'lightning' AND l.status = 'succeeded'))";
+
+/// Aggregate columns read back by `ActivityDB::totals_from_row`.
+const TOTALS_COLUMNS: &str = "
+    COUNT(*) AS activity_count,
+    COALESCE(SUM(CASE WHEN a.tx_type = 'sent' THEN COALESCE(o.value, l.value) END), 0) AS sent,
+    COALESCE(SUM(CASE WHEN a.tx_type = 'received' THEN COALESCE(o.value, l.value) END), 0) AS received,
+    COALESCE(SUM(CASE WHEN a.tx_type = 'sent' THEN COALESCE(o.fee, l.fee, 0) END), 0) AS fees,
+    COUNT(f.rate) AS fiat_count,
+    COALESCE(SUM(CASE WHEN a.tx_type = 'sent' THEN COALESCE(o.value, l.value) * f.rate END), 0) / 100000000.0 AS fiat_sent,
+    COALESCE(SUM(CASE WHEN a.tx_type = 'received' THEN COALESCE(o.value, l.value) * f.rate END), 0) / 100000000.0 AS fiat_received,
+    COALESCE(SUM(CASE WHEN a.tx_type = 'sent' THEN COALESCE(o.fee, l.fee, 0) * f.rate END), 0) / 100000000.0 AS fiat_fees";
+
+/// Schema migrations in order. Released steps must never change; schema
+/// changes are added as new steps.
+pub(crate) const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "initial schema", apply: create_initial_schema },
+    Migration { version: 2, description: "activity full-text search", apply: create_search_index },
+    Migration { version: 3, description: "activity fiat rates", apply: create_fiat_table },
+];
+
+/// The schema as it was before versioning. `IF NOT EXISTS` lets databases
+/// created by those releases adopt version 1 unchanged.
+fn create_initial_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    let tables = [CREATE_ACTIVITIES_TABLE, CREATE_ONCHAIN_TABLE, CREATE_LIGHTNING_TABLE, CREATE_TAGS_TABLE];
+    for statement in tables.iter().chain(INDEX_STATEMENTS).chain(TRIGGER_STATEMENTS) {
+        tx.execute(statement, [])?;
+    }
+    Ok(())
+}
+
+/// Searchable text of each activity, kept in sync with the activity tables by
+/// triggers. `docid` gives the FTS index a rowid that survives VACUUM.
+const CREATE_SEARCH_CONTENT_TABLE: &str = "
+    CREATE TABLE activity_search_content (
+        docid INTEGER PRIMARY KEY,
+        activity_id TEXT NOT NULL UNIQUE,
+        tx_id TEXT,
+        address TEXT,
+        invoice TEXT,
+        message TEXT,
+        preimage TEXT,
+        tags TEXT
+    )";
+
+const CREATE_SEARCH_INDEX: &str = "
+    CREATE VIRTUAL TABLE activity_search USING fts5(
+        activity_id, tx_id, address, invoice, message, preimage, tags,
+        content = 'activity_search_content',
+        content_rowid = 'docid',
+        tokenize = 'unicode61 remove_diacritics 2',
+        prefix = '2 3'
+    )";
+
+const SEARCH_TRIGGER_STATEMENTS: &[&str] = &[
+    // Mirror the content table into the FTS index
+    "CREATE TRIGGER activity_search_content_insert
+     AFTER INSERT ON activity_search_content
+     BEGIN
+         INSERT INTO activity_search (rowid, activity_id, tx_id, address, invoice, message, preimage, tags)
+         VALUES (NEW.docid, NEW.activity_id, NEW.tx_id, NEW.address, NEW.invoice, NEW.message, NEW.preimage, NEW.tags);
+     END",
+
+    "CREATE TRIGGER activity_search_content_delete
+     AFTER DELETE ON activity_search_content
+     BEGIN
+         INSERT INTO activity_search (activity_search, rowid, activity_id, tx_id, address, invoice, message, preimage, tags)
+         VALUES ('delete', OLD.docid, OLD.activity_id, OLD.tx_id, OLD.address, OLD.invoice, OLD.message, OLD.preimage, OLD.tags);
+     END",
+
+    "CREATE TRIGGER activity_search_content_update
+     AFTER UPDATE ON activity_search_content
+     BEGIN
+         INSERT INTO activity_search (activity_search, rowid, activity_id, tx_id, address, invoice, message, preimage, tags)
+         VALUES ('delete', OLD.docid, OLD.activity_id, OLD.tx_id, OLD.address, OLD.invoice, OLD.message, OLD.preimage, OLD.tags);
+         INSERT INTO activity_search (rowid, activity_id, tx_id, address, invoice, message, preimage, tags)
+         VALUES (NEW.docid, NEW.activity_id, NEW.tx_id, NEW.address, NEW.invoice, NEW.message, NEW.preimage, NEW.tags);
+     END",
+
+    // Onchain activities
+    "CREATE TRIGGER onchain_search_insert
+     AFTER INSERT ON onchain_activity
+     BEGIN
+         INSERT INTO activity_search_content (activity_id, tx_id, address, tags)
+         VALUES (
+             NEW.id, NEW.tx_id, NEW.address,
+             (SELECT group_concat(tag, ' ') FROM activity_tags WHERE activity_id = NEW.id)
+         );
+     END",
+
+    "CREATE TRIGGER onchain_search_update
+     AFTER UPDATE ON onchain_activity
+     BEGIN
+         UPDATE activity_search_content
+         SET activity_id = NEW.id, tx_id = NEW.tx_id, address = NEW.address
+         WHERE activity_id = OLD.id;
+     END",
+
+    "CREATE TRIGGER onchain_search_delete
+     AFTER DELETE ON onchain_activity
+     BEGIN
+         DELETE FROM activity_search_content WHERE activity_id = OLD.id;
+     END",
+
+    // Lightning activities
+    "CREATE TRIGGER lightning_search_insert
+     AFTER INSERT ON lightning_activity
+     BEGIN
+         INSERT INTO activity_search_content (activity_id, invoice, message, preimage, tags)
+         VALUES (
+             NEW.id, NEW.invoice, NEW.message, NEW.preimage,
+             (SELECT group_concat(tag, ' ') FROM activity_tags WHERE activity_id = NEW.id)
+         );
+     END",
+
+    "CREATE TRIGGER lightning_search_update
+     AFTER UPDATE ON lightning_activity
+     BEGIN
+         UPDATE activity_search_content
+         SET activity_id = NEW.id, invoice = NEW.invoice, message = NEW.message, preimage = NEW.preimage
+         WHERE activity_id = OLD.id;
+     END",
+
+    "CREATE TRIGGER lightning_search_delete
+     AFTER DELETE ON lightning_activity
+     BEGIN
+         DELETE FROM activity_search_content WHERE activity_id = OLD.id;
+     END",
+
+    // Tags
+    "CREATE TRIGGER activity_tags_search_insert
+     AFTER INSERT ON activity_tags
+     BEGIN
+         UPDATE activity_search_content
+         SET tags = (SELECT group_concat(tag, ' ') FROM activity_tags WHERE activity_id = NEW.activity_id)
+         WHERE activity_id = NEW.activity_id;
+     END",
+
+    "CREATE TRIGGER activity_tags_search_delete
+     AFTER DELETE ON activity_tags
+     BEGIN
+         UPDATE activity_search_content
+         SET tags = (SELECT group_concat(tag, ' ') FROM activity_tags WHERE activity_id = OLD.activity_id)
+         WHERE activity_id = OLD.activity_id;
+     END",
+];
+
+/// Adds the FTS5 index behind `search_activities` and fills it from the
+/// existing activities.
+fn create_search_index(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(CREATE_SEARCH_CONTENT_TABLE, [])?;
+    tx.execute(CREATE_SEARCH_INDEX, [])?;
+    for statement in SEARCH_TRIGGER_STATEMENTS {
+        tx.execute(statement, [])?;
+    }
+    tx.execute_batch(
+        "INSERT INTO activity_search_content (activity_id, tx_id, address, tags)
+         SELECT o.id, o.tx_id, o.address,
+                (SELECT group_concat(tag, ' ') FROM activity_tags WHERE activity_id = o.id)
+         FROM onchain_activity o;
+         INSERT INTO activity_search_content (activity_id, invoice, message, preimage, tags)
+         SELECT l.id, l.invoice, l.message, l.preimage,
+                (SELECT group_concat(tag, ' ') FROM activity_tags WHERE activity_id = l.id)
+         FROM lightning_activity l;"
+    )
+}
+
+/// Bitcoin price in a fiat currency at the time of each activity, one row per currency.
+const CREATE_FIAT_TABLE: &str = "
+    CREATE TABLE activity_fiat (
+        activity_id TEXT NOT NULL,
+        currency TEXT NOT NULL CHECK (length(currency) = 3),
+        rate REAL NOT NULL CHECK (rate > 0),
+        source TEXT NOT NULL,
+        PRIMARY KEY (activity_id, currency),
+        FOREIGN KEY (activity_id) REFERENCES activities(id)
+            ON DELETE CASCADE
+    )";
+
+fn create_fiat_table(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(CREATE_FIAT_TABLE, [])?;
+    Ok(())
+}
+
 impl ActivityDB {
     /// Creates a new ActivityDB instance with the specified database path.
     /// Initializes the database schema if it doesn't exist.
@@ -145,59 +344,14 @@ impl ActivityDB {
                 });
             }
         };
-        let db = ActivityDB { conn };
+        let mut db = ActivityDB { conn, notifier: ActivityNotifier::default() };
         db.initialize()?;
         Ok(db)
     }
 
-    /// Initialize database schema with tables, indexes, and triggers
-    fn initialize(&self) -> Result<(), ActivityError> {
-        // Create base activities table
-        if let Err(e) = self.conn.execute(CREATE_ACTIVITIES_TABLE, []) {
-            return Err(ActivityError::InitializationError {
-                error_details: format!("Error creating activities table: {}", e),
-            });
-        }
-
-        // Create onchain table
-        if let Err(e) = self.conn.execute(CREATE_ONCHAIN_TABLE, []) {
-            return Err(ActivityError::InitializationError {
-                error_details: format!("Error creating onchain_activity table: {}", e),
-            });
-        }
-
-        // Create lightning table
-        if let Err(e) = self.conn.execute(CREATE_LIGHTNING_TABLE, []) {
-            return Err(ActivityError::InitializationError {
-                error_details: format!("Error creating lightning_activity table: {}", e),
-            });
-        }
-
-        // Create tags table
-        if let Err(e) = self.conn.execute(CREATE_TAGS_TABLE, []) {
-            return Err(ActivityError::InitializationError {
-                error_details: format!("Error creating tags table: {}", e),
-            });
-        }
-
-        // Create indexes
-        for statement in INDEX_STATEMENTS {
-            if let Err(e) = self.conn.execute(statement, []) {
-                return Err(ActivityError::InitializationError {
-                    error_details: format!("Error creating index: {}", e),
-                });
-            }
-        }
-
-        // Create triggers
-        for statement in TRIGGER_STATEMENTS {
-            if let Err(e) = self.conn.execute(statement, []) {
-                return Err(ActivityError::InitializationError {
-                    error_details: format!("Error creating trigger: {}", e),
-                });
-            }
-        }
-
+    /// Brings the database schema up to date with the migrations
+    fn initialize(&mut self) -> Result<(), ActivityError> {
+        run_migrations(&mut self.conn, MIGRATIONS)?;
         Ok(())
     }
 
@@ -224,6 +378,139 @@ impl ActivityDB {
         }
     }
 
+    /// Inserts or updates `activities` in one transaction, validating all of them first.
+    /// Activities that fail are skipped and reported in the result, unless `abort_on_failure`
+    /// is set: then the first failure is returned as an error and nothing is written.
+    pub fn upsert_activities(&mut self, activities: &[Activity], abort_on_failure: bool) -> Result<ActivityUpsertResult, ActivityError> {
+        let mut result = ActivityUpsertResult::default();
+        let to_failure = |index: usize, activity: &Activity, error: ActivityError| ActivityUpsertFailure {
+            index: index as u32,
+            activity_id: activity.get_id().to_string(),
+            error_details: error.to_string(),
+        };
+        let abort = |failure: &ActivityUpsertFailure| ActivityError::DataError {
+            error_details: format!(
+                "Failed to upsert activity {} at index {}: {}",
+                failure.activity_id, failure.index, failure.error_details
+            ),
+        };
+
+        let mut ids = HashSet::new();
+        let mut valid = Vec::with_capacity(activities.len());
+        for (index, activity) in activities.iter().enumerate() {
+            let validation = if ids.insert(activity.get_id()) {
+                Self::validate_activity(activity)
+            } else {
+                Err(ActivityError::InvalidActivity {
+                    error_details: "Duplicate activity ID in batch".to_string(),
+                })
+            };
+            match validation {
+                Ok(()) => valid.push((index, activity)),
+                Err(e) => result.failures.push(to_failure(index, activity, e)),
+            }
+        }
+        if abort_on_failure {
+            if let Some(first) = result.failures.first() {
+                return Err(abort(first));
+            }
+        }
+
+        let mut tx = self.conn.transaction().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to start transaction: {}", e),
+        })?;
+
+        let mut events = Vec::new();
+        for (index, activity) in valid {
+            // Each activity gets a savepoint so a failure only undoes its own writes
+            let savepoint = tx.savepoint().map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to create savepoint: {}", e),
+            })?;
+            match Self::upsert_in(&savepoint, activity) {
+                Ok(inserted) => {
+                    savepoint.commit().map_err(|e| ActivityError::DataError {
+                        error_details: format!("Failed to release savepoint: {}", e),
+                    })?;
+                    let activity_id = activity.get_id().to_string();
+                    if inserted {
+                        result.inserted += 1;
+                        events.push(ActivityChangeEvent::Inserted { activity_id });
+                    } else {
+                        result.updated += 1;
+                        events.push(ActivityChangeEvent::Updated { activity_id });
+                    }
+                }
+                Err(e) => {
+                    let failure = to_failure(index, activity, e);
+                    if abort_on_failure {
+                        return Err(abort(&failure));
+                    }
+                    result.failures.push(failure);
+                }
+            }
+        }
+
+        tx.commit().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        result.failures.sort_by_key(|f| f.index);
+        self.notifier.notify(events);
+        Ok(result)
+    }
+
+    /// Updates the activity on `conn`, inserting it when there is none with its id and type.
+    /// Returns whether it was inserted.
+    fn upsert_in(conn: &Connection, activity: &Activity) -> Result<bool, ActivityError> {
+        let updated = match activity {
+            Activity::Onchain(onchain) => Self::update_onchain_in(conn, &onchain.id, onchain)?,
+            Activity::Lightning(lightning) => Self::update_lightning_in(conn, &lightning.id, lightning)?,
+        };
+        if updated {
+            return Ok(false);
+        }
+        match activity {
+            Activity::Onchain(onchain) => Self::insert_onchain_in(conn, onchain, false)?,
+            Activity::Lightning(lightning) => Self::insert_lightning_in(conn, lightning, false)?,
+        }
+        Ok(true)
+    }
+
+    /// Checks what the schema's constraints would reject, so batches can report it per activity.
+    fn validate_activity(activity: &Activity) -> Result<(), ActivityError> {
+        let invalid = |details: &str| Err(ActivityError::InvalidActivity {
+            error_details: details.to_string(),
+        });
+        if activity.get_id().is_empty() {
+            return invalid("Activity ID cannot be empty");
+        }
+        if activity.get_timestamp() == 0 {
+            return invalid("Timestamp must be greater than zero");
+        }
+        match activity {
+            Activity::Onchain(onchain) => {
+                if onchain.address.is_empty() {
+                    return invalid("Address cannot be empty");
+                }
+                if onchain.confirm_timestamp.is_some_and(|t| t < onchain.timestamp) {
+                    return invalid("confirm_timestamp must be greater than or equal to timestamp");
+                }
+                if onchain.channel_id.as_deref() == Some("") || onchain.transfer_tx_id.as_deref() == Some("") {
+                    return invalid("channel_id and transfer_tx_id cannot be empty when set");
+                }
+            }
+            Activity::Lightning(lightning) => {
+                if lightning.invoice.is_empty() {
+                    return invalid("Invoice cannot be empty");
+                }
+                if lightning.preimage.as_deref() == Some("") {
+                    return invalid("Preimage cannot be empty when set");
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Inserts a new onchain activity into the database.
     pub fn insert_onchain_activity(&mut self, activity: &OnchainActivity) -> Result<(), ActivityError> {
         if activity.id.is_empty() {
@@ -239,19 +526,57 @@ impl ActivityDB {
             Err(e) => return Err(e),
         };
 
+        Self::insert_onchain_in(&tx, activity, false)?;
+
+        tx.commit().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        self.notifier.notify(vec![ActivityChangeEvent::Inserted { activity_id: activity.id.clone() }]);
+        Ok(())
+    }
+
+    /// Inserts a new lightning activity into the database.
+    pub fn insert_lightning_activity(&mut self, activity: &LightningActivity) -> Result<(), ActivityError> {
+        let tx = self.conn.transaction().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to start transaction: {}", e),
+        })?;
+
+        Self::insert_lightning_in(&tx, activity, false)?;
+
+        tx.commit().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        self.notifier.notify(vec![ActivityChangeEvent::Inserted { activity_id: activity.id.clone() }]);
+        Ok(())
+    }
+
+    /// Inserts an onchain activity on `conn`, usually a transaction. `created_at` and
+    /// `updated_at` are taken from the activity when `keep_timestamps` is set and default
+    /// to now otherwise.
+    pub(crate) fn insert_onchain_in(conn: &Connection, activity: &OnchainActivity, keep_timestamps: bool) -> Result<(), ActivityError> {
         let activities_sql = "
             INSERT INTO activities (
-                id, activity_type, tx_type, timestamp
+                id, activity_type, tx_type, timestamp, created_at, updated_at
             ) VALUES (
-                ?1, 'onchain', ?2, ?3
+                ?1, 'onchain', ?2, ?3,
+                COALESCE(?4, strftime('%s', 'now')), COALESCE(?5, strftime('%s', 'now'))
             )";
 
-        tx.execute(
+        let (created_at, updated_at) = if keep_timestamps {
+            (activity.created_at, activity.updated_at)
+        } else {
+            (None, None)
+        };
+        conn.execute(
             activities_sql,
             (
                 &activity.id,
                 Self::payment_type_to_string(&activity.tx_type),
                 activity.timestamp,
+                created_at,
+                updated_at,
             ),
         ).map_err(|e| ActivityError::InsertError {
             error_details: format!("Failed to insert into activities: {}", e),
@@ -266,7 +591,7 @@ impl ActivityDB {
                 ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13
             )";
 
-        tx.execute(
+        conn.execute(
             onchain_sql,
             (
                 &activity.id,
@@ -287,32 +612,32 @@ impl ActivityDB {
             error_details: format!("Failed to insert into onchain_activity: {}", e),
         })?;
 
-        tx.commit().map_err(|e| ActivityError::DataError {
-            error_details: format!("Failed to commit transaction: {}", e),
-        })?;
-
         Ok(())
     }
 
-    /// Inserts a new lightning activity into the database.
-    pub fn insert_lightning_activity(&mut self, activity: &LightningActivity) -> Result<(), ActivityError> {
-        let tx = self.conn.transaction().map_err(|e| ActivityError::DataError {
-            error_details: format!("Failed to start transaction: {}", e),
-        })?;
-
+    /// Inserts a lightning activity on `conn`, see `insert_onchain_in`.
+    pub(crate) fn insert_lightning_in(conn: &Connection, activity: &LightningActivity, keep_timestamps: bool) -> Result<(), ActivityError> {
         let activities_sql = "
             INSERT INTO activities (
-                id, activity_type, tx_type, timestamp
+                id, activity_type, tx_type, timestamp, created_at, updated_at
             ) VALUES (
-                ?1, 'lightning', ?2, ?3
+                ?1, 'lightning', ?2, ?3,
+                COALESCE(?4, strftime('%s', 'now')), COALESCE(?5, strftime('%s', 'now'))
             )";
 
-        tx.execute(
+        let (created_at, updated_at) = if keep_timestamps {
+            (activity.created_at, activity.updated_at)
+        } else {
+            (None, None)
+        };
+        conn.execute(
             activities_sql,
             (
                 &activity.id,
                 Self::payment_type_to_string(&activity.tx_type),
                 activity.timestamp,
+                created_at,
+                updated_at,
             ),
         ).map_err(|e| ActivityError::InsertError {
             error_details: format!("Failed to insert into activities: {}", e),
@@ -325,7 +650,7 @@ impl ActivityDB {
                 ?1, ?2, ?3, ?4, ?5, ?6, ?7
             )";
 
-        tx.execute(
+        conn.execute(
             lightning_sql,
             (
                 &activity.id,
@@ -340,90 +665,48 @@ impl ActivityDB {
             error_details: format!("Failed to insert into lightning_activity: {}", e),
         })?;
 
-        tx.commit().map_err(|e| ActivityError::DataError {
-            error_details: format!("Failed to commit transaction: {}", e),
-        })?;
-
         Ok(())
     }
 
-    pub fn get_activities(
-        &self,
-        filter: Option<ActivityFilter>,
-        tx_type: Option<PaymentType>,
-        tags: Option<Vec<String>>,
-        search: Option<String>,
-        min_date: Option<u64>,
-        max_date: Option<u64>,
-        limit: Option<u32>,
-        sort_direction: Option<SortDirection>,
-    ) -> Result<Vec<Activity>, ActivityError> {
+    /// Lists the activities matching `query`, ordered by `sort_by` in `sort_direction`
+    /// with ties broken by timestamp, then id.
+    ///
+    /// `after_cursor` returns the activities following that position in the listing and
+    /// `before_cursor` the ones preceding it; both may be set to read a window. When only
+    /// `before_cursor` is set the page holds the `limit` activities closest to it and
+    /// `next_cursor` continues backwards, so it is passed as `before_cursor` again.
+    /// Otherwise `next_cursor` is passed as `after_cursor`.
+    pub fn get_activities(&self, query: ActivityQuery) -> Result<ActivityPage, ActivityError> {
+        let ActivityQuery {
+            filter,
+            tx_type,
+            statuses,
+            confirmed,
+            is_boosted,
+            is_transfer,
+            min_value,
+            max_value,
+            tags,
+            tag_match,
+            search,
+            min_date,
+            max_date,
+            limit,
+            sort_by,
+            sort_direction,
+            after_cursor,
+            before_cursor,
+        } = query;
         let direction = sort_direction.unwrap_or_default();
-        let filter = filter.unwrap_or(ActivityFilter::All);
-
-        let mut query = String::from(
-            "WITH filtered_activities AS (
-            SELECT DISTINCT a.id
-            FROM activities a
-            LEFT JOIN activity_tags t ON a.id = t.activity_id
-            LEFT JOIN onchain_activity o ON a.id = o.id
-            LEFT JOIN lightning_activity l ON a.id = l.id
-            WHERE 1=1"
-        );
-
-        // Activity type filter
-        match filter {
-            ActivityFilter::Lightning => query.push_str(" AND a.activity_type = 'lightning'"),
-            ActivityFilter::Onchain => query.push_str(" AND a.activity_type = 'onchain'"),
-            ActivityFilter::All => {}
-        }
-
-        // Transaction type filter
-        if let Some(tx_type) = tx_type {
-            query.push_str(&format!(" AND a.tx_type = '{}'",
-                                    Self::payment_type_to_string(&tx_type)));
-        }
-
-        // Tags filter (ANY of the provided tags)
-        if let Some(tag_list) = tags {
-            if !tag_list.is_empty() {
-                query.push_str(" AND t.tag IN (");
-                query.push_str(&tag_list
-                    .iter()
-                    .map(|t| format!("'{}'", t.replace('\'', "''")))
-                    .collect::<Vec<_>>()
-                    .join(","));
-                query.push(')');
-            }
-        }
-
-        // Date range filters
-        if let Some(min) = min_date {
-            query.push_str(&format!(" AND a.timestamp >= {}", min));
-        }
-        if let Some(max) = max_date {
-            query.push_str(&format!(" AND a.timestamp <= {}", max));
-        }
-
-        // Text search filter
-        if let Some(search_text) = search {
-            if !search_text.is_empty() {
-                let search_pattern = format!("%{}%", search_text.replace('\'', "''"));
-                query.push_str(&format!(
-                    " AND (
-                o.address LIKE '{}' OR
-                l.invoice LIKE '{}' OR
-                l.message LIKE '{}'
-            )",
-                    search_pattern, search_pattern, search_pattern
-                ));
-            }
-        }
-
-        query.push_str(")");
+        let sort_by = sort_by.unwrap_or_default();
+        let backwards = before_cursor.is_some() && after_cursor.is_none();
+        let scan_direction = match (direction, backwards) {
+            (SortDirection::Asc, true) => SortDirection::Desc,
+            (SortDirection::Desc, true) => SortDirection::Asc,
+            (direction, false) => direction,
+        };
 
-        // Main query
-        query.push_str("
+        let mut query = String::from("
         SELECT
             a.id,
             a.activity_type,
@@ -455,22 +738,133 @@ impl ActivityDB {
             l.preimage AS ln_preimage
 
         FROM activities a
-        INNER JOIN filtered_activities fa ON a.id = fa.id
         LEFT JOIN onchain_activity o ON a.id = o.id AND a.activity_type = 'onchain'
         LEFT JOIN lightning_activity l ON a.id = l.id AND a.activity_type = 'lightning'
-        ORDER BY a.timestamp ");
+        WHERE 1=1");
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+        // Activity type filter
+        match filter.unwrap_or(ActivityFilter::All) {
+            ActivityFilter::Lightning => query.push_str(" AND a.activity_type = 'lightning'"),
+            ActivityFilter::Onchain => query.push_str(" AND a.activity_type = 'onchain'"),
+            ActivityFilter::All => {}
+        }
+
+        // Transaction type filter
+        if let Some(tx_type) = tx_type {
+            query.push_str(" AND a.tx_type = ?");
+            params.push(Box::new(Self::payment_type_to_string(&tx_type)));
+        }
+
+        // Lightning status filter
+        if let Some(statuses) = statuses.filter(|s| !s.is_empty()) {
+            query.push_str(&format!(" AND l.status IN ({})", Self::placeholders(statuses.len())));
+            params.extend(statuses.iter().map(|s| Box::new(Self::payment_state_to_string(s)) as Box<dyn ToSql>));
+        }
+
+        // Onchain flag filters
+        for (column, flag) in [("o.confirmed", confirmed), ("o.is_boosted", is_boosted), ("o.is_transfer", is_transfer)] {
+            if let Some(flag) = flag {
+                query.push_str(&format!(" AND {} = ?", column));
+                params.push(Box::new(flag));
+            }
+        }
+
+        // Value range filters
+        if let Some(min) = min_value {
+            query.push_str(" AND COALESCE(o.value, l.value) >= ?");
+            params.push(Box::new(Self::clamp_to_i64(min)));
+        }
+        if let Some(max) = max_value {
+            query.push_str(" AND COALESCE(o.value, l.value) <= ?");
+            params.push(Box::new(Self::clamp_to_i64(max)));
+        }
+
+        // Tags filter, matching ANY or ALL of the provided tags
+        if let Some(tag_list) = tags {
+            if !tag_list.is_empty() {
+                query.push_str(&format!(
+                    " AND a.id IN (SELECT activity_id FROM activity_tags WHERE tag IN ({})",
+                    Self::placeholders(tag_list.len())
+                ));
+                let distinct_tags = tag_list.iter().collect::<HashSet<_>>().len() as i64;
+                params.extend(tag_list.into_iter().map(|t| Box::new(t) as Box<dyn ToSql>));
+                if tag_match.unwrap_or_default() == TagMatch::All {
+                    query.push_str(" GROUP BY activity_id HAVING COUNT(DISTINCT tag) = ?");
+                    params.push(Box::new(distinct_tags));
+                }
+                query.push(')');
+            }
+        }
+
+        // Date range filters
+        if let Some(min) = min_date {
+            query.push_str(" AND a.timestamp >= ?");
+            params.push(Box::new(Self::clamp_to_i64(min)));
+        }
+        if let Some(max) = max_date {
+            query.push_str(" AND a.timestamp <= ?");
+            params.push(Box::new(Self::clamp_to_i64(max)));
+        }
 
-        // Add sort direction and limit
-        query.push_str(Self::sort_direction_to_sql(direction));
+        // Text search filter, matching the text literally rather than as a LIKE pattern
+        if let Some(search_text) = search {
+            if !search_text.is_empty() {
+                query.push_str(
+                    " AND (
+                o.address LIKE ? ESCAPE '\\' OR
+                l.invoice LIKE ? ESCAPE '\\' OR
+                l.message LIKE ? ESCAPE '\\'
+            )"
+                );
+                let pattern = format!("%{}%", Self::escape_like(&search_text));
+                params.extend((0..3).map(|_| Box::new(pattern.clone()) as Box<dyn ToSql>));
+            }
+        }
+
+        // Keyset pagination on the sort key, then timestamp and id
+        let sort_column = match sort_by {
+            ActivitySortField::Timestamp => None,
+            ActivitySortField::Value => Some("COALESCE(o.value, l.value)"),
+            ActivitySortField::Fee => Some("COALESCE(o.fee, l.fee, 0)"),
+        };
+        let (after_op, before_op) = match direction {
+            SortDirection::Asc => (">", "<"),
+            SortDirection::Desc => ("<", ">"),
+        };
+        for (cursor, op) in [(after_cursor, after_op), (before_cursor, before_op)] {
+            let Some(cursor) = cursor else { continue };
+            match sort_column {
+                None => query.push_str(&format!(" AND (a.timestamp, a.id) {} (?, ?)", op)),
+                Some(column) => {
+                    let sort_value = cursor.sort_value.ok_or(ActivityError::DataError {
+                        error_details: format!("Cursor for activity {} has no sort_value for sorting by {:?}", cursor.id, sort_by),
+                    })?;
+                    query.push_str(&format!(" AND ({}, a.timestamp, a.id) {} (?, ?, ?)", column, op));
+                    params.push(Box::new(Self::clamp_to_i64(sort_value)));
+                }
+            }
+            params.push(Box::new(Self::clamp_to_i64(cursor.timestamp)));
+            params.push(Box::new(cursor.id));
+        }
+
+        // Add sort order and limit, fetching one extra row to tell whether more follow
+        let order = Self::sort_direction_to_sql(scan_direction);
+        query.push_str(" ORDER BY ");
+        if let Some(column) = sort_column {
+            query.push_str(&format!("{column} {order}, "));
+        }
+        query.push_str(&format!("a.timestamp {order}, a.id {order}"));
         if let Some(n) = limit {
-            query.push_str(&format!(" LIMIT {}", n));
+            query.push_str(" LIMIT ?");
+            params.push(Box::new(i64::from(n) + 1));
         }
 
         let mut stmt = self.conn.prepare(&query).map_err(|e| ActivityError::RetrievalError {
             error_details: format!("Failed to prepare statement: {}", e),
         })?;
 
-        let activity_iter = stmt.query_map([], |row| {
+        let activity_iter = stmt.query_map(params_from_iter(params), |row| {
             let activity_type: String = row.get(1)?;
             match activity_type.as_str() {
                 "onchain" => {
@@ -541,7 +935,22 @@ impl ActivityDB {
             activities.push(activity);
         }
 
-        Ok(activities)
+        let has_more = limit.is_some_and(|n| activities.len() > n as usize);
+        if let Some(n) = limit {
+            activities.truncate(n as usize);
+        }
+        if backwards {
+            activities.reverse();
+        }
+        let next_cursor = if !has_more {
+            None
+        } else if backwards {
+            activities.first().map(|a| ActivityCursor::new(a, sort_by))
+        } else {
+            activities.last().map(|a| ActivityCursor::new(a, sort_by))
+        };
+
+        Ok(ActivityPage { activities, next_cursor, has_more })
     }
 
     /// Retrieves a single activity by its ID.
@@ -661,13 +1070,30 @@ impl ActivityDB {
             error_details: format!("Failed to start transaction: {}", e),
         })?;
 
+        if !Self::update_onchain_in(&tx, activity_id, activity)? {
+            return Err(ActivityError::DataError {
+                error_details: "No activity found with given ID".to_string(),
+            });
+        }
+
+        tx.commit().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        self.notifier.notify(vec![ActivityChangeEvent::Updated { activity_id: activity_id.to_string() }]);
+        Ok(())
+    }
+
+    /// Updates an onchain activity on `conn`, usually a transaction. Returns false when
+    /// there is no onchain activity with `activity_id`.
+    fn update_onchain_in(conn: &Connection, activity_id: &str, activity: &OnchainActivity) -> Result<bool, ActivityError> {
         let activities_sql = "
             UPDATE activities SET
                 tx_type = ?1,
                 timestamp = ?2
             WHERE id = ?3 AND activity_type = 'onchain'";
 
-        let rows = tx.execute(
+        let rows = conn.execute(
             activities_sql,
             (
                 Self::payment_type_to_string(&activity.tx_type),
@@ -679,9 +1105,7 @@ impl ActivityDB {
         })?;
 
         if rows == 0 {
-            return Err(ActivityError::DataError {
-                error_details: "No activity found with given ID".to_string(),
-            });
+            return Ok(false);
         }
 
         let onchain_sql = "
@@ -700,7 +1124,7 @@ impl ActivityDB {
                 transfer_tx_id = ?12
             WHERE id = ?13";
 
-        tx.execute(
+        conn.execute(
             onchain_sql,
             (
                 &activity.tx_id,
@@ -721,11 +1145,7 @@ impl ActivityDB {
             error_details: format!("Failed to update onchain_activity: {}", e),
         })?;
 
-        tx.commit().map_err(|e| ActivityError::DataError {
-            error_details: format!("Failed to commit transaction: {}", e),
-        })?;
-
-        Ok(())
+        Ok(true)
     }
 
     /// Updates an existing lightning activity by ID.
@@ -734,13 +1154,29 @@ impl ActivityDB {
             error_details: format!("Failed to start transaction: {}", e),
         })?;
 
+        if !Self::update_lightning_in(&tx, activity_id, activity)? {
+            return Err(ActivityError::DataError {
+                error_details: "No activity found with given ID".to_string(),
+            });
+        }
+
+        tx.commit().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        self.notifier.notify(vec![ActivityChangeEvent::Updated { activity_id: activity_id.to_string() }]);
+        Ok(())
+    }
+
+    /// Updates a lightning activity on `conn`, see `update_onchain_in`.
+    fn update_lightning_in(conn: &Connection, activity_id: &str, activity: &LightningActivity) -> Result<bool, ActivityError> {
         let activities_sql = "
             UPDATE activities SET
                 tx_type = ?1,
                 timestamp = ?2
             WHERE id = ?3 AND activity_type = 'lightning'";
 
-        let rows = tx.execute(
+        let rows = conn.execute(
             activities_sql,
             (
                 Self::payment_type_to_string(&activity.tx_type),
@@ -752,9 +1188,7 @@ impl ActivityDB {
         })?;
 
         if rows == 0 {
-            return Err(ActivityError::DataError {
-                error_details: "No activity found with given ID".to_string(),
-            });
+            return Ok(false);
         }
 
         let lightning_sql = "
@@ -767,7 +1201,7 @@ impl ActivityDB {
                 preimage = ?6
             WHERE id = ?7";
 
-        tx.execute(
+        conn.execute(
             lightning_sql,
             (
                 &activity.invoice,
@@ -782,11 +1216,7 @@ impl ActivityDB {
             error_details: format!("Failed to update lightning_activity: {}", e),
         })?;
 
-        tx.commit().map_err(|e| ActivityError::DataError {
-            error_details: format!("Failed to commit transaction: {}", e),
-        })?;
-
-        Ok(())
+        Ok(true)
     }
 
     /// Deletes an activity and associated data.
@@ -813,6 +1243,9 @@ impl ActivityDB {
             error_details: format!("Failed to commit transaction: {}", e),
         })?;
 
+        if rows > 0 {
+            self.notifier.notify(vec![ActivityChangeEvent::Deleted { activity_id: activity_id.to_string() }]);
+        }
         Ok(rows > 0)
     }
 
@@ -837,19 +1270,26 @@ impl ActivityDB {
             error_details: format!("Failed to start transaction: {}", e),
         })?;
 
+        let mut added = Vec::new();
         for tag in tags {
-            tx.execute(
+            let inserted = tx.execute(
                 "INSERT OR IGNORE INTO activity_tags (activity_id, tag) VALUES (?1, ?2)",
                 [activity_id, tag],
             ).map_err(|e| ActivityError::DataError {
                 error_details: format!("Failed to insert tag: {}", e),
             })?;
+            if inserted > 0 {
+                added.push(tag.clone());
+            }
         }
 
         tx.commit().map_err(|e| ActivityError::DataError {
             error_details: format!("Failed to commit transaction: {}", e),
         })?;
 
+        if !added.is_empty() {
+            self.notifier.notify(vec![ActivityChangeEvent::TagsAdded { activity_id: activity_id.to_string(), tags: added }]);
+        }
         Ok(())
     }
 
@@ -859,19 +1299,26 @@ impl ActivityDB {
             error_details: format!("Failed to start transaction: {}", e),
         })?;
 
+        let mut removed = Vec::new();
         for tag in tags {
-            tx.execute(
+            let deleted = tx.execute(
                 "DELETE FROM activity_tags WHERE activity_id = ?1 AND tag = ?2",
                 [activity_id, tag],
             ).map_err(|e| ActivityError::DataError {
                 error_details: format!("Failed to remove tag: {}", e),
             })?;
+            if deleted > 0 {
+                removed.push(tag.clone());
+            }
         }
 
         tx.commit().map_err(|e| ActivityError::DataError {
             error_details: format!("Failed to commit transaction: {}", e),
         })?;
 
+        if !removed.is_empty() {
+            self.notifier.notify(vec![ActivityChangeEvent::TagsRemoved { activity_id: activity_id.to_string(), tags: removed }]);
+        }
         Ok(())
     }
 
@@ -908,38 +1355,366 @@ impl ActivityDB {
         Ok(tags)
     }
 
+    /// Stores the fiat rate of an activity, replacing a previous rate in the same currency.
+    pub fn set_activity_fiat_rate(&mut self, activity_id: &str, rate: &ActivityFiatRate) -> Result<(), ActivityError> {
+        let tx = self.conn.transaction().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to start transaction: {}", e),
+        })?;
+        let currency = Self::insert_fiat_rate_in(&tx, activity_id, rate)?.ok_or_else(|| ActivityError::DataError {
+            error_details: format!("Activity {} does not exist", activity_id),
+        })?;
+        tx.commit().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        self.notifier.notify(vec![ActivityChangeEvent::FiatRateChanged {
+            activity_id: activity_id.to_string(),
+            currency,
+        }]);
+        Ok(())
+    }
+
+    /// Stores `rate` for `activity_id` within `tx`. Returns the normalized currency code,
+    /// or None when the activity does not exist.
+    pub(crate) fn insert_fiat_rate_in(tx: &Transaction, activity_id: &str, rate: &ActivityFiatRate) -> Result<Option<String>, ActivityError> {
+        let currency = Self::normalize_currency(&rate.currency)?;
+        if !rate.rate.is_finite() || rate.rate <= 0.0 {
+            return Err(ActivityError::DataError {
+                error_details: format!("Invalid fiat rate: {}", rate.rate),
+            });
+        }
+
+        let inserted = tx.execute(
+            "INSERT INTO activity_fiat (activity_id, currency, rate, source)
+             SELECT id, ?2, ?3, ?4 FROM activities WHERE id = ?1
+             ON CONFLICT (activity_id, currency) DO UPDATE SET rate = excluded.rate, source = excluded.source",
+            (activity_id, &currency, rate.rate, &rate.source),
+        ).map_err(|e| ActivityError::InsertError {
+            error_details: format!("Failed to insert fiat rate: {}", e),
+        })?;
+        Ok((inserted > 0).then_some(currency))
+    }
+
+    /// Fiat rates stored for an activity, ordered by currency.
+    pub fn get_activity_fiat_rates(&self, activity_id: &str) -> Result<Vec<ActivityFiatRate>, ActivityError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT currency, rate, source FROM activity_fiat WHERE activity_id = ?1 ORDER BY currency"
+        ).map_err(|e| ActivityError::RetrievalError {
+            error_details: format!("Failed to prepare statement: {}", e),
+        })?;
+
+        let rates = stmt.query_map([activity_id], |row| {
+            Ok(ActivityFiatRate {
+                currency: row.get(0)?,
+                rate: row.get(1)?,
+                source: row.get(2)?,
+            })
+        })
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to process rows: {}", e),
+            })?;
+
+        Ok(rates)
+    }
+
+    /// Removes the rate of an activity in `currency`. Returns whether a rate was removed.
+    pub fn remove_activity_fiat_rate(&mut self, activity_id: &str, currency: &str) -> Result<bool, ActivityError> {
+        let currency = Self::normalize_currency(currency)?;
+        let removed = self.conn.execute(
+            "DELETE FROM activity_fiat WHERE activity_id = ?1 AND currency = ?2",
+            (activity_id, &currency),
+        ).map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to remove fiat rate: {}", e),
+        })?;
+
+        if removed > 0 {
+            self.notifier.notify(vec![ActivityChangeEvent::FiatRateChanged {
+                activity_id: activity_id.to_string(),
+                currency,
+            }]);
+        }
+        Ok(removed > 0)
+    }
+
+    /// Ids of activities without a rate in `currency`, newest first, so the host app can
+    /// look up historic prices for them.
+    pub fn get_activity_ids_missing_fiat_rate(&self, currency: &str, limit: Option<u32>) -> Result<Vec<String>, ActivityError> {
+        let currency = Self::normalize_currency(currency)?;
+        let mut stmt = self.conn.prepare(
+            "SELECT a.id FROM activities a
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM activity_fiat f WHERE f.activity_id = a.id AND f.currency = ?1
+             )
+             ORDER BY a.timestamp DESC, a.id DESC
+             LIMIT ?2"
+        ).map_err(|e| ActivityError::RetrievalError {
+            error_details: format!("Failed to prepare statement: {}", e),
+        })?;
+
+        // A negative LIMIT means no limit
+        let limit = limit.map_or(-1, i64::from);
+        let ids = stmt.query_map((currency, limit), |row| row.get(0))
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to process rows: {}", e),
+            })?;
+
+        Ok(ids)
+    }
+
     /// Get activities by tag with optional limit
-    pub fn get_activities_by_tag(&self, tag: &str, limit: Option<u32>, sort_direction: Option<SortDirection>) -> Result<Vec<Activity>, ActivityError> {
-        let direction = sort_direction.unwrap_or_default();
+    pub fn get_activities_by_tag(
+        &self,
+        tag: &str,
+        limit: Option<u32>,
+        sort_direction: Option<SortDirection>,
+        after_cursor: Option<ActivityCursor>,
+        before_cursor: Option<ActivityCursor>,
+    ) -> Result<ActivityPage, ActivityError> {
+        self.get_activities(ActivityQuery {
+            tags: Some(vec![tag.to_string()]),
+            limit,
+            sort_direction,
+            after_cursor,
+            before_cursor,
+            ..Default::default()
+        })
+    }
+
+    /// Sent, received and fee totals per activity type, optionally between two dates.
+    /// Onchain activities count while their transaction exists and lightning ones once
+    /// they succeeded. Amounts are also converted to `fiat_currency` when it is set.
+    pub fn get_activity_totals(
+        &self,
+        min_date: Option<u64>,
+        max_date: Option<u64>,
+        fiat_currency: Option<&str>,
+    ) -> Result<ActivityTypeTotals, ActivityError> {
+        let fiat_currency = fiat_currency.map(Self::normalize_currency).transpose()?;
+        let (date_sql, mut params) = Self::date_range_sql(min_date, max_date);
+        params.insert(0, Box::new(fiat_currency.clone()));
         let sql = format!(
-            "SELECT a.id, a.activity_type
-             FROM activities a
-             JOIN activity_tags t ON a.id = t.activity_id
-             WHERE t.tag = ?1
-             ORDER BY a.timestamp {} {}",
-                Self::sort_direction_to_sql(direction),
-                limit.map_or(String::new(), |n| format!("LIMIT {}", n))
+            "SELECT a.activity_type, {TOTALS_COLUMNS} {AGGREGATE_SOURCE}
+             WHERE {SETTLED_ACTIVITY}{date_sql}
+             GROUP BY a.activity_type"
         );
 
-        let mut stmt = self.conn.prepare(&sql).map_err(|e| ActivityError::RetrievalError {
-            error_details: format!("Failed to prepare statement: {}", e),
+        let rows = self.query_aggregates(&sql, params, |row| {
+            Ok((row.get::<_, String>(0)?, Self::totals_from_row(row, 1, fiat_currency.as_deref())?))
         })?;
 
-        let rows = match stmt.query_map([tag], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
-        }) {
-            Ok(rows) => rows,
-            Err(e) => return Err(ActivityError::RetrievalError {
-                error_details: format!("Failed to execute query: {}", e),
+        let mut totals = ActivityTypeTotals::default();
+        for (activity_type, type_totals) in rows {
+            match activity_type.as_str() {
+                "onchain" => totals.onchain = type_totals,
+                "lightning" => totals.lightning = type_totals,
+                _ => {}
+            }
+        }
+        Ok(totals)
+    }
+
+    /// Totals per day, week or month from `min_date` to `max_date`, including empty
+    /// intervals. Intervals follow the calendar at `utc_offset_seconds` (UTC when unset).
+    pub fn get_activity_buckets(
+        &self,
+        interval: ActivityBucketInterval,
+        min_date: u64,
+        max_date: u64,
+        filter: Option<ActivityFilter>,
+        utc_offset_seconds: Option<i32>,
+        fiat_currency: Option<&str>,
+    ) -> Result<Vec<ActivityBucket>, ActivityError> {
+        let fiat_currency = fiat_currency.map(Self::normalize_currency).transpose()?;
+        let (start_of, step) = match interval {
+            ActivityBucketInterval::Day => ("'start of day'", "'+1 day'"),
+            ActivityBucketInterval::Week => ("'-6 days', 'weekday 1', 'start of day'", "'+7 days'"),
+            ActivityBucketInterval::Month => ("'start of month'", "'+1 month'"),
+        };
+        // ?1 is the fiat currency, ?2 the UTC offset, ?3 and ?4 the date range
+        let bucket_start = |timestamp: &str| format!(
+            "(CAST(strftime('%s', {timestamp} + ?2, 'unixepoch', {start_of}) AS INTEGER) - ?2)"
+        );
+        let next_start = format!("(CAST(strftime('%s', start + ?2, 'unixepoch', {step}) AS INTEGER) - ?2)");
+        let type_sql = match filter.unwrap_or(ActivityFilter::All) {
+            ActivityFilter::Lightning => " AND a.activity_type = 'lightning'",
+            ActivityFilter::Onchain => " AND a.activity_type = 'onchain'",
+            ActivityFilter::All => "",
+        };
+
+        let sql = format!(
+            "WITH RECURSIVE buckets(start) AS (
+                 SELECT {first_bucket} WHERE ?3 <= ?4
+                 UNION ALL
+                 SELECT {next_start} FROM buckets WHERE {next_start} <= ?4
+             ),
+             totals AS (
+                 SELECT {activity_bucket} AS start, {TOTALS_COLUMNS} {AGGREGATE_SOURCE}
+                 WHERE {SETTLED_ACTIVITY} AND a.timestamp BETWEEN ?3 AND ?4{type_sql}
+                 GROUP BY 1
+             )
+             SELECT b.start,
+                    COALESCE(t.activity_count, 0), COALESCE(t.sent, 0),
+                    COALESCE(t.received, 0), COALESCE(t.fees, 0), COALESCE(t.fiat_count, 0),
+                    COALESCE(t.fiat_sent, 0), COALESCE(t.fiat_received, 0), COALESCE(t.fiat_fees, 0)
+             FROM buckets b
+             LEFT JOIN totals t ON t.start = b.start
+             ORDER BY b.start",
+            first_bucket = bucket_start("?3"),
+            activity_bucket = bucket_start("a.timestamp"),
+        );
+
+        let params: Vec<Box<dyn ToSql>> = vec![
+            Box::new(fiat_currency.clone()),
+            Box::new(utc_offset_seconds.unwrap_or(0)),
+            Box::new(Self::clamp_to_i64(min_date)),
+            Box::new(Self::clamp_to_i64(max_date)),
+        ];
+        self.query_aggregates(&sql, params, |row| {
+            let start: i64 = row.get(0)?;
+            Ok(ActivityBucket {
+                start: start.max(0) as u64,
+                totals: Self::totals_from_row(row, 1, fiat_currency.as_deref())?,
             })
+        })
+    }
+
+    /// Totals of lightning payments that are still pending, also in `fiat_currency` when set.
+    pub fn get_pending_lightning_totals(&self, fiat_currency: Option<&str>) -> Result<ActivityTotals, ActivityError> {
+        let fiat_currency = fiat_currency.map(Self::normalize_currency).transpose()?;
+        let sql = format!(
+            "SELECT {TOTALS_COLUMNS} {AGGREGATE_SOURCE}
+             WHERE a.activity_type = 'lightning' AND l.status = 'pending'"
+        );
+        let params: Vec<Box<dyn ToSql>> = vec![Box::new(fiat_currency.clone())];
+        let totals = self.query_aggregates(&sql, params, |row| {
+            Self::totals_from_row(row, 0, fiat_currency.as_deref())
+        })?;
+        Ok(totals.into_iter().next().unwrap_or_default())
+    }
+
+    /// Totals per tag, optionally between two dates, ordered by tag. Activities with
+    /// several tags count towards each of them. Amounts are also converted to `fiat_currency`
+    /// when it is set.
+    pub fn get_tag_totals(
+        &self,
+        min_date: Option<u64>,
+        max_date: Option<u64>,
+        fiat_currency: Option<&str>,
+    ) -> Result<Vec<TagTotals>, ActivityError> {
+        let fiat_currency = fiat_currency.map(Self::normalize_currency).transpose()?;
+        let (date_sql, mut params) = Self::date_range_sql(min_date, max_date);
+        params.insert(0, Box::new(fiat_currency.clone()));
+        let sql = format!(
+            "SELECT t.tag, {TOTALS_COLUMNS} {AGGREGATE_SOURCE}
+             JOIN activity_tags t ON t.activity_id = a.id
+             WHERE {SETTLED_ACTIVITY}{date_sql}
+             GROUP BY t.tag
+             ORDER BY t.tag"
+        );
+        self.query_aggregates(&sql, params, |row| {
+            Ok(TagTotals {
+                tag: row.get(0)?,
+                totals: Self::totals_from_row(row, 1, fiat_currency.as_deref())?,
+            })
+        })
+    }
+
+    fn query_aggregates<T>(
+        &self,
+        sql: &str,
+        params: Vec<Box<dyn ToSql>>,
+        map: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>, ActivityError> {
+        let mut stmt = self.conn.prepare(sql).map_err(|e| ActivityError::RetrievalError {
+            error_details: format!("Failed to prepare statement: {}", e),
+        })?;
+        let rows = stmt.query_map(params_from_iter(params), map).map_err(|e| ActivityError::RetrievalError {
+            error_details: format!("Failed to execute query: {}", e),
+        })?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to process row: {}", e),
+        })
+    }
+
+    /// Reads the `TOTALS_COLUMNS` starting at column `start`. The fiat amounts are only
+    /// read when a fiat currency was bound.
+    fn totals_from_row(row: &rusqlite::Row, start: usize, fiat_currency: Option<&str>) -> rusqlite::Result<ActivityTotals> {
+        let column = |offset: usize| row.get::<_, i64>(start + offset).map(|v| v as u64);
+        let activity_count = column(0)?;
+        let fiat = match fiat_currency {
+            Some(currency) => Some(ActivityFiatTotals {
+                currency: currency.to_string(),
+                sent: row.get(start + 5)?,
+                received: row.get(start + 6)?,
+                fees: row.get(start + 7)?,
+                missing_rate_count: activity_count.saturating_sub(column(4)?),
+            }),
+            None => None,
         };
+        Ok(ActivityTotals {
+            activity_count,
+            sent: column(1)?,
+            received: column(2)?,
+            fees: column(3)?,
+            fiat,
+        })
+    }
 
-        let mut activities = Vec::new();
-        for row in rows {
-            let (id, _) = row.map_err(|e| ActivityError::DataError {
+    fn date_range_sql(min_date: Option<u64>, max_date: Option<u64>) -> (String, Vec<Box<dyn ToSql>>) {
+        let mut sql = String::new();
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+        if let Some(min) = min_date {
+            sql.push_str(" AND a.timestamp >= ?");
+            params.push(Box::new(Self::clamp_to_i64(min)));
+        }
+        if let Some(max) = max_date {
+            sql.push_str(" AND a.timestamp <= ?");
+            params.push(Box::new(Self::clamp_to_i64(max)));
+        }
+        (sql, params)
+    }
+
+    /// Full-text search over ids, txids, addresses, invoices, messages, preimages and tags,
+    /// best matches first. Every word of `query` must match the start of a word in the
+    /// activity, so partial addresses and txids are found too.
+    pub fn search_activities(&self, query: &str, limit: Option<u32>) -> Result<Vec<Activity>, ActivityError> {
+        let Some(match_expression) = Self::fts_match_expression(query) else {
+            return Ok(Vec::new());
+        };
+
+        let mut stmt = self.conn.prepare(
+            "SELECT c.activity_id
+             FROM activity_search s
+             JOIN activity_search_content c ON c.docid = s.rowid
+             JOIN activities a ON a.id = c.activity_id
+             WHERE activity_search MATCH ?1
+             ORDER BY bm25(activity_search), a.timestamp DESC, a.id DESC
+             LIMIT ?2"
+        ).map_err(|e| ActivityError::RetrievalError {
+            error_details: format!("Failed to prepare statement: {}", e),
+        })?;
+
+        // A negative LIMIT means no limit
+        let limit = limit.map_or(-1, i64::from);
+        let ids = stmt.query_map((match_expression, limit), |row| row.get::<_, String>(0))
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ActivityError::DataError {
                 error_details: format!("Failed to process row: {}", e),
             })?;
 
+        let mut activities = Vec::with_capacity(ids.len());
+        for id in ids {
             if let Some(activity) = self.get_activity_by_id(&id)? {
                 activities.push(activity);
             }
@@ -1019,4 +1794,51 @@ impl ActivityDB {
             SortDirection::Desc => "DESC"
         }
     }
+
+    /// Uppercases an ISO 4217 currency code, rejecting anything but three ASCII letters.
+    pub(crate) fn normalize_currency(currency: &str) -> Result<String, ActivityError> {
+        let currency = currency.trim();
+        if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(ActivityError::DataError {
+                error_details: format!("Invalid currency code: {}", currency),
+            });
+        }
+        Ok(currency.to_ascii_uppercase())
+    }
+
+    fn placeholders(count: usize) -> String {
+        vec!["?"; count].join(",")
+    }
+
+    /// Timestamps are stored as INTEGER, so bounds past `i64::MAX` behave as `i64::MAX`.
+    fn clamp_to_i64(value: u64) -> i64 {
+        i64::try_from(value).unwrap_or(i64::MAX)
+    }
+
+    /// Turns free text into an FTS5 expression that prefix-matches every word.
+    /// Words are quoted so operators and punctuation in the input are plain text.
+    fn fts_match_expression(query: &str) -> Option<String> {
+        let terms: Vec<String> = query
+            .split_whitespace()
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+            .collect();
+        if terms.is_empty() {
+            None
+        } else {
+            Some(terms.join(" "))
+        }
+    }
+
+    /// Escapes LIKE wildcards so the text is matched literally with `ESCAPE '\'`.
+    fn escape_like(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 mod implementation;
 mod types;
 mod errors;
+mod export;
+mod notifications;
 mod tests;
 
 pub use implementation::*;
 pub use types::*;
-pub use errors::*;
\ No newline at end of file
+pub use errors::*;
+pub use export::*;
+pub use notifications::*;
@@ -0,0 +1,104 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::activity::{ActivityDB, ActivityError};
+
+/// A change to the stored activities.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum ActivityChangeEvent {
+    Inserted { activity_id: String },
+    Updated { activity_id: String },
+    Deleted { activity_id: String },
+    /// Only the tags the activity didn't have yet.
+    TagsAdded { activity_id: String, tags: Vec<String> },
+    /// Only the tags the activity had.
+    TagsRemoved { activity_id: String, tags: Vec<String> },
+    /// A fiat rate was set or removed.
+    FiatRateChanged { activity_id: String, currency: String },
+}
+
+/// Receives the changes of each write once its transaction has committed, in commit order.
+/// Calls come from a background thread, so listeners may query the activities again.
+#[uniffi::export(callback_interface)]
+pub trait ActivityChangeListener: Send + Sync {
+    fn on_activity_changes(&self, events: Vec<ActivityChangeEvent>);
+}
+
+type Listeners = Arc<Mutex<Vec<(u64, Arc<dyn ActivityChangeListener>)>>>;
+
+/// Delivers change events to the subscribed listeners on a dedicated thread, started by
+/// the first subscription and stopped when the notifier is dropped.
+#[derive(Default)]
+pub(crate) struct ActivityNotifier {
+    listeners: Listeners,
+    last_subscription_id: u64,
+    sender: Option<Sender<Vec<ActivityChangeEvent>>>,
+}
+
+impl ActivityNotifier {
+    fn subscribe(&mut self, listener: Box<dyn ActivityChangeListener>) -> Result<u64, ActivityError> {
+        if self.sender.is_none() {
+            let (sender, receiver) = channel::<Vec<ActivityChangeEvent>>();
+            let listeners = Arc::clone(&self.listeners);
+            thread::Builder::new()
+                .name("activity-notifications".to_string())
+                .spawn(move || {
+                    for events in receiver {
+                        // Call without holding the lock so listeners can unsubscribe
+                        let current: Vec<_> = listeners.lock().unwrap()
+                            .iter()
+                            .map(|(_, listener)| Arc::clone(listener))
+                            .collect();
+                        for listener in current {
+                            // A failing listener, e.g. a throwing foreign callback, must not
+                            // stop delivery to the others
+                            let delivered = catch_unwind(AssertUnwindSafe(|| listener.on_activity_changes(events.clone())));
+                            if delivered.is_err() {
+                                println!("✗ Activity change listener panicked");
+                            }
+                        }
+                    }
+                })
+                .map_err(|e| ActivityError::InitializationError {
+                    error_details: format!("Failed to start notification thread: {}", e),
+                })?;
+            self.sender = Some(sender);
+        }
+
+        self.last_subscription_id += 1;
+        self.listeners.lock().unwrap().push((self.last_subscription_id, Arc::from(listener)));
+        Ok(self.last_subscription_id)
+    }
+
+    fn unsubscribe(&mut self, subscription_id: u64) -> bool {
+        let mut listeners = self.listeners.lock().unwrap();
+        let count = listeners.len();
+        listeners.retain(|(id, _)| *id != subscription_id);
+        listeners.len() < count
+    }
+
+    /// Queues `events` for the listeners. Call only after the transaction that made the
+    /// changes committed.
+    pub(crate) fn notify(&self, events: Vec<ActivityChangeEvent>) {
+        if events.is_empty() || self.listeners.lock().unwrap().is_empty() {
+            return;
+        }
+        if let Some(sender) = &self.sender {
+            // The thread only stops once the sender is dropped
+            sender.send(events).ok();
+        }
+    }
+}
+
+impl ActivityDB {
+    /// Registers a listener for activity changes. Returns the id to unsubscribe with.
+    pub fn subscribe_activity_changes(&mut self, listener: Box<dyn ActivityChangeListener>) -> Result<u64, ActivityError> {
+        self.notifier.subscribe(listener)
+    }
+
+    /// Removes a listener. Returns whether it was subscribed.
+    pub fn unsubscribe_activity_changes(&mut self, subscription_id: u64) -> bool {
+        self.notifier.unsubscribe(subscription_id)
+    }
+}
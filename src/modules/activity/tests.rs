@@ -1,8 +1,13 @@
 #[cfg(test)]
 mod tests {
-    use crate::activity::{ActivityDB, OnchainActivity, LightningActivity, PaymentType, PaymentState, Activity, ActivityFilter, SortDirection};
+    use crate::activity::{ActivityDB, OnchainActivity, LightningActivity, PaymentType, PaymentState, Activity, ActivityFilter, SortDirection, ActivityCursor, ActivityPage, ActivityQuery, ActivitySortField, TagMatch, ActivityTotals, ActivityTypeTotals, ActivityBucket, ActivityBucketInterval, TagTotals, ActivityExportFormat, ActivityExportColumn, ActivityFiatRate, ActivityFiatTotals, ActivityChangeEvent, ActivityChangeListener, ActivityUpsertResult};
     use std::fs;
+    use std::sync::{mpsc, Mutex};
+    use std::time::Duration;
     use rand::random;
+    use rusqlite::Connection;
+    use crate::activity::{ActivityError, MIGRATIONS};
+    use crate::modules::migrations::{latest_version, run_migrations, schema_version, Migration};
 
     fn setup() -> (ActivityDB, String) {
         let db_path = format!("test_db_{}.sqlite", random::<u64>());
@@ -52,6 +57,22 @@ mod tests {
         }
     }
 
+    /// Inserts the test onchain activity under `id` after applying `edit` to it.
+    fn insert_onchain(db: &mut ActivityDB, id: &str, edit: impl FnOnce(&mut OnchainActivity)) {
+        let mut activity = create_test_onchain_activity();
+        activity.id = id.to_string();
+        edit(&mut activity);
+        db.insert_onchain_activity(&activity).unwrap();
+    }
+
+    /// Inserts the test lightning activity under `id` after applying `edit` to it.
+    fn insert_lightning(db: &mut ActivityDB, id: &str, edit: impl FnOnce(&mut LightningActivity)) {
+        let mut activity = create_test_lightning_activity();
+        activity.id = id.to_string();
+        edit(&mut activity);
+        db.insert_lightning_activity(&activity).unwrap();
+    }
+
     #[test]
     fn test_db_initialization() {
         let (db, db_path) = setup();
@@ -65,7 +86,7 @@ mod tests {
         let activity = create_test_onchain_activity();
         assert!(db.insert_onchain_activity(&activity).is_ok());
 
-        let activities = db.get_activities(Some(ActivityFilter::Onchain), None, None, None, None, None, None, None).unwrap();
+        let activities = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::Onchain), ..Default::default() }).unwrap().activities;
         assert_eq!(activities.len(), 1);
         if let Activity::Onchain(retrieved) = &activities[0] {
             assert_eq!(retrieved.id, activity.id);
@@ -86,7 +107,7 @@ mod tests {
         let activity = create_test_lightning_activity();
         assert!(db.insert_lightning_activity(&activity).is_ok());
 
-        let activities = db.get_activities(Some(ActivityFilter::Lightning), None, None, None, None, None, None, None).unwrap();
+        let activities = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::Lightning), ..Default::default() }).unwrap().activities;
         assert_eq!(activities.len(), 1);
         if let Activity::Lightning(retrieved) = &activities[0] {
             assert_eq!(retrieved.id, activity.id);
@@ -110,7 +131,7 @@ mod tests {
         db.insert_onchain_activity(&onchain).unwrap();
         db.insert_lightning_activity(&lightning).unwrap();
 
-        let all_activities = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, None, None).unwrap();
+        let all_activities = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), ..Default::default() }).unwrap().activities;
         assert_eq!(all_activities.len(), 2);
 
         // Check ordering by timestamp descending (they have the same timestamp in this test)
@@ -127,7 +148,7 @@ mod tests {
         let activity = create_test_onchain_activity();
         db.insert_onchain_activity(&activity).unwrap();
 
-        let retrieved = db.get_activities(Some(ActivityFilter::Onchain), None, None, None, None, None, None, None).unwrap();
+        let retrieved = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::Onchain), ..Default::default() }).unwrap().activities;
         if let Activity::Onchain(activity) = &retrieved[0] {
             assert!(activity.created_at.is_some());
             assert!(activity.updated_at.is_some());
@@ -150,7 +171,7 @@ mod tests {
         db.insert_onchain_activity(&activity1).unwrap();
         db_clone.insert_lightning_activity(&activity2).unwrap();
 
-        let all_activities = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, None, None).unwrap();
+        let all_activities = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), ..Default::default() }).unwrap().activities;
         assert_eq!(all_activities.len(), 2);
 
         cleanup(&db_path);
@@ -171,7 +192,7 @@ mod tests {
         db.insert_onchain_activity(&onchain2).unwrap();
         db.insert_lightning_activity(&lightning).unwrap();
 
-        let activities = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, None, None).unwrap();
+        let activities = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), ..Default::default() }).unwrap().activities;
         let timestamps: Vec<u64> = activities.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(timestamps, vec![2000, 1500, 1000]);
 
@@ -196,17 +217,17 @@ mod tests {
         }
 
         // Test limits with different filters
-        let all = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, Some(3), None).unwrap();
+        let all = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), limit: Some(3), ..Default::default() }).unwrap().activities;
         assert_eq!(all.len(), 3);
 
-        let onchain = db.get_activities(Some(ActivityFilter::Onchain), None, None, None, None, None, Some(2), None).unwrap();
+        let onchain = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::Onchain), limit: Some(2), ..Default::default() }).unwrap().activities;
         assert_eq!(onchain.len(), 2);
 
-        let lightning = db.get_activities(Some(ActivityFilter::Lightning), None, None, None, None, None, Some(4), None).unwrap();
+        let lightning = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::Lightning), limit: Some(4), ..Default::default() }).unwrap().activities;
         assert_eq!(lightning.len(), 4);
 
         // Test without limits
-        let all = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, None, None).unwrap();
+        let all = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), ..Default::default() }).unwrap().activities;
         assert_eq!(all.len(), 10);
 
         cleanup(&db_path);
@@ -218,13 +239,13 @@ mod tests {
         db.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
         db.insert_lightning_activity(&create_test_lightning_activity()).unwrap();
 
-        let all = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, Some(0), None).unwrap();
+        let all = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), limit: Some(0), ..Default::default() }).unwrap().activities;
         assert_eq!(all.len(), 0);
 
-        let onchain = db.get_activities(Some(ActivityFilter::Onchain), None, None, None, None, None, Some(0), None).unwrap();
+        let onchain = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::Onchain), limit: Some(0), ..Default::default() }).unwrap().activities;
         assert_eq!(onchain.len(), 0);
 
-        let lightning = db.get_activities(Some(ActivityFilter::Lightning), None, None, None, None, None, Some(0), None).unwrap();
+        let lightning = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::Lightning), limit: Some(0), ..Default::default() }).unwrap().activities;
         assert_eq!(lightning.len(), 0);
 
         cleanup(&db_path);
@@ -276,10 +297,10 @@ mod tests {
         db.add_tags(&onchain.id, &["payment".to_string()]).unwrap();
         db.add_tags(&lightning.id, &["payment".to_string()]).unwrap();
 
-        let activities = db.get_activities_by_tag("payment", None, None).unwrap();
+        let activities = db.get_activities_by_tag("payment", None, None, None, None).unwrap().activities;
         assert_eq!(activities.len(), 2);
 
-        let limited = db.get_activities_by_tag("payment", Some(1), None).unwrap();
+        let limited = db.get_activities_by_tag("payment", Some(1), None, None, None).unwrap().activities;
         assert_eq!(limited.len(), 1);
 
         cleanup(&db_path);
@@ -338,7 +359,7 @@ mod tests {
     #[test]
     fn test_get_activities_by_nonexistent_tag() {
         let (db, db_path) = setup();
-        let activities = db.get_activities_by_tag("nonexistent", None, None).unwrap();
+        let activities = db.get_activities_by_tag("nonexistent", None, None, None, None).unwrap().activities;
         assert!(activities.is_empty());
         cleanup(&db_path);
     }
@@ -432,7 +453,7 @@ mod tests {
         activity.fee = Some(i64::MAX as u64);
         assert!(db.insert_lightning_activity(&activity).is_ok());
 
-        let activities = db.get_activities(Some(ActivityFilter::Lightning), None, None, None, None, None, None, None).unwrap();
+        let activities = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::Lightning), ..Default::default() }).unwrap().activities;
         assert_eq!(activities.len(), 3);
 
         for act in activities {
@@ -636,12 +657,12 @@ mod tests {
         }
 
         // Test ascending order
-        let asc_results = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, None, Some(SortDirection::Asc)).unwrap();
+        let asc_results = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), sort_direction: Some(SortDirection::Asc), ..Default::default() }).unwrap().activities;
         let asc_timestamps: Vec<u64> = asc_results.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(asc_timestamps, vec![1000, 1001, 1002]);
 
         // Test descending order
-        let desc_results = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, None, Some(SortDirection::Desc)).unwrap();
+        let desc_results = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), sort_direction: Some(SortDirection::Desc), ..Default::default() }).unwrap().activities;
         let desc_timestamps: Vec<u64> = desc_results.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(desc_timestamps, vec![1002, 1001, 1000]);
 
@@ -668,12 +689,12 @@ mod tests {
         db.add_tags(&onchain2.id, &[tag.clone()]).unwrap();
 
         // Test ascending order
-        let asc_activities = db.get_activities_by_tag(&tag, None, Some(SortDirection::Asc)).unwrap();
+        let asc_activities = db.get_activities_by_tag(&tag, None, Some(SortDirection::Asc), None, None).unwrap().activities;
         let asc_timestamps: Vec<u64> = asc_activities.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(asc_timestamps, vec![1000, 2000]);
 
         // Test descending order
-        let desc_activities = db.get_activities_by_tag(&tag, None, Some(SortDirection::Desc)).unwrap();
+        let desc_activities = db.get_activities_by_tag(&tag, None, Some(SortDirection::Desc), None, None).unwrap().activities;
         let desc_timestamps: Vec<u64> = desc_activities.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(desc_timestamps, vec![2000, 1000]);
 
@@ -693,12 +714,12 @@ mod tests {
         }
 
         // Test ascending order with limit
-        let asc_limited = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, Some(3), Some(SortDirection::Asc)).unwrap();
+        let asc_limited = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), limit: Some(3), sort_direction: Some(SortDirection::Asc), ..Default::default() }).unwrap().activities;
         let asc_timestamps: Vec<u64> = asc_limited.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(asc_timestamps, vec![1000, 1001, 1002]);
 
         // Test descending order with limit
-        let desc_limited = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, Some(3), Some(SortDirection::Desc)).unwrap();
+        let desc_limited = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), limit: Some(3), sort_direction: Some(SortDirection::Desc), ..Default::default() }).unwrap().activities;
         let desc_timestamps: Vec<u64> = desc_limited.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(desc_timestamps, vec![1004, 1003, 1002]);
 
@@ -725,7 +746,7 @@ mod tests {
         db.insert_onchain_activity(&onchain2).unwrap();
 
         // Test ascending order
-        let asc_results = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, None, Some(SortDirection::Asc)).unwrap();
+        let asc_results = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), sort_direction: Some(SortDirection::Asc), ..Default::default() }).unwrap().activities;
         let asc_timestamps: Vec<u64> = asc_results.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(asc_timestamps, vec![1000, 2000, 3000]);
 
@@ -752,7 +773,7 @@ mod tests {
         db.insert_onchain_activity(&onchain2).unwrap();
 
         // Test with None sort direction (should default to Desc)
-        let default_results = db.get_activities(Some(ActivityFilter::All), None, None, None, None, None, None, None).unwrap();
+        let default_results = db.get_activities(ActivityQuery { filter: Some(ActivityFilter::All), ..Default::default() }).unwrap().activities;
         let timestamps: Vec<u64> = default_results.iter().map(|a| a.get_timestamp()).collect();
         assert_eq!(timestamps, vec![2000, 1000]);
 
@@ -775,30 +796,20 @@ mod tests {
         db.insert_onchain_activity(&received_activity).unwrap();
 
         // Test filtering by sent
-        let sent_activities = db.get_activities(
-            Some(ActivityFilter::All),
-            Some(PaymentType::Sent),
-            None,
-            None,
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let sent_activities = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            tx_type: Some(PaymentType::Sent),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(sent_activities.len(), 1);
         assert!(matches!(sent_activities[0], Activity::Onchain(ref a) if a.tx_type == PaymentType::Sent));
 
         // Test filtering by received
-        let received_activities = db.get_activities(
-            Some(ActivityFilter::All),
-            Some(PaymentType::Received),
-            None,
-            None,
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let received_activities = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            tx_type: Some(PaymentType::Received),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(received_activities.len(), 1);
         assert!(matches!(received_activities[0], Activity::Onchain(ref a) if a.tx_type == PaymentType::Received));
 
@@ -811,6 +822,7 @@ mod tests {
 
         let mut onchain = create_test_onchain_activity();
         onchain.address = "bc1qxyz123".to_string();
+        onchain.tx_id = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string();
 
         let mut lightning = create_test_lightning_activity();
         lightning.message = "Coffee payment".to_string();
@@ -820,33 +832,34 @@ mod tests {
         db.insert_lightning_activity(&lightning).unwrap();
 
         // Test address search
-        let address_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            Some("xyz123".to_string()),
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let address_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            search: Some("xyz123".to_string()),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(address_results.len(), 1);
         assert!(matches!(address_results[0], Activity::Onchain(_)));
 
         // Test message search
-        let message_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            Some("Coffee".to_string()),
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let message_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            search: Some("Coffee".to_string()),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(message_results.len(), 1);
         assert!(matches!(message_results[0], Activity::Lightning(_)));
 
+        // Txids and tags are left to search_activities
+        db.add_tags(&onchain.id, &["groceries".to_string()]).unwrap();
+        for text in ["4a5e1e4baab89f3a", "groceries"] {
+            let results = db.get_activities(ActivityQuery {
+                search: Some(text.to_string()),
+                ..Default::default()
+            }).unwrap().activities;
+            assert!(results.is_empty(), "search {:?} matched {} activities", text, results.len());
+            assert_eq!(db.search_activities(text, None).unwrap().len(), 1);
+        }
+
         cleanup(&db_path);
     }
 
@@ -870,42 +883,28 @@ mod tests {
         db.insert_onchain_activity(&activity3).unwrap();
 
         // Test min date
-        let min_date_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            None,
-            Some(1500),
-            None,
-            None,
-            None
-        ).unwrap();
+        let min_date_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            min_date: Some(1500),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(min_date_results.len(), 2);
 
         // Test max date
-        let max_date_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            None,
-            None,
-            Some(2500),
-            None,
-            None
-        ).unwrap();
+        let max_date_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            max_date: Some(2500),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(max_date_results.len(), 2);
 
         // Test date range
-        let range_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            None,
-            Some(1500),
-            Some(2500),
-            None,
-            None
-        ).unwrap();
+        let range_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            min_date: Some(1500),
+            max_date: Some(2500),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(range_results.len(), 1);
         assert_eq!(range_results[0].get_timestamp(), 2000);
 
@@ -935,16 +934,17 @@ mod tests {
         db.add_tags(&onchain2.id, &["payment".to_string(), "important".to_string()]).unwrap();
 
         // Test combined filters
-        let results = db.get_activities(
-            Some(ActivityFilter::Onchain),
-            Some(PaymentType::Received),
-            Some(vec!["payment".to_string()]),
-            Some("abc".to_string()),
-            Some(1500),
-            Some(2500),
-            Some(1),
-            Some(SortDirection::Desc)
-        ).unwrap();
+        let results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::Onchain),
+            tx_type: Some(PaymentType::Received),
+            tags: Some(vec!["payment".to_string()]),
+            search: Some("abc".to_string()),
+            min_date: Some(1500),
+            max_date: Some(2500),
+            limit: Some(1),
+            sort_direction: Some(SortDirection::Desc),
+            ..Default::default()
+        }).unwrap().activities;
 
         assert_eq!(results.len(), 1);
         if let Activity::Onchain(activity) = &results[0] {
@@ -967,29 +967,19 @@ mod tests {
         db.insert_onchain_activity(&activity).unwrap();
 
         // Test empty search string - should return all results, same as if no search was provided
-        let empty_search = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            Some("".to_string()),
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let empty_search = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            search: Some("".to_string()),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(empty_search.len(), 1); // Changed from 0 to 1
 
         // Test empty tags array
-        let empty_tags = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            Some(vec![]),
-            None,
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let empty_tags = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            tags: Some(vec![]),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(empty_tags.len(), 1);
 
         cleanup(&db_path);
@@ -1016,34 +1006,1206 @@ mod tests {
         db.add_tags(&activity3.id, &["tag1".to_string(), "tag3".to_string()]).unwrap();
 
         // Test filtering with multiple tags (OR condition)
-        let results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            Some(vec!["tag1".to_string(), "tag2".to_string()]),
-            None,
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            tags: Some(vec!["tag1".to_string(), "tag2".to_string()]),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(results.len(), 3);
 
         // Test with non-existent tag mixed with existing tags
-        let mixed_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            Some(vec!["tag1".to_string(), "nonexistent".to_string()]),
-            None,
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let mixed_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            tags: Some(vec!["tag1".to_string(), "nonexistent".to_string()]),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(mixed_results.len(), 2);
 
         cleanup(&db_path);
     }
 
+    const HOSTILE_INPUTS: &[&str] = &[
+        "'",
+        "''",
+        "' OR '1'='1",
+        "' OR 1=1 --",
+        "'); DROP TABLE activities; --",
+        "\") OR 1=1 --",
+        "x' UNION SELECT id FROM activities --",
+        "%",
+        "%%",
+        "_",
+        "\\",
+        "\\%",
+        "?",
+        "?1",
+        ":tag",
+        "@p",
+        "$1",
+        "*/ OR 1=1 /*",
+        "Ünïcødé ⚡ 'quoted'",
+    ];
+
+    /// Random text drawn mostly from characters that are meaningful to SQL or LIKE.
+    fn random_hostile_string(rng: &mut impl rand::Rng) -> String {
+        const ALPHABET: &[char] = &[
+            '\'', '"', '%', '_', '\\', '?', ':', '@', '$', ';', '-', '/', '*', '(', ')', ',', '=', ' ',
+            '\n', 'a', 'b', 'c', '1', 'O', 'R', '⚡',
+        ];
+        let len = rng.gen_range(1..16);
+        (0..len).map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())]).collect()
+    }
+
+    fn activity_count(db: &ActivityDB) -> usize {
+        db.get_activities(ActivityQuery::default()).unwrap().activities.len()
+    }
+
+    #[test]
+    fn test_hostile_tag_and_search_inputs() {
+        let (mut db, db_path) = setup();
+
+        let onchain = create_test_onchain_activity();
+        let lightning = create_test_lightning_activity();
+        db.insert_onchain_activity(&onchain).unwrap();
+        db.insert_lightning_activity(&lightning).unwrap();
+        db.add_tags(&onchain.id, &["safe".to_string()]).unwrap();
+
+        for input in HOSTILE_INPUTS {
+            let by_tag = db.get_activities(ActivityQuery {
+                tags: Some(vec![input.to_string()]),
+                ..Default::default()
+            }).unwrap().activities;
+            assert!(by_tag.is_empty(), "tag {:?} matched {} activities", input, by_tag.len());
+
+            let by_tags = db.get_activities(ActivityQuery {
+                tags: Some(vec![input.to_string(), "safe".to_string()]),
+                ..Default::default()
+            }).unwrap().activities;
+            assert_eq!(by_tags.len(), 1, "tag list with {:?}", input);
+
+            let by_search = db.get_activities(ActivityQuery {
+                search: Some(input.to_string()),
+                ..Default::default()
+            }).unwrap().activities;
+            assert!(by_search.is_empty(), "search {:?} matched {} activities", input, by_search.len());
+
+            assert_eq!(activity_count(&db), 2);
+        }
+
+        // Hostile tags stored on an activity are matched exactly
+        for input in HOSTILE_INPUTS {
+            db.add_tags(&lightning.id, &[input.to_string()]).unwrap();
+            let results = db.get_activities(ActivityQuery {
+                tags: Some(vec![input.to_string()]),
+                ..Default::default()
+            }).unwrap().activities;
+            assert_eq!(results.len(), 1, "tag {:?}", input);
+            assert_eq!(results[0].get_id(), lightning.id);
+        }
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_fuzz_tag_and_search_filters() {
+        let (mut db, db_path) = setup();
+        let mut rng = rand::thread_rng();
+
+        let onchain = create_test_onchain_activity();
+        let mut lightning = create_test_lightning_activity();
+        db.insert_onchain_activity(&onchain).unwrap();
+        db.insert_lightning_activity(&lightning).unwrap();
+
+        for _ in 0..200 {
+            let tag = random_hostile_string(&mut rng);
+            db.add_tags(&onchain.id, std::slice::from_ref(&tag)).unwrap();
+            let results = db.get_activities(ActivityQuery {
+                tags: Some(vec![tag.clone()]),
+                ..Default::default()
+            }).unwrap().activities;
+            assert_eq!(results.len(), 1, "tag {:?}", tag);
+            assert_eq!(results[0].get_id(), onchain.id);
+            db.remove_tags(&onchain.id, &[tag]).unwrap();
+
+            lightning.message = random_hostile_string(&mut rng);
+            db.update_lightning_activity_by_id(&lightning.id, &lightning).unwrap();
+
+            let search = random_hostile_string(&mut rng);
+            let mut expected: Vec<&str> = [
+                (onchain.id.as_str(), vec![onchain.address.as_str()]),
+                (lightning.id.as_str(), vec![lightning.invoice.as_str(), lightning.message.as_str()]),
+            ]
+                .into_iter()
+                .filter(|(_, fields)| fields.iter().any(|f| f.to_lowercase().contains(&search.to_lowercase())))
+                .map(|(id, _)| id)
+                .collect();
+            expected.sort();
+            let mut found: Vec<String> = db.get_activities(ActivityQuery {
+                search: Some(search.clone()),
+                ..Default::default()
+            }).unwrap().activities.iter().map(|a| a.get_id().to_string()).collect();
+            found.sort();
+            assert_eq!(found, expected, "search {:?} in message {:?}", search, lightning.message);
+
+            // The whole message always finds its activity
+            let results = db.get_activities(ActivityQuery {
+                search: Some(lightning.message.clone()),
+                ..Default::default()
+            }).unwrap().activities;
+            assert!(results.iter().any(|a| a.get_id() == lightning.id), "message {:?}", lightning.message);
+            assert_eq!(activity_count(&db), 2);
+        }
+
+        cleanup(&db_path);
+    }
+
+    /// Inserts activities with repeated timestamps so pages must break ties on id.
+    fn insert_paging_fixture(db: &mut ActivityDB) {
+        for (i, timestamp) in [1000, 1000, 1000, 2000, 2000, 3000, 4000, 4000].into_iter().enumerate() {
+            let id = format!("activity_{}", i);
+            if i % 2 == 0 {
+                insert_onchain(db, &id, |a| a.timestamp = timestamp);
+            } else {
+                insert_lightning(db, &id, |a| a.timestamp = timestamp);
+            }
+        }
+        db.add_tags("activity_1", &["paged".to_string()]).unwrap();
+        db.add_tags("activity_4", &["paged".to_string()]).unwrap();
+        db.add_tags("activity_6", &["paged".to_string()]).unwrap();
+    }
+
+    fn page_ids(page: &ActivityPage) -> Vec<String> {
+        page.activities.iter().map(|a| a.get_id().to_string()).collect()
+    }
+
+    #[test]
+    fn test_cursor_pagination_forward() {
+        let (mut db, db_path) = setup();
+        insert_paging_fixture(&mut db);
+
+        for direction in [SortDirection::Desc, SortDirection::Asc] {
+            let all = db.get_activities(ActivityQuery { sort_direction: Some(direction), ..Default::default() }).unwrap();
+            assert!(!all.has_more);
+            assert!(all.next_cursor.is_none());
+            assert_eq!(all.activities.len(), 8);
+
+            let mut paged = Vec::new();
+            let mut cursor = None;
+            let mut pages = 0;
+            loop {
+                let page = db.get_activities(ActivityQuery {
+                    limit: Some(3),
+                    sort_direction: Some(direction),
+                    after_cursor: cursor,
+                    ..Default::default()
+                }).unwrap();
+                pages += 1;
+                paged.extend(page_ids(&page));
+                assert_eq!(page.has_more, page.next_cursor.is_some());
+                match page.next_cursor {
+                    Some(next) => {
+                        assert_eq!(next, ActivityCursor::new(page.activities.last().unwrap(), ActivitySortField::Timestamp));
+                        cursor = Some(next);
+                    }
+                    None => break,
+                }
+            }
+            assert_eq!(pages, 3);
+            assert_eq!(paged, page_ids(&all));
+        }
+
+        // Ties on timestamp are ordered by id
+        let desc = db.get_activities(ActivityQuery::default()).unwrap();
+        assert_eq!(page_ids(&desc), vec![
+            "activity_7", "activity_6", "activity_5", "activity_4", "activity_3", "activity_2", "activity_1", "activity_0",
+        ]);
+
+        // A page ending exactly at the last activity has nothing more
+        let page = db.get_activities(ActivityQuery { limit: Some(8), ..Default::default() }).unwrap();
+        assert!(!page.has_more);
+        assert!(page.next_cursor.is_none());
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_cursor_pagination_backwards_and_window() {
+        let (mut db, db_path) = setup();
+        insert_paging_fixture(&mut db);
+
+        let cursor = ActivityCursor { timestamp: 1000, id: "activity_1".to_string(), sort_value: None };
+        let page = db.get_activities(ActivityQuery { limit: Some(3), before_cursor: Some(cursor), ..Default::default() }).unwrap();
+        assert_eq!(page_ids(&page), vec!["activity_4", "activity_3", "activity_2"]);
+        assert!(page.has_more);
+        let next = page.next_cursor.unwrap();
+        assert_eq!(next.id, "activity_4");
+
+        let page = db.get_activities(ActivityQuery { limit: Some(3), before_cursor: Some(next), ..Default::default() }).unwrap();
+        assert_eq!(page_ids(&page), vec!["activity_7", "activity_6", "activity_5"]);
+        assert!(!page.has_more);
+        assert!(page.next_cursor.is_none());
+
+        let page = db.get_activities(ActivityQuery {
+            limit: Some(2),
+            sort_direction: Some(SortDirection::Asc),
+            before_cursor: Some(ActivityCursor { timestamp: 3000, id: "activity_5".to_string(), sort_value: None }),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(page_ids(&page), vec!["activity_3", "activity_4"]);
+        assert!(page.has_more);
+
+        // Both cursors read the window between them
+        let after = ActivityCursor { timestamp: 4000, id: "activity_6".to_string(), sort_value: None };
+        let before = ActivityCursor { timestamp: 1000, id: "activity_1".to_string(), sort_value: None };
+        let window = db.get_activities(ActivityQuery {
+            after_cursor: Some(after.clone()),
+            before_cursor: Some(before.clone()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(page_ids(&window), vec!["activity_5", "activity_4", "activity_3", "activity_2"]);
+
+        let window = db.get_activities(ActivityQuery {
+            limit: Some(3),
+            after_cursor: Some(after),
+            before_cursor: Some(before),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(page_ids(&window), vec!["activity_5", "activity_4", "activity_3"]);
+        assert!(window.has_more);
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_cursor_pagination_with_filters() {
+        let (mut db, db_path) = setup();
+        insert_paging_fixture(&mut db);
+
+        let page = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::Onchain),
+            limit: Some(2),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(page_ids(&page), vec!["activity_6", "activity_4"]);
+        let page = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::Onchain),
+            limit: Some(2),
+            after_cursor: page.next_cursor,
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(page_ids(&page), vec!["activity_2", "activity_0"]);
+        assert!(!page.has_more);
+
+        let page = db.get_activities_by_tag("paged", Some(2), None, None, None).unwrap();
+        assert_eq!(page_ids(&page), vec!["activity_6", "activity_4"]);
+        assert!(page.has_more);
+        let page = db.get_activities_by_tag("paged", Some(2), None, page.next_cursor, None).unwrap();
+        assert_eq!(page_ids(&page), vec!["activity_1"]);
+        assert!(!page.has_more);
+
+        cleanup(&db_path);
+    }
+
+    /// Onchain and lightning activities with distinct flags, states, values and fees.
+    fn insert_query_fixture(db: &mut ActivityDB) {
+        insert_onchain(db, "oc_confirmed", |a| {
+            a.value = 50_000;
+            a.fee = 300;
+            a.timestamp = 1000;
+            a.confirm_timestamp = None;
+        });
+        insert_onchain(db, "oc_pending", |a| {
+            a.value = 20_000;
+            a.fee = 900;
+            a.timestamp = 1100;
+            a.confirm_timestamp = None;
+            a.confirmed = false;
+        });
+        insert_onchain(db, "oc_boosted", |a| {
+            a.value = 70_000;
+            a.fee = 1_500;
+            a.timestamp = 1200;
+            a.confirm_timestamp = None;
+            a.confirmed = false;
+            a.is_boosted = true;
+        });
+        insert_onchain(db, "oc_transfer", |a| {
+            a.value = 100_000;
+            a.fee = 300;
+            a.timestamp = 1300;
+            a.confirm_timestamp = None;
+            a.is_transfer = true;
+        });
+
+        insert_lightning(db, "ln_succeeded", |a| {
+            a.value = 5_000;
+            a.fee = Some(2);
+            a.timestamp = 1050;
+        });
+        insert_lightning(db, "ln_pending", |a| {
+            a.value = 70_000;
+            a.fee = None;
+            a.timestamp = 1150;
+            a.status = PaymentState::Pending;
+        });
+        insert_lightning(db, "ln_failed", |a| {
+            a.value = 1_000;
+            a.fee = Some(900);
+            a.timestamp = 1250;
+            a.status = PaymentState::Failed;
+        });
+
+        db.add_tags("oc_confirmed", &["rent".to_string(), "home".to_string()]).unwrap();
+        db.add_tags("oc_transfer", &["home".to_string()]).unwrap();
+        db.add_tags("ln_succeeded", &["rent".to_string()]).unwrap();
+    }
+
+    fn query_ids(db: &ActivityDB, query: ActivityQuery) -> Vec<String> {
+        page_ids(&db.get_activities(query).unwrap())
+    }
+
+    #[test]
+    fn test_query_predicates() {
+        let (mut db, db_path) = setup();
+        insert_query_fixture(&mut db);
+
+        let ids = query_ids(&db, ActivityQuery {
+            statuses: Some(vec![PaymentState::Pending, PaymentState::Failed]),
+            ..Default::default()
+        });
+        assert_eq!(ids, vec!["ln_failed", "ln_pending"]);
+
+        let ids = query_ids(&db, ActivityQuery { confirmed: Some(false), ..Default::default() });
+        assert_eq!(ids, vec!["oc_boosted", "oc_pending"]);
+        let ids = query_ids(&db, ActivityQuery { confirmed: Some(true), is_transfer: Some(false), ..Default::default() });
+        assert_eq!(ids, vec!["oc_confirmed"]);
+        let ids = query_ids(&db, ActivityQuery { is_boosted: Some(true), ..Default::default() });
+        assert_eq!(ids, vec!["oc_boosted"]);
+        let ids = query_ids(&db, ActivityQuery { is_transfer: Some(true), ..Default::default() });
+        assert_eq!(ids, vec!["oc_transfer"]);
+
+        // Value ranges are inclusive and span both kinds
+        let ids = query_ids(&db, ActivityQuery { min_value: Some(50_000), max_value: Some(70_000), ..Default::default() });
+        assert_eq!(ids, vec!["oc_boosted", "ln_pending", "oc_confirmed"]);
+        let ids = query_ids(&db, ActivityQuery { max_value: Some(5_000), ..Default::default() });
+        assert_eq!(ids, vec!["ln_failed", "ln_succeeded"]);
+        let ids = query_ids(&db, ActivityQuery { min_value: Some(u64::MAX), ..Default::default() });
+        assert!(ids.is_empty());
+
+        // Predicates combine with each other and the existing filters
+        let ids = query_ids(&db, ActivityQuery {
+            filter: Some(ActivityFilter::Onchain),
+            min_value: Some(30_000),
+            confirmed: Some(false),
+            min_date: Some(1150),
+            ..Default::default()
+        });
+        assert_eq!(ids, vec!["oc_boosted"]);
+
+        // An empty status list doesn't filter
+        let ids = query_ids(&db, ActivityQuery { statuses: Some(vec![]), ..Default::default() });
+        assert_eq!(ids.len(), 7);
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_query_tag_matching() {
+        let (mut db, db_path) = setup();
+        insert_query_fixture(&mut db);
+
+        let tags = Some(vec!["rent".to_string(), "home".to_string()]);
+        let any = query_ids(&db, ActivityQuery { tags: tags.clone(), ..Default::default() });
+        assert_eq!(any, vec!["oc_transfer", "ln_succeeded", "oc_confirmed"]);
+        let any = query_ids(&db, ActivityQuery { tags: tags.clone(), tag_match: Some(TagMatch::Any), ..Default::default() });
+        assert_eq!(any, vec!["oc_transfer", "ln_succeeded", "oc_confirmed"]);
+
+        let all = query_ids(&db, ActivityQuery { tags, tag_match: Some(TagMatch::All), ..Default::default() });
+        assert_eq!(all, vec!["oc_confirmed"]);
+
+        // Repeated tags count once
+        let all = query_ids(&db, ActivityQuery {
+            tags: Some(vec!["home".to_string(), "home".to_string()]),
+            tag_match: Some(TagMatch::All),
+            ..Default::default()
+        });
+        assert_eq!(all, vec!["oc_transfer", "oc_confirmed"]);
+
+        let all = query_ids(&db, ActivityQuery {
+            tags: Some(vec!["rent".to_string(), "missing".to_string()]),
+            tag_match: Some(TagMatch::All),
+            ..Default::default()
+        });
+        assert!(all.is_empty());
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_query_sort_by_value_and_fee() {
+        let (mut db, db_path) = setup();
+        insert_query_fixture(&mut db);
+
+        // Equal values fall back to timestamp
+        let ids = query_ids(&db, ActivityQuery { sort_by: Some(ActivitySortField::Value), ..Default::default() });
+        assert_eq!(ids, vec!["oc_transfer", "oc_boosted", "ln_pending", "oc_confirmed", "oc_pending", "ln_succeeded", "ln_failed"]);
+
+        // Unknown lightning fees sort as zero
+        let ids = query_ids(&db, ActivityQuery {
+            sort_by: Some(ActivitySortField::Fee),
+            sort_direction: Some(SortDirection::Asc),
+            ..Default::default()
+        });
+        assert_eq!(ids, vec!["ln_pending", "ln_succeeded", "oc_confirmed", "oc_transfer", "oc_pending", "ln_failed", "oc_boosted"]);
+
+        // Paging follows the sort field
+        for sort_by in [ActivitySortField::Value, ActivitySortField::Fee] {
+            for direction in [SortDirection::Asc, SortDirection::Desc] {
+                let query = ActivityQuery { sort_by: Some(sort_by), sort_direction: Some(direction), ..Default::default() };
+                let expected = query_ids(&db, query.clone());
+
+                let mut paged = Vec::new();
+                let mut cursor = None;
+                loop {
+                    let page = db.get_activities(ActivityQuery {
+                        limit: Some(2),
+                        after_cursor: cursor,
+                        ..query.clone()
+                    }).unwrap();
+                    paged.extend(page_ids(&page));
+                    match page.next_cursor {
+                        Some(next) => {
+                            assert!(next.sort_value.is_some());
+                            cursor = Some(next);
+                        }
+                        None => break,
+                    }
+                }
+                assert_eq!(paged, expected, "{:?} {:?}", sort_by, direction);
+
+                // And backwards from the end
+                let last = db.get_activities(ActivityQuery { limit: Some(1), ..query.clone() }).unwrap();
+                let first = ActivityCursor::new(&last.activities[0], sort_by);
+                let page = db.get_activities(ActivityQuery {
+                    limit: Some(3),
+                    before_cursor: Some(first),
+                    ..query.clone()
+                }).unwrap();
+                assert!(page.activities.is_empty());
+            }
+        }
+
+        // Value and fee cursors need the sort value
+        let result = db.get_activities(ActivityQuery {
+            sort_by: Some(ActivitySortField::Value),
+            after_cursor: Some(ActivityCursor { timestamp: 1000, id: "oc_confirmed".to_string(), sort_value: None }),
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(ActivityError::DataError { .. })));
+
+        cleanup(&db_path);
+    }
+
+    // 2024-01-01 00:00:00 UTC, a Monday
+    const JAN_1: u64 = 1704067200;
+    const DAY: u64 = 86400;
+
+    /// Settled, replaced, pending and failed activities across January and February 2024.
+    fn insert_aggregate_fixture(db: &mut ActivityDB) {
+        insert_onchain(db, "oc_received", |a| {
+            a.value = 100_000;
+            a.fee = 500;
+            a.timestamp = JAN_1 + 10 * 3600;
+            a.confirm_timestamp = None;
+            a.tx_type = PaymentType::Received;
+        });
+        insert_onchain(db, "oc_sent", |a| {
+            a.value = 30_000;
+            a.fee = 700;
+            a.timestamp = JAN_1 + DAY + 12 * 3600;
+            a.confirm_timestamp = None;
+        });
+        insert_onchain(db, "oc_replaced", |a| {
+            a.value = 40_000;
+            a.fee = 1_000;
+            a.timestamp = JAN_1 + DAY + 12 * 3600;
+            a.confirm_timestamp = None;
+            a.does_exist = false;
+        });
+
+        insert_lightning(db, "ln_sent", |a| {
+            a.value = 5_000;
+            a.fee = Some(10);
+            a.timestamp = JAN_1 + 8 * DAY + 8 * 3600;
+            a.tx_type = PaymentType::Sent;
+        });
+        insert_lightning(db, "ln_received", |a| {
+            a.value = 2_000;
+            a.fee = None;
+            a.timestamp = JAN_1 + 31 * DAY;
+        });
+        insert_lightning(db, "ln_pending_sent", |a| {
+            a.value = 7_000;
+            a.fee = None;
+            a.timestamp = JAN_1 + 2 * DAY;
+            a.tx_type = PaymentType::Sent;
+            a.status = PaymentState::Pending;
+        });
+        insert_lightning(db, "ln_pending_received", |a| {
+            a.value = 3_000;
+            a.fee = None;
+            a.timestamp = JAN_1 + 2 * DAY;
+            a.status = PaymentState::Pending;
+        });
+        insert_lightning(db, "ln_failed", |a| {
+            a.value = 9_000;
+            a.fee = Some(5);
+            a.timestamp = JAN_1 + 2 * DAY;
+            a.tx_type = PaymentType::Sent;
+            a.status = PaymentState::Failed;
+        });
+
+        db.add_tags("oc_received", &["income".to_string()]).unwrap();
+        db.add_tags("ln_received", &["income".to_string()]).unwrap();
+        db.add_tags("ln_sent", &["coffee".to_string()]).unwrap();
+        db.add_tags("ln_pending_sent", &["coffee".to_string()]).unwrap();
+        db.add_tags("oc_replaced", &["old".to_string()]).unwrap();
+    }
+
+    fn totals(activity_count: u64, sent: u64, received: u64, fees: u64) -> ActivityTotals {
+        ActivityTotals { activity_count, sent, received, fees, fiat: None }
+    }
+
+    fn bucket(start: u64, totals: ActivityTotals) -> ActivityBucket {
+        ActivityBucket { start, totals }
+    }
+
+    #[test]
+    fn test_activity_totals() {
+        let (mut db, db_path) = setup();
+        assert_eq!(db.get_activity_totals(None, None, None).unwrap(), ActivityTypeTotals::default());
+        assert_eq!(db.get_pending_lightning_totals(None).unwrap(), ActivityTotals::default());
+
+        insert_aggregate_fixture(&mut db);
+
+        // Replaced transactions and unsettled lightning payments don't count
+        let all = db.get_activity_totals(None, None, None).unwrap();
+        assert_eq!(all.onchain, totals(2, 30_000, 100_000, 700));
+        assert_eq!(all.lightning, totals(2, 5_000, 2_000, 10));
+
+        let from_jan_2 = db.get_activity_totals(Some(JAN_1 + DAY), None, None).unwrap();
+        assert_eq!(from_jan_2.onchain, totals(1, 30_000, 0, 700));
+        assert_eq!(from_jan_2.lightning, totals(2, 5_000, 2_000, 10));
+
+        let january = db.get_activity_totals(Some(JAN_1), Some(JAN_1 + 31 * DAY - 1), None).unwrap();
+        assert_eq!(january.lightning, totals(1, 5_000, 0, 10));
+
+        assert_eq!(db.get_pending_lightning_totals(None).unwrap(), totals(2, 7_000, 3_000, 0));
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_activity_buckets() {
+        let (mut db, db_path) = setup();
+        insert_aggregate_fixture(&mut db);
+
+        // Empty days are included
+        let days = db.get_activity_buckets(ActivityBucketInterval::Day, JAN_1, JAN_1 + 3 * DAY - 1, None, None, None).unwrap();
+        assert_eq!(days, vec![
+            bucket(JAN_1, totals(1, 0, 100_000, 0)),
+            bucket(JAN_1 + DAY, totals(1, 30_000, 0, 700)),
+            bucket(JAN_1 + 2 * DAY, ActivityTotals::default()),
+        ]);
+
+        // Weeks start on Monday, even when the range starts mid-week
+        let weeks = db.get_activity_buckets(ActivityBucketInterval::Week, JAN_1 + 2 * DAY, JAN_1 + 31 * DAY, None, None, None).unwrap();
+        let starts: Vec<u64> = weeks.iter().map(|b| b.start).collect();
+        assert_eq!(starts, (0..5).map(|w| JAN_1 + w * 7 * DAY).collect::<Vec<_>>());
+        assert_eq!(weeks[0].totals, ActivityTotals::default());
+        assert_eq!(weeks[1].totals, totals(1, 5_000, 0, 10));
+        assert_eq!(weeks[4].totals, totals(1, 0, 2_000, 0));
+
+        let months = db.get_activity_buckets(ActivityBucketInterval::Month, JAN_1, JAN_1 + 31 * DAY, None, None, None).unwrap();
+        assert_eq!(months, vec![
+            bucket(JAN_1, totals(3, 35_000, 100_000, 710)),
+            bucket(JAN_1 + 31 * DAY, totals(1, 0, 2_000, 0)),
+        ]);
+
+        let lightning_months = db.get_activity_buckets(
+            ActivityBucketInterval::Month, JAN_1, JAN_1 + 31 * DAY, Some(ActivityFilter::Lightning), None, None
+        ).unwrap();
+        assert_eq!(lightning_months[0].totals, totals(1, 5_000, 0, 10));
+
+        assert!(db.get_activity_buckets(ActivityBucketInterval::Day, JAN_1 + DAY, JAN_1, None, None, None).unwrap().is_empty());
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_activity_buckets_utc_offset() {
+        let (mut db, db_path) = setup();
+        insert_aggregate_fixture(&mut db);
+        let offset: i32 = -5 * 3600;
+        let local_midnight = |utc_midnight: u64| utc_midnight + 5 * 3600;
+
+        let days = db.get_activity_buckets(ActivityBucketInterval::Day, JAN_1, JAN_1 + 3 * DAY - 1, None, Some(offset), None).unwrap();
+        assert_eq!(days, vec![
+            bucket(local_midnight(JAN_1 - DAY), ActivityTotals::default()),
+            bucket(local_midnight(JAN_1), totals(1, 0, 100_000, 0)),
+            bucket(local_midnight(JAN_1 + DAY), totals(1, 30_000, 0, 700)),
+            bucket(local_midnight(JAN_1 + 2 * DAY), ActivityTotals::default()),
+        ]);
+
+        // The payment at midnight UTC on February 1st is still January locally
+        let months = db.get_activity_buckets(ActivityBucketInterval::Month, JAN_1, JAN_1 + 31 * DAY, None, Some(offset), None).unwrap();
+        assert_eq!(months, vec![
+            bucket(local_midnight(JAN_1 - 31 * DAY), ActivityTotals::default()),
+            bucket(local_midnight(JAN_1), totals(4, 35_000, 102_000, 710)),
+        ]);
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_tag_totals() {
+        let (mut db, db_path) = setup();
+        insert_aggregate_fixture(&mut db);
+
+        assert_eq!(db.get_tag_totals(None, None, None).unwrap(), vec![
+            TagTotals { tag: "coffee".to_string(), totals: totals(1, 5_000, 0, 10) },
+            TagTotals { tag: "income".to_string(), totals: totals(2, 0, 102_000, 0) },
+        ]);
+
+        assert_eq!(db.get_tag_totals(Some(JAN_1 + DAY), Some(JAN_1 + 30 * DAY), None).unwrap(), vec![
+            TagTotals { tag: "coffee".to_string(), totals: totals(1, 5_000, 0, 10) },
+        ]);
+
+        cleanup(&db_path);
+    }
+
+    fn fiat_rate(currency: &str, rate: f64) -> ActivityFiatRate {
+        ActivityFiatRate { currency: currency.to_string(), rate, source: "test".to_string() }
+    }
+
+    #[test]
+    fn test_activity_fiat_rates() {
+        let (mut db, db_path) = setup();
+        db.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
+        db.insert_lightning_activity(&create_test_lightning_activity()).unwrap();
+
+        // Currencies are stored uppercase and setting a currency again replaces its rate
+        db.set_activity_fiat_rate("test_onchain_1", &fiat_rate("usd", 40_000.0)).unwrap();
+        db.set_activity_fiat_rate("test_onchain_1", &fiat_rate("EUR", 37_000.0)).unwrap();
+        db.set_activity_fiat_rate("test_onchain_1", &fiat_rate("USD", 41_000.5)).unwrap();
+        assert_eq!(db.get_activity_fiat_rates("test_onchain_1").unwrap(), vec![
+            fiat_rate("EUR", 37_000.0),
+            fiat_rate("USD", 41_000.5),
+        ]);
+        assert_eq!(fiat_rate("USD", 40_000.0).convert(50_000), 20.0);
+
+        assert_eq!(db.get_activity_ids_missing_fiat_rate("usd", None).unwrap(), vec!["test_lightning_1"]);
+        assert_eq!(db.get_activity_ids_missing_fiat_rate("CHF", Some(1)).unwrap(), vec!["test_onchain_1"]);
+
+        for invalid in [fiat_rate("US", 1.0), fiat_rate("US1", 1.0), fiat_rate("USD", 0.0), fiat_rate("USD", -1.0), fiat_rate("USD", f64::NAN)] {
+            assert!(db.set_activity_fiat_rate("test_lightning_1", &invalid).is_err(), "{:?}", invalid);
+        }
+        assert!(db.set_activity_fiat_rate("missing", &fiat_rate("USD", 1.0)).is_err());
+        assert!(db.get_activity_fiat_rates("test_lightning_1").unwrap().is_empty());
+
+        assert!(db.remove_activity_fiat_rate("test_onchain_1", "eur").unwrap());
+        assert!(!db.remove_activity_fiat_rate("test_onchain_1", "EUR").unwrap());
+        assert_eq!(db.get_activity_fiat_rates("test_onchain_1").unwrap(), vec![fiat_rate("USD", 41_000.5)]);
+
+        // Rates go away with their activity
+        db.delete_activity_by_id("test_onchain_1").unwrap();
+        let rates: i64 = db.conn.query_row("SELECT COUNT(*) FROM activity_fiat", [], |row| row.get(0)).unwrap();
+        assert_eq!(rates, 0);
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_fiat_totals() {
+        let (mut db, db_path) = setup();
+        insert_aggregate_fixture(&mut db);
+        db.set_activity_fiat_rate("oc_received", &fiat_rate("USD", 40_000.0)).unwrap();
+        db.set_activity_fiat_rate("oc_sent", &fiat_rate("USD", 50_000.0)).unwrap();
+        db.set_activity_fiat_rate("ln_sent", &fiat_rate("EUR", 60_000.0)).unwrap();
+        db.set_activity_fiat_rate("ln_pending_sent", &fiat_rate("USD", 10_000.0)).unwrap();
+
+        let fiat = |sent: f64, received: f64, fees: f64, missing_rate_count: u64| Some(ActivityFiatTotals {
+            currency: "USD".to_string(),
+            sent,
+            received,
+            fees,
+            missing_rate_count,
+        });
+
+        // Satoshi amounts are unchanged, activities without a USD rate are counted as missing
+        let all = db.get_activity_totals(None, None, Some("usd")).unwrap();
+        assert_eq!(all.onchain, ActivityTotals { fiat: fiat(15.0, 40.0, 0.35, 0), ..totals(2, 30_000, 100_000, 700) });
+        assert_eq!(all.lightning, ActivityTotals { fiat: fiat(0.0, 0.0, 0.0, 2), ..totals(2, 5_000, 2_000, 10) });
+
+        let tags = db.get_tag_totals(None, None, Some("USD")).unwrap();
+        assert_eq!(tags[1].tag, "income");
+        assert_eq!(tags[1].totals.fiat, fiat(0.0, 40.0, 0.0, 1));
+
+        let days = db.get_activity_buckets(ActivityBucketInterval::Day, JAN_1, JAN_1 + 3 * DAY - 1, None, None, Some("USD")).unwrap();
+        assert_eq!(days.iter().map(|d| d.totals.fiat.clone()).collect::<Vec<_>>(), vec![
+            fiat(0.0, 40.0, 0.0, 0),
+            fiat(15.0, 0.0, 0.35, 0),
+            fiat(0.0, 0.0, 0.0, 0),
+        ]);
+
+        assert_eq!(db.get_pending_lightning_totals(Some("USD")).unwrap().fiat, fiat(0.7, 0.0, 0.0, 1));
+        assert!(db.get_activity_totals(None, None, Some("dollars")).is_err());
+
+        cleanup(&db_path);
+    }
+
+    struct ChannelListener(Mutex<mpsc::Sender<Vec<ActivityChangeEvent>>>);
+
+    impl ActivityChangeListener for ChannelListener {
+        fn on_activity_changes(&self, events: Vec<ActivityChangeEvent>) {
+            self.0.lock().unwrap().send(events).ok();
+        }
+    }
+
+    struct PanickingListener;
+
+    impl ActivityChangeListener for PanickingListener {
+        fn on_activity_changes(&self, _events: Vec<ActivityChangeEvent>) {
+            panic!("listener failed");
+        }
+    }
+
+    fn subscribe(db: &mut ActivityDB) -> (u64, mpsc::Receiver<Vec<ActivityChangeEvent>>) {
+        let (sender, receiver) = mpsc::channel();
+        let id = db.subscribe_activity_changes(Box::new(ChannelListener(Mutex::new(sender)))).unwrap();
+        (id, receiver)
+    }
+
+    fn next_batch(receiver: &mpsc::Receiver<Vec<ActivityChangeEvent>>) -> Vec<ActivityChangeEvent> {
+        receiver.recv_timeout(Duration::from_secs(5)).expect("Expected a change notification")
+    }
+
+    #[test]
+    fn test_activity_change_notifications() {
+        let (mut db, db_path) = setup();
+        let (_, receiver) = subscribe(&mut db);
+        let id = || "test_onchain_1".to_string();
+
+        let mut activity = create_test_onchain_activity();
+        db.upsert_activity(&Activity::Onchain(activity.clone())).unwrap();
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::Inserted { activity_id: id() }]);
+
+        activity.confirmed = false;
+        db.upsert_activity(&Activity::Onchain(activity.clone())).unwrap();
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::Updated { activity_id: id() }]);
+
+        db.add_tags(&id(), &["coffee".to_string()]).unwrap();
+        db.add_tags(&id(), &["coffee".to_string(), "food".to_string()]).unwrap();
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::TagsAdded { activity_id: id(), tags: vec!["coffee".to_string()] }]);
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::TagsAdded { activity_id: id(), tags: vec!["food".to_string()] }]);
+
+        db.remove_tags(&id(), &["food".to_string(), "missing".to_string()]).unwrap();
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::TagsRemoved { activity_id: id(), tags: vec!["food".to_string()] }]);
+
+        db.set_activity_fiat_rate(&id(), &fiat_rate("eur", 37_000.0)).unwrap();
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::FiatRateChanged { activity_id: id(), currency: "EUR".to_string() }]);
+
+        // Failed and no-op writes are not reported
+        assert!(db.insert_onchain_activity(&activity).is_err());
+        db.add_tags(&id(), &["coffee".to_string()]).unwrap();
+        db.remove_tags(&id(), &["missing".to_string()]).unwrap();
+        assert!(!db.delete_activity_by_id("missing").unwrap());
+
+        assert!(db.delete_activity_by_id(&id()).unwrap());
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::Deleted { activity_id: id() }]);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_activity_change_subscriptions() {
+        let (mut db, db_path) = setup();
+        let (first_id, first) = subscribe(&mut db);
+        let (second_id, second) = subscribe(&mut db);
+        assert_ne!(first_id, second_id);
+
+        // An import is reported as one batch
+        let mut source = ActivityDB::new(&format!("test_db_{}.sqlite", random::<u64>())).unwrap();
+        source.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
+        source.insert_lightning_activity(&create_test_lightning_activity()).unwrap();
+        let exported = source.export_activities(ActivityExportFormat::JsonLines, ActivityQuery::default()).unwrap();
+        db.import_activities(&exported).unwrap();
+        let imported = vec![
+            ActivityChangeEvent::Inserted { activity_id: "test_onchain_1".to_string() },
+            ActivityChangeEvent::Inserted { activity_id: "test_lightning_1".to_string() },
+        ];
+        assert_eq!(next_batch(&first), imported);
+        assert_eq!(next_batch(&second), imported);
+
+        assert!(db.unsubscribe_activity_changes(first_id));
+        assert!(!db.unsubscribe_activity_changes(first_id));
+        db.delete_activity_by_id("test_lightning_1").unwrap();
+        assert_eq!(next_batch(&second), vec![ActivityChangeEvent::Deleted { activity_id: "test_lightning_1".to_string() }]);
+        assert!(first.recv_timeout(Duration::from_millis(100)).is_err());
+
+        cleanup(&db_path);
+        cleanup(source.conn.path().unwrap());
+    }
+
+    #[test]
+    fn test_activity_change_listener_panics() {
+        let (mut db, db_path) = setup();
+        db.subscribe_activity_changes(Box::new(PanickingListener)).unwrap();
+        let (_, receiver) = subscribe(&mut db);
+
+        // Other listeners keep receiving every batch
+        db.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::Inserted { activity_id: "test_onchain_1".to_string() }]);
+        db.insert_lightning_activity(&create_test_lightning_activity()).unwrap();
+        assert_eq!(next_batch(&receiver), vec![ActivityChangeEvent::Inserted { activity_id: "test_lightning_1".to_string() }]);
+
+        cleanup(&db_path);
+    }
+
+    /// A batch mixing an update, inserts and activities that fail validation or in the database.
+    fn upsert_batch() -> Vec<Activity> {
+        let mut updated = create_test_onchain_activity();
+        updated.confirmed = false;
+        let mut new_lightning = create_test_lightning_activity();
+        new_lightning.id = "new_lightning".to_string();
+        let mut no_address = create_test_onchain_activity();
+        no_address.id = "no_address".to_string();
+        no_address.address = String::new();
+        // Passes validation but fails after its activities row was written
+        let mut too_large = create_test_onchain_activity();
+        too_large.id = "too_large".to_string();
+        too_large.value = u64::MAX;
+        let mut new_onchain = create_test_onchain_activity();
+        new_onchain.id = "new_onchain".to_string();
+
+        vec![
+            Activity::Onchain(updated),
+            Activity::Lightning(new_lightning.clone()),
+            Activity::Onchain(no_address),
+            Activity::Onchain(too_large),
+            Activity::Lightning(new_lightning),
+            Activity::Onchain(new_onchain),
+        ]
+    }
+
+    #[test]
+    fn test_upsert_activities() {
+        let (mut db, db_path) = setup();
+        db.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
+        let (_, receiver) = subscribe(&mut db);
+
+        let result = db.upsert_activities(&upsert_batch(), false).unwrap();
+        assert_eq!((result.inserted, result.updated), (2, 1));
+        let failures: Vec<(u32, &str)> = result.failures.iter().map(|f| (f.index, f.activity_id.as_str())).collect();
+        assert_eq!(failures, vec![(2, "no_address"), (3, "too_large"), (4, "new_lightning")]);
+        assert!(result.failures[0].error_details.contains("Address cannot be empty"));
+        assert!(result.failures[2].error_details.contains("Duplicate activity ID"));
+
+        // Only the successful activities were written, all in one notification
+        assert_eq!(query_ids(&db, ActivityQuery::default()), vec!["test_onchain_1", "new_onchain", "new_lightning"]);
+        match db.get_activity_by_id("test_onchain_1").unwrap() {
+            Some(Activity::Onchain(activity)) => assert!(!activity.confirmed),
+            other => panic!("Expected the updated onchain activity, got {:?}", other),
+        }
+        let too_large_rows: i64 = db.conn
+            .query_row("SELECT COUNT(*) FROM activities WHERE id = 'too_large'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(too_large_rows, 0);
+        assert_eq!(next_batch(&receiver), vec![
+            ActivityChangeEvent::Updated { activity_id: "test_onchain_1".to_string() },
+            ActivityChangeEvent::Inserted { activity_id: "new_lightning".to_string() },
+            ActivityChangeEvent::Inserted { activity_id: "new_onchain".to_string() },
+        ]);
+
+        assert_eq!(db.upsert_activities(&[], false).unwrap(), ActivityUpsertResult::default());
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_upsert_activities_abort_on_failure() {
+        let (mut db, db_path) = setup();
+        db.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
+
+        // Validation failures are found before anything is written
+        match db.upsert_activities(&upsert_batch(), true) {
+            Err(ActivityError::DataError { error_details }) => assert!(error_details.contains("no_address at index 2")),
+            other => panic!("Expected DataError, got {:?}", other),
+        }
+
+        // A database failure rolls back the activities written before it
+        let batch: Vec<Activity> = upsert_batch().into_iter().enumerate()
+            .filter(|(index, _)| *index != 2 && *index != 4)
+            .map(|(_, activity)| activity)
+            .collect();
+        match db.upsert_activities(&batch, true) {
+            Err(ActivityError::DataError { error_details }) => assert!(error_details.contains("too_large at index 2")),
+            other => panic!("Expected DataError, got {:?}", other),
+        }
+        assert_eq!(query_ids(&db, ActivityQuery::default()), vec!["test_onchain_1"]);
+        match db.get_activity_by_id("test_onchain_1").unwrap() {
+            Some(Activity::Onchain(activity)) => assert!(activity.confirmed),
+            other => panic!("Expected the original onchain activity, got {:?}", other),
+        }
+
+        let result = db.upsert_activities(&batch[..2], true).unwrap();
+        assert_eq!((result.inserted, result.updated, result.failures.len()), (1, 1, 0));
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_export_activities_csv() {
+        let (mut db, db_path) = setup();
+        let mut onchain = create_test_onchain_activity();
+        onchain.is_transfer = true;
+        onchain.channel_id = Some("channel_1".to_string());
+        onchain.transfer_tx_id = Some("transfer_tx_1".to_string());
+        db.insert_onchain_activity(&onchain).unwrap();
+        let mut lightning = create_test_lightning_activity();
+        lightning.timestamp = 1234567900;
+        lightning.message = "=HYPERLINK(\"x\"), hi".to_string();
+        db.insert_lightning_activity(&lightning).unwrap();
+        db.add_tags("test_lightning_1", &["food".to_string(), "coffee".to_string()]).unwrap();
+
+        let csv = db.export_activities(ActivityExportFormat::Csv { columns: None, fiat_currency: None }, ActivityQuery::default()).unwrap();
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "timestamp,activity_type,tx_type,status,value,fee,id,tx_id,address,invoice,message,confirm_timestamp,is_transfer,transfer_tx_id,channel_id,tags"
+        );
+        assert_eq!(
+            lines[2],
+            "2009-02-13T23:31:30Z,onchain,sent,confirmed,50000,500,test_onchain_1,txid123,bc1q...,,,2009-02-13T23:48:10Z,true,transfer_tx_1,channel_1,"
+        );
+        assert_eq!(lines[3], "");
+
+        // Formula-like text is neutralised and quoted fields escape their quotes
+        let columns = vec![
+            ActivityExportColumn::Id,
+            ActivityExportColumn::Status,
+            ActivityExportColumn::Fee,
+            ActivityExportColumn::Message,
+            ActivityExportColumn::Tags,
+            ActivityExportColumn::CreatedAt,
+        ];
+        let query = ActivityQuery { filter: Some(ActivityFilter::Lightning), ..Default::default() };
+        let csv = db.export_activities(ActivityExportFormat::Csv { columns: Some(columns), fiat_currency: None }, query).unwrap();
+        let created_at = db.get_activity_by_id("test_lightning_1").unwrap().unwrap().get_created_at().unwrap();
+        let created_at = chrono::DateTime::from_timestamp(created_at as i64, 0).unwrap().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        assert_eq!(
+            csv,
+            format!("id,status,fee,message,tags,created_at\r\ntest_lightning_1,succeeded,1,\"'=HYPERLINK(\"\"x\"\"), hi\",coffee;food,{}\r\n", created_at)
+        );
+
+        // Fiat columns follow the defaults and are empty without a rate
+        db.set_activity_fiat_rate("test_onchain_1", &fiat_rate("USD", 41_234.5)).unwrap();
+        let format = ActivityExportFormat::Csv { columns: None, fiat_currency: Some("usd".to_string()) };
+        let csv = db.export_activities(format, ActivityQuery::default()).unwrap();
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert!(lines[0].ends_with(",tags,fiat_currency,fiat_rate,fiat_value,fiat_fee"));
+        assert!(lines[1].ends_with(",coffee;food,,,,"));
+        assert!(lines[2].ends_with(",channel_1,,USD,41234.5,20.62,0.21"));
+
+        let columns = vec![ActivityExportColumn::Id, ActivityExportColumn::FiatValue];
+        let format = ActivityExportFormat::Csv { columns: Some(columns), fiat_currency: None };
+        assert!(db.export_activities(format, ActivityQuery::default()).is_err());
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_export_activities_json_lines_round_trip() {
+        let (mut db, db_path) = setup();
+        insert_aggregate_fixture(&mut db);
+        let mut transfer = create_test_onchain_activity();
+        transfer.is_transfer = true;
+        transfer.channel_id = Some("channel_1".to_string());
+        transfer.transfer_tx_id = Some("transfer_tx_1".to_string());
+        db.insert_onchain_activity(&transfer).unwrap();
+        db.set_activity_fiat_rate("oc_sent", &fiat_rate("USD", 42_000.0)).unwrap();
+        db.set_activity_fiat_rate("oc_sent", &fiat_rate("EUR", 39_000.0)).unwrap();
+        let exported = db.export_activities(ActivityExportFormat::JsonLines, ActivityQuery::default()).unwrap();
+        assert_eq!(exported.lines().count(), 9);
+
+        let (mut restored, restored_path) = setup();
+        assert_eq!(restored.import_activities(&exported).unwrap(), 9);
+        assert_eq!(restored.export_activities(ActivityExportFormat::JsonLines, ActivityQuery::default()).unwrap(), exported);
+
+        let original = db.get_activity_by_id("test_onchain_1").unwrap().unwrap();
+        let imported = restored.get_activity_by_id("test_onchain_1").unwrap().unwrap();
+        assert_eq!(format!("{:?}", imported), format!("{:?}", original));
+        assert_eq!(restored.get_tags("ln_sent").unwrap(), vec!["coffee"]);
+        assert_eq!(restored.get_activity_fiat_rates("oc_sent").unwrap(), db.get_activity_fiat_rates("oc_sent").unwrap());
+        assert_eq!(restored.get_activity_totals(None, None, None).unwrap(), db.get_activity_totals(None, None, None).unwrap());
+        assert_eq!(restored.search_activities("coffee", None).unwrap().len(), 2);
+
+        cleanup(&db_path);
+        cleanup(&restored_path);
+    }
+
+    #[test]
+    fn test_import_activities_rejects_bad_input() {
+        let (mut db, db_path) = setup();
+        db.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
+        let exported = db.export_activities(ActivityExportFormat::JsonLines, ActivityQuery::default()).unwrap();
+        let line = exported.trim_end();
+
+        let (mut restored, restored_path) = setup();
+        let inputs = [
+            format!("{}\nnot json", line),
+            format!("{}\n{}", line, line),
+            format!("{}\n{}", line, line.replace("test_onchain_1", "")),
+            format!("{}\n{{\"activity_type\":\"unknown\"}}", line),
+        ];
+        for input in inputs {
+            assert!(restored.import_activities(&input).is_err(), "Expected an error for {:?}", input);
+            assert_eq!(activity_count(&restored), 0);
+        }
+
+        assert_eq!(restored.import_activities(&format!("\n{}\n\n", line)).unwrap(), 1);
+        assert_eq!(activity_count(&restored), 1);
+
+        cleanup(&db_path);
+        cleanup(&restored_path);
+    }
+
+    fn search_ids(db: &ActivityDB, query: &str) -> Vec<String> {
+        let mut ids: Vec<String> = db.search_activities(query, None).unwrap()
+            .iter()
+            .map(|a| a.get_id().to_string())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_search_activities_fields() {
+        let (mut db, db_path) = setup();
+
+        let mut onchain = create_test_onchain_activity();
+        onchain.tx_id = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string();
+        onchain.address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_string();
+        db.insert_onchain_activity(&onchain).unwrap();
+
+        let mut lightning = create_test_lightning_activity();
+        lightning.id = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".to_string();
+        lightning.invoice = "lnbc1pvjluezpp5qqqsyqcyq5rqwzqf".to_string();
+        lightning.message = "Crème brûlée at the café".to_string();
+        lightning.preimage = Some("b0f00a08preimage".to_string());
+        db.insert_lightning_activity(&lightning).unwrap();
+        db.add_tags(&onchain.id, &["rent".to_string(), "monthly bills".to_string()]).unwrap();
+
+        assert_eq!(search_ids(&db, "4a5e1e4b"), vec![onchain.id.clone()]);
+        assert_eq!(search_ids(&db, "bc1qar0s"), vec![onchain.id.clone()]);
+        assert_eq!(search_ids(&db, "9f86d0"), vec![lightning.id.clone()]);
+        assert_eq!(search_ids(&db, "lnbc1pvj"), vec![lightning.id.clone()]);
+        assert_eq!(search_ids(&db, "b0f00a08"), vec![lightning.id.clone()]);
+        assert_eq!(search_ids(&db, "rent"), vec![onchain.id.clone()]);
+        assert_eq!(search_ids(&db, "bills"), vec![onchain.id.clone()]);
+
+        // Prefixes, case and diacritics
+        assert_eq!(search_ids(&db, "CREME brul"), vec![lightning.id.clone()]);
+        assert_eq!(search_ids(&db, "cafe"), vec![lightning.id.clone()]);
+
+        // Every word has to match
+        assert!(search_ids(&db, "creme rent").is_empty());
+        assert!(search_ids(&db, "nothing").is_empty());
+        assert!(search_ids(&db, "   ").is_empty());
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_search_activities_follows_changes() {
+        let (mut db, db_path) = setup();
+
+        let mut lightning = create_test_lightning_activity();
+        lightning.message = "Pizza night".to_string();
+        db.insert_lightning_activity(&lightning).unwrap();
+        let onchain = create_test_onchain_activity();
+        db.insert_onchain_activity(&onchain).unwrap();
+
+        lightning.message = "Sushi night".to_string();
+        db.update_lightning_activity_by_id(&lightning.id, &lightning).unwrap();
+        assert!(search_ids(&db, "pizza").is_empty());
+        assert_eq!(search_ids(&db, "sushi"), vec![lightning.id.clone()]);
+
+        lightning.message = "Ramen night".to_string();
+        db.upsert_activity(&Activity::Lightning(lightning.clone())).unwrap();
+        assert!(search_ids(&db, "sushi").is_empty());
+        assert_eq!(search_ids(&db, "ramen"), vec![lightning.id.clone()]);
+
+        db.add_tags(&lightning.id, &["food".to_string(), "friends".to_string()]).unwrap();
+        db.add_tags(&onchain.id, &["food".to_string()]).unwrap();
+        assert_eq!(search_ids(&db, "food"), vec![lightning.id.clone(), onchain.id.clone()]);
+        db.remove_tags(&lightning.id, &["food".to_string()]).unwrap();
+        assert_eq!(search_ids(&db, "food"), vec![onchain.id.clone()]);
+        assert_eq!(search_ids(&db, "friends"), vec![lightning.id.clone()]);
+
+        assert!(db.delete_activity_by_id(&lightning.id).unwrap());
+        assert!(search_ids(&db, "ramen").is_empty());
+        assert!(search_ids(&db, "friends").is_empty());
+        assert_eq!(search_ids(&db, "food"), vec![onchain.id.clone()]);
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_search_activities_ranking_and_limit() {
+        let (mut db, db_path) = setup();
+
+        let messages = [
+            ("ln_long", "Paid back for dinner, drinks, the taxi home and one coffee"),
+            ("ln_short", "Coffee"),
+            ("ln_other", "Groceries"),
+        ];
+        for (id, message) in messages {
+            insert_lightning(&mut db, id, |a| a.message = message.to_string());
+        }
+
+        let results = db.search_activities("coffee", None).unwrap();
+        let ids: Vec<&str> = results.iter().map(|a| a.get_id()).collect();
+        assert_eq!(ids, vec!["ln_short", "ln_long"]);
+
+        let limited = db.search_activities("coffee", Some(1)).unwrap();
+        assert_eq!(limited.len(), 1);
+        assert_eq!(limited[0].get_id(), "ln_short");
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_search_activities_hostile_queries() {
+        let (mut db, db_path) = setup();
+        db.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
+        db.insert_lightning_activity(&create_test_lightning_activity()).unwrap();
+
+        let fts_syntax = ["AND", "OR", "NOT", "NEAR(a b)", "a OR b", "*", "^a", "address:bc1q", "\"", "\"unterminated", "(", ")", "+", "-a"];
+        for query in HOSTILE_INPUTS.iter().chain(fts_syntax.iter()) {
+            assert!(db.search_activities(query, None).is_ok(), "query {:?}", query);
+        }
+
+        // Operators are searched as words rather than applied
+        assert!(search_ids(&db, "payment OR nothing").is_empty());
+        assert_eq!(search_ids(&db, "payment"), vec!["test_lightning_1".to_string()]);
+        assert_eq!(activity_count(&db), 2);
+
+        cleanup(&db_path);
+    }
+
     #[test]
     fn test_invalid_date_ranges() {
         let (mut db, db_path) = setup();
@@ -1052,29 +2214,20 @@ mod tests {
         db.insert_onchain_activity(&activity).unwrap();
 
         // Test max date before min date
-        let invalid_range = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            None,
-            Some(2000),
-            Some(1000),
-            None,
-            None
-        ).unwrap();
+        let invalid_range = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            min_date: Some(2000),
+            max_date: Some(1000),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(invalid_range.len(), 0);
 
         // Test dates way in the future
-        let future_date = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            None,
-            Some(u64::MAX - 1000),
-            None,
-            None,
-            None
-        ).unwrap();
+        let future_date = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            min_date: Some(u64::MAX - 1000),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(future_date.len(), 0);
 
         cleanup(&db_path);
@@ -1089,42 +2242,27 @@ mod tests {
         db.insert_lightning_activity(&lightning).unwrap();
 
         // Test lowercase search
-        let lower_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            Some("coffee".to_string()),
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let lower_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            search: Some("coffee".to_string()),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(lower_results.len(), 1);
 
         // Test uppercase search
-        let upper_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            Some("COFFEE".to_string()),
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let upper_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            search: Some("COFFEE".to_string()),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(upper_results.len(), 1);
 
         // Test mixed case search
-        let mixed_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            Some("CoFfEe".to_string()),
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let mixed_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            search: Some("CoFfEe".to_string()),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(mixed_results.len(), 1);
 
         cleanup(&db_path);
@@ -1143,16 +2281,11 @@ mod tests {
         db_clone.add_tags(&activity.id, &["tag2".to_string()]).unwrap();
 
         // Verify tags from both connections
-        let results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            Some(vec!["tag1".to_string(), "tag2".to_string()]),
-            None,
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            tags: Some(vec!["tag1".to_string(), "tag2".to_string()]),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(results.len(), 1);
 
         cleanup(&db_path);
@@ -1172,29 +2305,19 @@ mod tests {
         db.insert_lightning_activity(&lightning).unwrap();
 
         // Search with special characters
-        let special_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            Some("%chars".to_string()),
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let special_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            search: Some("%chars".to_string()),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(special_results.len(), 1);
 
         // Search with underscore
-        let underscore_results = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            None,
-            Some("_special".to_string()),
-            None,
-            None,
-            None,
-            None
-        ).unwrap();
+        let underscore_results = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            search: Some("_special".to_string()),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(underscore_results.len(), 1);
 
         cleanup(&db_path);
@@ -1219,30 +2342,28 @@ mod tests {
         }
 
         // Test pagination with combined filters
-        let page1 = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            Some(vec!["even".to_string()]),
-            Some("address".to_string()),
-            Some(1000),
-            None,
-            Some(2),
-            Some(SortDirection::Asc)
-        ).unwrap();
+        let page1 = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            tags: Some(vec!["even".to_string()]),
+            search: Some("address".to_string()),
+            min_date: Some(1000),
+            limit: Some(2),
+            sort_direction: Some(SortDirection::Asc),
+            ..Default::default()
+        }).unwrap().activities;
         assert_eq!(page1.len(), 2);
 
         // Get next page
         let min_date = page1.last().unwrap().get_timestamp();
-        let page2 = db.get_activities(
-            Some(ActivityFilter::All),
-            None,
-            Some(vec!["even".to_string()]),
-            Some("address".to_string()),
-            Some(min_date + 1),
-            None,
-            Some(2),
-            Some(SortDirection::Asc)
-        ).unwrap();
+        let page2 = db.get_activities(ActivityQuery {
+            filter: Some(ActivityFilter::All),
+            tags: Some(vec!["even".to_string()]),
+            search: Some("address".to_string()),
+            min_date: Some(min_date + 1),
+            limit: Some(2),
+            sort_direction: Some(SortDirection::Asc),
+            ..Default::default()
+        }).unwrap().activities;
 
         assert_eq!(page2.len(), 1);
         assert!(page2[0].get_timestamp() > page1[1].get_timestamp());
@@ -1280,4 +2401,164 @@ mod tests {
 
         cleanup(&db_path);
     }
+
+    /// Schemas of earlier releases, by `user_version`, with sample rows. When a
+    /// migration is added, the schema it upgrades from is added here.
+    const SCHEMA_FIXTURES: &[(u32, &str)] = &[
+        // Unversioned schema from before migrations
+        (0, "
+            CREATE TABLE activities (
+                id TEXT PRIMARY KEY,
+                activity_type TEXT NOT NULL CHECK (activity_type IN ('onchain', 'lightning')),
+                tx_type TEXT NOT NULL CHECK (tx_type IN ('sent', 'received')),
+                timestamp INTEGER NOT NULL CHECK (timestamp > 0),
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            );
+            CREATE TABLE onchain_activity (
+                id TEXT PRIMARY KEY,
+                tx_id TEXT NOT NULL,
+                address TEXT NOT NULL CHECK (length(address) > 0),
+                confirmed BOOLEAN NOT NULL,
+                value INTEGER NOT NULL CHECK (value >= 0),
+                fee INTEGER NOT NULL CHECK (fee >= 0),
+                fee_rate INTEGER NOT NULL CHECK (fee_rate >= 0),
+                is_boosted BOOLEAN NOT NULL,
+                is_transfer BOOLEAN NOT NULL,
+                does_exist BOOLEAN NOT NULL,
+                confirm_timestamp INTEGER CHECK (confirm_timestamp IS NULL OR confirm_timestamp >= 0),
+                channel_id TEXT CHECK (channel_id IS NULL OR length(channel_id) > 0),
+                transfer_tx_id TEXT CHECK (transfer_tx_id IS NULL OR length(transfer_tx_id) > 0),
+                FOREIGN KEY (id) REFERENCES activities(id) ON DELETE CASCADE
+            );
+            CREATE TABLE lightning_activity (
+                id TEXT PRIMARY KEY,
+                invoice TEXT NOT NULL CHECK (length(invoice) > 0),
+                value INTEGER NOT NULL CHECK (value >= 0),
+                status TEXT NOT NULL CHECK (status IN ('pending', 'succeeded', 'failed')),
+                fee INTEGER CHECK (fee IS NULL OR fee >= 0),
+                message TEXT NOT NULL,
+                preimage TEXT CHECK (preimage IS NULL OR length(preimage) > 0),
+                FOREIGN KEY (id) REFERENCES activities(id) ON DELETE CASCADE
+            );
+            CREATE TABLE activity_tags (
+                activity_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (activity_id, tag),
+                FOREIGN KEY (activity_id) REFERENCES activities(id) ON DELETE CASCADE
+            );
+            CREATE INDEX idx_activities_timestamp ON activities(timestamp DESC);
+
+            INSERT INTO activities (id, activity_type, tx_type, timestamp) VALUES
+                ('legacy_onchain', 'onchain', 'received', 1700000000),
+                ('legacy_lightning', 'lightning', 'sent', 1700000100);
+            INSERT INTO onchain_activity VALUES
+                ('legacy_onchain', 'txid', 'bc1qlegacy', 1, 25000, 200, 2, 0, 0, 1, 1700000600, NULL, NULL);
+            INSERT INTO lightning_activity VALUES
+                ('legacy_lightning', 'lnbc1legacy', 1000, 'succeeded', 1, 'Coffee', NULL);
+            INSERT INTO activity_tags VALUES ('legacy_onchain', 'salary');
+        "),
+        // Version 1, created by the released migrations
+        (1, "
+            INSERT INTO activities (id, activity_type, tx_type, timestamp) VALUES
+                ('legacy_onchain', 'onchain', 'received', 1700000000),
+                ('legacy_lightning', 'lightning', 'sent', 1700000100);
+            INSERT INTO onchain_activity VALUES
+                ('legacy_onchain', 'txid', 'bc1qlegacy', 1, 25000, 200, 2, 0, 0, 1, 1700000600, NULL, NULL);
+            INSERT INTO lightning_activity VALUES
+                ('legacy_lightning', 'lnbc1legacy', 1000, 'succeeded', 1, 'Coffee', NULL);
+            INSERT INTO activity_tags VALUES ('legacy_onchain', 'salary');
+        "),
+        // Version 2, adds the full-text search index
+        (2, "
+            INSERT INTO activities (id, activity_type, tx_type, timestamp) VALUES
+                ('legacy_onchain', 'onchain', 'received', 1700000000),
+                ('legacy_lightning', 'lightning', 'sent', 1700000100);
+            INSERT INTO onchain_activity VALUES
+                ('legacy_onchain', 'txid', 'bc1qlegacy', 1, 25000, 200, 2, 0, 0, 1, 1700000600, NULL, NULL);
+            INSERT INTO lightning_activity VALUES
+                ('legacy_lightning', 'lnbc1legacy', 1000, 'succeeded', 1, 'Coffee', NULL);
+            INSERT INTO activity_tags VALUES ('legacy_onchain', 'salary');
+        "),
+    ];
+
+    #[test]
+    fn test_schema_migrations_upgrade_fixtures() {
+        for (version, fixture) in SCHEMA_FIXTURES {
+            let db_path = format!("test_db_{}.sqlite", random::<u64>());
+            let mut conn = Connection::open(&db_path).unwrap();
+            // Versioned fixtures only hold rows; their schema comes from the released migrations
+            let released = MIGRATIONS.iter().take_while(|m| m.version <= *version).count();
+            run_migrations(&mut conn, &MIGRATIONS[..released]).unwrap();
+            conn.execute_batch(fixture).unwrap();
+            conn.pragma_update(None, "user_version", version).unwrap();
+            drop(conn);
+
+            let mut db = ActivityDB::new(&db_path).unwrap();
+            assert_eq!(schema_version(&db.conn).unwrap(), latest_version(MIGRATIONS), "upgrading from {}", version);
+
+            // Existing rows survive and the upgraded schema accepts new ones
+            let activities = db.get_activities(ActivityQuery::default()).unwrap().activities;
+            assert_eq!(activities.len(), 2, "upgrading from {}", version);
+            assert_eq!(db.get_tags("legacy_onchain").unwrap(), vec!["salary"]);
+            assert_eq!(search_ids(&db, "salary"), vec!["legacy_onchain"], "upgrading from {}", version);
+            assert_eq!(search_ids(&db, "coffee"), vec!["legacy_lightning"], "upgrading from {}", version);
+            db.insert_onchain_activity(&create_test_onchain_activity()).unwrap();
+            db.set_activity_fiat_rate("legacy_onchain", &fiat_rate("USD", 37_000.0)).unwrap();
+            drop(db);
+
+            // Reopening applies nothing again
+            let db = ActivityDB::new(&db_path).unwrap();
+            assert_eq!(schema_version(&db.conn).unwrap(), latest_version(MIGRATIONS));
+            assert_eq!(db.get_activities(ActivityQuery::default()).unwrap().activities.len(), 3);
+            cleanup(&db_path);
+        }
+    }
+
+    #[test]
+    fn test_schema_migrations_fresh_database() {
+        let (db, db_path) = setup();
+        assert_eq!(schema_version(&db.conn).unwrap(), latest_version(MIGRATIONS));
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_newer_schema_version_rejected() {
+        let (db, db_path) = setup();
+        let newer = latest_version(MIGRATIONS) + 1;
+        db.conn.pragma_update(None, "user_version", newer).unwrap();
+        drop(db);
+
+        match ActivityDB::new(&db_path) {
+            Err(ActivityError::UnsupportedSchemaVersion { error_details }) => {
+                assert!(error_details.contains(&newer.to_string()));
+            }
+            Err(e) => panic!("Expected UnsupportedSchemaVersion, got {}", e),
+            Ok(_) => panic!("Expected UnsupportedSchemaVersion"),
+        }
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let migrations = [
+            Migration { version: 1, description: "create table", apply: |tx| tx.execute_batch("CREATE TABLE a (x INTEGER)") },
+            Migration { version: 2, description: "broken", apply: |tx| tx.execute_batch("CREATE TABLE b (x INTEGER); INSERT INTO missing VALUES (1)") },
+        ];
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        assert!(run_migrations(&mut conn, &migrations).is_err());
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        let tables: i64 = conn
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('a', 'b')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tables, 1);
+
+        // Only the first step is kept, so fixing the broken one lets it apply
+        let fixed = [
+            Migration { version: 1, description: "create table", apply: |_| panic!("already applied") },
+            Migration { version: 2, description: "fixed", apply: |tx| tx.execute_batch("CREATE TABLE b (x INTEGER)") },
+        ];
+        assert_eq!(run_migrations(&mut conn, &fixed).unwrap(), 2);
+    }
 }
@@ -9,7 +9,7 @@ pub enum Activity {
     Lightning(LightningActivity),
 }
 
-#[derive(Debug, uniffi::Enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
 pub enum ActivityFilter {
     All,
     Lightning,
@@ -51,6 +51,21 @@ impl Activity {
             Activity::Lightning(l) => l.updated_at,
         }
     }
+
+    pub fn get_value(&self) -> u64 {
+        match self {
+            Activity::Onchain(o) => o.value,
+            Activity::Lightning(l) => l.value,
+        }
+    }
+
+    /// Fee paid, counting an unknown lightning fee as zero.
+    pub fn get_fee(&self) -> u64 {
+        match self {
+            Activity::Onchain(o) => o.fee,
+            Activity::Lightning(l) => l.fee.unwrap_or(0),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, uniffi::Enum)]
@@ -126,6 +141,244 @@ pub enum SortDirection {
     Desc,
 }
 
+/// Field activity listings are sorted by. Ties are ordered by timestamp, then id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, uniffi::Enum)]
+pub enum ActivitySortField {
+    #[default]
+    Timestamp,
+    Value,
+    /// Unknown lightning fees sort as zero.
+    Fee,
+}
+
+/// How a list of tags is matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, uniffi::Enum)]
+pub enum TagMatch {
+    /// The activity has at least one of the tags.
+    #[default]
+    Any,
+    /// The activity has every one of the tags.
+    All,
+}
+
+/// Predicates, ordering and paging for activity listings. Unset fields don't filter and
+/// default to `None` in the bindings.
+///
+/// `statuses` only matches lightning activities and `confirmed`, `is_boosted` and
+/// `is_transfer` only match onchain activities.
+#[derive(Debug, Clone, Default, uniffi::Record)]
+pub struct ActivityQuery {
+    #[uniffi(default = None)]
+    pub filter: Option<ActivityFilter>,
+    #[uniffi(default = None)]
+    pub tx_type: Option<PaymentType>,
+    #[uniffi(default = None)]
+    pub statuses: Option<Vec<PaymentState>>,
+    #[uniffi(default = None)]
+    pub confirmed: Option<bool>,
+    #[uniffi(default = None)]
+    pub is_boosted: Option<bool>,
+    #[uniffi(default = None)]
+    pub is_transfer: Option<bool>,
+    #[uniffi(default = None)]
+    pub min_value: Option<u64>,
+    #[uniffi(default = None)]
+    pub max_value: Option<u64>,
+    #[uniffi(default = None)]
+    pub tags: Option<Vec<String>>,
+    #[uniffi(default = None)]
+    pub tag_match: Option<TagMatch>,
+    /// Literal substring, ignoring ASCII case, of the onchain address or the lightning invoice or
+    /// message. Txids, tags and preimages are not searched; use `search_activities` for ranked
+    /// full-text search over every field.
+    #[uniffi(default = None)]
+    pub search: Option<String>,
+    #[uniffi(default = None)]
+    pub min_date: Option<u64>,
+    #[uniffi(default = None)]
+    pub max_date: Option<u64>,
+    #[uniffi(default = None)]
+    pub limit: Option<u32>,
+    #[uniffi(default = None)]
+    pub sort_by: Option<ActivitySortField>,
+    #[uniffi(default = None)]
+    pub sort_direction: Option<SortDirection>,
+    #[uniffi(default = None)]
+    pub after_cursor: Option<ActivityCursor>,
+    #[uniffi(default = None)]
+    pub before_cursor: Option<ActivityCursor>,
+}
+
+/// Position of an activity in a listing, ordered by `(sort_value, timestamp, id)`.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct ActivityCursor {
+    pub timestamp: u64,
+    pub id: String,
+    /// Value or fee of the activity when the listing is sorted by that field.
+    pub sort_value: Option<u64>,
+}
+
+impl ActivityCursor {
+    pub fn new(activity: &Activity, sort_by: ActivitySortField) -> Self {
+        let sort_value = match sort_by {
+            ActivitySortField::Timestamp => None,
+            ActivitySortField::Value => Some(activity.get_value()),
+            ActivitySortField::Fee => Some(activity.get_fee()),
+        };
+        ActivityCursor {
+            timestamp: activity.get_timestamp(),
+            id: activity.get_id().to_string(),
+            sort_value,
+        }
+    }
+}
+
+/// One page of an activity listing.
+#[derive(Debug, uniffi::Record)]
+pub struct ActivityPage {
+    pub activities: Vec<Activity>,
+    /// Cursor to request the following page with, set while `has_more` is true.
+    pub next_cursor: Option<ActivityCursor>,
+    pub has_more: bool,
+}
+
+/// Outcome of `upsert_activities`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct ActivityUpsertResult {
+    pub inserted: u32,
+    pub updated: u32,
+    /// Activities that were not written, in input order.
+    pub failures: Vec<ActivityUpsertFailure>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct ActivityUpsertFailure {
+    /// Position of the activity in the input.
+    pub index: u32,
+    pub activity_id: String,
+    pub error_details: String,
+}
+
+/// Bitcoin price in a fiat currency at the time of an activity, supplied by the host app.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, uniffi::Record)]
+pub struct ActivityFiatRate {
+    /// ISO 4217 currency code, stored uppercase.
+    pub currency: String,
+    /// Units of `currency` per bitcoin.
+    pub rate: f64,
+    /// Where the rate came from, such as the price provider.
+    pub source: String,
+}
+
+impl ActivityFiatRate {
+    /// Value of `sats` in the rate's currency.
+    pub fn convert(&self, sats: u64) -> f64 {
+        sats as f64 * self.rate / 100_000_000.0
+    }
+}
+
+/// Amounts in satoshis over a set of activities.
+#[derive(Debug, Clone, Default, PartialEq, uniffi::Record)]
+pub struct ActivityTotals {
+    pub activity_count: u64,
+    pub sent: u64,
+    pub received: u64,
+    /// Fees of sent activities.
+    pub fees: u64,
+    /// The same amounts in fiat, set when a fiat currency was requested.
+    pub fiat: Option<ActivityFiatTotals>,
+}
+
+/// Fiat amounts over a set of activities, each converted at the rate stored for it.
+#[derive(Debug, Clone, Default, PartialEq, uniffi::Record)]
+pub struct ActivityFiatTotals {
+    pub currency: String,
+    pub sent: f64,
+    pub received: f64,
+    pub fees: f64,
+    /// Activities left out of the fiat amounts because they have no rate in `currency`.
+    pub missing_rate_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, uniffi::Record)]
+pub struct ActivityTypeTotals {
+    pub onchain: ActivityTotals,
+    pub lightning: ActivityTotals,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum ActivityBucketInterval {
+    Day,
+    /// Weeks start on Monday.
+    Week,
+    Month,
+}
+
+/// Totals of the activities in one calendar interval.
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct ActivityBucket {
+    /// Unix timestamp of the first second of the interval.
+    pub start: u64,
+    pub totals: ActivityTotals,
+}
+
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct TagTotals {
+    pub tag: String,
+    pub totals: ActivityTotals,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum ActivityExportFormat {
+    /// Comma separated values with a header row. Uses the default columns when `columns` is unset,
+    /// followed by the fiat columns when `fiat_currency` is set. Fiat columns need `fiat_currency`.
+    Csv {
+        columns: Option<Vec<ActivityExportColumn>>,
+        fiat_currency: Option<String>,
+    },
+    /// One JSON object per line holding the full activity, its tags and fiat rates, readable by
+    /// `import_activities`.
+    JsonLines,
+}
+
+/// CSV export columns. Times are RFC 3339 in UTC and amounts in satoshis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum ActivityExportColumn {
+    Id,
+    ActivityType,
+    TxType,
+    /// Lightning payment state, or `confirmed`/`unconfirmed` for onchain activities.
+    Status,
+    Timestamp,
+    Value,
+    Fee,
+    FeeRate,
+    TxId,
+    Address,
+    Invoice,
+    Message,
+    Preimage,
+    Confirmed,
+    ConfirmTimestamp,
+    IsBoosted,
+    IsTransfer,
+    DoesExist,
+    ChannelId,
+    TransferTxId,
+    /// Tags separated by `;`.
+    Tags,
+    CreatedAt,
+    UpdatedAt,
+    /// Fiat columns use the export's `fiat_currency` and are empty for activities without a rate.
+    FiatCurrency,
+    FiatRate,
+    FiatSource,
+    /// Value in fiat, rounded to two decimals.
+    FiatValue,
+    /// Fee in fiat, rounded to two decimals.
+    FiatFee,
+}
+
 #[derive(uniffi::Error, Debug, Error)]
 #[non_exhaustive]
 pub enum DbError {
@@ -1,11 +1,48 @@
-use rusqlite::{Connection, OptionalExtension};
+use rusqlite::{Connection, OptionalExtension, Transaction};
 use rust_blocktank_client::*;
 use tokio::sync::Mutex;
 use std::result::Result;
 use crate::modules::blocktank::{BlocktankDB, BlocktankError};
 use crate::modules::blocktank::models::*;
+use crate::modules::migrations::{run_migrations, Migration};
 pub const DEFAULT_BLOCKTANK_URL: &str = "https://api1.blocktank.to/api";
 
+/// Schema migrations in order. Released steps must never change; schema
+/// changes are added as new steps.
+pub(crate) const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "initial schema", apply: create_initial_schema },
+];
+
+/// The schema as it was before versioning. `IF NOT EXISTS` and `INSERT OR IGNORE`
+/// let databases created by those releases adopt version 1 unchanged.
+fn create_initial_schema(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    for statement in CREATE_ENUM_TABLES {
+        tx.execute(statement, [])?;
+    }
+    for statement in [CREATE_ORDERS_TABLE, CREATE_INFO_TABLE, CREATE_CJIT_ENTRIES_TABLE] {
+        tx.execute(statement, [])?;
+    }
+
+    let enum_values: [(&str, &[&str]); 3] = [
+        ("order_states", &["Created", "Expired", "Open", "Closed"]),
+        ("payment_states", &["Created", "PartiallyPaid", "Paid", "Refunded", "RefundAvailable"]),
+        ("cjit_states", &["Created", "Completed", "Expired", "Failed"]),
+    ];
+    for (table, states) in enum_values {
+        for state in states {
+            tx.execute(
+                &format!("INSERT OR IGNORE INTO {} (state, description) VALUES (?1, ?1)", table),
+                [state],
+            )?;
+        }
+    }
+
+    for statement in TRIGGER_STATEMENTS.iter().chain(INDEX_STATEMENTS) {
+        tx.execute(statement, [])?;
+    }
+    Ok(())
+}
+
 impl BlocktankDB {
     pub async fn new(db_path: &str, blocktank_url: Option<&str>) -> Result<BlocktankDB, BlocktankError> {
         let conn = Connection::open(db_path).map_err(|e| BlocktankError::InitializationError {
@@ -27,74 +64,10 @@ impl BlocktankDB {
         Ok(db)
     }
 
+    /// Brings the database schema up to date with the migrations
     async fn initialize(&self) -> Result<(), BlocktankError> {
-        let conn = self.conn.lock().await;
-
-        // Create enum tables
-        for create_stmt in CREATE_ENUM_TABLES {
-            conn.execute(create_stmt, []).map_err(|e| BlocktankError::InitializationError {
-                error_details: format!("Failed to create enum table: {}", e),
-            })?;
-        }
-
-        // Create main tables
-        conn.execute(CREATE_ORDERS_TABLE, []).map_err(|e| BlocktankError::InitializationError {
-            error_details: format!("Failed to create orders table: {}", e),
-        })?;
-
-        conn.execute(CREATE_INFO_TABLE, []).map_err(|e| BlocktankError::InitializationError {
-            error_details: format!("Failed to create info table: {}", e),
-        })?;
-
-        conn.execute(CREATE_CJIT_ENTRIES_TABLE, []).map_err(|e| BlocktankError::InitializationError {
-            error_details: format!("Failed to create CJIT entries table: {}", e),
-        })?;
-
-        // Populate enum tables
-        // Order states
-        for state in ["Created", "Expired", "Open", "Closed"] {
-            conn.execute(
-                "INSERT OR IGNORE INTO order_states (state, description) VALUES (?1, ?1)",
-                [state],
-            ).map_err(|e| BlocktankError::InitializationError {
-                error_details: format!("Failed to insert order state {}: {}", state, e),
-            })?;
-        }
-
-        // Payment states
-        for state in ["Created", "PartiallyPaid", "Paid", "Refunded", "RefundAvailable"] {
-            conn.execute(
-                "INSERT OR IGNORE INTO payment_states (state, description) VALUES (?1, ?1)",
-                [state],
-            ).map_err(|e| BlocktankError::InitializationError {
-                error_details: format!("Failed to insert payment state {}: {}", state, e),
-            })?;
-        }
-
-        // CJIT states
-        for state in ["Created", "Completed", "Expired", "Failed"] {
-            conn.execute(
-                "INSERT OR IGNORE INTO cjit_states (state, description) VALUES (?1, ?1)",
-                [state],
-            ).map_err(|e| BlocktankError::InitializationError {
-                error_details: format!("Failed to insert cjit state {}: {}", state, e),
-            })?;
-        }
-
-        // Create triggers
-        for trigger_stmt in TRIGGER_STATEMENTS {
-            conn.execute(trigger_stmt, []).map_err(|e| BlocktankError::InitializationError {
-                error_details: format!("Failed to create trigger: {}", e),
-            })?;
-        }
-
-        // Create indexes
-        for index_stmt in INDEX_STATEMENTS {
-            conn.execute(index_stmt, []).map_err(|e| BlocktankError::InitializationError {
-                error_details: format!("Failed to create index: {}", e),
-            })?;
-        }
-
+        let mut conn = self.conn.lock().await;
+        run_migrations(&mut conn, MIGRATIONS)?;
         Ok(())
     }
 
@@ -1,5 +1,6 @@
 use thiserror::Error;
 use crate::modules::blocktank::BtChannelOrderErrorType;
+use crate::modules::migrations::MigrationError;
 
 #[derive(uniffi::Error, Debug, Error)]
 pub enum BlocktankError {
@@ -66,6 +67,23 @@ pub enum BlocktankError {
     DatabaseError {
         error_details: String,
     },
+    #[error("Unsupported database version: {error_details}")]
+    UnsupportedSchemaVersion {
+        error_details: String,
+    },
+}
+
+impl From<MigrationError> for BlocktankError {
+    fn from(error: MigrationError) -> Self {
+        match error {
+            MigrationError::NewerVersion { .. } => BlocktankError::UnsupportedSchemaVersion {
+                error_details: error.to_string(),
+            },
+            MigrationError::Failed { .. } => BlocktankError::InitializationError {
+                error_details: error.to_string(),
+            },
+        }
+    }
 }
 
 impl From<serde_json::Error> for BlocktankError {
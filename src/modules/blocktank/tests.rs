@@ -6,6 +6,8 @@ const STAGING_SERVER: &str = "https://api.stag.blocktank.to/blocktank/api/v2";
 mod tests {
     use rust_blocktank_client::*;
     use crate::modules::blocktank::{BlocktankDB, BlocktankError};
+    use crate::modules::blocktank::db::MIGRATIONS;
+    use crate::modules::migrations::{latest_version, schema_version};
     use super::*;
 
     #[tokio::test]
@@ -1462,4 +1464,68 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Schemas of earlier releases, by `user_version`. When a migration is
+    /// added, the schema it upgrades from is added here.
+    const SCHEMA_FIXTURES: &[(u32, &str)] = &[
+        // Unversioned schema from before migrations
+        (0, "
+            CREATE TABLE order_states (state TEXT PRIMARY KEY, description TEXT NOT NULL);
+            CREATE TABLE payment_states (state TEXT PRIMARY KEY, description TEXT NOT NULL);
+            CREATE TABLE cjit_states (state TEXT PRIMARY KEY, description TEXT NOT NULL);
+            INSERT INTO order_states VALUES ('Created', 'Created'), ('Expired', 'Expired'), ('Open', 'Open'), ('Closed', 'Closed');
+            INSERT INTO cjit_states VALUES ('Created', 'Created'), ('Completed', 'Completed'), ('Expired', 'Expired'), ('Failed', 'Failed');
+            CREATE TABLE info (
+                version INTEGER PRIMARY KEY,
+                nodes TEXT NOT NULL,
+                options TEXT NOT NULL,
+                versions TEXT NOT NULL,
+                onchain TEXT NOT NULL,
+                updated_at TEXT,
+                is_current BOOLEAN NOT NULL DEFAULT 1
+            );
+            INSERT INTO info (version, nodes, options, versions, onchain) VALUES (
+                3,
+                '[]',
+                '{\"minChannelSizeSat\":20000,\"maxChannelSizeSat\":5000000,\"minExpiryWeeks\":2,\"maxExpiryWeeks\":52,\"minPaymentConfirmations\":1,\"minHighRiskPaymentConfirmations\":6,\"max0ConfClientBalanceSat\":100000,\"maxClientBalanceSat\":5000000}',
+                '{\"http\":\"1.0\",\"btc\":\"0.21.0\",\"ln2\":\"0.10.0\"}',
+                '{\"network\":\"regtest\",\"feeRates\":{\"fast\":10,\"mid\":5,\"slow\":1}}'
+            );
+        "),
+    ];
+
+    #[tokio::test]
+    async fn test_schema_migrations_upgrade_fixtures() {
+        for (version, fixture) in SCHEMA_FIXTURES {
+            let dir = tempfile::tempdir().unwrap();
+            let db_path = dir.path().join("blocktank.db");
+            let conn = rusqlite::Connection::open(&db_path).unwrap();
+            conn.execute_batch(fixture).unwrap();
+            conn.pragma_update(None, "user_version", version).unwrap();
+            drop(conn);
+
+            let db = BlocktankDB::new(db_path.to_str().unwrap(), Some(STAGING_SERVER)).await.unwrap();
+            assert_eq!(schema_version(&*db.conn.lock().await).unwrap(), latest_version(MIGRATIONS));
+            assert_eq!(db.get_info().await.unwrap().unwrap().version, 3, "upgrading from {}", version);
+            let payment_states: i64 = db.conn.lock().await
+                .query_row("SELECT COUNT(*) FROM payment_states", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(payment_states, 5);
+            assert!(db.get_orders(None, None).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_newer_schema_version_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("blocktank.db");
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        conn.pragma_update(None, "user_version", latest_version(MIGRATIONS) + 1).unwrap();
+        drop(conn);
+
+        assert!(matches!(
+            BlocktankDB::new(db_path.to_str().unwrap(), Some(STAGING_SERVER)).await,
+            Err(BlocktankError::UnsupportedSchemaVersion { .. })
+        ));
+    }
+}
@@ -0,0 +1,79 @@
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+use thiserror::Error;
+
+/// A schema change that brings a database to `version`.
+pub(crate) struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum MigrationError {
+    #[error("database schema version {found} is newer than the supported version {supported}")]
+    NewerVersion {
+        found: u32,
+        supported: u32,
+    },
+    #[error("migration {version} ({description}) failed: {source}")]
+    Failed {
+        version: u32,
+        description: &'static str,
+        source: rusqlite::Error,
+    },
+}
+
+/// Version of the schema `migrations` produce.
+pub(crate) fn latest_version(migrations: &[Migration]) -> u32 {
+    migrations.last().map_or(0, |m| m.version)
+}
+
+pub(crate) fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Applies the migrations newer than the database's `user_version`, in order.
+/// Each migration runs in its own transaction together with the version bump,
+/// so a failed step leaves the database at the previous version. Returns the
+/// resulting version.
+pub(crate) fn run_migrations(conn: &mut Connection, migrations: &[Migration]) -> Result<u32, MigrationError> {
+    let supported = latest_version(migrations);
+    let failed = |migration: &Migration, source| MigrationError::Failed {
+        version: migration.version,
+        description: migration.description,
+        source,
+    };
+
+    let mut version = schema_version(conn).map_err(|source| MigrationError::Failed {
+        version: 0,
+        description: "read schema version",
+        source,
+    })?;
+    if version > supported {
+        return Err(MigrationError::NewerVersion { found: version, supported });
+    }
+
+    for migration in migrations {
+        if version >= migration.version {
+            continue;
+        }
+        // Take the write lock before reading the version again so concurrent
+        // openers cannot apply the same step twice
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|e| failed(migration, e))?;
+        version = schema_version(&tx).map_err(|e| failed(migration, e))?;
+        if version >= migration.version {
+            continue;
+        }
+
+        (migration.apply)(&tx).map_err(|e| failed(migration, e))?;
+        tx.pragma_update(None, "user_version", migration.version)
+            .map_err(|e| failed(migration, e))?;
+        tx.commit().map_err(|e| failed(migration, e))?;
+        version = migration.version;
+        println!("✓ Applied database migration {}: {}", migration.version, migration.description);
+    }
+
+    Ok(version)
+}
@@ -3,4 +3,5 @@ pub mod lnurl;
 pub mod onchain;
 pub mod activity;
 pub mod blocktank;
-pub mod trezor;
\ No newline at end of file
+pub mod trezor;
+pub(crate) mod migrations;
@@ -0,0 +1,134 @@
+use bitcoin::bip32::{ChainCode, ChildNumber, DerivationPath, Fingerprint, Xpriv};
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha512, Hash, HashEngine};
+use bitcoin::hex::DisplayHex;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Network, NetworkKind, PrivateKey};
+use bip39::{Language, Mnemonic};
+use crate::onchain::AddressError;
+use super::keys;
+use super::types::WordCount;
+
+/// BIP85 purpose, "DEEP" on a phone keypad.
+const BIP85_PURPOSE: u32 = 83696968;
+/// HMAC key used to turn a derived private key into entropy.
+const BIP85_HMAC_KEY: &[u8] = b"bip-entropy-from-k";
+
+const APP_BIP39: u32 = 39;
+const APP_WIF: u32 = 2;
+const APP_XPRV: u32 = 32;
+const APP_HEX: u32 = 128169;
+
+/// BIP85 language code for English word lists.
+const LANGUAGE_ENGLISH: u32 = 0;
+
+/// Smallest and largest number of bytes the HEX application can derive.
+const HEX_MIN_BYTES: u32 = 16;
+const HEX_MAX_BYTES: u32 = 64;
+
+/// Derives a BIP39 child mnemonic from the wallet seed.
+pub fn derive_bip85_mnemonic(
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    word_count: WordCount,
+    index: u32,
+) -> Result<String, AddressError> {
+    let root = keys::master_key(mnemonic_phrase, bip39_passphrase, Network::Bitcoin)?;
+    mnemonic_from_root(&root, word_count, index)
+}
+
+/// Derives a WIF-encoded private key from the wallet seed.
+pub fn derive_bip85_wif(
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    index: u32,
+    network: Network,
+) -> Result<String, AddressError> {
+    let root = keys::master_key(mnemonic_phrase, bip39_passphrase, Network::Bitcoin)?;
+    wif_from_root(&root, index, network)
+}
+
+/// Derives an extended private key from the wallet seed.
+pub fn derive_bip85_xprv(
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    index: u32,
+    network: Network,
+) -> Result<String, AddressError> {
+    let root = keys::master_key(mnemonic_phrase, bip39_passphrase, Network::Bitcoin)?;
+    xprv_from_root(&root, index, network)
+}
+
+/// Derives `num_bytes` (16 to 64) bytes of hex-encoded entropy from the wallet seed.
+pub fn derive_bip85_hex(
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    num_bytes: u32,
+    index: u32,
+) -> Result<String, AddressError> {
+    let root = keys::master_key(mnemonic_phrase, bip39_passphrase, Network::Bitcoin)?;
+    hex_from_root(&root, num_bytes, index)
+}
+
+pub(crate) fn mnemonic_from_root(root: &Xpriv, word_count: WordCount, index: u32) -> Result<String, AddressError> {
+    let words = word_count as u32;
+    let entropy = entropy_at(root, &[APP_BIP39, LANGUAGE_ENGLISH, words, index])?;
+    // 12 words use 128 bits, each further 3 words add 32 bits
+    let length = (words * 4 / 3) as usize;
+    let mnemonic = Mnemonic::from_entropy_in(Language::English, &entropy[..length])
+        .map_err(|_| AddressError::MnemonicGenerationFailed)?;
+
+    println!("✓ Derived BIP85 {}-word mnemonic at index {}", words, index);
+    Ok(mnemonic.to_string())
+}
+
+pub(crate) fn wif_from_root(root: &Xpriv, index: u32, network: Network) -> Result<String, AddressError> {
+    let entropy = entropy_at(root, &[APP_WIF, index])?;
+    let private_key = PrivateKey::from_slice(&entropy[..32], network).map_err(|_| AddressError::AddressDerivationFailed)?;
+    Ok(private_key.to_wif())
+}
+
+pub(crate) fn xprv_from_root(root: &Xpriv, index: u32, network: Network) -> Result<String, AddressError> {
+    let entropy = entropy_at(root, &[APP_XPRV, index])?;
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&entropy[..32]);
+    let private_key = SecretKey::from_slice(&entropy[32..]).map_err(|_| AddressError::AddressDerivationFailed)?;
+
+    let xprv = Xpriv {
+        network: NetworkKind::from(network),
+        depth: 0,
+        parent_fingerprint: Fingerprint::default(),
+        child_number: ChildNumber::Normal { index: 0 },
+        private_key,
+        chain_code: ChainCode::from(chain_code),
+    };
+    Ok(xprv.to_string())
+}
+
+pub(crate) fn hex_from_root(root: &Xpriv, num_bytes: u32, index: u32) -> Result<String, AddressError> {
+    if !(HEX_MIN_BYTES..=HEX_MAX_BYTES).contains(&num_bytes) {
+        println!("✗ BIP85 HEX length must be between {} and {} bytes", HEX_MIN_BYTES, HEX_MAX_BYTES);
+        return Err(AddressError::InvalidLength);
+    }
+    let entropy = entropy_at(root, &[APP_HEX, num_bytes, index])?;
+    Ok(entropy[..num_bytes as usize].to_lower_hex_string())
+}
+
+/// Derives the hardened path `m/83696968'/<levels'>` and returns its 64 bytes of entropy.
+fn entropy_at(root: &Xpriv, levels: &[u32]) -> Result<[u8; 64], AddressError> {
+    let path = std::iter::once(BIP85_PURPOSE)
+        .chain(levels.iter().copied())
+        .map(|index| ChildNumber::from_hardened_idx(index).map_err(|_| AddressError::InvalidDerivationPath))
+        .collect::<Result<Vec<_>, _>>()?;
+    derive_entropy(root, &DerivationPath::from(path))
+}
+
+/// BIP85 entropy: HMAC-SHA512 keyed with "bip-entropy-from-k" over the private key at `path`.
+pub(crate) fn derive_entropy(root: &Xpriv, path: &DerivationPath) -> Result<[u8; 64], AddressError> {
+    let secp = Secp256k1::new();
+    let derived = keys::derive_xpriv(&secp, root, path)?;
+
+    let mut engine = HmacEngine::<sha512::Hash>::new(BIP85_HMAC_KEY);
+    engine.input(&derived.private_key.secret_bytes());
+    Ok(Hmac::<sha512::Hash>::from_engine(engine).to_byte_array())
+}
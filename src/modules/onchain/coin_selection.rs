@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use crate::onchain::TransactionError;
+use super::fees::{self, fee_for_weight};
+use super::types::{AddressType, CoinSelectionResult, CoinSelectionStrategy, TargetOutput, Utxo};
+
+/// Long-term fee rate assumed when none is given, in sat/vB.
+pub const DEFAULT_LONG_TERM_FEE_RATE: f64 = 10.0;
+
+/// Maximum number of branch-and-bound iterations before giving up.
+const BNB_TOTAL_TRIES: usize = 100_000;
+
+/// A UTXO together with the cost of spending it.
+#[derive(Debug, Clone)]
+struct Candidate {
+    utxo: Utxo,
+    /// Value minus the fee of spending it at the current fee rate
+    effective_value: u64,
+    /// Fee of spending it at the current fee rate
+    fee: u64,
+    /// Fee of spending it at the long-term fee rate
+    long_term_fee: u64,
+}
+
+impl Candidate {
+    fn waste(&self) -> i64 {
+        self.fee as i64 - self.long_term_fee as i64
+    }
+}
+
+/// Fixed parameters of a selection run.
+struct SelectionContext {
+    fee_rate: f64,
+    /// Sum of the target output values
+    target_value: u64,
+    output_types: Vec<AddressType>,
+    /// Weight of everything but the inputs, change output and segwit marker,
+    /// assuming single-byte counts. Only used for the search target, results
+    /// are priced with `estimate_tx_weight`
+    base_weight: u64,
+    /// Segwit marker weight assumed during selection, if any candidate is segwit
+    marker_weight: u64,
+    change_type: AddressType,
+    /// Fee of adding a change output now plus spending it later
+    cost_of_change: u64,
+}
+
+impl SelectionContext {
+    /// Effective value the inputs must cover for a changeless transaction.
+    fn selection_target(&self) -> u64 {
+        self.target_value + fee_for_weight(self.base_weight + self.marker_weight, self.fee_rate)
+    }
+}
+
+/// Selects UTXOs to fund `outputs` at `fee_rate` sat/vB using `strategy`.
+pub fn select_coins(
+    utxos: Vec<Utxo>,
+    outputs: Vec<TargetOutput>,
+    fee_rate: f64,
+    change_type: AddressType,
+    strategy: CoinSelectionStrategy,
+    long_term_fee_rate: Option<f64>,
+) -> Result<CoinSelectionResult, TransactionError> {
+    fees::check_fee_rate(fee_rate)?;
+    let long_term_fee_rate = long_term_fee_rate.unwrap_or(DEFAULT_LONG_TERM_FEE_RATE);
+    fees::check_fee_rate(long_term_fee_rate)?;
+
+    if outputs.is_empty() {
+        return Err(TransactionError::InvalidOutput {
+            error_details: "At least one output is required".to_string(),
+        });
+    }
+
+    let mut target_value = 0u64;
+    let mut base_weight = fees::TX_OVERHEAD_WEIGHT;
+    let mut output_types = Vec::with_capacity(outputs.len());
+    for output in &outputs {
+        let address_type = output_address_type(output)?;
+        output_types.push(address_type);
+        if output.amount < fees::dust_limit(address_type) {
+            return Err(TransactionError::InvalidOutput {
+                error_details: format!("Output to {} is below the dust limit", output.address),
+            });
+        }
+        target_value = target_value.checked_add(output.amount).ok_or(TransactionError::InvalidOutput {
+            error_details: "Output amounts overflow".to_string(),
+        })?;
+        base_weight += fees::output_weight(address_type)?;
+    }
+
+    let change_output_weight = fees::output_weight(change_type)?;
+    let cost_of_change = fee_for_weight(change_output_weight, fee_rate)
+        + fee_for_weight(fees::input_weight(change_type)?, long_term_fee_rate);
+
+    let marker_weight = if utxos.iter().any(|u| fees::is_segwit(u.address_type)) {
+        fees::SEGWIT_MARKER_WEIGHT
+    } else {
+        0
+    };
+
+    let context = SelectionContext {
+        fee_rate,
+        target_value,
+        output_types,
+        base_weight,
+        marker_weight,
+        change_type,
+        cost_of_change,
+    };
+
+    let mut candidates = Vec::with_capacity(utxos.len());
+    for utxo in utxos {
+        let weight = fees::input_weight(utxo.address_type)?;
+        let fee = fee_for_weight(weight, fee_rate);
+        // Inputs that cost more to spend than they are worth are never selected
+        if utxo.value <= fee {
+            continue;
+        }
+        candidates.push(Candidate {
+            effective_value: utxo.value - fee,
+            fee,
+            long_term_fee: fee_for_weight(weight, long_term_fee_rate),
+            utxo,
+        });
+    }
+    // Largest effective value first, ties broken by outpoint for determinism
+    candidates.sort_by(|a, b| {
+        b.effective_value
+            .cmp(&a.effective_value)
+            .then_with(|| a.utxo.txid.cmp(&b.utxo.txid))
+            .then_with(|| a.utxo.vout.cmp(&b.utxo.vout))
+    });
+
+    let result = match strategy {
+        CoinSelectionStrategy::BranchAndBound => select_bnb_or_largest_first(&candidates, &context),
+        CoinSelectionStrategy::LargestFirst => select_largest_first(&candidates, &context),
+        CoinSelectionStrategy::SingleAddressType => select_single_address_type(&candidates, &context),
+    };
+
+    result.ok_or_else(|| {
+        let available = candidates.iter().map(|c| c.effective_value).sum();
+        TransactionError::InsufficientFunds {
+            available,
+            required: context.selection_target(),
+        }
+    })
+}
+
+fn output_address_type(output: &TargetOutput) -> Result<AddressType, TransactionError> {
+    match AddressType::from_address(&output.address) {
+        Ok(AddressType::Unknown) | Err(_) => Err(TransactionError::InvalidOutput {
+            error_details: format!("Unsupported output address: {}", output.address),
+        }),
+        Ok(address_type) => Ok(address_type),
+    }
+}
+
+fn select_bnb_or_largest_first(candidates: &[Candidate], context: &SelectionContext) -> Option<CoinSelectionResult> {
+    select_branch_and_bound(candidates, context).or_else(|| select_largest_first(candidates, context))
+}
+
+/// Searches for a changeless input set whose excess is below the cost of change,
+/// minimising waste (Bitcoin Core's branch-and-bound).
+fn select_branch_and_bound(candidates: &[Candidate], context: &SelectionContext) -> Option<CoinSelectionResult> {
+    let target = context.selection_target();
+    let upper_bound = target + context.cost_of_change;
+    let is_fee_rate_high = context.fee_rate > 0.0
+        && candidates.first().is_some_and(|c| c.fee > c.long_term_fee);
+
+    let mut available: u64 = candidates.iter().map(|c| c.effective_value).sum();
+    if available < target {
+        return None;
+    }
+
+    let mut current_value = 0u64;
+    let mut current_waste = 0i64;
+    let mut selection: Vec<usize> = Vec::new();
+    let mut best_selection: Option<Vec<usize>> = None;
+    let mut best_waste = i64::MAX;
+    let mut index = 0usize;
+
+    for _ in 0..BNB_TOTAL_TRIES {
+        let mut backtrack = false;
+        if current_value + available < target
+            || current_value > upper_bound
+            || (current_waste > best_waste && is_fee_rate_high)
+        {
+            backtrack = true;
+        } else if current_value >= target {
+            let waste = current_waste + (current_value - target) as i64;
+            if waste <= best_waste {
+                best_selection = Some(selection.clone());
+                best_waste = waste;
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let Some(&last) = selection.last() else { break };
+            // Return the skipped candidates to the lookahead, then exclude the last included one
+            while index > last + 1 {
+                index -= 1;
+                available += candidates[index].effective_value;
+            }
+            index = last;
+            current_value -= candidates[last].effective_value;
+            current_waste -= candidates[last].waste();
+            selection.pop();
+        } else {
+            let candidate = &candidates[index];
+            available -= candidate.effective_value;
+            // Skip candidates equivalent to an excluded predecessor, that branch was already explored
+            let equivalent_to_excluded = index > 0
+                && !selection.is_empty()
+                && selection.last() != Some(&(index - 1))
+                && candidate.effective_value == candidates[index - 1].effective_value
+                && candidate.fee == candidates[index - 1].fee;
+            if !equivalent_to_excluded {
+                selection.push(index);
+                current_value += candidate.effective_value;
+                current_waste += candidate.waste();
+            }
+        }
+        index += 1;
+    }
+
+    let selected: Vec<&Candidate> = best_selection?.into_iter().map(|i| &candidates[i]).collect();
+    build_result(&selected, context, false)
+}
+
+/// Adds the largest UTXOs until the outputs and fees are covered.
+fn select_largest_first(candidates: &[Candidate], context: &SelectionContext) -> Option<CoinSelectionResult> {
+    let target = context.selection_target();
+    let mut selected = Vec::new();
+    let mut value = 0u64;
+    for candidate in candidates {
+        selected.push(candidate);
+        value += candidate.effective_value;
+        if value >= target {
+            // The exact weight can exceed the search estimate, e.g. with more than 252 inputs
+            if let Some(result) = build_result(&selected, context, true) {
+                return Some(result);
+            }
+        }
+    }
+    None
+}
+
+/// Only spends UTXOs of one address type, so inputs do not reveal that the
+/// wallet holds several script types. Picks the type with the lowest waste,
+/// preferring the change type on ties.
+fn select_single_address_type(candidates: &[Candidate], context: &SelectionContext) -> Option<CoinSelectionResult> {
+    let mut groups: BTreeMap<u8, (AddressType, Vec<Candidate>)> = BTreeMap::new();
+    for candidate in candidates {
+        let address_type = candidate.utxo.address_type;
+        // Order groups so that the change type comes first, then by a stable type order
+        let key = if address_type == context.change_type { 0 } else { 1 + address_type as u8 };
+        groups.entry(key).or_insert_with(|| (address_type, Vec::new())).1.push(candidate.clone());
+    }
+
+    groups
+        .into_values()
+        .filter_map(|(_, group)| select_bnb_or_largest_first(&group, context))
+        .fold(None, |best: Option<CoinSelectionResult>, result| match best {
+            Some(best) if best.waste <= result.waste => Some(best),
+            _ => Some(result),
+        })
+}
+
+/// Computes the final fee, change and waste for `selected`.
+fn build_result(selected: &[&Candidate], context: &SelectionContext, allow_change: bool) -> Option<CoinSelectionResult> {
+    let total_input: u64 = selected.iter().map(|c| c.utxo.value).sum();
+    let input_types: Vec<AddressType> = selected.iter().map(|c| c.utxo.address_type).collect();
+    let mut output_types = context.output_types.clone();
+    let weight_without_change = fees::estimate_tx_weight(&input_types, &output_types).ok()?;
+    let fee_without_change = fee_for_weight(weight_without_change, context.fee_rate);
+    let input_waste: i64 = selected.iter().map(|c| c.waste()).sum();
+
+    let excess = total_input.checked_sub(context.target_value + fee_without_change)?;
+
+    output_types.push(context.change_type);
+    let weight_with_change = fees::estimate_tx_weight(&input_types, &output_types).ok()?;
+    let fee_with_change = fee_for_weight(weight_with_change, context.fee_rate);
+    let change_amount = (total_input - context.target_value).saturating_sub(fee_with_change);
+
+    let (change_amount, fee, weight, waste) = if allow_change && change_amount >= fees::dust_limit(context.change_type) {
+        (Some(change_amount), fee_with_change, weight_with_change, input_waste + context.cost_of_change as i64)
+    } else {
+        // Excess goes to the miners
+        (None, fee_without_change + excess, weight_without_change, input_waste + excess as i64)
+    };
+
+    Some(CoinSelectionResult {
+        selected_utxos: selected.iter().map(|c| c.utxo.clone()).collect(),
+        change_amount,
+        fee,
+        vsize: weight.div_ceil(4),
+        waste,
+    })
+}
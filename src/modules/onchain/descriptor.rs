@@ -0,0 +1,352 @@
+use std::str::FromStr;
+use bitcoin::base58;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, Xpub};
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::{Address, CompressedPublicKey, Network, NetworkKind};
+use crate::onchain::AddressError;
+use super::types::{AddressType, GetAddressResponse, GetAddressesResponse};
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const CHECKSUM_GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+
+/// SLIP-132 version bytes with the script type they imply, whether they denote
+/// a multisig key and the network they belong to.
+const SLIP132_VERSIONS: [([u8; 4], Option<AddressType>, bool, NetworkKind); 10] = [
+    ([0x04, 0x88, 0xb2, 0x1e], None, false, NetworkKind::Main),                      // xpub
+    ([0x04, 0x9d, 0x7c, 0xb2], Some(AddressType::P2SH), false, NetworkKind::Main),   // ypub
+    ([0x04, 0xb2, 0x47, 0x46], Some(AddressType::P2WPKH), false, NetworkKind::Main), // zpub
+    ([0x02, 0x95, 0xb4, 0x3f], Some(AddressType::P2SH), true, NetworkKind::Main),    // Ypub
+    ([0x02, 0xaa, 0x7e, 0xd3], Some(AddressType::P2WSH), true, NetworkKind::Main),   // Zpub
+    ([0x04, 0x35, 0x87, 0xcf], None, false, NetworkKind::Test),                      // tpub
+    ([0x04, 0x4a, 0x52, 0x62], Some(AddressType::P2SH), false, NetworkKind::Test),   // upub
+    ([0x04, 0x5f, 0x1c, 0xf6], Some(AddressType::P2WPKH), false, NetworkKind::Test), // vpub
+    ([0x02, 0x42, 0x89, 0xef], Some(AddressType::P2SH), true, NetworkKind::Test),    // Upub
+    ([0x02, 0x57, 0x54, 0x83], Some(AddressType::P2WSH), true, NetworkKind::Test),   // Vpub
+];
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+
+/// A single step of the derivation that follows the extended key in a descriptor.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PathStep {
+    Fixed(u32),
+    /// `<receive;change>` multipath step
+    Multipath(u32, u32),
+    Wildcard,
+}
+
+/// An extended public key with optional key origin and derivation steps.
+#[derive(Debug, Clone)]
+pub(crate) struct DescriptorKey {
+    pub(crate) origin: Option<(Fingerprint, DerivationPath)>,
+    pub(crate) xpub: Xpub,
+    pub(crate) steps: Vec<PathStep>,
+    /// Script type implied by a SLIP-132 version prefix, if any.
+    pub(crate) implied_type: Option<AddressType>,
+    /// Whether the SLIP-132 version prefix denotes a multisig key.
+    pub(crate) multisig_prefix: bool,
+}
+
+/// A parsed single-key output descriptor.
+#[derive(Debug, Clone)]
+pub(crate) struct SingleKeyDescriptor {
+    pub(crate) address_type: AddressType,
+    pub(crate) key: DescriptorKey,
+}
+
+impl DescriptorKey {
+    pub(crate) fn parse(input: &str) -> Result<Self, AddressError> {
+        let (origin, rest) = match input.strip_prefix('[') {
+            Some(stripped) => {
+                let (origin_str, rest) = stripped.split_once(']').ok_or(AddressError::InvalidDescriptor)?;
+                (Some(parse_key_origin(origin_str)?), rest)
+            },
+            None => (None, input),
+        };
+
+        let mut parts = rest.split('/');
+        let key_str = parts.next().ok_or(AddressError::InvalidDescriptor)?;
+        let (xpub, implied_type, multisig_prefix) = parse_extended_public_key(key_str)?;
+
+        let mut steps = Vec::new();
+        for part in parts {
+            if steps.last() == Some(&PathStep::Wildcard) {
+                return Err(AddressError::InvalidDescriptor);
+            }
+            steps.push(parse_path_step(part)?);
+        }
+        if !steps.is_empty() && steps.last() != Some(&PathStep::Wildcard) {
+            return Err(AddressError::InvalidDescriptor);
+        }
+
+        Ok(DescriptorKey { origin, xpub, steps, implied_type, multisig_prefix })
+    }
+
+    /// Resolves the relative path from the extended key to the child at `index`
+    /// on the receive or change chain.
+    pub(crate) fn child_path(&self, is_change: bool, index: u32) -> Result<Vec<ChildNumber>, AddressError> {
+        let chain = if is_change { 1 } else { 0 };
+        let steps = if self.steps.is_empty() {
+            vec![PathStep::Fixed(chain), PathStep::Wildcard]
+        } else {
+            self.steps.clone()
+        };
+
+        steps
+            .into_iter()
+            .map(|step| {
+                let idx = match step {
+                    PathStep::Fixed(idx) => idx,
+                    PathStep::Multipath(receive, change) => if is_change { change } else { receive },
+                    PathStep::Wildcard => index,
+                };
+                ChildNumber::from_normal_idx(idx).map_err(|_| AddressError::AddressDerivationFailed)
+            })
+            .collect()
+    }
+
+    /// Derives the public key at `index` together with its full derivation path.
+    pub(crate) fn derive<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        is_change: bool,
+        index: u32,
+    ) -> Result<(CompressedPublicKey, DerivationPath), AddressError> {
+        let child_path = self.child_path(is_change, index)?;
+        let child = self.xpub
+            .derive_pub(secp, &child_path)
+            .map_err(|_| AddressError::AddressDerivationFailed)?;
+
+        let full_path = match &self.origin {
+            Some((_, origin_path)) => origin_path.extend(&child_path),
+            None => DerivationPath::from(child_path),
+        };
+
+        Ok((child.to_pub(), full_path))
+    }
+
+    pub(crate) fn network_kind(&self) -> NetworkKind {
+        self.xpub.network
+    }
+}
+
+impl SingleKeyDescriptor {
+    pub(crate) fn parse(descriptor: &str) -> Result<Self, AddressError> {
+        let body = strip_checksum(descriptor)?;
+
+        let (address_type, key_str) = if let Some(inner) = unwrap_fn(body, "sh") {
+            (AddressType::P2SH, unwrap_fn(inner, "wpkh").ok_or(AddressError::InvalidDescriptor)?)
+        } else if let Some(inner) = unwrap_fn(body, "wpkh") {
+            (AddressType::P2WPKH, inner)
+        } else if let Some(inner) = unwrap_fn(body, "pkh") {
+            (AddressType::P2PKH, inner)
+        } else if let Some(inner) = unwrap_fn(body, "tr") {
+            // Only key-path spends are supported, script trees are rejected
+            if inner.contains(',') {
+                return Err(AddressError::InvalidDescriptor);
+            }
+            (AddressType::P2TR, inner)
+        } else if !body.contains('(') {
+            // Bare extended key, the script type comes from its SLIP-132 prefix
+            let key = DescriptorKey::parse(body)?;
+            if key.multisig_prefix {
+                return Err(AddressError::InvalidDescriptor);
+            }
+            let address_type = key.implied_type.unwrap_or(AddressType::P2PKH);
+            return Ok(SingleKeyDescriptor { address_type, key });
+        } else {
+            return Err(AddressError::InvalidDescriptor);
+        };
+
+        let key = DescriptorKey::parse(key_str)?;
+        if key.multisig_prefix {
+            println!("✗ Multisig extended key used in a single-key descriptor");
+            return Err(AddressError::InvalidDescriptor);
+        }
+        if let Some(implied) = key.implied_type {
+            if implied != address_type {
+                println!("✗ Extended key prefix does not match descriptor script type");
+                return Err(AddressError::InvalidDescriptor);
+            }
+        }
+
+        Ok(SingleKeyDescriptor { address_type, key })
+    }
+
+    /// Resolves the network to derive for, checking it against the key's version bytes.
+    pub(crate) fn resolve_network(&self, network: Option<Network>) -> Result<Network, AddressError> {
+        let kind = self.key.network_kind();
+        match network {
+            Some(network) if NetworkKind::from(network) != kind => Err(AddressError::InvalidNetwork),
+            Some(network) => Ok(network),
+            None if kind.is_mainnet() => Ok(Network::Bitcoin),
+            None => Ok(Network::Testnet),
+        }
+    }
+
+    pub(crate) fn derive_address<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        network: Network,
+        is_change: bool,
+        index: u32,
+    ) -> Result<GetAddressResponse, AddressError> {
+        let (public_key, path) = self.key.derive(secp, is_change, index)?;
+        let address = script_address(secp, self.address_type, &public_key, network)?;
+
+        Ok(GetAddressResponse {
+            address: address.to_string(),
+            path: format!("m/{}", path),
+            public_key: public_key.to_string(),
+        })
+    }
+}
+
+/// Builds the single-key address of `address_type` for `public_key`.
+pub(crate) fn script_address<C: Verification>(
+    secp: &Secp256k1<C>,
+    address_type: AddressType,
+    public_key: &CompressedPublicKey,
+    network: Network,
+) -> Result<Address, AddressError> {
+    match address_type {
+        AddressType::P2PKH => Ok(Address::p2pkh(public_key, network)),
+        AddressType::P2SH => Ok(Address::p2shwpkh(public_key, network)),
+        AddressType::P2WPKH => Ok(Address::p2wpkh(public_key, network)),
+        AddressType::P2TR => Ok(Address::p2tr(secp, public_key.0.x_only_public_key().0, None, network)),
+        AddressType::P2WSH | AddressType::Unknown => Err(AddressError::AddressDerivationFailed),
+    }
+}
+
+/// Derives a range of addresses from a single-key output descriptor or SLIP-132 extended public key.
+pub fn derive_addresses_from_descriptor(
+    descriptor: &str,
+    network: Option<Network>,
+    is_change: Option<bool>,
+    start_index: Option<u32>,
+    count: Option<u32>,
+) -> Result<GetAddressesResponse, AddressError> {
+    let descriptor = SingleKeyDescriptor::parse(descriptor)?;
+    let network = descriptor.resolve_network(network)?;
+    let is_change = is_change.unwrap_or(false);
+    let start_index = start_index.unwrap_or(0);
+    let count = count.unwrap_or(1);
+
+    let secp = Secp256k1::verification_only();
+    let addresses = (0..count)
+        .map(|offset| {
+            let index = start_index.checked_add(offset).ok_or(AddressError::AddressDerivationFailed)?;
+            descriptor.derive_address(&secp, network, is_change, index)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(GetAddressesResponse { addresses })
+}
+
+/// Decodes an extended public key, accepting SLIP-132 variants (ypub, zpub, ...).
+/// Returns the key alongside the script type and multisig flag its prefix implies.
+pub(crate) fn parse_extended_public_key(key: &str) -> Result<(Xpub, Option<AddressType>, bool), AddressError> {
+    let mut data = base58::decode_check(key).map_err(|_| AddressError::InvalidExtendedKey)?;
+    if data.len() != 78 {
+        return Err(AddressError::InvalidExtendedKey);
+    }
+
+    let (implied_type, multisig, kind) = SLIP132_VERSIONS
+        .iter()
+        .find(|(version, _, _, _)| data[..4] == version[..])
+        .map(|(_, implied_type, multisig, kind)| (*implied_type, *multisig, *kind))
+        .ok_or(AddressError::InvalidExtendedKey)?;
+
+    let version = if kind.is_mainnet() { XPUB_VERSION } else { TPUB_VERSION };
+    data[..4].copy_from_slice(&version);
+
+    let xpub = Xpub::decode(&data).map_err(|_| AddressError::InvalidExtendedKey)?;
+    Ok((xpub, implied_type, multisig))
+}
+
+/// Parses the `fingerprint/path` part of a `[fingerprint/path]` key origin.
+pub(crate) fn parse_key_origin(origin: &str) -> Result<(Fingerprint, DerivationPath), AddressError> {
+    let (fingerprint_str, path_str) = match origin.split_once('/') {
+        Some((fingerprint, path)) => (fingerprint, path),
+        None => (origin, ""),
+    };
+    let fingerprint = Fingerprint::from_str(fingerprint_str).map_err(|_| AddressError::InvalidDescriptor)?;
+    let path = DerivationPath::from_str(path_str).map_err(|_| AddressError::InvalidDerivationPath)?;
+    Ok((fingerprint, path))
+}
+
+fn parse_path_step(step: &str) -> Result<PathStep, AddressError> {
+    if step == "*" {
+        return Ok(PathStep::Wildcard);
+    }
+    if let Some(inner) = step.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+        let (receive, change) = inner.split_once(';').ok_or(AddressError::InvalidDescriptor)?;
+        let receive = receive.parse::<u32>().map_err(|_| AddressError::InvalidDescriptor)?;
+        let change = change.parse::<u32>().map_err(|_| AddressError::InvalidDescriptor)?;
+        return Ok(PathStep::Multipath(receive, change));
+    }
+    // Hardened steps cannot be derived from a public key
+    step.parse::<u32>()
+        .map(PathStep::Fixed)
+        .map_err(|_| AddressError::InvalidDerivationPath)
+}
+
+fn unwrap_fn<'a>(input: &'a str, name: &str) -> Option<&'a str> {
+    input
+        .strip_prefix(name)
+        .and_then(|s| s.strip_prefix('('))
+        .and_then(|s| s.strip_suffix(')'))
+}
+
+/// Removes and verifies a trailing `#checksum`, if present.
+pub(crate) fn strip_checksum(descriptor: &str) -> Result<&str, AddressError> {
+    let descriptor = descriptor.trim();
+    match descriptor.split_once('#') {
+        Some((body, checksum)) => {
+            if descriptor_checksum(body)? != checksum {
+                println!("✗ Descriptor checksum mismatch");
+                return Err(AddressError::InvalidDescriptor);
+            }
+            Ok(body)
+        },
+        None => Ok(descriptor),
+    }
+}
+
+/// Computes the BIP380 descriptor checksum.
+pub(crate) fn descriptor_checksum(descriptor: &str) -> Result<String, AddressError> {
+    let mut symbols = Vec::with_capacity(descriptor.len() * 2);
+    let mut groups = Vec::with_capacity(3);
+    for c in descriptor.chars() {
+        let value = INPUT_CHARSET.find(c).ok_or(AddressError::InvalidDescriptor)? as u64;
+        symbols.push(value & 31);
+        groups.push(value >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups.len() {
+        1 => symbols.push(groups[0]),
+        2 => symbols.push(groups[0] * 3 + groups[1]),
+        _ => {},
+    }
+    symbols.extend([0; 8]);
+
+    let mut chk: u64 = 1;
+    for value in symbols {
+        let top = chk >> 35;
+        chk = ((chk & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in CHECKSUM_GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk ^= 1;
+
+    Ok((0..8)
+        .map(|i| CHECKSUM_CHARSET[((chk >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
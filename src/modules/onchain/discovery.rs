@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::Address;
+use openssl::ssl::{SslConnector, SslMethod};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use url::Url;
+use crate::onchain::{AddressError, DiscoveryError};
+use super::types::{AddressDiscoveryResult, AddressStats, ChainBackend, DiscoveredAddress, GetAddressesResponse};
+
+/// Default number of consecutive unused addresses after which a chain is considered exhausted.
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+const ELECTRUM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A backend that can report the on-chain history of an address.
+#[async_trait]
+pub trait ChainSource: Send + Sync {
+    async fn address_stats(&self, address: &str) -> Result<AddressStats, DiscoveryError>;
+}
+
+impl ChainBackend {
+    /// Creates the chain source for this backend.
+    pub fn into_source(self) -> Result<Box<dyn ChainSource>, DiscoveryError> {
+        match self {
+            ChainBackend::Esplora { url } => Ok(Box::new(EsploraChainSource::new(&url)?)),
+            ChainBackend::Electrum { url } => Ok(Box::new(ElectrumChainSource::new(&url)?)),
+        }
+    }
+}
+
+/// Walks the receive and change chains until `gap_limit` consecutive unused
+/// addresses are found on each. `derive` is called with `(is_change, start_index, count)`.
+pub async fn discover_addresses<F>(
+    source: &dyn ChainSource,
+    gap_limit: Option<u32>,
+    derive: F,
+) -> Result<AddressDiscoveryResult, DiscoveryError>
+where
+    F: Fn(bool, u32, u32) -> Result<GetAddressesResponse, AddressError>,
+{
+    let gap_limit = gap_limit.unwrap_or(DEFAULT_GAP_LIMIT);
+    if gap_limit == 0 {
+        return Err(DiscoveryError::InvalidParameter {
+            error_details: "Gap limit must be greater than zero".to_string(),
+        });
+    }
+
+    let mut used_addresses = Vec::new();
+    let mut next_indexes = [0u32; 2];
+
+    for (chain, is_change) in [false, true].into_iter().enumerate() {
+        let mut start_index = 0u32;
+        let mut unused_run = 0u32;
+
+        while unused_run < gap_limit {
+            let batch = derive(is_change, start_index, gap_limit - unused_run)?;
+            let batch_len = batch.addresses.len() as u32;
+            if batch_len == 0 {
+                return Err(DiscoveryError::DerivationError {
+                    error_details: "No addresses derived".to_string(),
+                });
+            }
+
+            for (offset, derived) in batch.addresses.into_iter().enumerate() {
+                let index = start_index + offset as u32;
+                let stats = source.address_stats(&derived.address).await?;
+
+                if stats.tx_count > 0 {
+                    println!("✓ Found used address {} at index {}", derived.address, index);
+                    used_addresses.push(DiscoveredAddress {
+                        address: derived.address,
+                        path: derived.path,
+                        is_change,
+                        index,
+                        tx_count: stats.tx_count,
+                        confirmed_balance: stats.confirmed_balance,
+                        unconfirmed_balance: stats.unconfirmed_balance,
+                    });
+                    next_indexes[chain] = index + 1;
+                    unused_run = 0;
+                } else {
+                    unused_run += 1;
+                }
+            }
+
+            start_index = start_index.checked_add(batch_len).ok_or(DiscoveryError::DerivationError {
+                error_details: "Address index overflow".to_string(),
+            })?;
+        }
+    }
+
+    let confirmed_balance = used_addresses.iter().map(|a| a.confirmed_balance).sum();
+    let unconfirmed_balance = used_addresses.iter().map(|a| a.unconfirmed_balance).sum();
+
+    Ok(AddressDiscoveryResult {
+        used_addresses,
+        next_receive_index: next_indexes[0],
+        next_change_index: next_indexes[1],
+        confirmed_balance,
+        unconfirmed_balance,
+    })
+}
+
+/// Chain source backed by an Esplora REST API.
+pub struct EsploraChainSource {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct EsploraTxoStats {
+    funded_txo_sum: u64,
+    spent_txo_sum: u64,
+    tx_count: u32,
+}
+
+#[derive(Deserialize)]
+struct EsploraAddressResponse {
+    chain_stats: EsploraTxoStats,
+    mempool_stats: EsploraTxoStats,
+}
+
+impl EsploraChainSource {
+    pub fn new(base_url: &str) -> Result<Self, DiscoveryError> {
+        Url::parse(base_url).map_err(|e| DiscoveryError::InvalidParameter {
+            error_details: format!("Invalid Esplora URL: {}", e),
+        })?;
+
+        Ok(EsploraChainSource {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl ChainSource for EsploraChainSource {
+    async fn address_stats(&self, address: &str) -> Result<AddressStats, DiscoveryError> {
+        let response = self.client
+            .get(format!("{}/address/{}", self.base_url, address))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| DiscoveryError::BackendError {
+                error_details: format!("Esplora request failed: {}", e),
+            })?;
+
+        let body: EsploraAddressResponse = response.json().await.map_err(|e| DiscoveryError::InvalidResponse {
+            error_details: e.to_string(),
+        })?;
+
+        Ok(AddressStats {
+            tx_count: body.chain_stats.tx_count + body.mempool_stats.tx_count,
+            confirmed_balance: body.chain_stats.funded_txo_sum.saturating_sub(body.chain_stats.spent_txo_sum),
+            unconfirmed_balance: body.mempool_stats.funded_txo_sum as i64 - body.mempool_stats.spent_txo_sum as i64,
+        })
+    }
+}
+
+trait ElectrumStream: Read + Write + Send {}
+impl<T: Read + Write + Send> ElectrumStream for T {}
+
+struct ElectrumConnection {
+    stream: BufReader<Box<dyn ElectrumStream>>,
+    next_id: u64,
+}
+
+impl ElectrumConnection {
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, DiscoveryError> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let mut line = serde_json::to_vec(&request).map_err(|e| DiscoveryError::BackendError {
+            error_details: e.to_string(),
+        })?;
+        line.push(b'\n');
+        self.stream.get_mut().write_all(&line).map_err(electrum_io_error)?;
+
+        loop {
+            let mut response = String::new();
+            if self.stream.read_line(&mut response).map_err(electrum_io_error)? == 0 {
+                return Err(DiscoveryError::BackendError {
+                    error_details: "Electrum server closed the connection".to_string(),
+                });
+            }
+            let response: Value = serde_json::from_str(&response).map_err(|e| DiscoveryError::InvalidResponse {
+                error_details: e.to_string(),
+            })?;
+            // Skip notifications and responses to other requests
+            if response.get("id").and_then(Value::as_u64) != Some(id) {
+                continue;
+            }
+            if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+                return Err(DiscoveryError::BackendError {
+                    error_details: format!("Electrum error: {}", error),
+                });
+            }
+            return Ok(response.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+}
+
+fn electrum_io_error(e: std::io::Error) -> DiscoveryError {
+    DiscoveryError::BackendError {
+        error_details: format!("Electrum connection error: {}", e),
+    }
+}
+
+/// Chain source backed by an Electrum server, reached over `tcp://` or `ssl://`.
+pub struct ElectrumChainSource {
+    host: String,
+    port: u16,
+    use_ssl: bool,
+    connection: Arc<Mutex<Option<ElectrumConnection>>>,
+}
+
+impl ElectrumChainSource {
+    pub fn new(url: &str) -> Result<Self, DiscoveryError> {
+        let invalid = |details: &str| DiscoveryError::InvalidParameter {
+            error_details: format!("Invalid Electrum URL {}: {}", url, details),
+        };
+        let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+        let use_ssl = match parsed.scheme() {
+            "ssl" => true,
+            "tcp" => false,
+            _ => return Err(invalid("expected tcp:// or ssl:// scheme")),
+        };
+        let host = parsed.host_str().ok_or_else(|| invalid("missing host"))?.to_string();
+        let port = parsed.port().ok_or_else(|| invalid("missing port"))?;
+
+        Ok(ElectrumChainSource {
+            host,
+            port,
+            use_ssl,
+            connection: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    fn connect(host: &str, port: u16, use_ssl: bool) -> Result<ElectrumConnection, DiscoveryError> {
+        let tcp = TcpStream::connect((host, port)).map_err(electrum_io_error)?;
+        tcp.set_read_timeout(Some(ELECTRUM_TIMEOUT)).map_err(electrum_io_error)?;
+        tcp.set_write_timeout(Some(ELECTRUM_TIMEOUT)).map_err(electrum_io_error)?;
+
+        let stream: Box<dyn ElectrumStream> = if use_ssl {
+            let connector = SslConnector::builder(SslMethod::tls())
+                .map_err(|e| DiscoveryError::BackendError { error_details: e.to_string() })?
+                .build();
+            Box::new(connector.connect(host, tcp).map_err(|e| DiscoveryError::BackendError {
+                error_details: format!("TLS handshake failed: {}", e),
+            })?)
+        } else {
+            Box::new(tcp)
+        };
+
+        Ok(ElectrumConnection { stream: BufReader::new(stream), next_id: 0 })
+    }
+
+    /// Electrum script hash: the reversed SHA256 of the output script.
+    fn script_hash(address: &str) -> Result<String, DiscoveryError> {
+        let script = Address::from_str(address)
+            .map_err(|e| DiscoveryError::InvalidParameter { error_details: e.to_string() })?
+            .assume_checked()
+            .script_pubkey();
+        let mut hash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+        hash.reverse();
+        Ok(hash.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+#[async_trait]
+impl ChainSource for ElectrumChainSource {
+    async fn address_stats(&self, address: &str) -> Result<AddressStats, DiscoveryError> {
+        let script_hash = Self::script_hash(address)?;
+        let connection = self.connection.clone();
+        let (host, port, use_ssl) = (self.host.clone(), self.port, self.use_ssl);
+
+        tokio::task::spawn_blocking(move || {
+            let mut guard = connection.lock().map_err(|e| DiscoveryError::BackendError {
+                error_details: e.to_string(),
+            })?;
+            if guard.is_none() {
+                *guard = Some(Self::connect(&host, port, use_ssl)?);
+            }
+            let conn = guard.as_mut().expect("connection initialised above");
+
+            let result = conn.call("blockchain.scripthash.get_history", json!([script_hash]))
+                .and_then(|history| {
+                    let balance = conn.call("blockchain.scripthash.get_balance", json!([script_hash]))?;
+                    Ok((history, balance))
+                });
+            let (history, balance) = match result {
+                Ok(values) => values,
+                Err(e) => {
+                    // Drop the connection so the next request reconnects
+                    *guard = None;
+                    return Err(e);
+                }
+            };
+
+            let invalid = |field: &str| DiscoveryError::InvalidResponse {
+                error_details: format!("Missing or invalid {} in Electrum response", field),
+            };
+            let tx_count = history.as_array().ok_or_else(|| invalid("history"))?.len() as u32;
+            let confirmed_balance = balance.get("confirmed").and_then(Value::as_u64).ok_or_else(|| invalid("confirmed"))?;
+            let unconfirmed_balance = balance.get("unconfirmed").and_then(Value::as_i64).ok_or_else(|| invalid("unconfirmed"))?;
+
+            Ok(AddressStats { tx_count, confirmed_balance, unconfirmed_balance })
+        })
+            .await
+            .unwrap_or_else(|e| Err(DiscoveryError::BackendError {
+                error_details: format!("Runtime error: {}", e),
+            }))
+    }
+}
+
+/// In-memory chain source, useful for tests and offline tooling.
+#[derive(Default)]
+pub struct InMemoryChainSource {
+    stats: Mutex<HashMap<String, AddressStats>>,
+}
+
+impl InMemoryChainSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_address_stats(&self, address: &str, stats: AddressStats) {
+        self.stats.lock().unwrap().insert(address.to_string(), stats);
+    }
+}
+
+#[async_trait]
+impl ChainSource for InMemoryChainSource {
+    async fn address_stats(&self, address: &str) -> Result<AddressStats, DiscoveryError> {
+        Ok(self.stats.lock().unwrap().get(address).cloned().unwrap_or_default())
+    }
+}
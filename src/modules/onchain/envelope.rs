@@ -0,0 +1,212 @@
+use bitcoin::base64::engine::general_purpose::STANDARD as BASE64;
+use bitcoin::base64::Engine;
+use bitcoin::secp256k1::Secp256k1;
+use openssl::error::ErrorStack;
+use openssl::pkcs5::scrypt;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+use crate::onchain::SeedEnvelopeError;
+use super::keys;
+use super::types::{Network, SeedEnvelopeContents};
+
+/// Envelope format version written by `encrypt_seed_envelope`.
+const ENVELOPE_VERSION: u32 = 1;
+const KDF_ALGORITHM: &str = "scrypt";
+const CIPHER_ALGORITHM: &str = "chacha20-poly1305";
+/// Bound to the ciphertext so it cannot be moved into another format.
+const ASSOCIATED_DATA: &[u8] = b"bitkit-seed-envelope-v1";
+
+/// scrypt cost: N = 2^17, r = 8, p = 1 needs 128 MiB and about half a second on a phone.
+/// The file can be attacked offline, so every PIN guess should be expensive.
+pub(crate) const DEFAULT_KDF_LOG_N: u8 = 17;
+const KDF_R: u32 = 8;
+const KDF_P: u32 = 1;
+/// Costs accepted when decrypting: what this format writes with one step of headroom,
+/// so a crafted file cannot demand more than 256 MiB or a few passes. Lighter costs
+/// down to N = 2^10 are accepted for envelopes written with `encrypt_with_cost`.
+const MIN_KDF_LOG_N: u8 = 10;
+const MAX_KDF_LOG_N: u8 = DEFAULT_KDF_LOG_N + 1;
+const MAX_KDF_P: u32 = 4;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const KEY_LENGTH: usize = 32;
+const TAG_LENGTH: usize = 16;
+/// Even at the default cost a short numeric PIN falls to an offline search, so
+/// require at least 8 characters.
+const MIN_PIN_LENGTH: usize = 8;
+
+/// The portable file: KDF and cipher parameters in the clear, secrets in `ciphertext`.
+#[derive(Serialize, Deserialize)]
+struct SeedEnvelope {
+    version: u32,
+    kdf: KdfParams,
+    cipher: CipherParams,
+    /// Base64 of the encrypted payload followed by the authentication tag
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CipherParams {
+    algorithm: String,
+    nonce: String,
+}
+
+/// Encrypts a mnemonic, its BIP39 passphrase and wallet metadata with `pin` and
+/// returns the envelope as a JSON string.
+pub fn encrypt_seed_envelope(
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    pin: &str,
+    network: Network,
+) -> Result<String, SeedEnvelopeError> {
+    encrypt_with_cost(mnemonic_phrase, bip39_passphrase, pin, network, DEFAULT_KDF_LOG_N)
+}
+
+pub(crate) fn encrypt_with_cost(
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    pin: &str,
+    network: Network,
+    log_n: u8,
+) -> Result<String, SeedEnvelopeError> {
+    if pin.chars().count() < MIN_PIN_LENGTH {
+        return Err(SeedEnvelopeError::InvalidPin {
+            error_details: format!("PIN must have at least {} characters", MIN_PIN_LENGTH),
+        });
+    }
+
+    let contents = SeedEnvelopeContents {
+        mnemonic: mnemonic_phrase.trim().to_string(),
+        passphrase: bip39_passphrase.filter(|p| !p.is_empty()).map(str::to_string),
+        network,
+        fingerprint: fingerprint(mnemonic_phrase, bip39_passphrase)?,
+        created_at: chrono::Utc::now().timestamp() as u64,
+    };
+    let plaintext = serde_json::to_vec(&contents).map_err(|e| SeedEnvelopeError::EncryptionFailed {
+        error_details: e.to_string(),
+    })?;
+
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    openssl::rand::rand_bytes(&mut salt)
+        .and_then(|_| openssl::rand::rand_bytes(&mut nonce))
+        .map_err(|e| SeedEnvelopeError::EncryptionFailed {
+            error_details: e.to_string(),
+        })?;
+
+    let key = derive_key(pin, &salt, log_n, KDF_R, KDF_P).map_err(|e| SeedEnvelopeError::EncryptionFailed {
+        error_details: format!("Key derivation failed: {}", e),
+    })?;
+    let mut tag = [0u8; TAG_LENGTH];
+    let mut ciphertext = encrypt_aead(Cipher::chacha20_poly1305(), &key, Some(&nonce), ASSOCIATED_DATA, &plaintext, &mut tag)
+        .map_err(|e| SeedEnvelopeError::EncryptionFailed {
+            error_details: e.to_string(),
+        })?;
+    ciphertext.extend_from_slice(&tag);
+
+    let envelope = SeedEnvelope {
+        version: ENVELOPE_VERSION,
+        kdf: KdfParams {
+            algorithm: KDF_ALGORITHM.to_string(),
+            log_n,
+            r: KDF_R,
+            p: KDF_P,
+            salt: BASE64.encode(salt),
+        },
+        cipher: CipherParams {
+            algorithm: CIPHER_ALGORITHM.to_string(),
+            nonce: BASE64.encode(nonce),
+        },
+        ciphertext: BASE64.encode(ciphertext),
+    };
+
+    println!("✓ Encrypted seed envelope for wallet {}", contents.fingerprint);
+    serde_json::to_string(&envelope).map_err(|e| SeedEnvelopeError::EncryptionFailed {
+        error_details: e.to_string(),
+    })
+}
+
+/// Decrypts an envelope created by `encrypt_seed_envelope`.
+pub fn decrypt_seed_envelope(envelope: &str, pin: &str) -> Result<SeedEnvelopeContents, SeedEnvelopeError> {
+    let envelope: SeedEnvelope = serde_json::from_str(envelope).map_err(|e| SeedEnvelopeError::InvalidEnvelope {
+        error_details: e.to_string(),
+    })?;
+    if envelope.version != ENVELOPE_VERSION {
+        return Err(SeedEnvelopeError::UnsupportedVersion {
+            error_details: format!("Envelope version {} is not supported, expected {}", envelope.version, ENVELOPE_VERSION),
+        });
+    }
+    if envelope.kdf.algorithm != KDF_ALGORITHM || envelope.cipher.algorithm != CIPHER_ALGORITHM {
+        return Err(SeedEnvelopeError::UnsupportedVersion {
+            error_details: format!("Unsupported algorithms {} and {}", envelope.kdf.algorithm, envelope.cipher.algorithm),
+        });
+    }
+    // Checked before any key derivation runs
+    let KdfParams { log_n, r, p, .. } = envelope.kdf;
+    if !(MIN_KDF_LOG_N..=MAX_KDF_LOG_N).contains(&log_n) || r != KDF_R || !(1..=MAX_KDF_P).contains(&p) {
+        return Err(SeedEnvelopeError::InvalidEnvelope {
+            error_details: "Key derivation parameters are out of range".to_string(),
+        });
+    }
+
+    let salt = decode_field(&envelope.kdf.salt, "salt")?;
+    let nonce = decode_field(&envelope.cipher.nonce, "nonce")?;
+    let ciphertext = decode_field(&envelope.ciphertext, "ciphertext")?;
+    if salt.len() != SALT_LENGTH || nonce.len() != NONCE_LENGTH || ciphertext.len() < TAG_LENGTH {
+        return Err(SeedEnvelopeError::InvalidEnvelope {
+            error_details: "Salt, nonce or ciphertext has the wrong length".to_string(),
+        });
+    }
+
+    let key = derive_key(pin, &salt, log_n, r, p).map_err(|e| SeedEnvelopeError::InvalidEnvelope {
+        error_details: format!("Key derivation failed: {}", e),
+    })?;
+    let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_LENGTH);
+    // Authentication fails both for a wrong PIN and for a modified file
+    let plaintext = decrypt_aead(Cipher::chacha20_poly1305(), &key, Some(&nonce), ASSOCIATED_DATA, ciphertext, tag)
+        .map_err(|_| SeedEnvelopeError::DecryptionFailed)?;
+    let contents: SeedEnvelopeContents = serde_json::from_slice(&plaintext).map_err(|e| SeedEnvelopeError::InvalidEnvelope {
+        error_details: e.to_string(),
+    })?;
+
+    if fingerprint(&contents.mnemonic, contents.passphrase.as_deref())? != contents.fingerprint {
+        return Err(SeedEnvelopeError::InvalidEnvelope {
+            error_details: "Fingerprint does not match the decrypted mnemonic".to_string(),
+        });
+    }
+
+    println!("✓ Decrypted seed envelope for wallet {}", contents.fingerprint);
+    Ok(contents)
+}
+
+fn derive_key(pin: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<[u8; KEY_LENGTH], ErrorStack> {
+    let n = 1u64 << log_n;
+    // scrypt needs 128 * r * (N + p) bytes, plus headroom for OpenSSL's bookkeeping
+    let max_memory = 128 * r as u64 * (n + p as u64) + 1024 * 1024;
+    let mut key = [0u8; KEY_LENGTH];
+    scrypt(pin.as_bytes(), salt, n, r as u64, p as u64, max_memory, &mut key)?;
+    Ok(key)
+}
+
+fn decode_field(value: &str, name: &str) -> Result<Vec<u8>, SeedEnvelopeError> {
+    BASE64.decode(value).map_err(|e| SeedEnvelopeError::InvalidEnvelope {
+        error_details: format!("Invalid {}: {}", name, e),
+    })
+}
+
+/// Master key fingerprint, which identifies the wallet without revealing it.
+fn fingerprint(mnemonic_phrase: &str, bip39_passphrase: Option<&str>) -> Result<String, SeedEnvelopeError> {
+    let master = keys::master_key(mnemonic_phrase, bip39_passphrase, bitcoin::Network::Bitcoin)
+        .map_err(|_| SeedEnvelopeError::InvalidMnemonic)?;
+    Ok(master.fingerprint(&Secp256k1::new()).to_string())
+}
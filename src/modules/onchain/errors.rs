@@ -13,4 +13,217 @@ pub enum AddressError {
     InvalidMnemonic,
     #[error("Address derivation failed")]
     AddressDerivationFailed,
-}
\ No newline at end of file
+    #[error("Invalid output descriptor")]
+    InvalidDescriptor,
+    #[error("Invalid extended public key")]
+    InvalidExtendedKey,
+    #[error("Invalid derivation path")]
+    InvalidDerivationPath,
+    #[error("Derivation path does not match the address type or network")]
+    DerivationPathMismatch,
+    #[error("Address type is not supported for this operation")]
+    UnsupportedAddressType,
+    #[error("Invalid multisig configuration")]
+    InvalidMultisig,
+    #[error("Requested length is out of range")]
+    InvalidLength,
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum DiscoveryError {
+    #[error("Chain backend error: {error_details}")]
+    BackendError {
+        error_details: String,
+    },
+    #[error("Invalid chain backend response: {error_details}")]
+    InvalidResponse {
+        error_details: String,
+    },
+    #[error("Invalid parameter: {error_details}")]
+    InvalidParameter {
+        error_details: String,
+    },
+    #[error("Address derivation failed: {error_details}")]
+    DerivationError {
+        error_details: String,
+    },
+}
+
+impl From<AddressError> for DiscoveryError {
+    fn from(error: AddressError) -> Self {
+        DiscoveryError::DerivationError {
+            error_details: error.to_string(),
+        }
+    }
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum TransactionError {
+    #[error("Insufficient funds: {available} sats available, {required} sats required")]
+    InsufficientFunds {
+        available: u64,
+        required: u64,
+    },
+    #[error("Invalid input: {error_details}")]
+    InvalidInput {
+        error_details: String,
+    },
+    #[error("Invalid output: {error_details}")]
+    InvalidOutput {
+        error_details: String,
+    },
+    #[error("Invalid fee rate: {error_details}")]
+    InvalidFeeRate {
+        error_details: String,
+    },
+    #[error("Address type is not supported for this operation")]
+    UnsupportedAddressType,
+    #[error("Invalid transaction: {error_details}")]
+    InvalidTransaction {
+        error_details: String,
+    },
+    #[error("Invalid PSBT: {error_details}")]
+    InvalidPsbt {
+        error_details: String,
+    },
+    #[error("Invalid mnemonic phrase")]
+    InvalidMnemonic,
+    #[error("Signing failed: {error_details}")]
+    SigningFailed {
+        error_details: String,
+    },
+}
+
+impl From<AddressError> for TransactionError {
+    fn from(error: AddressError) -> Self {
+        match error {
+            AddressError::InvalidMnemonic => TransactionError::InvalidMnemonic,
+            AddressError::UnsupportedAddressType => TransactionError::UnsupportedAddressType,
+            other => TransactionError::SigningFailed {
+                error_details: other.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum MessageSigningError {
+    #[error("Invalid address: {error_details}")]
+    InvalidAddress {
+        error_details: String,
+    },
+    #[error("Invalid signature: {error_details}")]
+    InvalidSignature {
+        error_details: String,
+    },
+    #[error("Unsupported signature format: {error_details}")]
+    UnsupportedFormat {
+        error_details: String,
+    },
+    #[error("Key derivation failed: {error_details}")]
+    KeyDerivationFailed {
+        error_details: String,
+    },
+    #[error("Signing failed: {error_details}")]
+    SigningFailed {
+        error_details: String,
+    },
+}
+
+impl From<AddressError> for MessageSigningError {
+    fn from(error: AddressError) -> Self {
+        MessageSigningError::KeyDerivationFailed {
+            error_details: error.to_string(),
+        }
+    }
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum Slip39Error {
+    #[error("Invalid mnemonic: {error_details}")]
+    InvalidMnemonic {
+        error_details: String,
+    },
+    #[error("Invalid share: {error_details}")]
+    InvalidShare {
+        error_details: String,
+    },
+    #[error("Invalid share checksum: {error_details}")]
+    InvalidChecksum {
+        error_details: String,
+    },
+    #[error("Invalid sharing parameters: {error_details}")]
+    InvalidParameters {
+        error_details: String,
+    },
+    #[error("Shares do not belong together: {error_details}")]
+    MismatchedShares {
+        error_details: String,
+    },
+    #[error("Not enough shares: {error_details}")]
+    InsufficientShares {
+        error_details: String,
+    },
+    #[error("Secret recovery failed: {error_details}")]
+    RecoveryFailed {
+        error_details: String,
+    },
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum SeedEnvelopeError {
+    #[error("Invalid mnemonic")]
+    InvalidMnemonic,
+    #[error("Invalid PIN: {error_details}")]
+    InvalidPin {
+        error_details: String,
+    },
+    #[error("Invalid envelope: {error_details}")]
+    InvalidEnvelope {
+        error_details: String,
+    },
+    #[error("Unsupported envelope: {error_details}")]
+    UnsupportedVersion {
+        error_details: String,
+    },
+    #[error("Decryption failed: wrong PIN or modified envelope")]
+    DecryptionFailed,
+    #[error("Encryption failed: {error_details}")]
+    EncryptionFailed {
+        error_details: String,
+    },
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum PayjoinError {
+    #[error("Invalid PSBT: {error_details}")]
+    InvalidPsbt {
+        error_details: String,
+    },
+    #[error("Invalid payjoin URL: {error_details}")]
+    InvalidUrl {
+        error_details: String,
+    },
+    #[error("Invalid parameter: {error_details}")]
+    InvalidParameter {
+        error_details: String,
+    },
+    #[error("Payjoin request failed: {error_details}")]
+    TransportError {
+        error_details: String,
+    },
+    #[error("Payjoin receiver rejected the request: {error_details}")]
+    ReceiverError {
+        error_details: String,
+    },
+    #[error("Invalid payjoin proposal: {error_details}")]
+    InvalidProposal {
+        error_details: String,
+    },
+}
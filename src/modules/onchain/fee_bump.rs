@@ -0,0 +1,256 @@
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::{Address, Network, Transaction, TxOut};
+use crate::onchain::TransactionError;
+use super::fees::{self, estimate_tx_weight};
+use super::psbt::{build_psbt, DEFAULT_MAX_FEE_RATE};
+use super::types::{AddressType, ChangeOutput, FeeBumpResult, TargetOutput, Utxo};
+
+/// Default incremental relay fee in sat/vB (BIP125 rule 4).
+pub const DEFAULT_INCREMENTAL_RELAY_FEE: f64 = 1.0;
+
+/// Minimum relay fee rate a CPFP child must pay on its own, in sat/vB.
+const MIN_RELAY_FEE_RATE: f64 = 1.0;
+
+/// Builds a BIP125 replacement for the unconfirmed `tx_hex` that pays
+/// `target_fee_rate` sat/vB by reducing the change output at `change_output_index`,
+/// whose key origin is recorded from `change_derivation_path`. The change output is
+/// dropped if it would fall below the dust limit.
+#[allow(clippy::too_many_arguments)]
+pub fn build_rbf_psbt(
+    tx_hex: &str,
+    utxos: Vec<Utxo>,
+    change_output_index: u32,
+    change_derivation_path: Option<&str>,
+    target_fee_rate: f64,
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    network: Network,
+    incremental_relay_fee: Option<f64>,
+) -> Result<FeeBumpResult, TransactionError> {
+    fees::check_fee_rate(target_fee_rate)?;
+    let incremental_relay_fee = incremental_relay_fee.unwrap_or(DEFAULT_INCREMENTAL_RELAY_FEE);
+    fees::check_fee_rate(incremental_relay_fee)?;
+
+    let tx = parse_signed_transaction(tx_hex)?;
+    if !tx.is_explicitly_rbf() {
+        return Err(TransactionError::InvalidInput {
+            error_details: "Transaction does not signal BIP125 replaceability".to_string(),
+        });
+    }
+
+    let utxos = spent_utxos(&tx, utxos)?;
+    let original_fee = transaction_fee(&tx, &utxos)?;
+    let original_fee_rate = original_fee as f64 / tx.vsize() as f64;
+    if target_fee_rate <= original_fee_rate {
+        return Err(TransactionError::InvalidFeeRate {
+            error_details: format!(
+                "Target fee rate must be higher than the original {:.2} sat/vB",
+                original_fee_rate
+            ),
+        });
+    }
+
+    let change_index = change_output_index as usize;
+    let change_value = tx
+        .output
+        .get(change_index)
+        .ok_or_else(|| TransactionError::InvalidOutput {
+            error_details: format!("Change output {} does not exist", change_output_index),
+        })?
+        .value
+        .to_sat();
+    let mut outputs = Vec::with_capacity(tx.output.len());
+    for (index, output) in tx.output.iter().enumerate() {
+        if index != change_index {
+            outputs.push(output_to_target(output, network)?);
+        }
+    }
+    let change_address = output_to_target(&tx.output[change_index], network)?.address;
+
+    let input_types: Vec<AddressType> = utxos.iter().map(|u| u.address_type).collect();
+    let mut output_types = output_types(&outputs)?;
+    let change_type = AddressType::from_address(&change_address)?;
+
+    // Keep the change output if it stays above the dust limit after paying the new fee
+    output_types.push(change_type);
+    let vsize = estimate_tx_weight(&input_types, &output_types)?.div_ceil(4);
+    let fee = replacement_fee(original_fee, vsize, target_fee_rate, incremental_relay_fee);
+    let available = original_fee + change_value;
+    let new_change = available.checked_sub(fee).filter(|change| *change >= fees::dust_limit(change_type));
+
+    let (change_output, fee, vsize) = match new_change {
+        Some(amount) => {
+            let derivation_path = change_derivation_path.map(str::to_string);
+            (Some(ChangeOutput { address: change_address, amount, derivation_path }), fee, vsize)
+        }
+        None => {
+            output_types.pop();
+            if output_types.is_empty() {
+                return Err(TransactionError::InsufficientFunds {
+                    available,
+                    required: fee,
+                });
+            }
+            // Without change everything left over goes to the fee
+            let vsize = estimate_tx_weight(&input_types, &output_types)?.div_ceil(4);
+            let required = replacement_fee(original_fee, vsize, target_fee_rate, incremental_relay_fee);
+            if available < required {
+                return Err(TransactionError::InsufficientFunds { available, required });
+            }
+            (None, available, vsize)
+        }
+    };
+
+    let fee_rate = fee as f64 / vsize as f64;
+    let max_fee_rate = fee_rate.max(DEFAULT_MAX_FEE_RATE);
+    let psbt = build_psbt(utxos, outputs, change_output, mnemonic_phrase, bip39_passphrase, network, true, Some(max_fee_rate))?;
+
+    println!("✓ Built RBF replacement paying {} sats ({:.2} sat/vB)", fee, fee_rate);
+    Ok(FeeBumpResult {
+        psbt: psbt.to_string(),
+        fee,
+        vsize,
+        fee_rate,
+        effective_fee_rate: fee_rate,
+    })
+}
+
+/// Builds a CPFP child spending output `output_index` of the unconfirmed parent
+/// `parent_tx_hex` to `destination_address`, paying enough that the parent and
+/// child together reach `target_fee_rate` sat/vB.
+#[allow(clippy::too_many_arguments)]
+pub fn build_cpfp_psbt(
+    parent_tx_hex: &str,
+    parent_utxos: Vec<Utxo>,
+    output_index: u32,
+    derivation_path: &str,
+    destination_address: &str,
+    target_fee_rate: f64,
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    network: Network,
+) -> Result<FeeBumpResult, TransactionError> {
+    fees::check_fee_rate(target_fee_rate)?;
+
+    let parent = parse_signed_transaction(parent_tx_hex)?;
+    let parent_utxos = spent_utxos(&parent, parent_utxos)?;
+    let parent_fee = transaction_fee(&parent, &parent_utxos)?;
+    let parent_vsize = parent.vsize() as u64;
+    let parent_fee_rate = parent_fee as f64 / parent_vsize as f64;
+    if target_fee_rate <= parent_fee_rate {
+        return Err(TransactionError::InvalidFeeRate {
+            error_details: format!("Parent already pays {:.2} sat/vB", parent_fee_rate),
+        });
+    }
+
+    let spent_output = parent.output.get(output_index as usize).ok_or_else(|| TransactionError::InvalidInput {
+        error_details: format!("Parent output {} does not exist", output_index),
+    })?;
+    let spent = output_to_target(spent_output, network)?;
+    let input_type = AddressType::from_address(&spent.address)?;
+    let destination_type = AddressType::from_address(destination_address)
+        .map_err(|_| TransactionError::InvalidOutput {
+            error_details: format!("Invalid address: {}", destination_address),
+        })?;
+
+    let vsize = estimate_tx_weight(&[input_type], &[destination_type])?.div_ceil(4);
+    let package_fee = fees::fee_for_vsize(parent_vsize + vsize, target_fee_rate);
+    let min_fee = fees::fee_for_vsize(vsize, MIN_RELAY_FEE_RATE);
+    let fee = package_fee.saturating_sub(parent_fee).max(min_fee);
+    let amount = spent
+        .amount
+        .checked_sub(fee)
+        .filter(|amount| *amount >= fees::dust_limit(destination_type))
+        .ok_or(TransactionError::InsufficientFunds {
+            available: spent.amount,
+            required: fee + fees::dust_limit(destination_type),
+        })?;
+
+    let utxo = Utxo {
+        txid: parent.compute_txid().to_string(),
+        vout: output_index,
+        value: spent.amount,
+        address: spent.address,
+        address_type: input_type,
+        derivation_path: Some(derivation_path.to_string()),
+        confirmations: 0,
+    };
+    let outputs = vec![TargetOutput { address: destination_address.to_string(), amount }];
+    // The child also pays for its parent, so its own rate may exceed the usual maximum
+    let fee_rate = fee as f64 / vsize as f64;
+    let max_fee_rate = fee_rate.max(DEFAULT_MAX_FEE_RATE);
+    let psbt = build_psbt(vec![utxo], outputs, None, mnemonic_phrase, bip39_passphrase, network, true, Some(max_fee_rate))?;
+    let effective_fee_rate = (parent_fee + fee) as f64 / (parent_vsize + vsize) as f64;
+
+    println!("✓ Built CPFP child paying {} sats, package rate {:.2} sat/vB", fee, effective_fee_rate);
+    Ok(FeeBumpResult {
+        psbt: psbt.to_string(),
+        fee,
+        vsize,
+        fee_rate,
+        effective_fee_rate,
+    })
+}
+
+/// Fee a replacement of `vsize` vbytes must pay: the target rate, but at least the
+/// original fee plus the incremental relay fee for its own size (BIP125 rules 3 and 4).
+fn replacement_fee(original_fee: u64, vsize: u64, target_fee_rate: f64, incremental_relay_fee: f64) -> u64 {
+    let target_fee = fees::fee_for_vsize(vsize, target_fee_rate);
+    let min_fee = original_fee + fees::fee_for_vsize(vsize, incremental_relay_fee);
+    target_fee.max(min_fee)
+}
+
+fn parse_signed_transaction(tx_hex: &str) -> Result<Transaction, TransactionError> {
+    let tx: Transaction = deserialize_hex(tx_hex.trim()).map_err(|e| TransactionError::InvalidInput {
+        error_details: format!("Invalid transaction: {}", e),
+    })?;
+    // The size of an unsigned transaction would understate its fee rate
+    if tx.input.iter().any(|input| input.script_sig.is_empty() && input.witness.is_empty()) {
+        return Err(TransactionError::InvalidInput {
+            error_details: "Transaction must be fully signed".to_string(),
+        });
+    }
+    Ok(tx)
+}
+
+/// Orders `utxos` to match the inputs of `tx`, failing if any input is missing.
+fn spent_utxos(tx: &Transaction, utxos: Vec<Utxo>) -> Result<Vec<Utxo>, TransactionError> {
+    tx.input
+        .iter()
+        .map(|input| {
+            let outpoint = input.previous_output;
+            utxos
+                .iter()
+                .find(|u| u.vout == outpoint.vout && u.txid == outpoint.txid.to_string())
+                .cloned()
+                .ok_or_else(|| TransactionError::InvalidInput {
+                    error_details: format!("Missing UTXO data for input {}", outpoint),
+                })
+        })
+        .collect()
+}
+
+fn transaction_fee(tx: &Transaction, utxos: &[Utxo]) -> Result<u64, TransactionError> {
+    let total_input: u64 = utxos.iter().map(|u| u.value).sum();
+    let total_output: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+    total_input.checked_sub(total_output).ok_or(TransactionError::InvalidInput {
+        error_details: "UTXO values are lower than the transaction outputs".to_string(),
+    })
+}
+
+fn output_to_target(output: &TxOut, network: Network) -> Result<TargetOutput, TransactionError> {
+    let address = Address::from_script(&output.script_pubkey, network).map_err(|_| TransactionError::InvalidOutput {
+        error_details: "Output script has no address form".to_string(),
+    })?;
+    Ok(TargetOutput {
+        address: address.to_string(),
+        amount: output.value.to_sat(),
+    })
+}
+
+fn output_types(outputs: &[TargetOutput]) -> Result<Vec<AddressType>, TransactionError> {
+    outputs
+        .iter()
+        .map(|o| AddressType::from_address(&o.address).map_err(TransactionError::from))
+        .collect()
+}
@@ -0,0 +1,135 @@
+use crate::onchain::TransactionError;
+use super::types::AddressType;
+
+/// Version, locktime and single-byte input/output counts, in weight units.
+pub(crate) const TX_OVERHEAD_WEIGHT: u64 = (4 + 4 + 1 + 1) * 4;
+/// Segwit marker and flag bytes, in weight units.
+pub(crate) const SEGWIT_MARKER_WEIGHT: u64 = 2;
+
+/// Outpoint, sequence and script length prefix, counted as non-witness data.
+const INPUT_BASE_SIZE: u64 = 32 + 4 + 4 + 1;
+
+/// Worst-case weight of an input spending `address_type`, assuming 72-byte
+/// ECDSA signatures, key-path Taproot spends and 2-of-3 multisig for P2WSH.
+pub(crate) fn input_weight(address_type: AddressType) -> Result<u64, TransactionError> {
+    // (non-witness bytes, witness bytes)
+    let (base, witness) = match address_type {
+        // scriptSig: <sig> <pubkey>
+        AddressType::P2PKH => (INPUT_BASE_SIZE + 1 + 72 + 1 + 33, 0),
+        // scriptSig: <0 <20-byte-hash>>, witness: <sig> <pubkey>
+        AddressType::P2SH => (INPUT_BASE_SIZE + 23, 1 + 1 + 72 + 1 + 33),
+        // witness: <sig> <pubkey>
+        AddressType::P2WPKH => (INPUT_BASE_SIZE, 1 + 1 + 72 + 1 + 33),
+        // witness: <> <sig> <sig> <2 <pk> <pk> <pk> 3 CHECKMULTISIG>
+        AddressType::P2WSH => (INPUT_BASE_SIZE, 1 + 1 + 2 * (1 + 72) + 1 + 105),
+        // witness: <schnorr sig>
+        AddressType::P2TR => (INPUT_BASE_SIZE, 1 + 1 + 64),
+        AddressType::Unknown => return Err(TransactionError::UnsupportedAddressType),
+    };
+
+    Ok(base * 4 + witness)
+}
+
+/// Weight of an output paying to `address_type`.
+pub(crate) fn output_weight(address_type: AddressType) -> Result<u64, TransactionError> {
+    let script_len = match address_type {
+        AddressType::P2PKH => 25,
+        AddressType::P2SH => 23,
+        AddressType::P2WPKH => 22,
+        AddressType::P2WSH | AddressType::P2TR => 34,
+        AddressType::Unknown => return Err(TransactionError::UnsupportedAddressType),
+    };
+
+    // value, script length prefix, script
+    Ok((8 + 1 + script_len) * 4)
+}
+
+/// Whether spending `address_type` requires the segwit serialization.
+pub(crate) fn is_segwit(address_type: AddressType) -> bool {
+    !matches!(address_type, AddressType::P2PKH | AddressType::Unknown)
+}
+
+/// Smallest output value relayed by default policy for `address_type`.
+pub(crate) fn dust_limit(address_type: AddressType) -> u64 {
+    match address_type {
+        AddressType::P2PKH => 546,
+        AddressType::P2SH => 540,
+        AddressType::P2WPKH => 294,
+        AddressType::P2WSH | AddressType::P2TR | AddressType::Unknown => 330,
+    }
+}
+
+/// Length of the CompactSize encoding of `n`.
+fn compact_size_len(n: usize) -> u64 {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x10000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+/// Estimated weight of a transaction spending `inputs` to `outputs`.
+pub(crate) fn estimate_tx_weight(inputs: &[AddressType], outputs: &[AddressType]) -> Result<u64, TransactionError> {
+    if inputs.is_empty() {
+        return Err(TransactionError::InvalidInput {
+            error_details: "At least one input is required".to_string(),
+        });
+    }
+    if outputs.is_empty() {
+        return Err(TransactionError::InvalidOutput {
+            error_details: "At least one output is required".to_string(),
+        });
+    }
+
+    let mut weight = (4 + 4 + compact_size_len(inputs.len()) + compact_size_len(outputs.len())) * 4;
+    let segwit = inputs.iter().any(|t| is_segwit(*t));
+    if segwit {
+        weight += SEGWIT_MARKER_WEIGHT;
+    }
+    for input in inputs {
+        weight += input_weight(*input)?;
+        // Legacy inputs still carry an empty witness stack in a segwit transaction
+        if segwit && !is_segwit(*input) {
+            weight += 1;
+        }
+    }
+    for output in outputs {
+        weight += output_weight(*output)?;
+    }
+
+    Ok(weight)
+}
+
+/// Estimated virtual size in vbytes of a transaction spending `inputs` to `outputs`.
+pub fn estimate_tx_vsize(inputs: &[AddressType], outputs: &[AddressType]) -> Result<u64, TransactionError> {
+    Ok(estimate_tx_weight(inputs, outputs)?.div_ceil(4))
+}
+
+/// Estimated fee in satoshis of a transaction spending `inputs` to `outputs` at
+/// `fee_rate` sat/vB.
+pub fn estimate_fee(inputs: &[AddressType], outputs: &[AddressType], fee_rate: f64) -> Result<u64, TransactionError> {
+    check_fee_rate(fee_rate)?;
+    let vsize = estimate_tx_vsize(inputs, outputs)?;
+    Ok(fee_for_vsize(vsize, fee_rate))
+}
+
+/// Fee in satoshis for `vsize` vbytes at `fee_rate` sat/vB, rounded up.
+pub(crate) fn fee_for_vsize(vsize: u64, fee_rate: f64) -> u64 {
+    (vsize as f64 * fee_rate).ceil() as u64
+}
+
+/// Fee in satoshis for `weight` weight units at `fee_rate` sat/vB, rounding the
+/// vsize up first like `estimate_fee`.
+pub(crate) fn fee_for_weight(weight: u64, fee_rate: f64) -> u64 {
+    fee_for_vsize(weight.div_ceil(4), fee_rate)
+}
+
+pub(crate) fn check_fee_rate(fee_rate: f64) -> Result<(), TransactionError> {
+    if !fee_rate.is_finite() || fee_rate < 0.0 {
+        return Err(TransactionError::InvalidFeeRate {
+            error_details: format!("Fee rate must be a non-negative number, got {}", fee_rate),
+        });
+    }
+    Ok(())
+}
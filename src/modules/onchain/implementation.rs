@@ -5,6 +5,11 @@ use crate::onchain::AddressError;
 use bitcoin::Network;
 use crate::onchain::types::{GetAddressResponse, GetAddressesResponse, WordCount};
 use super::types::{AddressType, ValidationResult};
+use super::keys;
+use super::discovery::{self, ChainSource};
+use super::types::AddressDiscoveryResult;
+use super::errors::DiscoveryError;
+use bitcoin::secp256k1::Secp256k1;
 
 pub struct BitcoinAddressValidator;
 
@@ -117,6 +122,158 @@ impl BitcoinAddressValidator {
 
         Ok(private_key)
     }
+
+    pub fn get_derivation_path(
+        address_type: AddressType,
+        network: Option<Network>,
+        account: Option<u32>,
+        is_change: Option<bool>,
+        index: Option<u32>,
+    ) -> Result<String, AddressError> {
+        let path = keys::address_path(
+            address_type,
+            network.unwrap_or(Network::Bitcoin),
+            account.unwrap_or(0),
+            is_change.unwrap_or(false),
+            index.unwrap_or(0),
+        )?;
+
+        Ok(format!("m/{}", path))
+    }
+
+    pub fn derive_address_for_type(
+        mnemonic_phrase: &str,
+        address_type: AddressType,
+        network: Option<Network>,
+        bip39_passphrase: Option<&str>,
+        account: Option<u32>,
+        is_change: Option<bool>,
+        index: Option<u32>,
+    ) -> Result<GetAddressResponse, AddressError> {
+        let mut addresses = Self::derive_addresses_for_type(
+            mnemonic_phrase,
+            address_type,
+            network,
+            bip39_passphrase,
+            account,
+            is_change,
+            index,
+            Some(1),
+        )?;
+
+        addresses.addresses.pop().ok_or(AddressError::AddressDerivationFailed)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn derive_addresses_for_type(
+        mnemonic_phrase: &str,
+        address_type: AddressType,
+        network: Option<Network>,
+        bip39_passphrase: Option<&str>,
+        account: Option<u32>,
+        is_change: Option<bool>,
+        start_index: Option<u32>,
+        count: Option<u32>,
+    ) -> Result<GetAddressesResponse, AddressError> {
+        let network = network.unwrap_or(Network::Bitcoin);
+        let account = account.unwrap_or(0);
+        let is_change = is_change.unwrap_or(false);
+        let start_index = start_index.unwrap_or(0);
+        let count = count.unwrap_or(1);
+
+        let secp = Secp256k1::new();
+        let master = keys::master_key(mnemonic_phrase, bip39_passphrase, network)?;
+        let addresses = (0..count)
+            .map(|offset| {
+                let index = start_index.checked_add(offset).ok_or(AddressError::AddressDerivationFailed)?;
+                let path = keys::address_path(address_type, network, account, is_change, index)?;
+                keys::derive_typed_address(&secp, &master, &path, address_type, network)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                println!("✗ Failed to derive {} addresses: {:?}", address_type.common_name(), e);
+                e
+            })?;
+
+        Ok(GetAddressesResponse { addresses })
+    }
+
+    pub fn derive_address_at_path(
+        mnemonic_phrase: &str,
+        derivation_path_str: &str,
+        address_type: AddressType,
+        network: Option<Network>,
+        bip39_passphrase: Option<&str>,
+    ) -> Result<GetAddressResponse, AddressError> {
+        let network = network.unwrap_or(Network::Bitcoin);
+        let path = keys::check_address_path(derivation_path_str, address_type, network)?;
+
+        let secp = Secp256k1::new();
+        let master = keys::master_key(mnemonic_phrase, bip39_passphrase, network)?;
+        keys::derive_typed_address(&secp, &master, &path, address_type, network)
+    }
+
+    /// Scans the receive and change chains of a mnemonic account until `gap_limit`
+    /// consecutive unused addresses are found on each.
+    pub async fn discover_addresses(
+        source: &dyn ChainSource,
+        mnemonic_phrase: &str,
+        derivation_path_str: Option<&str>,
+        network: Option<Network>,
+        bip39_passphrase: Option<&str>,
+        gap_limit: Option<u32>,
+    ) -> Result<AddressDiscoveryResult, DiscoveryError> {
+        discovery::discover_addresses(source, gap_limit, |is_change, start_index, count| {
+            Self::derive_bitcoin_addresses(
+                mnemonic_phrase,
+                derivation_path_str,
+                network,
+                bip39_passphrase,
+                Some(is_change),
+                Some(start_index),
+                Some(count),
+            )
+        }).await
+    }
+
+    /// Scans the receive and change chains of a watch-only descriptor until
+    /// `gap_limit` consecutive unused addresses are found on each.
+    pub async fn discover_descriptor_addresses(
+        source: &dyn ChainSource,
+        descriptor: &str,
+        network: Option<Network>,
+        gap_limit: Option<u32>,
+    ) -> Result<AddressDiscoveryResult, DiscoveryError> {
+        discovery::discover_addresses(source, gap_limit, |is_change, start_index, count| {
+            Self::derive_addresses_from_descriptor(
+                descriptor,
+                network,
+                Some(is_change),
+                Some(start_index),
+                Some(count),
+            )
+        }).await
+    }
+
+    pub fn derive_addresses_from_descriptor(
+        descriptor: &str,
+        network: Option<Network>,
+        is_change: Option<bool>,
+        start_index: Option<u32>,
+        count: Option<u32>,
+    ) -> Result<GetAddressesResponse, AddressError> {
+        super::descriptor::derive_addresses_from_descriptor(
+            descriptor,
+            network,
+            is_change,
+            start_index,
+            count,
+        )
+            .map_err(|e| {
+                println!("✗ Failed to derive addresses from descriptor: {:?}", e);
+                e
+            })
+    }
 }
 
 fn parse_address(address: &str) -> Result<Address<NetworkUnchecked>, AddressError> {
@@ -0,0 +1,147 @@
+use std::str::FromStr;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv};
+use bitcoin::secp256k1::{Secp256k1, Signing, Verification};
+use bitcoin::{CompressedPublicKey, Network};
+use bip39::{Language, Mnemonic};
+use crate::onchain::AddressError;
+use super::descriptor::script_address;
+use super::types::{AddressType, GetAddressResponse};
+
+/// BIP44 coin type for the given network.
+pub(crate) fn coin_type(network: Network) -> u32 {
+    match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    }
+}
+
+/// BIP43 purpose used for single-key accounts of `address_type`.
+pub(crate) fn purpose(address_type: AddressType) -> Result<u32, AddressError> {
+    match address_type {
+        AddressType::P2PKH => Ok(44),
+        AddressType::P2SH => Ok(49),
+        AddressType::P2WPKH => Ok(84),
+        AddressType::P2TR => Ok(86),
+        AddressType::P2WSH | AddressType::Unknown => Err(AddressError::UnsupportedAddressType),
+    }
+}
+
+/// Single-key address type implied by the BIP43 purpose of `path`.
+pub(crate) fn address_type_for_path(path: &str) -> Result<AddressType, AddressError> {
+    let path = DerivationPath::from_str(path).map_err(|_| AddressError::InvalidDerivationPath)?;
+    match path.as_ref().first() {
+        Some(ChildNumber::Hardened { index: 44 }) => Ok(AddressType::P2PKH),
+        Some(ChildNumber::Hardened { index: 49 }) => Ok(AddressType::P2SH),
+        Some(ChildNumber::Hardened { index: 84 }) => Ok(AddressType::P2WPKH),
+        Some(ChildNumber::Hardened { index: 86 }) => Ok(AddressType::P2TR),
+        _ => Err(AddressError::UnsupportedAddressType),
+    }
+}
+
+/// Parses a BIP39 mnemonic and returns the BIP32 master key for `network`.
+pub(crate) fn master_key(
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    network: Network,
+) -> Result<Xpriv, AddressError> {
+    let mnemonic = Mnemonic::parse_in(Language::English, mnemonic_phrase)
+        .map_err(|_| AddressError::InvalidMnemonic)?;
+    let seed = mnemonic.to_seed(bip39_passphrase.unwrap_or(""));
+    Xpriv::new_master(network, &seed).map_err(|_| AddressError::AddressDerivationFailed)
+}
+
+/// Derives the extended private key at `path` below `master`.
+pub(crate) fn derive_xpriv<C: Signing>(
+    secp: &Secp256k1<C>,
+    master: &Xpriv,
+    path: &DerivationPath,
+) -> Result<Xpriv, AddressError> {
+    master
+        .derive_priv(secp, path)
+        .map_err(|_| AddressError::AddressDerivationFailed)
+}
+
+/// Account-level path `m/purpose'/coin'/account'` for `address_type`.
+pub(crate) fn account_path(
+    address_type: AddressType,
+    network: Network,
+    account: u32,
+) -> Result<DerivationPath, AddressError> {
+    let hardened = |idx: u32| ChildNumber::from_hardened_idx(idx).map_err(|_| AddressError::InvalidDerivationPath);
+    Ok(DerivationPath::from(vec![
+        hardened(purpose(address_type)?)?,
+        hardened(coin_type(network))?,
+        hardened(account)?,
+    ]))
+}
+
+/// Full BIP44/49/84/86 path `m/purpose'/coin'/account'/change/index` for `address_type`.
+pub(crate) fn address_path(
+    address_type: AddressType,
+    network: Network,
+    account: u32,
+    is_change: bool,
+    index: u32,
+) -> Result<DerivationPath, AddressError> {
+    let normal = |idx: u32| ChildNumber::from_normal_idx(idx).map_err(|_| AddressError::InvalidDerivationPath);
+    Ok(account_path(address_type, network, account)?
+        .extend([normal(if is_change { 1 } else { 0 })?, normal(index)?]))
+}
+
+/// Parses `path` and checks it is a BIP44-style address path whose purpose and
+/// coin type agree with `address_type` and `network`.
+pub(crate) fn check_address_path(
+    path: &str,
+    address_type: AddressType,
+    network: Network,
+) -> Result<DerivationPath, AddressError> {
+    let path = DerivationPath::from_str(path).map_err(|_| AddressError::InvalidDerivationPath)?;
+    let steps: &[ChildNumber] = path.as_ref();
+    if steps.len() != 5 {
+        return Err(AddressError::InvalidDerivationPath);
+    }
+
+    let expected_purpose = ChildNumber::from_hardened_idx(purpose(address_type)?)
+        .map_err(|_| AddressError::InvalidDerivationPath)?;
+    if steps[0] != expected_purpose {
+        println!("✗ Purpose {} does not match address type {:?}", steps[0], address_type);
+        return Err(AddressError::DerivationPathMismatch);
+    }
+    let expected_coin = ChildNumber::from_hardened_idx(coin_type(network))
+        .map_err(|_| AddressError::InvalidDerivationPath)?;
+    if steps[1] != expected_coin {
+        println!("✗ Coin type {} does not match network {:?}", steps[1], network);
+        return Err(AddressError::DerivationPathMismatch);
+    }
+    if !steps[2].is_hardened() {
+        return Err(AddressError::InvalidDerivationPath);
+    }
+    match steps[3] {
+        ChildNumber::Normal { index: 0 } | ChildNumber::Normal { index: 1 } => {},
+        _ => return Err(AddressError::InvalidDerivationPath),
+    }
+    if !steps[4].is_normal() {
+        return Err(AddressError::InvalidDerivationPath);
+    }
+
+    Ok(path)
+}
+
+/// Derives the `address_type` address at `path` below `master`.
+pub(crate) fn derive_typed_address<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    master: &Xpriv,
+    path: &DerivationPath,
+    address_type: AddressType,
+    network: Network,
+) -> Result<GetAddressResponse, AddressError> {
+    let xpriv = derive_xpriv(secp, master, path)?;
+    let public_key = CompressedPublicKey(xpriv.private_key.public_key(secp));
+    let address = script_address(secp, address_type, &public_key, network)?;
+
+    Ok(GetAddressResponse {
+        address: address.to_string(),
+        path: format!("m/{}", path),
+        public_key: public_key.to_string(),
+    })
+}
@@ -0,0 +1,284 @@
+use std::str::FromStr;
+use bitcoin::absolute::LockTime;
+use bitcoin::base64::engine::general_purpose::STANDARD as BASE64;
+use bitcoin::base64::Engine;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::TapTweak;
+use bitcoin::opcodes::all::OP_RETURN;
+use bitcoin::opcodes::OP_0;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{ecdsa, Keypair, Message, Secp256k1, Signing, Verification, XOnlyPublicKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::sign_message::{signed_msg_hash, MessageSignature};
+use bitcoin::transaction::Version;
+use bitcoin::{Address, Amount, CompressedPublicKey, Network, OutPoint, PrivateKey, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use crate::onchain::MessageSigningError;
+use super::descriptor::script_address;
+use super::keys;
+use super::types::{AddressType, MessageSignatureFormat, SignedMessage};
+
+/// BIP137 header byte offsets, before adding the recovery id.
+const BIP137_P2PKH_COMPRESSED: u8 = 31;
+const BIP137_P2SH_P2WPKH: u8 = 35;
+const BIP137_P2WPKH: u8 = 39;
+
+/// Signs `message` with the key at `derivation_path`, whose purpose selects the address type.
+pub fn sign_message(
+    mnemonic_phrase: &str,
+    derivation_path: &str,
+    message: &str,
+    format: MessageSignatureFormat,
+    bip39_passphrase: Option<&str>,
+    network: Network,
+) -> Result<SignedMessage, MessageSigningError> {
+    let secp = Secp256k1::new();
+    let address_type = keys::address_type_for_path(derivation_path)?;
+    let path = keys::check_address_path(derivation_path, address_type, network)?;
+    let master = keys::master_key(mnemonic_phrase, bip39_passphrase, network)?;
+    let private_key = keys::derive_xpriv(&secp, &master, &path)?.to_priv();
+
+    let signed = sign_message_with_key(&secp, &private_key, address_type, message, format, network)?;
+    println!("✓ Signed message for {}", signed.address);
+    Ok(signed)
+}
+
+/// Signs `message` with `private_key` for its `address_type` address.
+pub(crate) fn sign_message_with_key<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    private_key: &PrivateKey,
+    address_type: AddressType,
+    message: &str,
+    format: MessageSignatureFormat,
+    network: Network,
+) -> Result<SignedMessage, MessageSigningError> {
+    let public_key = CompressedPublicKey::from_private_key(secp, private_key).map_err(|_| MessageSigningError::SigningFailed {
+        error_details: "Message signing requires a compressed key".to_string(),
+    })?;
+    let address = script_address(secp, address_type, &public_key, network)?;
+
+    let signature = match format {
+        MessageSignatureFormat::Bip137 => {
+            let header_base = match address_type {
+                AddressType::P2PKH => BIP137_P2PKH_COMPRESSED,
+                AddressType::P2SH => BIP137_P2SH_P2WPKH,
+                AddressType::P2WPKH => BIP137_P2WPKH,
+                _ => return Err(unsupported(address_type, format)),
+            };
+            let digest = Message::from_digest(signed_msg_hash(message).to_byte_array());
+            let (recovery_id, compact) = secp.sign_ecdsa_recoverable(&digest, &private_key.inner).serialize_compact();
+            let mut bytes = [0u8; 65];
+            bytes[0] = header_base + recovery_id.to_i32() as u8;
+            bytes[1..].copy_from_slice(&compact);
+            BASE64.encode(bytes)
+        }
+        MessageSignatureFormat::Bip322Simple => {
+            let to_spend = to_spend_transaction(&address.script_pubkey(), message);
+            let mut to_sign = to_sign_transaction(&to_spend);
+            let witness = match address_type {
+                AddressType::P2WPKH => {
+                    let sighash = SighashCache::new(&to_sign)
+                        .p2wpkh_signature_hash(0, &to_spend.output[0].script_pubkey, Amount::ZERO, EcdsaSighashType::All)
+                        .map_err(|e| MessageSigningError::SigningFailed { error_details: e.to_string() })?;
+                    let signature = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa_low_r(&Message::from(sighash), &private_key.inner));
+                    Witness::from_slice(&[signature.to_vec(), public_key.to_bytes().to_vec()])
+                }
+                AddressType::P2TR => {
+                    let sighash = SighashCache::new(&to_sign)
+                        .taproot_key_spend_signature_hash(0, &Prevouts::All(&to_spend.output), TapSighashType::Default)
+                        .map_err(|e| MessageSigningError::SigningFailed { error_details: e.to_string() })?;
+                    let keypair = Keypair::from_secret_key(secp, &private_key.inner).tap_tweak(secp, None).to_inner();
+                    let signature = secp.sign_schnorr_no_aux_rand(&Message::from(sighash), &keypair);
+                    Witness::from_slice(&[signature.as_ref().to_vec()])
+                }
+                _ => return Err(unsupported(address_type, format)),
+            };
+            to_sign.input[0].witness = witness;
+            BASE64.encode(serialize(&to_sign.input[0].witness))
+        }
+    };
+
+    Ok(SignedMessage {
+        address: address.to_string(),
+        signature,
+        format,
+    })
+}
+
+/// Verifies a BIP137 or BIP322 simple `signature` of `message` by `address`.
+/// Returns `false` for well-formed signatures that do not match.
+pub fn verify_message(address: &str, message: &str, signature: &str) -> Result<bool, MessageSigningError> {
+    let address = Address::from_str(address)
+        .map_err(|_| MessageSigningError::InvalidAddress { error_details: address.to_string() })?
+        .assume_checked();
+    let bytes = BASE64.decode(signature.trim()).map_err(|_| MessageSigningError::InvalidSignature {
+        error_details: "Signature is not valid base64".to_string(),
+    })?;
+
+    let secp = Secp256k1::verification_only();
+    let valid = match detect_format(&bytes) {
+        MessageSignatureFormat::Bip137 => verify_bip137(&secp, &address, message, &bytes)?,
+        MessageSignatureFormat::Bip322Simple => verify_bip322_simple(&secp, &address, message, &bytes)?,
+    };
+
+    println!("✓ Verified message signature for {}: {}", address, valid);
+    Ok(valid)
+}
+
+fn unsupported(address_type: AddressType, format: MessageSignatureFormat) -> MessageSigningError {
+    MessageSigningError::UnsupportedFormat {
+        error_details: format!("{:?} signatures are not supported for {:?} addresses", format, address_type),
+    }
+}
+
+/// BIP137 signatures are 65 bytes with a 27..=42 header; anything else is a BIP322 witness.
+fn detect_format(bytes: &[u8]) -> MessageSignatureFormat {
+    if bytes.len() == 65 && (27..=42).contains(&bytes[0]) {
+        MessageSignatureFormat::Bip137
+    } else {
+        MessageSignatureFormat::Bip322Simple
+    }
+}
+
+fn verify_bip137<C: Verification>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &str,
+    bytes: &[u8],
+) -> Result<bool, MessageSigningError> {
+    let header = bytes[0];
+    // Normalise the segwit headers to the compressed P2PKH range understood by `MessageSignature`
+    let mut normalised = [0u8; 65];
+    normalised.copy_from_slice(bytes);
+    if header >= BIP137_P2SH_P2WPKH {
+        normalised[0] = BIP137_P2PKH_COMPRESSED + (header - BIP137_P2PKH_COMPRESSED) % 4;
+    }
+    let signature = MessageSignature::from_slice(&normalised).map_err(|e| MessageSigningError::InvalidSignature {
+        error_details: e.to_string(),
+    })?;
+    let public_key = match signature.recover_pubkey(secp, signed_msg_hash(message)) {
+        Ok(public_key) => public_key,
+        Err(_) => return Ok(false),
+    };
+
+    if !public_key.compressed {
+        return Ok(address.script_pubkey() == ScriptBuf::new_p2pkh(&public_key.pubkey_hash()));
+    }
+    // Wallets disagree on headers for segwit addresses, so accept any single-key
+    // script of the recovered key that matches the address
+    let public_key = CompressedPublicKey(public_key.inner);
+    let candidates = [
+        ScriptBuf::new_p2pkh(&PublicKey::from(public_key).pubkey_hash()),
+        ScriptBuf::new_p2sh(&ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()).script_hash()),
+        ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()),
+    ];
+    Ok(candidates.contains(&address.script_pubkey()))
+}
+
+fn verify_bip322_simple<C: Verification>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &str,
+    bytes: &[u8],
+) -> Result<bool, MessageSigningError> {
+    let witness: Witness = deserialize(bytes).map_err(|_| MessageSigningError::InvalidSignature {
+        error_details: "Signature is neither BIP137 nor a BIP322 witness".to_string(),
+    })?;
+    let script_pubkey = address.script_pubkey();
+    let to_spend = to_spend_transaction(&script_pubkey, message);
+    let mut to_sign = to_sign_transaction(&to_spend);
+    to_sign.input[0].witness = witness.clone();
+    let mut cache = SighashCache::new(&to_sign);
+
+    match AddressType::from_script(&script_pubkey) {
+        AddressType::P2WPKH => {
+            if witness.len() != 2 {
+                return Ok(false);
+            }
+            let (Ok(signature), Ok(public_key)) = (
+                bitcoin::ecdsa::Signature::from_slice(&witness[0]),
+                CompressedPublicKey::from_slice(&witness[1]),
+            ) else {
+                return Ok(false);
+            };
+            if ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()) != script_pubkey || signature.sighash_type != EcdsaSighashType::All {
+                return Ok(false);
+            }
+            let sighash = cache
+                .p2wpkh_signature_hash(0, &script_pubkey, Amount::ZERO, EcdsaSighashType::All)
+                .map_err(|e| MessageSigningError::InvalidSignature { error_details: e.to_string() })?;
+            Ok(verify_ecdsa(secp, &Message::from(sighash), &signature.signature, &public_key))
+        }
+        AddressType::P2TR => {
+            if witness.len() != 1 {
+                return Ok(false);
+            }
+            let Ok(signature) = bitcoin::taproot::Signature::from_slice(&witness[0]) else {
+                return Ok(false);
+            };
+            if !matches!(signature.sighash_type, TapSighashType::Default | TapSighashType::All) {
+                return Ok(false);
+            }
+            let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).map_err(|e| MessageSigningError::InvalidAddress {
+                error_details: e.to_string(),
+            })?;
+            let sighash = cache
+                .taproot_key_spend_signature_hash(0, &Prevouts::All(&to_spend.output), signature.sighash_type)
+                .map_err(|e| MessageSigningError::InvalidSignature { error_details: e.to_string() })?;
+            Ok(secp.verify_schnorr(&signature.signature, &Message::from(sighash), &output_key).is_ok())
+        }
+        address_type => Err(unsupported(address_type, MessageSignatureFormat::Bip322Simple)),
+    }
+}
+
+fn verify_ecdsa<C: Verification>(
+    secp: &Secp256k1<C>,
+    message: &Message,
+    signature: &ecdsa::Signature,
+    public_key: &CompressedPublicKey,
+) -> bool {
+    secp.verify_ecdsa(message, signature, &public_key.0).is_ok()
+}
+
+/// BIP322 tagged hash of the message.
+fn message_hash(message: &str) -> [u8; 32] {
+    let tag = sha256::Hash::hash(b"BIP0322-signed-message");
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Virtual transaction committing to the message and the address script (BIP322 `to_spend`).
+fn to_spend_transaction(script_pubkey: &ScriptBuf, message: &str) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: Txid::all_zeros(), vout: 0xFFFF_FFFF },
+            script_sig: Builder::new().push_opcode(OP_0).push_slice(message_hash(message)).into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut { value: Amount::ZERO, script_pubkey: script_pubkey.clone() }],
+    }
+}
+
+/// Virtual transaction spending `to_spend`, whose witness is the signature (BIP322 `to_sign`).
+fn to_sign_transaction(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: to_spend.compute_txid(), vout: 0 },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
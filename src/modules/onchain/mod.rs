@@ -1,10 +1,40 @@
 mod implementation;
 mod types;
 mod errors;
+mod descriptor;
+mod keys;
+mod discovery;
+mod fees;
+mod coin_selection;
+mod psbt;
+mod fee_bump;
+mod message;
+mod transaction;
+mod bip85;
+mod slip39;
+mod slip39_wordlist;
+mod envelope;
+mod origin;
+mod multisig;
+mod payjoin;
 
 pub use implementation::BitcoinAddressValidator;
-pub use types::{AddressType, ValidationResult, WordCount, GetAddressResponse, GetAddressesResponse, Network};
-pub use errors::AddressError;
+pub use types::{AddressType, ValidationResult, WordCount, GetAddressResponse, GetAddressesResponse, Network, ChainBackend, AddressStats, DiscoveredAddress, AddressDiscoveryResult, Utxo, TargetOutput, CoinSelectionStrategy, CoinSelectionResult, ChangeOutput, SignPsbtResult, FeeBumpResult, MessageSignatureFormat, SignedMessage, DecodedTransaction, DecodedInput, DecodedOutput, InputWitnessType, OutputScriptType, Slip39Group, Slip39GroupShares, Slip39ShareInfo, SeedEnvelopeContents, MultisigScriptType, MultisigDescriptors, MultisigAddress, PayjoinParams};
+pub use errors::{AddressError, DiscoveryError, TransactionError, MessageSigningError, Slip39Error, SeedEnvelopeError, PayjoinError};
+pub use discovery::{ChainSource, EsploraChainSource, ElectrumChainSource, InMemoryChainSource, DEFAULT_GAP_LIMIT};
+pub use coin_selection::{select_coins, DEFAULT_LONG_TERM_FEE_RATE};
+pub use psbt::{build_psbt, sign_psbt, DEFAULT_MAX_FEE_RATE};
+pub use fees::{estimate_fee, estimate_tx_vsize};
+pub use fee_bump::{build_rbf_psbt, build_cpfp_psbt, DEFAULT_INCREMENTAL_RELAY_FEE};
+pub use message::{sign_message, verify_message};
+pub use transaction::decode_transaction;
+pub use bip85::{derive_bip85_mnemonic, derive_bip85_wif, derive_bip85_xprv, derive_bip85_hex};
+pub use slip39::{split_slip39_mnemonic, validate_slip39_share, combine_slip39_shares, DEFAULT_ITERATION_EXPONENT};
+pub use envelope::{encrypt_seed_envelope, decrypt_seed_envelope};
+pub use origin::{get_master_fingerprint, get_key_origin};
+pub(crate) use origin::parse_key_expression;
+pub use multisig::{create_multisig_descriptors, derive_multisig_addresses, get_multisig_redeem_script};
+pub use payjoin::{send_payjoin_request, PayjoinTransport, PayjoinResponse, HttpPayjoinTransport};
 
 #[cfg(test)]
 mod tests;
\ No newline at end of file
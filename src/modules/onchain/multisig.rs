@@ -0,0 +1,184 @@
+use bitcoin::hex::DisplayHex;
+use bitcoin::opcodes::all::OP_CHECKMULTISIG;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::{Address, CompressedPublicKey, Network, NetworkKind, PublicKey, ScriptBuf};
+use crate::modules::trezor::{HDNodePathType, HDNodeType, HDNodeTypeOrString, MultisigRedeemScriptType};
+use crate::onchain::AddressError;
+use super::descriptor::{descriptor_checksum, DescriptorKey};
+use super::origin::{format_key_origin, parse_key_expression};
+use super::types::{AddressType, MultisigAddress, MultisigDescriptors, MultisigScriptType};
+
+/// Most cosigners Trezor and standard P2WSH CHECKMULTISIG policy both support.
+const MAX_MULTISIG_KEYS: usize = 15;
+
+/// Trezor `pubkeys_order` value for BIP67 lexicographic ordering.
+const PUBKEYS_ORDER_LEXICOGRAPHIC: u8 = 1;
+
+/// A validated `threshold`-of-n set of cosigner keys.
+struct MultisigConfig {
+    threshold: u8,
+    keys: Vec<DescriptorKey>,
+}
+
+impl MultisigConfig {
+    fn parse(threshold: u32, keys: &[String], script_type: MultisigScriptType, network: Network) -> Result<Self, AddressError> {
+        if keys.is_empty() || keys.len() > MAX_MULTISIG_KEYS {
+            println!("✗ Multisig needs between 1 and {} keys, got {}", MAX_MULTISIG_KEYS, keys.len());
+            return Err(AddressError::InvalidMultisig);
+        }
+        if threshold == 0 || threshold as usize > keys.len() {
+            println!("✗ Multisig threshold {} is invalid for {} keys", threshold, keys.len());
+            return Err(AddressError::InvalidMultisig);
+        }
+
+        let keys = keys.iter().map(|key| parse_key_expression(key)).collect::<Result<Vec<_>, _>>()?;
+        for (i, key) in keys.iter().enumerate() {
+            if key.network_kind() != NetworkKind::from(network) {
+                return Err(AddressError::InvalidNetwork);
+            }
+            // SLIP-132 prefixes must be the multisig variant of the requested script (Zpub, Ypub)
+            let expected = match script_type {
+                MultisigScriptType::P2WSH => AddressType::P2WSH,
+                MultisigScriptType::P2SHP2WSH => AddressType::P2SH,
+            };
+            if key.implied_type.is_some_and(|implied| !key.multisig_prefix || implied != expected) {
+                println!("✗ Extended key {} has a prefix for a different script type", i + 1);
+                return Err(AddressError::InvalidMultisig);
+            }
+            if keys[..i].iter().any(|other| other.xpub == key.xpub) {
+                println!("✗ Extended key {} is used more than once", i + 1);
+                return Err(AddressError::InvalidMultisig);
+            }
+        }
+
+        Ok(MultisigConfig { threshold: threshold as u8, keys })
+    }
+
+    /// Cosigner public keys at `index`, sorted as in BIP67.
+    fn public_keys<C: Verification>(&self, secp: &Secp256k1<C>, is_change: bool, index: u32) -> Result<Vec<CompressedPublicKey>, AddressError> {
+        let mut public_keys = self
+            .keys
+            .iter()
+            .map(|key| key.derive(secp, is_change, index).map(|(public_key, _)| public_key))
+            .collect::<Result<Vec<_>, _>>()?;
+        public_keys.sort_by_key(|key| key.to_bytes());
+        Ok(public_keys)
+    }
+
+    fn witness_script(&self, public_keys: &[CompressedPublicKey]) -> ScriptBuf {
+        public_keys
+            .iter()
+            .fold(Builder::new().push_int(self.threshold as i64), |builder, key| builder.push_key(&PublicKey::from(*key)))
+            .push_int(public_keys.len() as i64)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script()
+    }
+
+    fn descriptor(&self, script_type: MultisigScriptType, chain: u32) -> Result<String, AddressError> {
+        let keys: Vec<String> = self
+            .keys
+            .iter()
+            .map(|key| {
+                let key_str = match &key.origin {
+                    Some((fingerprint, path)) => format_key_origin(*fingerprint, path, &key.xpub),
+                    None => key.xpub.to_string(),
+                };
+                format!("{}/{}/*", key_str, chain)
+            })
+            .collect();
+        let multi = format!("sortedmulti({},{})", self.threshold, keys.join(","));
+        let body = match script_type {
+            MultisigScriptType::P2WSH => format!("wsh({})", multi),
+            MultisigScriptType::P2SHP2WSH => format!("sh(wsh({}))", multi),
+        };
+        let checksum = descriptor_checksum(&body)?;
+        Ok(format!("{}#{}", body, checksum))
+    }
+}
+
+/// Builds the receive and change `sortedmulti` descriptors of a multisig wallet.
+/// Keys are `[fingerprint/path]xpub` key origins or bare extended public keys.
+pub fn create_multisig_descriptors(
+    threshold: u32,
+    keys: &[String],
+    script_type: MultisigScriptType,
+    network: Network,
+) -> Result<MultisigDescriptors, AddressError> {
+    let config = MultisigConfig::parse(threshold, keys, script_type, network)?;
+    Ok(MultisigDescriptors {
+        receive_descriptor: config.descriptor(script_type, 0)?,
+        change_descriptor: config.descriptor(script_type, 1)?,
+    })
+}
+
+/// Derives `count` addresses of a multisig wallet starting at `start_index`.
+#[allow(clippy::too_many_arguments)]
+pub fn derive_multisig_addresses(
+    threshold: u32,
+    keys: &[String],
+    script_type: MultisigScriptType,
+    network: Network,
+    is_change: bool,
+    start_index: u32,
+    count: u32,
+) -> Result<Vec<MultisigAddress>, AddressError> {
+    let config = MultisigConfig::parse(threshold, keys, script_type, network)?;
+    let secp = Secp256k1::verification_only();
+
+    (0..count)
+        .map(|offset| {
+            let index = start_index.checked_add(offset).ok_or(AddressError::AddressDerivationFailed)?;
+            let public_keys = config.public_keys(&secp, is_change, index)?;
+            let witness_script = config.witness_script(&public_keys);
+            let address = match script_type {
+                MultisigScriptType::P2WSH => Address::p2wsh(&witness_script, network),
+                MultisigScriptType::P2SHP2WSH => Address::p2shwsh(&witness_script, network),
+            };
+            Ok(MultisigAddress {
+                address: address.to_string(),
+                index,
+                is_change,
+                witness_script: witness_script.to_hex_string(),
+                public_keys: public_keys.iter().map(|key| key.to_bytes().to_lower_hex_string()).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Builds the Trezor `MultisigRedeemScriptType` for the multisig address at `index`,
+/// to pass as the `multisig` field of inputs and change outputs.
+pub fn get_multisig_redeem_script(
+    threshold: u32,
+    keys: &[String],
+    script_type: MultisigScriptType,
+    network: Network,
+    is_change: bool,
+    index: u32,
+) -> Result<MultisigRedeemScriptType, AddressError> {
+    let config = MultisigConfig::parse(threshold, keys, script_type, network)?;
+    let secp = Secp256k1::verification_only();
+    let chain = if is_change { 1 } else { 0 };
+
+    // Order the cosigners by their derived key so the pubkeys match the script
+    let mut cosigners = config
+        .keys
+        .iter()
+        .map(|key| key.derive(&secp, is_change, index).map(|(public_key, _)| (public_key.to_bytes(), key)))
+        .collect::<Result<Vec<_>, _>>()?;
+    cosigners.sort_by_key(|(public_key, _)| *public_key);
+
+    Ok(MultisigRedeemScriptType {
+        pubkeys: cosigners
+            .iter()
+            .map(|(_, key)| HDNodePathType {
+                node: HDNodeTypeOrString::Node(HDNodeType::from_xpub(&key.xpub)),
+                address_n: vec![chain, index],
+            })
+            .collect(),
+        signatures: vec![String::new(); cosigners.len()],
+        m: config.threshold as u32,
+        nodes: None,
+        pubkeys_order: Some(PUBKEYS_ORDER_LEXICOGRAPHIC),
+    })
+}
@@ -0,0 +1,56 @@
+use std::str::FromStr;
+use bitcoin::bip32::{DerivationPath, Fingerprint, Xpub};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::Network;
+use crate::onchain::AddressError;
+use super::descriptor::DescriptorKey;
+use super::keys;
+
+/// Returns the BIP32 master key fingerprint as 8 lowercase hex characters.
+pub fn get_master_fingerprint(mnemonic_phrase: &str, bip39_passphrase: Option<&str>) -> Result<String, AddressError> {
+    let master = keys::master_key(mnemonic_phrase, bip39_passphrase, Network::Bitcoin)?;
+    Ok(master.fingerprint(&Secp256k1::new()).to_string())
+}
+
+/// Returns the extended public key at `derivation_path` with its key origin,
+/// formatted as `[fingerprint/path]xpub` as used in output descriptors.
+pub fn get_key_origin(
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    derivation_path: &str,
+    network: Network,
+) -> Result<String, AddressError> {
+    let secp = Secp256k1::new();
+    let path = DerivationPath::from_str(derivation_path).map_err(|_| AddressError::InvalidDerivationPath)?;
+    let master = keys::master_key(mnemonic_phrase, bip39_passphrase, network)?;
+    let xpub = Xpub::from_priv(&secp, &keys::derive_xpriv(&secp, &master, &path)?);
+
+    Ok(format_key_origin(master.fingerprint(&secp), &path, &xpub))
+}
+
+pub(crate) fn format_key_origin(fingerprint: Fingerprint, path: &DerivationPath, xpub: &Xpub) -> String {
+    if path.is_empty() {
+        format!("[{}]{}", fingerprint, xpub)
+    } else {
+        format!("[{}/{}]{}", fingerprint, path.to_string().trim_start_matches("m/"), xpub)
+    }
+}
+
+/// Parses `[fingerprint/path]xpub` or a bare extended public key. SLIP-132
+/// prefixes (ypub, zpub, ...) are accepted and normalised.
+pub(crate) fn parse_key_expression(key: &str) -> Result<DescriptorKey, AddressError> {
+    let key = DescriptorKey::parse(key.trim())?;
+    if !key.steps.is_empty() {
+        return Err(AddressError::InvalidDescriptor);
+    }
+    if let Some((_, path)) = &key.origin {
+        // The origin path must lead to the key: its length is the key's depth and
+        // its last step the key's child number
+        if path.len() != key.xpub.depth as usize
+            || path.into_iter().last().is_some_and(|step| *step != key.xpub.child_number)
+        {
+            return Err(AddressError::DerivationPathMismatch);
+        }
+    }
+    Ok(key)
+}
@@ -0,0 +1,365 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+use async_trait::async_trait;
+use bitcoin::psbt::{Input, Output, Psbt};
+use bitcoin::{Address, Network, OutPoint};
+use serde::Deserialize;
+use url::Url;
+use crate::onchain::PayjoinError;
+use super::fees;
+use super::types::{AddressType, PayjoinParams};
+
+/// How long to wait for a proposal before the original transaction should be broadcast instead.
+const PAYJOIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// HTTP response returned by a payjoin receiver.
+#[derive(Debug, Clone)]
+pub struct PayjoinResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Delivers payjoin requests to a receiver endpoint.
+#[async_trait]
+pub trait PayjoinTransport: Send + Sync {
+    /// POSTs `body` to `url` as `text/plain`.
+    async fn post(&self, url: &str, body: String) -> Result<PayjoinResponse, PayjoinError>;
+}
+
+/// Payjoin transport over HTTP(S).
+pub struct HttpPayjoinTransport {
+    client: reqwest::Client,
+}
+
+impl HttpPayjoinTransport {
+    pub fn new() -> Result<Self, PayjoinError> {
+        let client = reqwest::Client::builder()
+            .timeout(PAYJOIN_TIMEOUT)
+            .build()
+            .map_err(|e| PayjoinError::TransportError { error_details: e.to_string() })?;
+
+        Ok(HttpPayjoinTransport { client })
+    }
+}
+
+#[async_trait]
+impl PayjoinTransport for HttpPayjoinTransport {
+    async fn post(&self, url: &str, body: String) -> Result<PayjoinResponse, PayjoinError> {
+        let response = self.client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| PayjoinError::TransportError { error_details: e.to_string() })?;
+
+        let status = response.status().as_u16();
+        let body = response.text().await.map_err(|e| PayjoinError::TransportError {
+            error_details: e.to_string(),
+        })?;
+        Ok(PayjoinResponse { status, body })
+    }
+}
+
+/// Well-known BIP78 error body.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReceiverErrorBody {
+    error_code: String,
+    message: Option<String>,
+}
+
+/// Sends the finalized `original_psbt` paying `payee_address` to the BIP78 endpoint
+/// at `payjoin_url` and returns the receiver's proposal once it passes the sender checks.
+/// Sender inputs of the proposal get their data from `unsigned_psbt`, the same
+/// transaction before signing, as finalization removed their key paths. The proposal
+/// can then be passed to `sign_psbt`.
+pub async fn send_payjoin_request(
+    transport: &dyn PayjoinTransport,
+    original_psbt: &str,
+    unsigned_psbt: &str,
+    payjoin_url: &str,
+    payee_address: &str,
+    params: &PayjoinParams,
+    network: Network,
+) -> Result<Psbt, PayjoinError> {
+    let original = Psbt::from_str(original_psbt.trim()).map_err(|e| PayjoinError::InvalidPsbt {
+        error_details: e.to_string(),
+    })?;
+    let unsigned = Psbt::from_str(unsigned_psbt.trim()).map_err(|e| PayjoinError::InvalidPsbt {
+        error_details: e.to_string(),
+    })?;
+    if unsigned.unsigned_tx != original.unsigned_tx {
+        return Err(PayjoinError::InvalidParameter {
+            error_details: "Unsigned PSBT is not the original transaction".to_string(),
+        });
+    }
+    let payee_script = Address::from_str(payee_address)
+        .ok()
+        .and_then(|address| address.require_network(network).ok())
+        .ok_or_else(|| PayjoinError::InvalidParameter {
+            error_details: format!("Invalid payee address: {}", payee_address),
+        })?
+        .script_pubkey();
+    let payment_index = original
+        .unsigned_tx
+        .output
+        .iter()
+        .position(|output| output.script_pubkey == payee_script)
+        .ok_or_else(|| PayjoinError::InvalidPsbt {
+            error_details: format!("Original transaction does not pay {}", payee_address),
+        })?;
+
+    let original_fee = check_original(&original, payment_index, params)?;
+    let url = request_url(payjoin_url, params)?;
+
+    let response = transport.post(url.as_str(), request_psbt(&original).to_string()).await?;
+    if !(200..300).contains(&response.status) {
+        return Err(receiver_error(&response));
+    }
+    let proposal = Psbt::from_str(response.body.trim()).map_err(|e| PayjoinError::InvalidProposal {
+        error_details: format!("Response is not a PSBT: {}", e),
+    })?;
+
+    let proposal = check_proposal(&original, &unsigned, proposal, payment_index, params, original_fee)?;
+    println!(
+        "✓ Received payjoin proposal with {} inputs and {} outputs",
+        proposal.inputs.len(),
+        proposal.outputs.len()
+    );
+    Ok(proposal)
+}
+
+/// Checks that the original is fully signed and the parameters fit it. Returns its fee.
+fn check_original(original: &Psbt, payment_index: usize, params: &PayjoinParams) -> Result<u64, PayjoinError> {
+    let invalid_psbt = |error_details: String| PayjoinError::InvalidPsbt { error_details };
+    for (index, input) in original.inputs.iter().enumerate() {
+        if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+            return Err(invalid_psbt(format!("Input {} is not finalized", index)));
+        }
+        original.spend_utxo(index).map_err(|_| invalid_psbt(format!("Input {} is missing its UTXO", index)))?;
+    }
+    let fee = original.fee().map_err(|e| invalid_psbt(e.to_string()))?;
+
+    let invalid_parameter = |error_details: String| PayjoinError::InvalidParameter { error_details };
+    match (params.additional_fee_output_index, params.max_additional_fee_contribution) {
+        (Some(index), Some(_)) => {
+            if index as usize >= original.unsigned_tx.output.len() || index as usize == payment_index {
+                return Err(invalid_parameter(format!("Output {} cannot pay the additional fee", index)));
+            }
+        }
+        (None, None) => {}
+        _ => {
+            return Err(invalid_parameter(
+                "Additional fee output index and maximum contribution must be set together".to_string(),
+            ));
+        }
+    }
+    if let Some(min_fee_rate) = params.min_fee_rate {
+        if !min_fee_rate.is_finite() || min_fee_rate < 0.0 {
+            return Err(invalid_parameter(format!("Invalid minimum fee rate: {}", min_fee_rate)));
+        }
+    }
+
+    Ok(fee.to_sat())
+}
+
+fn request_url(payjoin_url: &str, params: &PayjoinParams) -> Result<Url, PayjoinError> {
+    let mut url = Url::parse(payjoin_url).map_err(|e| PayjoinError::InvalidUrl {
+        error_details: e.to_string(),
+    })?;
+    let is_onion = url.host_str().is_some_and(|host| host.ends_with(".onion"));
+    if url.scheme() != "https" && !(url.scheme() == "http" && is_onion) {
+        return Err(PayjoinError::InvalidUrl {
+            error_details: "Payjoin endpoints must use https or a .onion host".to_string(),
+        });
+    }
+
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("v", "1");
+        if let (Some(index), Some(max_contribution)) = (params.additional_fee_output_index, params.max_additional_fee_contribution) {
+            query.append_pair("additionalfeeoutputindex", &index.to_string());
+            query.append_pair("maxadditionalfeecontribution", &max_contribution.to_string());
+        }
+        if let Some(min_fee_rate) = params.min_fee_rate {
+            query.append_pair("minfeerate", &min_fee_rate.to_string());
+        }
+        if params.disable_output_substitution {
+            query.append_pair("disableoutputsubstitution", "true");
+        }
+    }
+    Ok(url)
+}
+
+/// The original without output key paths or xpubs, which would reveal the sender's wallet.
+fn request_psbt(original: &Psbt) -> Psbt {
+    let mut psbt = original.clone();
+    psbt.xpub.clear();
+    psbt.outputs = vec![Output::default(); psbt.outputs.len()];
+    psbt
+}
+
+fn receiver_error(response: &PayjoinResponse) -> PayjoinError {
+    let error_details = match serde_json::from_str::<ReceiverErrorBody>(&response.body) {
+        Ok(ReceiverErrorBody { error_code, message: Some(message) }) => format!("{}: {}", error_code, message),
+        Ok(ReceiverErrorBody { error_code, message: None }) => error_code,
+        Err(_) => format!("HTTP status {}", response.status),
+    };
+    println!("✗ Payjoin receiver returned an error: {}", error_details);
+    PayjoinError::ReceiverError { error_details }
+}
+
+/// Runs the BIP78 sender checks on `proposal` and restores the sender's input
+/// and output data from `unsigned`, the original before signing.
+fn check_proposal(
+    original: &Psbt,
+    unsigned: &Psbt,
+    mut proposal: Psbt,
+    payment_index: usize,
+    params: &PayjoinParams,
+    original_fee: u64,
+) -> Result<Psbt, PayjoinError> {
+    let invalid = |error_details: String| PayjoinError::InvalidProposal { error_details };
+
+    if proposal.unsigned_tx.version != original.unsigned_tx.version
+        || proposal.unsigned_tx.lock_time != original.unsigned_tx.lock_time
+    {
+        return Err(invalid("Transaction version or locktime changed".to_string()));
+    }
+
+    let original_inputs: HashMap<OutPoint, usize> = original
+        .unsigned_tx
+        .input
+        .iter()
+        .enumerate()
+        .map(|(index, txin)| (txin.previous_output, index))
+        .collect();
+    let sender_types = (0..original.inputs.len())
+        .map(|index| original.spend_utxo(index).map(|txout| AddressType::from_script(&txout.script_pubkey)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| PayjoinError::InvalidPsbt { error_details: e.to_string() })?;
+    let common_type = sender_types.iter().all(|t| *t == sender_types[0]).then_some(sender_types[0]);
+    let sequence = original.unsigned_tx.input[0].sequence;
+
+    let mut sender_inputs = HashSet::new();
+    let mut receiver_inputs = 0u64;
+    for index in 0..proposal.inputs.len() {
+        let txin = &proposal.unsigned_tx.input[index];
+        let input = &proposal.inputs[index];
+        if let Some(&original_index) = original_inputs.get(&txin.previous_output) {
+            if txin.sequence != original.unsigned_tx.input[original_index].sequence {
+                return Err(invalid(format!("Sequence of sender input {} changed", index)));
+            }
+            if !input.partial_sigs.is_empty() || !input.bip32_derivation.is_empty() || !input.tap_key_origins.is_empty() {
+                return Err(invalid(format!("Sender input {} contains signatures or key paths", index)));
+            }
+            if !sender_inputs.insert(original_index) {
+                return Err(invalid(format!("Sender input {} is spent twice", original_index)));
+            }
+            proposal.inputs[index] = Input {
+                final_script_sig: None,
+                final_script_witness: None,
+                ..unsigned.inputs[original_index].clone()
+            };
+        } else {
+            if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+                return Err(invalid(format!("Receiver input {} is not finalized", index)));
+            }
+            if txin.sequence != sequence {
+                return Err(invalid(format!("Receiver input {} uses a different sequence", index)));
+            }
+            let spent = proposal
+                .spend_utxo(index)
+                .map_err(|_| invalid(format!("Receiver input {} is missing its UTXO", index)))?;
+            if common_type.is_some_and(|t| t != AddressType::from_script(&spent.script_pubkey)) {
+                return Err(invalid(format!("Receiver input {} uses a different script type", index)));
+            }
+            receiver_inputs += 1;
+        }
+    }
+    if sender_inputs.len() != original.inputs.len() {
+        return Err(invalid("Sender inputs are missing".to_string()));
+    }
+
+    if proposal.outputs.iter().any(|o| !o.bip32_derivation.is_empty() || !o.tap_key_origins.is_empty()) {
+        return Err(invalid("Outputs contain key paths".to_string()));
+    }
+
+    let fee_output = params.additional_fee_output_index.map(|index| index as usize);
+    let max_contribution = params.max_additional_fee_contribution.unwrap_or(0);
+    let mut matched = vec![false; proposal.outputs.len()];
+    let mut contribution = 0u64;
+    for (index, original_output) in original.unsigned_tx.output.iter().enumerate() {
+        if index == payment_index {
+            continue;
+        }
+        let position = (0..matched.len())
+            .find(|&i| !matched[i] && proposal.unsigned_tx.output[i].script_pubkey == original_output.script_pubkey)
+            .ok_or_else(|| invalid(format!("Sender output {} is missing", index)))?;
+        matched[position] = true;
+
+        let value = proposal.unsigned_tx.output[position].value;
+        if fee_output == Some(index) && value < original_output.value {
+            contribution = (original_output.value - value).to_sat();
+            if contribution > max_contribution {
+                return Err(invalid(format!(
+                    "Receiver takes {} sats for fees, more than the allowed {}",
+                    contribution, max_contribution
+                )));
+            }
+        } else if value != original_output.value {
+            return Err(invalid(format!("Value of sender output {} changed", index)));
+        }
+        proposal.outputs[position] = unsigned.outputs[index].clone();
+    }
+
+    if params.disable_output_substitution {
+        let payment = &original.unsigned_tx.output[payment_index];
+        let kept = proposal.unsigned_tx.output.iter().enumerate().any(|(i, output)| {
+            !matched[i] && output.script_pubkey == payment.script_pubkey && output.value >= payment.value
+        });
+        if !kept {
+            return Err(invalid("Payment output was substituted".to_string()));
+        }
+    }
+
+    let proposal_fee = proposal.fee().map_err(|e| invalid(e.to_string()))?.to_sat();
+    if contribution > 0 {
+        if contribution > proposal_fee.saturating_sub(original_fee) {
+            return Err(invalid(format!("Fee contribution of {} sats is not paying fees", contribution)));
+        }
+        // The sender only pays, at the original fee rate, for inputs of its own type
+        let original_vsize = original.clone().extract_tx_unchecked_fee_rate().vsize() as u64;
+        let input_weight = fees::input_weight(sender_types[0]).map_err(|e| PayjoinError::InvalidPsbt {
+            error_details: e.to_string(),
+        })?;
+        let allowed = fees::fee_for_weight(input_weight * receiver_inputs, original_fee as f64 / original_vsize as f64);
+        if contribution > allowed {
+            return Err(invalid(format!(
+                "Fee contribution of {} sats exceeds the {} sats the receiver inputs cost",
+                contribution, allowed
+            )));
+        }
+    }
+
+    if let Some(min_fee_rate) = params.min_fee_rate {
+        // The transaction with the sender's original scripts, to size the signed proposal
+        let mut signed_tx = proposal.unsigned_tx.clone();
+        for (index, txin) in signed_tx.input.iter_mut().enumerate() {
+            let input = match original_inputs.get(&txin.previous_output) {
+                Some(&original_index) => &original.inputs[original_index],
+                None => &proposal.inputs[index],
+            };
+            txin.script_sig = input.final_script_sig.clone().unwrap_or_default();
+            txin.witness = input.final_script_witness.clone().unwrap_or_default();
+        }
+        let fee_rate = proposal_fee as f64 / signed_tx.vsize() as f64;
+        if fee_rate < min_fee_rate {
+            return Err(invalid(format!("Fee rate of {:.2} sat/vB is below the minimum of {}", fee_rate, min_fee_rate)));
+        }
+    }
+
+    Ok(proposal)
+}
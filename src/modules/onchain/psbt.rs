@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use bitcoin::absolute::LockTime;
+use bitcoin::bip32::{DerivationPath, Fingerprint, KeySource, Xpriv};
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::psbt::{Input, Output, Psbt};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::secp256k1::{Secp256k1, Signing, Verification, XOnlyPublicKey};
+use bitcoin::transaction::Version;
+use bitcoin::{Address, Amount, CompressedPublicKey, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use crate::onchain::TransactionError;
+use super::descriptor::script_address;
+use super::fees;
+use super::keys;
+use super::types::{AddressType, ChangeOutput, SignPsbtResult, TargetOutput, Utxo};
+
+/// Highest fee rate `build_psbt` accepts when no maximum is given, in sat/vB.
+pub const DEFAULT_MAX_FEE_RATE: f64 = 1_000.0;
+
+/// Wallet key controlling an input or change output.
+struct WalletKey {
+    address_type: AddressType,
+    public_key: CompressedPublicKey,
+    origin: KeySource,
+}
+
+/// Builds an unsigned PSBT spending `utxos` to `outputs` plus an optional change
+/// output, with BIP32 derivation info for the mnemonic's master fingerprint.
+/// Fails if the inputs left over after the outputs pay more than `max_fee_rate`
+/// sat/vB (default `DEFAULT_MAX_FEE_RATE`), e.g. because the change was left out.
+#[allow(clippy::too_many_arguments)]
+pub fn build_psbt(
+    utxos: Vec<Utxo>,
+    outputs: Vec<TargetOutput>,
+    change_output: Option<ChangeOutput>,
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    network: Network,
+    enable_rbf: bool,
+    max_fee_rate: Option<f64>,
+) -> Result<Psbt, TransactionError> {
+    let max_fee_rate = max_fee_rate.unwrap_or(DEFAULT_MAX_FEE_RATE);
+    fees::check_fee_rate(max_fee_rate)?;
+    if utxos.is_empty() {
+        return Err(TransactionError::InvalidInput {
+            error_details: "At least one input is required".to_string(),
+        });
+    }
+    if outputs.is_empty() && change_output.is_none() {
+        return Err(TransactionError::InvalidOutput {
+            error_details: "At least one output is required".to_string(),
+        });
+    }
+
+    let secp = Secp256k1::new();
+    let master = keys::master_key(mnemonic_phrase, bip39_passphrase, network)?;
+    let fingerprint = master.fingerprint(&secp);
+
+    let sequence = if enable_rbf {
+        Sequence::ENABLE_RBF_NO_LOCKTIME
+    } else {
+        Sequence::ENABLE_LOCKTIME_NO_RBF
+    };
+
+    let mut tx_inputs = Vec::with_capacity(utxos.len());
+    let mut psbt_inputs = Vec::with_capacity(utxos.len());
+    let mut input_types = Vec::with_capacity(utxos.len());
+    let mut total_input = 0u64;
+    for utxo in &utxos {
+        let txid = Txid::from_str(&utxo.txid).map_err(|_| TransactionError::InvalidInput {
+            error_details: format!("Invalid txid: {}", utxo.txid),
+        })?;
+        let address = parse_address(&utxo.address, network).map_err(|error_details| TransactionError::InvalidInput { error_details })?;
+        let path = utxo.derivation_path.as_deref().ok_or_else(|| TransactionError::InvalidInput {
+            error_details: format!("Missing derivation path for {}:{}", utxo.txid, utxo.vout),
+        })?;
+        let key = wallet_key(&secp, &master, fingerprint, path, &address, network)
+            .map_err(|error_details| TransactionError::InvalidInput { error_details })?;
+
+        input_types.push(key.address_type);
+        total_input = total_input.checked_add(utxo.value).ok_or(TransactionError::InvalidInput {
+            error_details: "Input amounts overflow".to_string(),
+        })?;
+        tx_inputs.push(TxIn {
+            previous_output: OutPoint { txid, vout: utxo.vout },
+            script_sig: ScriptBuf::new(),
+            sequence,
+            witness: Witness::new(),
+        });
+        psbt_inputs.push(input_for_key(&key, TxOut {
+            value: Amount::from_sat(utxo.value),
+            script_pubkey: address.script_pubkey(),
+        }));
+    }
+
+    let mut tx_outputs = Vec::with_capacity(outputs.len() + 1);
+    let mut psbt_outputs = Vec::with_capacity(outputs.len() + 1);
+    let mut output_types = Vec::with_capacity(outputs.len() + 1);
+    let mut total_output = 0u64;
+    let change = change_output.map(|c| (TargetOutput { address: c.address, amount: c.amount }, c.derivation_path));
+    let all_outputs = outputs.into_iter().map(|o| (o, None)).chain(change);
+    for (output, derivation_path) in all_outputs {
+        let address = parse_address(&output.address, network).map_err(|error_details| TransactionError::InvalidOutput { error_details })?;
+        let address_type = AddressType::from_script(&address.script_pubkey());
+        if address_type == AddressType::Unknown {
+            return Err(TransactionError::InvalidOutput {
+                error_details: format!("Unsupported output address: {}", output.address),
+            });
+        }
+        if output.amount < fees::dust_limit(address_type) {
+            return Err(TransactionError::InvalidOutput {
+                error_details: format!("Output to {} is below the dust limit", output.address),
+            });
+        }
+        output_types.push(address_type);
+        total_output = total_output.checked_add(output.amount).ok_or(TransactionError::InvalidOutput {
+            error_details: "Output amounts overflow".to_string(),
+        })?;
+
+        let psbt_output = match derivation_path {
+            Some(path) => {
+                let key = wallet_key(&secp, &master, fingerprint, &path, &address, network)
+                    .map_err(|error_details| TransactionError::InvalidOutput { error_details })?;
+                output_for_key(&key)
+            }
+            None => Output::default(),
+        };
+        tx_outputs.push(TxOut {
+            value: Amount::from_sat(output.amount),
+            script_pubkey: address.script_pubkey(),
+        });
+        psbt_outputs.push(psbt_output);
+    }
+
+    if total_input < total_output {
+        return Err(TransactionError::InsufficientFunds {
+            available: total_input,
+            required: total_output,
+        });
+    }
+    let fee = total_input - total_output;
+    let vsize = fees::estimate_tx_vsize(&input_types, &output_types)?;
+    let fee_rate = fee as f64 / vsize as f64;
+    if fee_rate > max_fee_rate {
+        return Err(TransactionError::InvalidFeeRate {
+            error_details: format!(
+                "Fee of {} sats ({:.2} sat/vB) exceeds the maximum of {} sat/vB",
+                fee, fee_rate, max_fee_rate
+            ),
+        });
+    }
+
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: tx_inputs,
+        output: tx_outputs,
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx).map_err(|e| TransactionError::InvalidPsbt {
+        error_details: e.to_string(),
+    })?;
+    psbt.inputs = psbt_inputs;
+    psbt.outputs = psbt_outputs;
+
+    println!("✓ Built PSBT with {} inputs and {} outputs", psbt.inputs.len(), psbt.outputs.len());
+    Ok(psbt)
+}
+
+/// Signs every P2WPKH, P2SH-P2WPKH and P2TR key-path input whose BIP32 derivation
+/// info matches the mnemonic's master fingerprint, then finalizes the signed inputs.
+pub fn sign_psbt(
+    psbt: &str,
+    mnemonic_phrase: &str,
+    bip39_passphrase: Option<&str>,
+    network: Network,
+) -> Result<SignPsbtResult, TransactionError> {
+    let mut psbt = Psbt::from_str(psbt.trim()).map_err(|e| TransactionError::InvalidPsbt {
+        error_details: e.to_string(),
+    })?;
+
+    let secp = Secp256k1::new();
+    let master = keys::master_key(mnemonic_phrase, bip39_passphrase, network)?;
+
+    let signing_keys = match psbt.sign(&master, &secp) {
+        Ok(signing_keys) => signing_keys,
+        Err((_, errors)) => {
+            let details = errors
+                .iter()
+                .map(|(index, error)| format!("input {}: {}", index, error))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("✗ Failed to sign PSBT: {}", details);
+            return Err(TransactionError::SigningFailed { error_details: details });
+        }
+    };
+    let signed_inputs: Vec<u32> = signing_keys
+        .iter()
+        .filter(|(_, keys)| match keys {
+            bitcoin::psbt::SigningKeys::Ecdsa(keys) => !keys.is_empty(),
+            bitcoin::psbt::SigningKeys::Schnorr(keys) => !keys.is_empty(),
+        })
+        .map(|(index, _)| *index as u32)
+        .collect();
+
+    let mut is_finalized = true;
+    for index in 0..psbt.inputs.len() {
+        let spent_script = psbt.spend_utxo(index).map(|txout| txout.script_pubkey.clone());
+        let finalized = match spent_script {
+            Ok(script) => finalize_input(&mut psbt.inputs[index], &script)?,
+            Err(_) => false,
+        };
+        is_finalized &= finalized;
+    }
+
+    let (tx_hex, txid) = if is_finalized {
+        let tx = psbt.clone().extract_tx().map_err(|e| TransactionError::SigningFailed {
+            error_details: e.to_string(),
+        })?;
+        (Some(serialize_hex(&tx)), Some(tx.compute_txid().to_string()))
+    } else {
+        (None, None)
+    };
+
+    println!("✓ Signed {} PSBT inputs, finalized: {}", signed_inputs.len(), is_finalized);
+    Ok(SignPsbtResult {
+        psbt: psbt.to_string(),
+        signed_inputs,
+        is_finalized,
+        tx_hex,
+        txid,
+    })
+}
+
+fn parse_address(address: &str, network: Network) -> Result<Address, String> {
+    Address::from_str(address)
+        .map_err(|_| format!("Invalid address: {}", address))?
+        .require_network(network)
+        .map_err(|_| format!("Address {} is not valid for {}", address, network))
+}
+
+/// Derives the key at `path` and checks that it controls `address`.
+fn wallet_key<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    master: &Xpriv,
+    fingerprint: Fingerprint,
+    path: &str,
+    address: &Address,
+    network: Network,
+) -> Result<WalletKey, String> {
+    let address_type = AddressType::from_script(&address.script_pubkey());
+    if !matches!(address_type, AddressType::P2WPKH | AddressType::P2SH | AddressType::P2TR) {
+        return Err(format!("Address {} is not a P2WPKH, P2SH-P2WPKH or P2TR address", address));
+    }
+
+    let derivation_path = DerivationPath::from_str(path).map_err(|_| format!("Invalid derivation path: {}", path))?;
+    let xpriv = keys::derive_xpriv(secp, master, &derivation_path).map_err(|e| e.to_string())?;
+    let public_key = CompressedPublicKey(xpriv.private_key.public_key(secp));
+    let derived = script_address(secp, address_type, &public_key, network).map_err(|e| e.to_string())?;
+    if &derived != address {
+        return Err(format!("Key at {} does not control {}", path, address));
+    }
+
+    Ok(WalletKey {
+        address_type,
+        public_key,
+        origin: (fingerprint, derivation_path),
+    })
+}
+
+fn input_for_key(key: &WalletKey, witness_utxo: TxOut) -> Input {
+    let mut input = Input {
+        witness_utxo: Some(witness_utxo),
+        ..Default::default()
+    };
+    match key.address_type {
+        AddressType::P2TR => {
+            let internal_key = XOnlyPublicKey::from(key.public_key.0);
+            input.tap_internal_key = Some(internal_key);
+            input.tap_key_origins.insert(internal_key, (vec![], key.origin.clone()));
+        }
+        address_type => {
+            if address_type == AddressType::P2SH {
+                input.redeem_script = Some(ScriptBuf::new_p2wpkh(&key.public_key.wpubkey_hash()));
+            }
+            input.bip32_derivation.insert(key.public_key.0, key.origin.clone());
+        }
+    }
+    input
+}
+
+fn output_for_key(key: &WalletKey) -> Output {
+    let mut output = Output::default();
+    match key.address_type {
+        AddressType::P2TR => {
+            let internal_key = XOnlyPublicKey::from(key.public_key.0);
+            output.tap_internal_key = Some(internal_key);
+            output.tap_key_origins.insert(internal_key, (vec![], key.origin.clone()));
+        }
+        address_type => {
+            if address_type == AddressType::P2SH {
+                output.redeem_script = Some(ScriptBuf::new_p2wpkh(&key.public_key.wpubkey_hash()));
+            }
+            output.bip32_derivation = BTreeMap::from([(key.public_key.0, key.origin.clone())]);
+        }
+    }
+    output
+}
+
+/// Builds the final scriptSig and witness for a signed single-key input and
+/// clears the fields BIP174 finalizers remove. Returns whether the input is final.
+fn finalize_input(input: &mut Input, spent_script: &ScriptBuf) -> Result<bool, TransactionError> {
+    if input.final_script_witness.is_some() || input.final_script_sig.is_some() {
+        return Ok(true);
+    }
+
+    let (script_sig, witness) = if spent_script.is_p2tr() {
+        match input.tap_key_sig {
+            Some(signature) => (None, Witness::from_slice(&[signature.to_vec()])),
+            None => return Ok(false),
+        }
+    } else {
+        let Some((public_key, signature)) = input.partial_sigs.iter().next() else {
+            return Ok(false);
+        };
+        let witness = Witness::from_slice(&[signature.to_vec(), public_key.to_bytes()]);
+        if spent_script.is_p2wpkh() {
+            (None, witness)
+        } else if spent_script.is_p2sh() {
+            let redeem_script = input
+                .redeem_script
+                .as_ref()
+                .filter(|script| script.is_p2wpkh())
+                .ok_or_else(|| TransactionError::InvalidPsbt {
+                    error_details: "P2SH input is missing a P2WPKH redeem script".to_string(),
+                })?;
+            let push = PushBytesBuf::try_from(redeem_script.to_bytes()).map_err(|e| TransactionError::InvalidPsbt {
+                error_details: e.to_string(),
+            })?;
+            (Some(Builder::new().push_slice(push).into_script()), witness)
+        } else {
+            return Ok(false);
+        }
+    };
+
+    *input = Input {
+        non_witness_utxo: input.non_witness_utxo.take(),
+        witness_utxo: input.witness_utxo.take(),
+        final_script_sig: script_sig,
+        final_script_witness: Some(witness),
+        proprietary: std::mem::take(&mut input.proprietary),
+        unknown: std::mem::take(&mut input.unknown),
+        ..Default::default()
+    };
+    Ok(true)
+}
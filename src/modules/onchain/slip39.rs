@@ -0,0 +1,618 @@
+//! SLIP-39 shares of an existing BIP39 wallet.
+//!
+//! The shared secret is the BIP39 entropy, and recovery gives back the BIP39
+//! mnemonic. SLIP-39 wallets such as Trezor instead use the master secret itself
+//! as the wallet seed, so these shares recover a different wallet there. Restore
+//! them with `combine_slip39_shares`, not on a SLIP-39 device.
+
+use std::collections::BTreeMap;
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bip39::{Language, Mnemonic};
+use rand::{Rng, RngCore};
+use crate::onchain::Slip39Error;
+use super::slip39_wordlist::WORDLIST;
+use super::types::{Slip39Group, Slip39GroupShares, Slip39ShareInfo};
+
+/// Bits encoded by one word.
+const RADIX_BITS: usize = 10;
+/// Words holding the identifier, extendable flag and iteration exponent.
+const ID_EXP_LENGTH_WORDS: usize = 2;
+/// Words holding the group and member parameters.
+const SHARE_PARAMS_LENGTH_WORDS: usize = 2;
+/// Words holding the RS1024 checksum.
+const CHECKSUM_LENGTH_WORDS: usize = 3;
+const METADATA_LENGTH_WORDS: usize = ID_EXP_LENGTH_WORDS + SHARE_PARAMS_LENGTH_WORDS + CHECKSUM_LENGTH_WORDS;
+/// Shortest share, holding a 128-bit secret.
+const MIN_MNEMONIC_LENGTH_WORDS: usize = METADATA_LENGTH_WORDS + (MIN_STRENGTH_BITS as usize).div_ceil(RADIX_BITS);
+const MIN_STRENGTH_BITS: u32 = 128;
+
+const ID_LENGTH_BITS: u32 = 15;
+const MAX_ITERATION_EXPONENT: u8 = 15;
+const MAX_SHARE_COUNT: u8 = 16;
+
+const BASE_ITERATION_COUNT: u32 = 10000;
+const ROUND_COUNT: u8 = 4;
+
+/// x-coordinates of the secret and of its digest in the sharing polynomial.
+const SECRET_INDEX: u8 = 255;
+const DIGEST_INDEX: u8 = 254;
+const DIGEST_LENGTH_BYTES: usize = 4;
+
+const CUSTOMIZATION_STRING_ORIG: &[u8] = b"shamir";
+const CUSTOMIZATION_STRING_EXTENDABLE: &[u8] = b"shamir_extendable";
+
+/// Default passphrase stretching exponent, as used by Trezor.
+pub const DEFAULT_ITERATION_EXPONENT: u8 = 1;
+
+/// A decoded SLIP-39 share.
+#[derive(Debug, Clone, PartialEq)]
+struct Share {
+    identifier: u16,
+    extendable: bool,
+    iteration_exponent: u8,
+    group_index: u8,
+    group_threshold: u8,
+    group_count: u8,
+    member_index: u8,
+    member_threshold: u8,
+    value: Vec<u8>,
+}
+
+/// Splits the entropy of a BIP39 mnemonic into SLIP-39 shares. Any `group_threshold`
+/// of the `groups` recover the secret, each group needing its own member threshold.
+/// The shares are not compatible with SLIP-39 wallets, see the module docs.
+pub fn split_slip39_mnemonic(
+    mnemonic_phrase: &str,
+    group_threshold: u8,
+    groups: &[Slip39Group],
+    passphrase: Option<&str>,
+    iteration_exponent: u8,
+    extendable: bool,
+) -> Result<Vec<Slip39GroupShares>, Slip39Error> {
+    let mnemonic = Mnemonic::parse_in(Language::English, mnemonic_phrase).map_err(|e| Slip39Error::InvalidMnemonic {
+        error_details: e.to_string(),
+    })?;
+    let passphrase = check_passphrase(passphrase)?;
+    if iteration_exponent > MAX_ITERATION_EXPONENT {
+        return Err(Slip39Error::InvalidParameters {
+            error_details: format!("Iteration exponent must be at most {}", MAX_ITERATION_EXPONENT),
+        });
+    }
+
+    let mut rng = rand::thread_rng();
+    let identifier = rng.gen_range(0..1u16 << ID_LENGTH_BITS);
+    let master_secret = mnemonic.to_entropy();
+    let encrypted = encrypt(&master_secret, passphrase, iteration_exponent, identifier, extendable);
+    let group_shares = split_encrypted_secret(&mut rng, group_threshold, groups, &encrypted)?;
+
+    let result = groups
+        .iter()
+        .zip(group_shares)
+        .map(|(group, (group_index, members))| Slip39GroupShares {
+            member_threshold: group.member_threshold,
+            shares: members
+                .into_iter()
+                .map(|(member_index, value)| {
+                    encode_share(&Share {
+                        identifier,
+                        extendable,
+                        iteration_exponent,
+                        group_index,
+                        group_threshold,
+                        group_count: groups.len() as u8,
+                        member_index,
+                        member_threshold: group.member_threshold,
+                        value,
+                    })
+                })
+                .collect(),
+        })
+        .collect();
+
+    println!("✓ Split mnemonic into {} SLIP-39 groups, {} required", groups.len(), group_threshold);
+    Ok(result)
+}
+
+/// Decodes a single share, checking its words, padding and checksum.
+pub fn validate_slip39_share(share: &str) -> Result<Slip39ShareInfo, Slip39Error> {
+    let decoded = decode_share(share)?;
+    Ok(Slip39ShareInfo {
+        identifier: decoded.identifier,
+        extendable: decoded.extendable,
+        iteration_exponent: decoded.iteration_exponent,
+        group_index: decoded.group_index,
+        group_threshold: decoded.group_threshold,
+        group_count: decoded.group_count,
+        member_index: decoded.member_index,
+        member_threshold: decoded.member_threshold,
+        word_count: share.split_whitespace().count() as u32,
+    })
+}
+
+/// Recombines SLIP-39 shares into the BIP39 mnemonic they were split from. Shares
+/// created by a SLIP-39 wallet such as Trezor decode to a mnemonic of a different wallet.
+pub fn combine_slip39_shares(shares: &[String], passphrase: Option<&str>) -> Result<String, Slip39Error> {
+    let master_secret = combine_master_secret(shares, passphrase)?;
+    let mnemonic = Mnemonic::from_entropy_in(Language::English, &master_secret).map_err(|e| Slip39Error::RecoveryFailed {
+        error_details: format!("Recovered secret is not a valid BIP39 entropy: {}", e),
+    })?;
+
+    println!("✓ Recovered {}-word mnemonic from {} SLIP-39 shares", mnemonic.word_count(), shares.len());
+    Ok(mnemonic.to_string())
+}
+
+/// Recombines SLIP-39 shares into the raw master secret.
+pub(crate) fn combine_master_secret(shares: &[String], passphrase: Option<&str>) -> Result<Vec<u8>, Slip39Error> {
+    let passphrase = check_passphrase(passphrase)?;
+    let shares = shares.iter().map(|s| decode_share(s)).collect::<Result<Vec<_>, _>>()?;
+    let first = shares.first().ok_or_else(|| Slip39Error::InsufficientShares {
+        error_details: "No shares provided".to_string(),
+    })?;
+
+    for share in &shares {
+        if share.identifier != first.identifier
+            || share.extendable != first.extendable
+            || share.iteration_exponent != first.iteration_exponent
+        {
+            return Err(Slip39Error::MismatchedShares {
+                error_details: "Shares have different identifiers or iteration exponents".to_string(),
+            });
+        }
+        if share.group_threshold != first.group_threshold || share.group_count != first.group_count {
+            return Err(Slip39Error::MismatchedShares {
+                error_details: "Shares have different group thresholds or group counts".to_string(),
+            });
+        }
+        if share.value.len() != first.value.len() {
+            return Err(Slip39Error::MismatchedShares {
+                error_details: "Shares have different lengths".to_string(),
+            });
+        }
+    }
+
+    let mut groups: BTreeMap<u8, Vec<&Share>> = BTreeMap::new();
+    for share in &shares {
+        let members = groups.entry(share.group_index).or_default();
+        if members.iter().any(|m| m.member_threshold != share.member_threshold) {
+            return Err(Slip39Error::MismatchedShares {
+                error_details: format!("Shares of group {} have different member thresholds", share.group_index + 1),
+            });
+        }
+        match members.iter().find(|m| m.member_index == share.member_index) {
+            Some(existing) if existing.value != share.value => {
+                return Err(Slip39Error::MismatchedShares {
+                    error_details: format!(
+                        "Group {} contains two different shares with member index {}",
+                        share.group_index + 1,
+                        share.member_index + 1
+                    ),
+                });
+            }
+            Some(_) => {}
+            None => members.push(share),
+        }
+    }
+
+    let complete: Vec<(u8, &Vec<&Share>)> = groups
+        .iter()
+        .filter(|(_, members)| members.len() >= members[0].member_threshold as usize)
+        .map(|(index, members)| (*index, members))
+        .collect();
+    if complete.len() < first.group_threshold as usize {
+        let incomplete: Vec<String> = groups
+            .iter()
+            .filter(|(_, members)| members.len() < members[0].member_threshold as usize)
+            .map(|(index, members)| format!("group {} has {} of {}", index + 1, members.len(), members[0].member_threshold))
+            .collect();
+        return Err(Slip39Error::InsufficientShares {
+            error_details: format!(
+                "{} of {} required groups are complete{}",
+                complete.len(),
+                first.group_threshold,
+                if incomplete.is_empty() { String::new() } else { format!(" ({})", incomplete.join(", ")) }
+            ),
+        });
+    }
+
+    let group_shares = complete
+        .into_iter()
+        .take(first.group_threshold as usize)
+        .map(|(group_index, members)| {
+            let threshold = members[0].member_threshold;
+            let points: Vec<(u8, Vec<u8>)> = members
+                .iter()
+                .take(threshold as usize)
+                .map(|m| (m.member_index, m.value.clone()))
+                .collect();
+            recover_secret(threshold, &points).map(|secret| (group_index, secret))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let encrypted = recover_secret(first.group_threshold, &group_shares)?;
+
+    Ok(decrypt(&encrypted, passphrase, first.iteration_exponent, first.identifier, first.extendable))
+}
+
+/// SLIP-39 passphrases are limited to printable ASCII.
+fn check_passphrase(passphrase: Option<&str>) -> Result<&[u8], Slip39Error> {
+    let passphrase = passphrase.unwrap_or("");
+    if !passphrase.bytes().all(|b| (32..=126).contains(&b)) {
+        return Err(Slip39Error::InvalidParameters {
+            error_details: "Passphrase must contain only printable ASCII characters".to_string(),
+        });
+    }
+    Ok(passphrase.as_bytes())
+}
+
+/// Two-level split: the encrypted secret into group shares, then each group share into member shares.
+#[allow(clippy::type_complexity)]
+fn split_encrypted_secret<R: RngCore>(
+    rng: &mut R,
+    group_threshold: u8,
+    groups: &[Slip39Group],
+    encrypted: &[u8],
+) -> Result<Vec<(u8, Vec<(u8, Vec<u8>)>)>, Slip39Error> {
+    if groups.is_empty() || groups.len() > MAX_SHARE_COUNT as usize {
+        return Err(Slip39Error::InvalidParameters {
+            error_details: format!("Group count must be between 1 and {}", MAX_SHARE_COUNT),
+        });
+    }
+    if group_threshold == 0 || group_threshold as usize > groups.len() {
+        return Err(Slip39Error::InvalidParameters {
+            error_details: format!("Group threshold must be between 1 and the group count ({})", groups.len()),
+        });
+    }
+    for (index, group) in groups.iter().enumerate() {
+        if group.member_threshold == 1 && group.member_count > 1 {
+            return Err(Slip39Error::InvalidParameters {
+                error_details: format!("Group {} uses a member threshold of 1 with several members, use 1-of-1 instead", index + 1),
+            });
+        }
+    }
+
+    let group_shares = split_secret(rng, group_threshold, groups.len() as u8, encrypted)?;
+    groups
+        .iter()
+        .zip(group_shares)
+        .map(|(group, (group_index, group_secret))| {
+            split_secret(rng, group.member_threshold, group.member_count, &group_secret).map(|members| (group_index, members))
+        })
+        .collect()
+}
+
+/// Shamir split over GF(256). For thresholds above one the polynomial also passes
+/// through a digest of the secret at `DIGEST_INDEX`, so bad recoveries are detected.
+fn split_secret<R: RngCore>(rng: &mut R, threshold: u8, share_count: u8, secret: &[u8]) -> Result<Vec<(u8, Vec<u8>)>, Slip39Error> {
+    if threshold == 0 || threshold > share_count || share_count > MAX_SHARE_COUNT {
+        return Err(Slip39Error::InvalidParameters {
+            error_details: format!(
+                "Member threshold {} of {} is invalid, counts must be at most {}",
+                threshold, share_count, MAX_SHARE_COUNT
+            ),
+        });
+    }
+    if threshold == 1 {
+        return Ok((0..share_count).map(|index| (index, secret.to_vec())).collect());
+    }
+
+    let random_share_count = threshold - 2;
+    let mut shares: Vec<(u8, Vec<u8>)> = (0..random_share_count).map(|index| (index, random_bytes(rng, secret.len()))).collect();
+    let random_part = random_bytes(rng, secret.len() - DIGEST_LENGTH_BYTES);
+    let mut digest_share = create_digest(&random_part, secret).to_vec();
+    digest_share.extend_from_slice(&random_part);
+
+    let mut base_shares = shares.clone();
+    base_shares.push((DIGEST_INDEX, digest_share));
+    base_shares.push((SECRET_INDEX, secret.to_vec()));
+    for index in random_share_count..share_count {
+        shares.push((index, interpolate(&base_shares, index)?));
+    }
+    Ok(shares)
+}
+
+fn recover_secret(threshold: u8, shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>, Slip39Error> {
+    if threshold == 1 {
+        return Ok(shares[0].1.clone());
+    }
+    let secret = interpolate(shares, SECRET_INDEX)?;
+    let digest_share = interpolate(shares, DIGEST_INDEX)?;
+    let (digest, random_part) = digest_share.split_at(DIGEST_LENGTH_BYTES);
+    if digest != create_digest(random_part, &secret) {
+        return Err(Slip39Error::RecoveryFailed {
+            error_details: "Invalid digest of the shared secret, the shares do not belong together".to_string(),
+        });
+    }
+    Ok(secret)
+}
+
+fn create_digest(random_part: &[u8], secret: &[u8]) -> [u8; DIGEST_LENGTH_BYTES] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(random_part);
+    engine.input(secret);
+    let hmac = Hmac::<sha256::Hash>::from_engine(engine).to_byte_array();
+    let mut digest = [0u8; DIGEST_LENGTH_BYTES];
+    digest.copy_from_slice(&hmac[..DIGEST_LENGTH_BYTES]);
+    digest
+}
+
+fn random_bytes<R: RngCore>(rng: &mut R, length: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; length];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Exponent and logarithm tables of GF(256) with the Rijndael polynomial and generator 3.
+fn gf256_tables() -> ([u8; 255], [u8; 256]) {
+    let mut exp = [0u8; 255];
+    let mut log = [0u8; 256];
+    let mut poly: u16 = 1;
+    for (i, entry) in exp.iter_mut().enumerate() {
+        *entry = poly as u8;
+        log[poly as usize] = i as u8;
+        // Multiply by the generator x + 1
+        poly = (poly << 1) ^ poly;
+        if poly & 0x100 != 0 {
+            poly ^= 0x11b;
+        }
+    }
+    (exp, log)
+}
+
+/// Lagrange interpolation of the polynomial through `shares`, evaluated at `x`.
+fn interpolate(shares: &[(u8, Vec<u8>)], x: u8) -> Result<Vec<u8>, Slip39Error> {
+    let length = shares[0].1.len();
+    for (i, (share_x, value)) in shares.iter().enumerate() {
+        if shares[..i].iter().any(|(other_x, _)| other_x == share_x) {
+            return Err(Slip39Error::MismatchedShares {
+                error_details: "Share indices must be unique".to_string(),
+            });
+        }
+        if value.len() != length {
+            return Err(Slip39Error::MismatchedShares {
+                error_details: "All share values must have the same length".to_string(),
+            });
+        }
+    }
+    if let Some((_, value)) = shares.iter().find(|(share_x, _)| *share_x == x) {
+        return Ok(value.clone());
+    }
+
+    let (exp, log) = gf256_tables();
+    let log_product: u32 = shares.iter().map(|(share_x, _)| log[(share_x ^ x) as usize] as u32).sum();
+    let mut result = vec![0u8; length];
+    for (share_x, value) in shares {
+        let log_denominator: u32 = log[(share_x ^ x) as usize] as u32
+            + shares
+                .iter()
+                .filter(|(other_x, _)| other_x != share_x)
+                .map(|(other_x, _)| log[(share_x ^ other_x) as usize] as u32)
+                .sum::<u32>();
+        let log_basis = (log_product + 255 * shares.len() as u32 - log_denominator) % 255;
+        for (r, v) in result.iter_mut().zip(value) {
+            if *v != 0 {
+                *r ^= exp[((log[*v as usize] as u32 + log_basis) % 255) as usize];
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn salt(identifier: u16, extendable: bool) -> Vec<u8> {
+    if extendable {
+        return Vec::new();
+    }
+    let mut salt = CUSTOMIZATION_STRING_ORIG.to_vec();
+    salt.extend_from_slice(&identifier.to_be_bytes());
+    salt
+}
+
+/// Four-round Feistel network keyed by PBKDF2 of the passphrase.
+fn feistel(secret: &[u8], passphrase: &[u8], iteration_exponent: u8, identifier: u16, extendable: bool, rounds: &[u8]) -> Vec<u8> {
+    let half = secret.len() / 2;
+    let mut left = secret[..half].to_vec();
+    let mut right = secret[half..].to_vec();
+    let salt = salt(identifier, extendable);
+    let iterations = (BASE_ITERATION_COUNT << iteration_exponent) / ROUND_COUNT as u32;
+
+    for &round in rounds {
+        let mut password = vec![round];
+        password.extend_from_slice(passphrase);
+        let mut round_salt = salt.clone();
+        round_salt.extend_from_slice(&right);
+        let f = pbkdf2_sha256(&password, &round_salt, iterations, right.len());
+        let next = left.iter().zip(&f).map(|(l, f)| l ^ f).collect();
+        left = std::mem::replace(&mut right, next);
+    }
+    [right, left].concat()
+}
+
+fn encrypt(master_secret: &[u8], passphrase: &[u8], iteration_exponent: u8, identifier: u16, extendable: bool) -> Vec<u8> {
+    let rounds: Vec<u8> = (0..ROUND_COUNT).collect();
+    feistel(master_secret, passphrase, iteration_exponent, identifier, extendable, &rounds)
+}
+
+fn decrypt(encrypted: &[u8], passphrase: &[u8], iteration_exponent: u8, identifier: u16, extendable: bool) -> Vec<u8> {
+    let rounds: Vec<u8> = (0..ROUND_COUNT).rev().collect();
+    feistel(encrypted, passphrase, iteration_exponent, identifier, extendable, &rounds)
+}
+
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, length: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(length);
+    for block in 1u32.. {
+        let mut engine = HmacEngine::<sha256::Hash>::new(password);
+        engine.input(salt);
+        engine.input(&block.to_be_bytes());
+        let mut u = Hmac::<sha256::Hash>::from_engine(engine).to_byte_array();
+        let mut t = u;
+        for _ in 1..iterations {
+            let mut engine = HmacEngine::<sha256::Hash>::new(password);
+            engine.input(&u);
+            u = Hmac::<sha256::Hash>::from_engine(engine).to_byte_array();
+            t.iter_mut().zip(&u).for_each(|(t, u)| *t ^= u);
+        }
+        output.extend_from_slice(&t);
+        if output.len() >= length {
+            break;
+        }
+    }
+    output.truncate(length);
+    output
+}
+
+fn customization_string(extendable: bool) -> &'static [u8] {
+    if extendable {
+        CUSTOMIZATION_STRING_EXTENDABLE
+    } else {
+        CUSTOMIZATION_STRING_ORIG
+    }
+}
+
+fn rs1024_polymod(values: impl Iterator<Item = u32>) -> u32 {
+    const GEN: [u32; 10] = [
+        0xe0e040, 0x1c1c080, 0x3838100, 0x7070200, 0xe0e0009, 0x1c0c2412, 0x38086c24, 0x3090fc48, 0x21b1f890, 0x3f3f120,
+    ];
+    let mut chk: u32 = 1;
+    for value in values {
+        let b = chk >> 20;
+        chk = ((chk & 0xfffff) << 10) ^ value;
+        for (i, generator) in GEN.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+fn rs1024_create_checksum(data: &[u16], extendable: bool) -> [u16; CHECKSUM_LENGTH_WORDS] {
+    let values = customization_string(extendable)
+        .iter()
+        .map(|&b| b as u32)
+        .chain(data.iter().map(|&w| w as u32))
+        .chain([0; CHECKSUM_LENGTH_WORDS]);
+    let polymod = rs1024_polymod(values) ^ 1;
+    let mut checksum = [0u16; CHECKSUM_LENGTH_WORDS];
+    for (i, word) in checksum.iter_mut().enumerate() {
+        *word = ((polymod >> (RADIX_BITS * (CHECKSUM_LENGTH_WORDS - 1 - i))) & 1023) as u16;
+    }
+    checksum
+}
+
+fn rs1024_verify_checksum(data: &[u16], extendable: bool) -> bool {
+    let values = customization_string(extendable)
+        .iter()
+        .map(|&b| b as u32)
+        .chain(data.iter().map(|&w| w as u32));
+    rs1024_polymod(values) == 1
+}
+
+fn encode_share(share: &Share) -> String {
+    let id_exp = ((share.identifier as u32) << 5) | ((share.extendable as u32) << 4) | share.iteration_exponent as u32;
+    let params = ((share.group_index as u32) << 16)
+        | (((share.group_threshold - 1) as u32) << 12)
+        | (((share.group_count - 1) as u32) << 8)
+        | ((share.member_index as u32) << 4)
+        | (share.member_threshold - 1) as u32;
+
+    let value_word_count = (share.value.len() * 8).div_ceil(RADIX_BITS);
+    let mut words: Vec<u16> = vec![(id_exp >> 10) as u16, (id_exp & 1023) as u16, (params >> 10) as u16, (params & 1023) as u16];
+    words.extend(bytes_to_words(&share.value, value_word_count));
+    let checksum = rs1024_create_checksum(&words, share.extendable);
+    words.extend_from_slice(&checksum);
+
+    words.iter().map(|&w| WORDLIST[w as usize]).collect::<Vec<_>>().join(" ")
+}
+
+fn decode_share(mnemonic: &str) -> Result<Share, Slip39Error> {
+    let words = mnemonic
+        .split_whitespace()
+        .map(|word| {
+            let word = word.to_lowercase();
+            WORDLIST
+                .binary_search(&word.as_str())
+                .map(|index| index as u16)
+                .map_err(|_| Slip39Error::InvalidShare {
+                    error_details: format!("Unknown word: {}", word),
+                })
+        })
+        .collect::<Result<Vec<u16>, _>>()?;
+
+    if words.len() < MIN_MNEMONIC_LENGTH_WORDS {
+        return Err(Slip39Error::InvalidShare {
+            error_details: format!("Share must have at least {} words, got {}", MIN_MNEMONIC_LENGTH_WORDS, words.len()),
+        });
+    }
+    let value_word_count = words.len() - METADATA_LENGTH_WORDS;
+    // Values are a whole number of 16-bit units, left-padded to a word boundary
+    let padding_bits = (RADIX_BITS * value_word_count) % 16;
+    if padding_bits > 8 {
+        return Err(Slip39Error::InvalidShare {
+            error_details: format!("Invalid share length of {} words", words.len()),
+        });
+    }
+
+    let id_exp = ((words[0] as u32) << 10) | words[1] as u32;
+    let extendable = (id_exp >> 4) & 1 == 1;
+    if !rs1024_verify_checksum(&words, extendable) {
+        return Err(Slip39Error::InvalidChecksum {
+            error_details: format!("Checksum of share starting with \"{} {}\" does not match", WORDLIST[words[0] as usize], WORDLIST[words[1] as usize]),
+        });
+    }
+
+    let params = ((words[2] as u32) << 10) | words[3] as u32;
+    let group_index = (params >> 16) as u8;
+    let group_threshold = ((params >> 12) & 15) as u8 + 1;
+    let group_count = ((params >> 8) & 15) as u8 + 1;
+    if group_count < group_threshold {
+        return Err(Slip39Error::InvalidShare {
+            error_details: format!("Group threshold {} exceeds group count {}", group_threshold, group_count),
+        });
+    }
+
+    let value_words = &words[ID_EXP_LENGTH_WORDS + SHARE_PARAMS_LENGTH_WORDS..words.len() - CHECKSUM_LENGTH_WORDS];
+    let value_length = (RADIX_BITS * value_word_count - padding_bits) / 8;
+    let value = words_to_bytes(value_words, value_length).ok_or_else(|| Slip39Error::InvalidShare {
+        error_details: "Invalid padding bits".to_string(),
+    })?;
+
+    Ok(Share {
+        identifier: (id_exp >> 5) as u16,
+        extendable,
+        iteration_exponent: (id_exp & 15) as u8,
+        group_index,
+        group_threshold,
+        group_count,
+        member_index: ((params >> 4) & 15) as u8,
+        member_threshold: (params & 15) as u8 + 1,
+        value,
+    })
+}
+
+/// Big-endian bytes as `word_count` 10-bit words, zero-padded at the front.
+fn bytes_to_words(bytes: &[u8], word_count: usize) -> Vec<u16> {
+    let padding = word_count * RADIX_BITS - bytes.len() * 8;
+    let bit = |i: usize| -> u16 {
+        if i < padding {
+            0
+        } else {
+            let i = i - padding;
+            ((bytes[i / 8] >> (7 - i % 8)) & 1) as u16
+        }
+    };
+    (0..word_count)
+        .map(|w| (0..RADIX_BITS).fold(0u16, |acc, b| (acc << 1) | bit(w * RADIX_BITS + b)))
+        .collect()
+}
+
+/// Inverse of `bytes_to_words`, returning `None` if any padding bit is set.
+fn words_to_bytes(words: &[u16], length: usize) -> Option<Vec<u8>> {
+    let padding = words.len() * RADIX_BITS - length * 8;
+    let bit = |i: usize| (words[i / RADIX_BITS] >> (RADIX_BITS - 1 - i % RADIX_BITS)) & 1;
+    if (0..padding).any(|i| bit(i) != 0) {
+        return None;
+    }
+    Some(
+        (0..length)
+            .map(|byte| (0..8).fold(0u8, |acc, b| (acc << 1) | bit(padding + byte * 8 + b) as u8))
+            .collect(),
+    )
+}
@@ -1,6 +1,43 @@
 #[cfg(test)]
 mod tests {
-    use crate::modules::onchain::{AddressType, BitcoinAddressValidator};
+    use crate::modules::onchain::{AddressError, AddressType, BitcoinAddressValidator};
+    use crate::modules::onchain::descriptor::descriptor_checksum;
+    use crate::modules::onchain::{AddressStats, ChainBackend, DiscoveryError, InMemoryChainSource};
+    use bitcoin::hashes::{sha256, Hash};
+    use bitcoin::Address;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::modules::onchain::{select_coins, CoinSelectionStrategy, TargetOutput, TransactionError, Utxo};
+    use crate::modules::trezor::AccountUtxo;
+    use crate::modules::onchain::{build_psbt, sign_psbt, ChangeOutput};
+    use bitcoin::bip32::Fingerprint;
+    use bitcoin::consensus::deserialize;
+    use bitcoin::psbt::Psbt;
+    use bitcoin::secp256k1::{Message, Secp256k1, XOnlyPublicKey};
+    use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+    use bitcoin::{CompressedPublicKey, ScriptBuf, Sequence, Transaction, TxOut};
+    use crate::modules::onchain::{descriptor, keys, estimate_fee, estimate_tx_vsize};
+    use bitcoin::bip32::DerivationPath;
+    use bitcoin::opcodes::all::OP_CHECKMULTISIG;
+    use bitcoin::script::Builder as ScriptBuilder;
+    use bitcoin::{Amount, OutPoint, PrivateKey, TxIn, Txid, Witness};
+    use bitcoin::consensus::encode::serialize_hex;
+    use crate::modules::onchain::{build_cpfp_psbt, build_rbf_psbt};
+    use crate::modules::onchain::{message, sign_message, verify_message, MessageSignatureFormat, MessageSigningError};
+    use bitcoin::base64::engine::general_purpose::STANDARD as BASE64;
+    use bitcoin::base64::Engine;
+    use crate::modules::onchain::{decode_transaction, InputWitnessType, OutputScriptType};
+    use crate::modules::onchain::{bip85, derive_bip85_hex, derive_bip85_mnemonic, derive_bip85_wif, derive_bip85_xprv};
+    use bitcoin::bip32::Xpriv;
+    use crate::modules::onchain::{create_multisig_descriptors, derive_multisig_addresses, get_multisig_redeem_script, MultisigScriptType};
+    use crate::modules::onchain::{send_payjoin_request, HttpPayjoinTransport, PayjoinError, PayjoinParams, PayjoinResponse, PayjoinTransport};
+    use std::sync::Mutex;
+    use crate::modules::onchain::{get_key_origin, get_master_fingerprint, parse_key_expression};
+    use crate::modules::onchain::{envelope, decrypt_seed_envelope, encrypt_seed_envelope, SeedEnvelopeError};
+    use crate::modules::onchain::{slip39, combine_slip39_shares, split_slip39_mnemonic, validate_slip39_share, Slip39Error, Slip39Group};
     use crate::modules::scanner::NetworkType;
     use crate::onchain::types::WordCount;
     use bitcoin::Network;
@@ -203,4 +240,1819 @@ mod tests {
 
         assert_eq!(private_key, "L4p2b9VAf8k5aUahF1JCJUzZkgNEAqLfq8DDdQiyAprQAKSbu8hf");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_derive_addresses_from_descriptor() {
+        // BIP84 account key for the standard test mnemonic
+        let descriptor = "wpkh([73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/<0;1>/*)";
+        let result = BitcoinAddressValidator::derive_addresses_from_descriptor(
+            descriptor,
+            None,
+            None,
+            None,
+            Some(2),
+        ).unwrap();
+
+        assert_eq!(result.addresses.len(), 2);
+        assert_eq!(result.addresses[0].address, "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+        assert_eq!(result.addresses[0].path, "m/84'/0'/0'/0/0");
+        assert_eq!(result.addresses[1].address, "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g");
+        assert_eq!(result.addresses[1].path, "m/84'/0'/0'/0/1");
+
+        let change = BitcoinAddressValidator::derive_addresses_from_descriptor(
+            descriptor,
+            None,
+            Some(true),
+            None,
+            Some(1),
+        ).unwrap();
+        assert_eq!(change.addresses[0].address, "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el");
+        assert_eq!(change.addresses[0].path, "m/84'/0'/0'/1/0");
+    }
+
+    #[test]
+    fn test_descriptor_script_types_match_mnemonic_derivation() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let cases = vec![
+            ("pkh(xpub6BosfCnifzxcFwrSzQiqu2DBVTshkCXacvNsWGYJVVhhawA7d4R5WSWGFNbi8Aw6ZRc1brxMyWMzG3DSSSSoekkudhUd9yLb6qx39T9nMdj)", "m/44'/0'/0'"),
+            ("sh(wpkh(xpub6C6nQwHaWbSrzs5tZ1q7m5R9cPK9eYpNMFesiXsYrgc1P8bvLLAet9JfHjYXKjToD8cBRswJXXbbFpXgwsswVPAZzKMa1jUp2kVkGVUaJa7))", "m/49'/0'/0'"),
+            ("wpkh(xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V)", "m/84'/0'/0'"),
+            ("tr(xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ)", "m/86'/0'/0'"),
+        ];
+
+        for (descriptor, base_path) in cases {
+            let from_descriptor = BitcoinAddressValidator::derive_addresses_from_descriptor(
+                descriptor,
+                Some(Network::Bitcoin),
+                Some(true),
+                Some(3),
+                Some(2),
+            ).unwrap();
+            let from_mnemonic = BitcoinAddressValidator::derive_bitcoin_addresses(
+                mnemonic,
+                Some(base_path),
+                Some(Network::Bitcoin),
+                None,
+                Some(true),
+                Some(3),
+                Some(2),
+            ).unwrap();
+
+            for (a, b) in from_descriptor.addresses.iter().zip(from_mnemonic.addresses.iter()) {
+                assert_eq!(a.address, b.address);
+                assert_eq!(a.public_key, b.public_key);
+            }
+            // Without a key origin the path is relative to the extended key
+            assert_eq!(from_descriptor.addresses[0].path, "m/1/3");
+        }
+    }
+
+    #[test]
+    fn test_derive_addresses_from_slip132_keys() {
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+
+        // A bare zpub implies native segwit
+        let result = BitcoinAddressValidator::derive_addresses_from_descriptor(zpub, None, None, None, None).unwrap();
+        assert_eq!(result.addresses.len(), 1);
+        assert_eq!(result.addresses[0].address, "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+
+        // A zpub inside a wpkh() descriptor is accepted
+        let result = BitcoinAddressValidator::derive_addresses_from_descriptor(
+            &format!("wpkh({}/0/*)", zpub), None, None, None, None,
+        ).unwrap();
+        assert_eq!(result.addresses[0].address, "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+
+        // A zpub inside a pkh() descriptor is inconsistent
+        assert!(matches!(
+            BitcoinAddressValidator::derive_addresses_from_descriptor(&format!("pkh({})", zpub), None, None, None, None),
+            Err(AddressError::InvalidDescriptor)
+        ));
+
+        // Testnet keys derive testnet addresses and reject mainnet
+        let tpub = "tpubDC8msFGeGuwnKG9Upg7DM2b4DaRqg3CUZa5g8v2SRQ6K4NSkxUgd7HsL2XVWbVm39yBA4LAxysQAm397zwQSQoQgewGiYZqrA9DsP4zbQ1M";
+        let result = BitcoinAddressValidator::derive_addresses_from_descriptor(
+            &format!("wpkh({})", tpub), None, None, None, None,
+        ).unwrap();
+        assert!(result.addresses[0].address.starts_with("tb1q"));
+        let result = BitcoinAddressValidator::derive_addresses_from_descriptor(
+            &format!("wpkh({})", tpub), Some(Network::Regtest), None, None, None,
+        ).unwrap();
+        assert!(result.addresses[0].address.starts_with("bcrt1q"));
+        assert!(matches!(
+            BitcoinAddressValidator::derive_addresses_from_descriptor(&format!("wpkh({})", tpub), Some(Network::Bitcoin), None, None, None),
+            Err(AddressError::InvalidNetwork)
+        ));
+    }
+
+    #[test]
+    fn test_descriptor_checksum() {
+        let descriptor = "wpkh([73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/0/*)";
+        let checksum = descriptor_checksum(descriptor).unwrap();
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+
+        let valid = format!("{}#{}", descriptor, checksum);
+        assert!(BitcoinAddressValidator::derive_addresses_from_descriptor(&valid, None, None, None, None).is_ok());
+
+        let invalid = format!("{}#{}", descriptor, "qqqqqqqq");
+        assert!(matches!(
+            BitcoinAddressValidator::derive_addresses_from_descriptor(&invalid, None, None, None, None),
+            Err(AddressError::InvalidDescriptor)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_descriptors() {
+        let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+        let test_cases = vec![
+            format!("wsh({})", xpub),
+            format!("wpkh({}/0'/*)", xpub),
+            format!("wpkh({}/*/0)", xpub),
+            format!("tr({},pk({}))", xpub, xpub),
+            "wpkh(xpubinvalid)".to_string(),
+        ];
+
+        for descriptor in test_cases {
+            assert!(BitcoinAddressValidator::derive_addresses_from_descriptor(&descriptor, None, None, None, None).is_err());
+        }
+    }
+
+    #[test]
+    fn test_get_derivation_path() {
+        let test_cases = vec![
+            (AddressType::P2PKH, Network::Bitcoin, "m/44'/0'/0'/0/0"),
+            (AddressType::P2SH, Network::Bitcoin, "m/49'/0'/0'/0/0"),
+            (AddressType::P2WPKH, Network::Bitcoin, "m/84'/0'/0'/0/0"),
+            (AddressType::P2TR, Network::Bitcoin, "m/86'/0'/0'/0/0"),
+            (AddressType::P2WPKH, Network::Testnet, "m/84'/1'/0'/0/0"),
+            (AddressType::P2TR, Network::Regtest, "m/86'/1'/0'/0/0"),
+        ];
+
+        for (address_type, network, expected) in test_cases {
+            let path = BitcoinAddressValidator::get_derivation_path(address_type, Some(network), None, None, None).unwrap();
+            assert_eq!(path, expected);
+        }
+
+        let path = BitcoinAddressValidator::get_derivation_path(
+            AddressType::P2WPKH, Some(Network::Bitcoin), Some(2), Some(true), Some(7),
+        ).unwrap();
+        assert_eq!(path, "m/84'/0'/2'/1/7");
+
+        assert!(matches!(
+            BitcoinAddressValidator::get_derivation_path(AddressType::P2WSH, None, None, None, None),
+            Err(AddressError::UnsupportedAddressType)
+        ));
+    }
+
+    #[test]
+    fn test_derive_address_for_type() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let test_cases = vec![
+            (AddressType::P2PKH, "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA", "m/44'/0'/0'/0/0"),
+            (AddressType::P2SH, "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf", "m/49'/0'/0'/0/0"),
+            (AddressType::P2WPKH, "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu", "m/84'/0'/0'/0/0"),
+            (AddressType::P2TR, "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr", "m/86'/0'/0'/0/0"),
+        ];
+
+        for (address_type, expected_address, expected_path) in test_cases {
+            let result = BitcoinAddressValidator::derive_address_for_type(
+                mnemonic, address_type, Some(Network::Bitcoin), None, None, None, None,
+            ).unwrap();
+            assert_eq!(result.address, expected_address);
+            assert_eq!(result.path, expected_path);
+            assert_eq!(BitcoinAddressValidator::validate_address(&result.address).unwrap().address_type, address_type);
+        }
+
+        // Testnet uses coin type 1
+        let result = BitcoinAddressValidator::derive_address_for_type(
+            mnemonic, AddressType::P2WPKH, Some(Network::Testnet), None, None, None, None,
+        ).unwrap();
+        assert_eq!(result.address, "tb1q6rz28mcfaxtmd6v789l9rrlrusdprr9pqcpvkl");
+        assert_eq!(result.path, "m/84'/1'/0'/0/0");
+
+        // BIP84 change vector
+        let result = BitcoinAddressValidator::derive_addresses_for_type(
+            mnemonic, AddressType::P2WPKH, Some(Network::Bitcoin), None, None, Some(true), None, Some(2),
+        ).unwrap();
+        assert_eq!(result.addresses.len(), 2);
+        assert_eq!(result.addresses[0].address, "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el");
+        assert_eq!(result.addresses[1].path, "m/84'/0'/0'/1/1");
+
+        assert!(matches!(
+            BitcoinAddressValidator::derive_address_for_type("abandon abandon", AddressType::P2WPKH, None, None, None, None, None),
+            Err(AddressError::InvalidMnemonic)
+        ));
+    }
+
+    #[test]
+    fn test_derive_address_at_path_rejects_inconsistent_paths() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+        let result = BitcoinAddressValidator::derive_address_at_path(
+            mnemonic, "m/86'/0'/0'/0/0", AddressType::P2TR, Some(Network::Bitcoin), None,
+        ).unwrap();
+        assert_eq!(result.address, "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr");
+
+        // Taproot path used for a P2WPKH address
+        assert!(matches!(
+            BitcoinAddressValidator::derive_address_at_path(mnemonic, "m/86'/0'/0'/0/0", AddressType::P2WPKH, Some(Network::Bitcoin), None),
+            Err(AddressError::DerivationPathMismatch)
+        ));
+        // Mainnet coin type on testnet
+        assert!(matches!(
+            BitcoinAddressValidator::derive_address_at_path(mnemonic, "m/84'/0'/0'/0/0", AddressType::P2WPKH, Some(Network::Testnet), None),
+            Err(AddressError::DerivationPathMismatch)
+        ));
+        // Malformed paths
+        for path in ["m/84'/0'/0'", "m/84'/0'/0/0/0", "m/84'/0'/0'/2/0", "m/84'/0'/0'/0/0'", "not a path"] {
+            assert!(matches!(
+                BitcoinAddressValidator::derive_address_at_path(mnemonic, path, AddressType::P2WPKH, Some(Network::Bitcoin), None),
+                Err(AddressError::InvalidDerivationPath)
+            ));
+        }
+    }
+
+    fn spawn_esplora_stub(used: Vec<(String, u64)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                BufReader::new(&stream).read_line(&mut request_line).unwrap();
+                let (tx_count, funded) = used
+                    .iter()
+                    .find(|(address, _)| request_line.contains(&format!("/address/{} ", address)))
+                    .map(|(_, value)| (1, *value))
+                    .unwrap_or((0, 0));
+                let body = format!(
+                    r#"{{"chain_stats":{{"funded_txo_sum":{},"spent_txo_sum":0,"tx_count":{}}},"mempool_stats":{{"funded_txo_sum":0,"spent_txo_sum":0,"tx_count":0}}}}"#,
+                    funded, tx_count
+                );
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    fn spawn_electrum_stub(used_script_hash: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let mut writer = stream.try_clone().unwrap();
+                for line in BufReader::new(stream).lines() {
+                    let request: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+                    let is_used = request["params"][0] == used_script_hash.as_str();
+                    let result = match request["method"].as_str().unwrap() {
+                        "blockchain.scripthash.get_history" if is_used => serde_json::json!([{ "tx_hash": "00", "height": 100 }]),
+                        "blockchain.scripthash.get_history" => serde_json::json!([]),
+                        _ if is_used => serde_json::json!({ "confirmed": 2500, "unconfirmed": -500 }),
+                        _ => serde_json::json!({ "confirmed": 0, "unconfirmed": 0 }),
+                    };
+                    let response = serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+                    writer.write_all(format!("{}\n", response).as_bytes()).unwrap();
+                }
+            }
+        });
+        url
+    }
+
+    fn derive_test_addresses(is_change: bool, count: u32) -> Vec<String> {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        BitcoinAddressValidator::derive_bitcoin_addresses(
+            mnemonic,
+            Some("m/84'/0'/0'"),
+            Some(Network::Bitcoin),
+            None,
+            Some(is_change),
+            None,
+            Some(count),
+        )
+            .unwrap()
+            .addresses
+            .into_iter()
+            .map(|a| a.address)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_discover_addresses_with_gap_limit() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let receive = derive_test_addresses(false, 12);
+        let change = derive_test_addresses(true, 2);
+
+        let source = InMemoryChainSource::new();
+        let stats = |tx_count, confirmed_balance, unconfirmed_balance| AddressStats { tx_count, confirmed_balance, unconfirmed_balance };
+        source.set_address_stats(&receive[0], stats(2, 0, 0));
+        source.set_address_stats(&receive[3], stats(1, 10_000, 0));
+        // Beyond the gap of 5 unused addresses after index 3, must not be found
+        source.set_address_stats(&receive[10], stats(1, 99_999, 0));
+        source.set_address_stats(&change[1], stats(1, 4_000, -1_000));
+
+        let result = BitcoinAddressValidator::discover_addresses(
+            &source,
+            mnemonic,
+            Some("m/84'/0'/0'"),
+            Some(Network::Bitcoin),
+            None,
+            Some(5),
+        ).await.unwrap();
+
+        assert_eq!(result.used_addresses.len(), 3);
+        assert_eq!(result.next_receive_index, 4);
+        assert_eq!(result.next_change_index, 2);
+        assert_eq!(result.confirmed_balance, 14_000);
+        assert_eq!(result.unconfirmed_balance, -1_000);
+
+        let used_receive: Vec<u32> = result.used_addresses.iter().filter(|a| !a.is_change).map(|a| a.index).collect();
+        assert_eq!(used_receive, vec![0, 3]);
+        assert_eq!(result.used_addresses[1].path, "m/84'/0'/0'/0/3");
+        assert!(result.used_addresses[2].is_change);
+        assert_eq!(result.used_addresses[2].address, change[1]);
+
+        // With a larger gap the far address is discovered
+        let result = BitcoinAddressValidator::discover_addresses(
+            &source,
+            mnemonic,
+            Some("m/84'/0'/0'"),
+            Some(Network::Bitcoin),
+            None,
+            Some(20),
+        ).await.unwrap();
+        assert_eq!(result.next_receive_index, 11);
+        assert_eq!(result.confirmed_balance, 113_999);
+    }
+
+    #[tokio::test]
+    async fn test_discover_addresses_empty_wallet() {
+        let source = InMemoryChainSource::new();
+        let xpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+
+        let result = BitcoinAddressValidator::discover_descriptor_addresses(&source, xpub, None, None).await.unwrap();
+        assert!(result.used_addresses.is_empty());
+        assert_eq!(result.next_receive_index, 0);
+        assert_eq!(result.next_change_index, 0);
+
+        assert!(matches!(
+            BitcoinAddressValidator::discover_descriptor_addresses(&source, xpub, None, Some(0)).await,
+            Err(DiscoveryError::InvalidParameter { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_discover_addresses_with_esplora() {
+        let receive = derive_test_addresses(false, 2);
+        let url = spawn_esplora_stub(vec![(receive[1].clone(), 50_000)]);
+        let source = ChainBackend::Esplora { url }.into_source().unwrap();
+
+        let descriptor = "wpkh([73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/<0;1>/*)";
+        let result = BitcoinAddressValidator::discover_descriptor_addresses(source.as_ref(), descriptor, None, Some(3)).await.unwrap();
+
+        assert_eq!(result.used_addresses.len(), 1);
+        assert_eq!(result.used_addresses[0].address, receive[1]);
+        assert_eq!(result.next_receive_index, 2);
+        assert_eq!(result.confirmed_balance, 50_000);
+    }
+
+    #[tokio::test]
+    async fn test_discover_addresses_with_electrum() {
+        let change = derive_test_addresses(true, 1);
+        let script = Address::from_str(&change[0]).unwrap().assume_checked().script_pubkey();
+        let mut script_hash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+        script_hash.reverse();
+        let url = spawn_electrum_stub(hex::encode(script_hash));
+        let source = ChainBackend::Electrum { url }.into_source().unwrap();
+
+        let descriptor = "wpkh(xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V)";
+        let result = BitcoinAddressValidator::discover_descriptor_addresses(source.as_ref(), descriptor, None, Some(3)).await.unwrap();
+
+        assert_eq!(result.used_addresses.len(), 1);
+        assert!(result.used_addresses[0].is_change);
+        assert_eq!(result.next_receive_index, 0);
+        assert_eq!(result.next_change_index, 1);
+        assert_eq!(result.confirmed_balance, 2_500);
+        assert_eq!(result.unconfirmed_balance, -500);
+
+        assert!(ChainBackend::Electrum { url: "http://example.com:50001".to_string() }.into_source().is_err());
+    }
+
+    const P2WPKH_ADDRESS: &str = "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu";
+    const P2TR_ADDRESS: &str = "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr";
+
+    fn test_utxo(vout: u32, value: u64, address_type: AddressType) -> Utxo {
+        let address = match address_type {
+            AddressType::P2TR => P2TR_ADDRESS,
+            _ => P2WPKH_ADDRESS,
+        };
+        Utxo {
+            txid: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string(),
+            vout,
+            value,
+            address: address.to_string(),
+            address_type,
+            derivation_path: None,
+            confirmations: 6,
+        }
+    }
+
+    fn test_output(amount: u64) -> Vec<TargetOutput> {
+        vec![TargetOutput { address: P2WPKH_ADDRESS.to_string(), amount }]
+    }
+
+    #[test]
+    fn test_select_coins_branch_and_bound_exact_match() {
+        // At 1 sat/vB a P2WPKH input costs 68 sats and the fixed part of a
+        // one-output P2WPKH transaction costs 42 sats
+        let utxos = vec![
+            test_utxo(0, 50_000, AddressType::P2WPKH),
+            test_utxo(1, 100_110, AddressType::P2WPKH),
+            test_utxo(2, 200_000, AddressType::P2WPKH),
+        ];
+
+        let result = select_coins(utxos, test_output(100_000), 1.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None).unwrap();
+        assert_eq!(result.selected_utxos.len(), 1);
+        assert_eq!(result.selected_utxos[0].vout, 1);
+        assert_eq!(result.change_amount, None);
+        assert_eq!(result.fee, 110);
+        assert_eq!(result.vsize, 110);
+        // 68 sats spent now instead of 680 at the long-term rate, no excess
+        assert_eq!(result.waste, -612);
+    }
+
+    #[test]
+    fn test_select_coins_branch_and_bound_combination() {
+        let utxos = vec![
+            test_utxo(0, 90_000, AddressType::P2WPKH),
+            test_utxo(1, 60_068, AddressType::P2WPKH),
+            test_utxo(2, 40_110, AddressType::P2WPKH),
+        ];
+
+        let result = select_coins(utxos, test_output(100_000), 1.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None).unwrap();
+        let mut vouts: Vec<u32> = result.selected_utxos.iter().map(|u| u.vout).collect();
+        vouts.sort();
+        assert_eq!(vouts, vec![1, 2]);
+        assert_eq!(result.change_amount, None);
+        assert_eq!(result.fee, 178);
+    }
+
+    #[test]
+    fn test_select_coins_largest_first_with_change() {
+        let utxos = vec![
+            test_utxo(0, 50_000, AddressType::P2WPKH),
+            test_utxo(1, 100_110, AddressType::P2WPKH),
+            test_utxo(2, 200_000, AddressType::P2WPKH),
+        ];
+
+        let result = select_coins(utxos.clone(), test_output(100_000), 1.0, AddressType::P2WPKH, CoinSelectionStrategy::LargestFirst, None).unwrap();
+        assert_eq!(result.selected_utxos.len(), 1);
+        assert_eq!(result.selected_utxos[0].vout, 2);
+        assert_eq!(result.fee, 141);
+        assert_eq!(result.change_amount, Some(99_859));
+        // Input waste plus the cost of creating (31) and later spending (680) the change
+        assert_eq!(result.waste, 99);
+
+        // Without a changeless solution branch-and-bound falls back to largest-first
+        let result = select_coins(utxos[2..].to_vec(), test_output(100_000), 1.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None).unwrap();
+        assert_eq!(result.change_amount, Some(99_859));
+
+        // Selection is deterministic regardless of input order
+        let mut reversed = utxos.clone();
+        reversed.reverse();
+        let a = select_coins(utxos, test_output(120_000), 5.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None).unwrap();
+        let b = select_coins(reversed, test_output(120_000), 5.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None).unwrap();
+        assert_eq!(a.selected_utxos, b.selected_utxos);
+        assert_eq!(a.fee, b.fee);
+    }
+
+    #[test]
+    fn test_select_coins_single_address_type() {
+        let utxos = vec![
+            test_utxo(0, 80_000, AddressType::P2WPKH),
+            test_utxo(1, 30_000, AddressType::P2WPKH),
+            test_utxo(2, 90_000, AddressType::P2TR),
+            test_utxo(3, 40_000, AddressType::P2TR),
+        ];
+
+        // Largest-first mixes script types
+        let result = select_coins(utxos.clone(), test_output(100_000), 2.0, AddressType::P2TR, CoinSelectionStrategy::LargestFirst, None).unwrap();
+        assert_eq!(result.selected_utxos.len(), 2);
+        assert_ne!(result.selected_utxos[0].address_type, result.selected_utxos[1].address_type);
+
+        let result = select_coins(utxos, test_output(100_000), 2.0, AddressType::P2TR, CoinSelectionStrategy::SingleAddressType, None).unwrap();
+        let first_type = result.selected_utxos[0].address_type;
+        assert!(result.selected_utxos.iter().all(|u| u.address_type == first_type));
+        let total: u64 = result.selected_utxos.iter().map(|u| u.value).sum();
+        assert_eq!(total, 100_000 + result.fee + result.change_amount.unwrap_or(0));
+    }
+
+    #[test]
+    fn test_select_coins_fee_matches_estimate() {
+        // A legacy input in a segwit transaction adds an empty witness, and the vsize
+        // is rounded up before applying the fee rate
+        let utxos = vec![
+            test_utxo(0, 60_000, AddressType::P2WPKH),
+            test_utxo(1, 60_000, AddressType::P2PKH),
+        ];
+        let result = select_coins(utxos, test_output(100_000), 2.0, AddressType::P2WPKH, CoinSelectionStrategy::LargestFirst, None).unwrap();
+        let inputs: Vec<AddressType> = result.selected_utxos.iter().map(|u| u.address_type).collect();
+        let outputs = [AddressType::P2WPKH, AddressType::P2WPKH];
+        assert_eq!(result.vsize, estimate_tx_vsize(&inputs, &outputs).unwrap());
+        assert_eq!(result.fee, estimate_fee(&inputs, &outputs, 2.0).unwrap());
+
+        // More than 252 inputs need a three-byte count
+        let utxos: Vec<Utxo> = (0..300).map(|vout| test_utxo(vout, 2_000, AddressType::P2WPKH)).collect();
+        let result = select_coins(utxos, test_output(500_000), 1.0, AddressType::P2WPKH, CoinSelectionStrategy::LargestFirst, None).unwrap();
+        let inputs = vec![AddressType::P2WPKH; result.selected_utxos.len()];
+        assert!(inputs.len() > 252);
+        let outputs = [AddressType::P2WPKH, AddressType::P2WPKH];
+        let total: u64 = result.selected_utxos.iter().map(|u| u.value).sum();
+        assert_eq!(result.vsize, estimate_tx_vsize(&inputs, &outputs).unwrap());
+        assert_eq!(result.fee, estimate_fee(&inputs, &outputs, 1.0).unwrap());
+        assert_eq!(total, 500_000 + result.fee + result.change_amount.unwrap());
+    }
+
+    #[test]
+    fn test_select_coins_errors() {
+        let utxos = vec![test_utxo(0, 50_000, AddressType::P2WPKH)];
+
+        assert!(matches!(
+            select_coins(utxos.clone(), test_output(100_000), 1.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None),
+            Err(TransactionError::InsufficientFunds { available: 49_932, required: 100_042 })
+        ));
+        assert!(matches!(
+            select_coins(utxos.clone(), test_output(100), 1.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None),
+            Err(TransactionError::InvalidOutput { .. })
+        ));
+        assert!(matches!(
+            select_coins(utxos.clone(), test_output(10_000), -1.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None),
+            Err(TransactionError::InvalidFeeRate { .. })
+        ));
+        assert!(matches!(
+            select_coins(utxos, vec![], 1.0, AddressType::P2WPKH, CoinSelectionStrategy::BranchAndBound, None),
+            Err(TransactionError::InvalidOutput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_utxo_from_account_utxo() {
+        let account_utxo = AccountUtxo {
+            txid: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string(),
+            vout: 1,
+            amount: "12345".to_string(),
+            block_height: Some(800_000),
+            address: P2TR_ADDRESS.to_string(),
+            path: "m/86'/0'/0'/0/0".to_string(),
+            confirmations: Some(3),
+        };
+
+        let utxo = Utxo::try_from(account_utxo).unwrap();
+        assert_eq!(utxo.value, 12_345);
+        assert_eq!(utxo.address_type, AddressType::P2TR);
+        assert_eq!(utxo.derivation_path.as_deref(), Some("m/86'/0'/0'/0/0"));
+        assert_eq!(utxo.confirmations, 3);
+    }
+
+    const TEST_MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const P2SH_ADDRESS: &str = "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf";
+    const CHANGE_ADDRESS: &str = "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el";
+    const CHANGE_PATH: &str = "m/84'/0'/0'/1/0";
+
+    fn wallet_utxos() -> Vec<Utxo> {
+        vec![
+            Utxo { derivation_path: Some("m/84'/0'/0'/0/0".to_string()), ..test_utxo(0, 50_000, AddressType::P2WPKH) },
+            Utxo {
+                address: P2SH_ADDRESS.to_string(),
+                derivation_path: Some("m/49'/0'/0'/0/0".to_string()),
+                ..test_utxo(1, 40_000, AddressType::P2SH)
+            },
+            Utxo { derivation_path: Some("m/86'/0'/0'/0/0".to_string()), ..test_utxo(2, 30_000, AddressType::P2TR) },
+        ]
+    }
+
+    fn wallet_change() -> ChangeOutput {
+        ChangeOutput {
+            address: CHANGE_ADDRESS.to_string(),
+            amount: 19_000,
+            derivation_path: Some(CHANGE_PATH.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_build_psbt() {
+        let psbt = build_psbt(wallet_utxos(), test_output(100_000), Some(wallet_change()), TEST_MNEMONIC, None, Network::Bitcoin, true, None).unwrap();
+        let fingerprint = Fingerprint::from_str("73c5da0a").unwrap();
+
+        assert_eq!(psbt.unsigned_tx.input.len(), 3);
+        assert_eq!(psbt.unsigned_tx.output.len(), 2);
+        assert!(psbt.unsigned_tx.input.iter().all(|i| i.sequence == Sequence::ENABLE_RBF_NO_LOCKTIME));
+        assert_eq!(psbt.fee().unwrap().to_sat(), 1_000);
+
+        let (fp, path) = psbt.inputs[0].bip32_derivation.values().next().unwrap();
+        assert_eq!(*fp, fingerprint);
+        assert_eq!(path.to_string(), "84'/0'/0'/0/0");
+        assert!(psbt.inputs[1].redeem_script.as_ref().unwrap().is_p2wpkh());
+        let (leaf_hashes, (fp, path)) = psbt.inputs[2].tap_key_origins.values().next().unwrap();
+        assert!(leaf_hashes.is_empty());
+        assert_eq!(*fp, fingerprint);
+        assert_eq!(path.to_string(), "86'/0'/0'/0/0");
+        assert!(psbt.inputs[2].tap_internal_key.is_some());
+
+        assert!(psbt.outputs[0].bip32_derivation.is_empty());
+        let (_, path) = psbt.outputs[1].bip32_derivation.values().next().unwrap();
+        assert_eq!(path.to_string(), "84'/0'/0'/1/0");
+
+        let psbt = build_psbt(wallet_utxos(), test_output(100_000), None, TEST_MNEMONIC, None, Network::Bitcoin, false, None).unwrap();
+        assert!(psbt.unsigned_tx.input.iter().all(|i| i.sequence == Sequence::ENABLE_LOCKTIME_NO_RBF));
+    }
+
+    #[test]
+    fn test_build_psbt_errors() {
+        // Path does not control the address
+        let mut utxos = wallet_utxos();
+        utxos[0].derivation_path = Some("m/84'/0'/0'/0/1".to_string());
+        assert!(matches!(
+            build_psbt(utxos, test_output(100_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, None),
+            Err(TransactionError::InvalidInput { .. })
+        ));
+
+        let mut utxos = wallet_utxos();
+        utxos[0].derivation_path = None;
+        assert!(matches!(
+            build_psbt(utxos, test_output(100_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, None),
+            Err(TransactionError::InvalidInput { .. })
+        ));
+
+        assert!(matches!(
+            build_psbt(wallet_utxos(), test_output(200_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, None),
+            Err(TransactionError::InsufficientFunds { available: 120_000, required: 200_000 })
+        ));
+        assert!(matches!(
+            build_psbt(wallet_utxos(), test_output(100_000), None, TEST_MNEMONIC, None, Network::Testnet, true, None),
+            Err(TransactionError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            build_psbt(wallet_utxos(), test_output(100_000), None, "invalid mnemonic", None, Network::Bitcoin, true, None),
+            Err(TransactionError::InvalidMnemonic)
+        ));
+
+        // Leaving out the change hands 20_000 sats to the miners, about 77 sat/vB
+        assert!(matches!(
+            build_psbt(wallet_utxos(), test_output(100_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, Some(50.0)),
+            Err(TransactionError::InvalidFeeRate { .. })
+        ));
+        assert!(build_psbt(wallet_utxos(), test_output(100_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, Some(100.0)).is_ok());
+        let mut utxos = wallet_utxos();
+        utxos[0].value = 5_000_000;
+        assert!(matches!(
+            build_psbt(utxos, test_output(100_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, None),
+            Err(TransactionError::InvalidFeeRate { .. })
+        ));
+        assert!(matches!(
+            build_psbt(wallet_utxos(), test_output(100_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, Some(f64::NAN)),
+            Err(TransactionError::InvalidFeeRate { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sign_psbt() {
+        let psbt = build_psbt(wallet_utxos(), test_output(100_000), Some(wallet_change()), TEST_MNEMONIC, None, Network::Bitcoin, true, None).unwrap();
+        let result = sign_psbt(&psbt.to_string(), TEST_MNEMONIC, None, Network::Bitcoin).unwrap();
+
+        assert_eq!(result.signed_inputs, vec![0, 1, 2]);
+        assert!(result.is_finalized);
+        let tx: Transaction = deserialize(&hex::decode(result.tx_hex.unwrap()).unwrap()).unwrap();
+        assert_eq!(tx.compute_txid().to_string(), result.txid.unwrap());
+
+        let signed = Psbt::from_str(&result.psbt).unwrap();
+        assert!(signed.inputs.iter().all(|i| i.bip32_derivation.is_empty() && i.partial_sigs.is_empty()));
+
+        // Check every signature against the sighash it commits to
+        let secp = Secp256k1::new();
+        let prevouts: Vec<TxOut> = psbt.inputs.iter().map(|i| i.witness_utxo.clone().unwrap()).collect();
+        let mut cache = SighashCache::new(&tx);
+
+        let witness = &tx.input[0].witness;
+        let signature = bitcoin::ecdsa::Signature::from_slice(&witness[0]).unwrap();
+        let public_key = bitcoin::PublicKey::from_slice(&witness[1]).unwrap();
+        let sighash = cache.p2wpkh_signature_hash(0, &prevouts[0].script_pubkey, prevouts[0].value, EcdsaSighashType::All).unwrap();
+        secp.verify_ecdsa(&Message::from(sighash), &signature.signature, &public_key.inner).unwrap();
+
+        let witness = &tx.input[1].witness;
+        let signature = bitcoin::ecdsa::Signature::from_slice(&witness[0]).unwrap();
+        let public_key = CompressedPublicKey::from_slice(&witness[1]).unwrap();
+        let redeem_script = ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash());
+        assert_eq!(tx.input[1].script_sig.as_bytes()[1..], *redeem_script.as_bytes());
+        let sighash = cache.p2wpkh_signature_hash(1, &redeem_script, prevouts[1].value, EcdsaSighashType::All).unwrap();
+        secp.verify_ecdsa(&Message::from(sighash), &signature.signature, &public_key.0).unwrap();
+
+        let witness = &tx.input[2].witness;
+        assert_eq!(witness.len(), 1);
+        let signature = bitcoin::taproot::Signature::from_slice(&witness[0]).unwrap();
+        let output_key = XOnlyPublicKey::from_slice(&prevouts[2].script_pubkey.as_bytes()[2..]).unwrap();
+        let sighash = cache.taproot_key_spend_signature_hash(2, &Prevouts::All(&prevouts), TapSighashType::Default).unwrap();
+        secp.verify_schnorr(&signature.signature, &Message::from(sighash), &output_key).unwrap();
+    }
+
+    #[test]
+    fn test_sign_psbt_with_foreign_keys() {
+        let psbt = build_psbt(wallet_utxos(), test_output(100_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, None).unwrap();
+
+        // A different passphrase yields a different master fingerprint, so nothing is signed
+        let result = sign_psbt(&psbt.to_string(), TEST_MNEMONIC, Some("passphrase"), Network::Bitcoin).unwrap();
+        assert!(result.signed_inputs.is_empty());
+        assert!(!result.is_finalized);
+        assert_eq!(result.tx_hex, None);
+
+        assert!(matches!(
+            sign_psbt("not a psbt", TEST_MNEMONIC, None, Network::Bitcoin),
+            Err(TransactionError::InvalidPsbt { .. })
+        ));
+    }
+
+    fn test_key(path: &str) -> PrivateKey {
+        let secp = Secp256k1::new();
+        let master = keys::master_key(TEST_MNEMONIC, None, Network::Bitcoin).unwrap();
+        master.derive_priv(&secp, &DerivationPath::from_str(path).unwrap()).unwrap().to_priv()
+    }
+
+    fn test_output_script(address_type: AddressType) -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let public_key = CompressedPublicKey::from_private_key(&secp, &test_key("m/0/0")).unwrap();
+        match address_type {
+            AddressType::P2WSH => ScriptBuf::new_p2wsh(&ScriptBuf::new_p2pk(&public_key.into()).wscript_hash()),
+            address_type => descriptor::script_address(&secp, address_type, &public_key, Network::Bitcoin).unwrap().script_pubkey(),
+        }
+    }
+
+    fn test_transaction(input_count: usize, outputs: &[AddressType]) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: (0..input_count)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint { txid: Txid::from_str(&test_utxo(0, 0, AddressType::P2WPKH).txid).unwrap(), vout: vout as u32 },
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    ..Default::default()
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|t| TxOut { value: Amount::from_sat(10_000), script_pubkey: test_output_script(*t) })
+                .collect(),
+        }
+    }
+
+    fn assert_estimate_covers(tx: &Transaction, inputs: &[AddressType], outputs: &[AddressType]) {
+        let estimate = estimate_tx_vsize(inputs, outputs).unwrap();
+        let actual = tx.vsize() as u64;
+        assert!(estimate >= actual, "estimate {} below actual {}", estimate, actual);
+        // Only shorter-than-worst-case signatures separate the estimate from the real size
+        assert!(estimate - actual <= inputs.len() as u64, "estimate {} too far above actual {}", estimate, actual);
+    }
+
+    #[test]
+    fn test_estimate_tx_vsize() {
+        assert_eq!(estimate_tx_vsize(&[AddressType::P2PKH], &[AddressType::P2PKH, AddressType::P2PKH]).unwrap(), 226);
+        assert_eq!(estimate_tx_vsize(&[AddressType::P2WPKH], &[AddressType::P2WPKH, AddressType::P2WPKH]).unwrap(), 141);
+        assert_eq!(estimate_tx_vsize(&[AddressType::P2TR], &[AddressType::P2TR, AddressType::P2TR]).unwrap(), 154);
+        assert_eq!(estimate_tx_vsize(&[AddressType::P2SH], &[AddressType::P2WPKH]).unwrap(), 133);
+        assert_eq!(estimate_tx_vsize(&[AddressType::P2WSH], &[AddressType::P2WSH]).unwrap(), 158);
+
+        // A legacy input in a segwit transaction carries an empty witness
+        let mixed = estimate_tx_vsize(&[AddressType::P2PKH, AddressType::P2WPKH], &[AddressType::P2WPKH]).unwrap();
+        assert_eq!(mixed, (40 + 2 + 592 + 1 + 272 + 124_u64).div_ceil(4));
+
+        // More than 252 inputs need a three-byte count
+        let many = vec![AddressType::P2WPKH; 253];
+        assert_eq!(estimate_tx_vsize(&many, &[AddressType::P2WPKH]).unwrap(), (48 + 2 + 253 * 272 + 124_u64).div_ceil(4));
+
+        assert!(matches!(
+            estimate_tx_vsize(&[AddressType::Unknown], &[AddressType::P2WPKH]),
+            Err(TransactionError::UnsupportedAddressType)
+        ));
+        assert!(matches!(estimate_tx_vsize(&[], &[AddressType::P2WPKH]), Err(TransactionError::InvalidInput { .. })));
+        assert!(matches!(estimate_tx_vsize(&[AddressType::P2WPKH], &[]), Err(TransactionError::InvalidOutput { .. })));
+    }
+
+    #[test]
+    fn test_estimate_fee() {
+        let inputs = [AddressType::P2WPKH];
+        let outputs = [AddressType::P2WPKH, AddressType::P2WPKH];
+        assert_eq!(estimate_fee(&inputs, &outputs, 1.0).unwrap(), 141);
+        assert_eq!(estimate_fee(&inputs, &outputs, 2.5).unwrap(), 353);
+        assert_eq!(estimate_fee(&inputs, &outputs, 0.0).unwrap(), 0);
+        assert!(matches!(estimate_fee(&inputs, &outputs, f64::NAN), Err(TransactionError::InvalidFeeRate { .. })));
+    }
+
+    #[test]
+    fn test_estimate_matches_signed_psbt_transaction() {
+        let psbt = build_psbt(wallet_utxos(), test_output(100_000), Some(wallet_change()), TEST_MNEMONIC, None, Network::Bitcoin, true, None).unwrap();
+        let result = sign_psbt(&psbt.to_string(), TEST_MNEMONIC, None, Network::Bitcoin).unwrap();
+        let tx: Transaction = deserialize(&hex::decode(result.tx_hex.unwrap()).unwrap()).unwrap();
+
+        assert_estimate_covers(
+            &tx,
+            &[AddressType::P2WPKH, AddressType::P2SH, AddressType::P2TR],
+            &[AddressType::P2WPKH, AddressType::P2WPKH],
+        );
+    }
+
+    #[test]
+    fn test_estimate_matches_signed_legacy_transaction() {
+        let secp = Secp256k1::new();
+        let key = test_key("m/44'/0'/0'/0/0");
+        let public_key = key.public_key(&secp);
+        let script_pubkey = ScriptBuf::new_p2pkh(&public_key.pubkey_hash());
+        let outputs = [AddressType::P2PKH, AddressType::P2SH];
+        let mut tx = test_transaction(2, &outputs);
+
+        let script_sigs: Vec<ScriptBuf> = (0..tx.input.len())
+            .map(|index| {
+                let sighash = SighashCache::new(&tx)
+                    .legacy_signature_hash(index, &script_pubkey, EcdsaSighashType::All.to_u32())
+                    .unwrap();
+                let signature = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&Message::from(sighash), &key.inner));
+                ScriptBuilder::new().push_slice(signature.serialize()).push_key(&public_key).into_script()
+            })
+            .collect();
+        for (input, script_sig) in tx.input.iter_mut().zip(script_sigs) {
+            input.script_sig = script_sig;
+        }
+
+        assert_estimate_covers(&tx, &[AddressType::P2PKH, AddressType::P2PKH], &outputs);
+    }
+
+    #[test]
+    fn test_estimate_matches_signed_multisig_transaction() {
+        let secp = Secp256k1::new();
+        let multisig_keys: Vec<PrivateKey> = (0..3).map(|i| test_key(&format!("m/48'/0'/0'/2'/0/{}", i))).collect();
+        let mut builder = ScriptBuilder::new().push_int(2);
+        for key in &multisig_keys {
+            builder = builder.push_key(&key.public_key(&secp));
+        }
+        let witness_script = builder.push_int(3).push_opcode(OP_CHECKMULTISIG).into_script();
+
+        let legacy_key = test_key("m/44'/0'/0'/0/0");
+        let legacy_public_key = legacy_key.public_key(&secp);
+        let wpkh_key = test_key("m/84'/0'/0'/0/0");
+        let wpkh_public_key = CompressedPublicKey::from_private_key(&secp, &wpkh_key).unwrap();
+
+        let inputs = [AddressType::P2WSH, AddressType::P2PKH, AddressType::P2WPKH];
+        let outputs = [AddressType::P2WSH, AddressType::P2TR];
+        let mut tx = test_transaction(inputs.len(), &outputs);
+        let value = Amount::from_sat(50_000);
+        let sign = |sighash: Message, key: &PrivateKey| {
+            bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&sighash, &key.inner)).to_vec()
+        };
+
+        let mut cache = SighashCache::new(tx.clone());
+        let sighash = Message::from(cache.p2wsh_signature_hash(0, &witness_script, value, EcdsaSighashType::All).unwrap());
+        let multisig_witness = Witness::from_slice(&[
+            vec![],
+            sign(sighash, &multisig_keys[0]),
+            sign(sighash, &multisig_keys[1]),
+            witness_script.to_bytes(),
+        ]);
+        let legacy_script = ScriptBuf::new_p2pkh(&legacy_public_key.pubkey_hash());
+        let sighash = Message::from(cache.legacy_signature_hash(1, &legacy_script, EcdsaSighashType::All.to_u32()).unwrap());
+        let legacy_signature = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&sighash, &legacy_key.inner));
+        let wpkh_script = ScriptBuf::new_p2wpkh(&wpkh_public_key.wpubkey_hash());
+        let sighash = Message::from(cache.p2wpkh_signature_hash(2, &wpkh_script, value, EcdsaSighashType::All).unwrap());
+
+        tx.input[0].witness = multisig_witness;
+        tx.input[1].script_sig = ScriptBuilder::new()
+            .push_slice(legacy_signature.serialize())
+            .push_key(&legacy_public_key)
+            .into_script();
+        tx.input[2].witness = Witness::from_slice(&[sign(sighash, &wpkh_key), wpkh_public_key.to_bytes().to_vec()]);
+
+        assert_estimate_covers(&tx, &inputs, &outputs);
+    }
+
+    /// Signed 3-input transaction paying 100,000 sats with 19,000 sats change and a 1,000 sat fee.
+    fn signed_wallet_transaction(enable_rbf: bool) -> Transaction {
+        let psbt = build_psbt(wallet_utxos(), test_output(100_000), Some(wallet_change()), TEST_MNEMONIC, None, Network::Bitcoin, enable_rbf, None).unwrap();
+        let result = sign_psbt(&psbt.to_string(), TEST_MNEMONIC, None, Network::Bitcoin).unwrap();
+        deserialize(&hex::decode(result.tx_hex.unwrap()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_build_rbf_psbt() {
+        let tx = signed_wallet_transaction(true);
+        let tx_hex = serialize_hex(&tx);
+
+        let result = build_rbf_psbt(&tx_hex, wallet_utxos(), 1, Some(CHANGE_PATH), 20.0, TEST_MNEMONIC, None, Network::Bitcoin, None).unwrap();
+        assert_eq!(result.vsize, 289);
+        assert_eq!(result.fee, 5_780);
+        assert_eq!(result.effective_fee_rate, 20.0);
+
+        let psbt = Psbt::from_str(&result.psbt).unwrap();
+        assert_eq!(psbt.fee().unwrap().to_sat(), 5_780);
+        let original_inputs: Vec<OutPoint> = tx.input.iter().map(|i| i.previous_output).collect();
+        let replacement_inputs: Vec<OutPoint> = psbt.unsigned_tx.input.iter().map(|i| i.previous_output).collect();
+        assert_eq!(original_inputs, replacement_inputs);
+        assert_eq!(psbt.unsigned_tx.output[0].value.to_sat(), 100_000);
+        assert_eq!(psbt.unsigned_tx.output[1].value.to_sat(), 19_000 - 4_780);
+        assert!(psbt.unsigned_tx.is_explicitly_rbf());
+        assert!(psbt.outputs[0].bip32_derivation.is_empty());
+        let (_, path) = psbt.outputs[1].bip32_derivation.values().next().unwrap();
+        assert_eq!(path.to_string(), "84'/0'/0'/1/0");
+
+        let signed = sign_psbt(&result.psbt, TEST_MNEMONIC, None, Network::Bitcoin).unwrap();
+        assert!(signed.is_finalized);
+        assert_ne!(signed.txid.unwrap(), tx.compute_txid().to_string());
+    }
+
+    #[test]
+    fn test_build_rbf_psbt_incremental_relay_fee() {
+        let tx_hex = serialize_hex(&signed_wallet_transaction(true));
+
+        // 4 sat/vB alone would only add 156 sats, less than the 289 sats the
+        // incremental relay fee requires for a 289 vB replacement
+        let result = build_rbf_psbt(&tx_hex, wallet_utxos(), 1, Some(CHANGE_PATH), 4.0, TEST_MNEMONIC, None, Network::Bitcoin, None).unwrap();
+        assert_eq!(result.fee, 1_000 + 289);
+        assert!(result.effective_fee_rate > 4.0);
+
+        let result = build_rbf_psbt(&tx_hex, wallet_utxos(), 1, Some(CHANGE_PATH), 4.0, TEST_MNEMONIC, None, Network::Bitcoin, Some(2.0)).unwrap();
+        assert_eq!(result.fee, 1_000 + 578);
+    }
+
+    #[test]
+    fn test_build_rbf_psbt_drops_dust_change() {
+        let tx_hex = serialize_hex(&signed_wallet_transaction(true));
+
+        let result = build_rbf_psbt(&tx_hex, wallet_utxos(), 1, Some(CHANGE_PATH), 69.0, TEST_MNEMONIC, None, Network::Bitcoin, None).unwrap();
+        let psbt = Psbt::from_str(&result.psbt).unwrap();
+        assert_eq!(psbt.unsigned_tx.output.len(), 1);
+        assert_eq!(result.fee, 20_000);
+        assert_eq!(result.vsize, 258);
+
+        assert!(matches!(
+            build_rbf_psbt(&tx_hex, wallet_utxos(), 1, Some(CHANGE_PATH), 200.0, TEST_MNEMONIC, None, Network::Bitcoin, None),
+            Err(TransactionError::InsufficientFunds { available: 20_000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_build_rbf_psbt_errors() {
+        let tx_hex = serialize_hex(&signed_wallet_transaction(true));
+
+        assert!(matches!(
+            build_rbf_psbt(&serialize_hex(&signed_wallet_transaction(false)), wallet_utxos(), 1, Some(CHANGE_PATH), 20.0, TEST_MNEMONIC, None, Network::Bitcoin, None),
+            Err(TransactionError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            build_rbf_psbt(&tx_hex, wallet_utxos()[..2].to_vec(), 1, Some(CHANGE_PATH), 20.0, TEST_MNEMONIC, None, Network::Bitcoin, None),
+            Err(TransactionError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            build_rbf_psbt(&tx_hex, wallet_utxos(), 1, Some(CHANGE_PATH), 2.0, TEST_MNEMONIC, None, Network::Bitcoin, None),
+            Err(TransactionError::InvalidFeeRate { .. })
+        ));
+        assert!(matches!(
+            build_rbf_psbt(&tx_hex, wallet_utxos(), 5, Some(CHANGE_PATH), 20.0, TEST_MNEMONIC, None, Network::Bitcoin, None),
+            Err(TransactionError::InvalidOutput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_build_cpfp_psbt() {
+        let parent = signed_wallet_transaction(true);
+        let parent_vsize = parent.vsize() as u64;
+        let destination = "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g";
+
+        let result = build_cpfp_psbt(
+            &serialize_hex(&parent), wallet_utxos(), 1, "m/84'/0'/0'/1/0", destination, 10.0, TEST_MNEMONIC, None, Network::Bitcoin,
+        ).unwrap();
+        assert_eq!(result.vsize, 110);
+        assert_eq!(result.fee, (parent_vsize + 110) * 10 - 1_000);
+        assert!(result.effective_fee_rate >= 10.0 && result.effective_fee_rate < 10.01);
+        assert!(result.fee_rate > 10.0);
+
+        let psbt = Psbt::from_str(&result.psbt).unwrap();
+        assert_eq!(psbt.unsigned_tx.input[0].previous_output, OutPoint { txid: parent.compute_txid(), vout: 1 });
+        assert_eq!(psbt.unsigned_tx.output[0].value.to_sat(), 19_000 - result.fee);
+        assert!(sign_psbt(&result.psbt, TEST_MNEMONIC, None, Network::Bitcoin).unwrap().is_finalized);
+
+        // Spending the output with a key that does not control it fails
+        assert!(matches!(
+            build_cpfp_psbt(&serialize_hex(&parent), wallet_utxos(), 1, "m/84'/0'/0'/1/1", destination, 10.0, TEST_MNEMONIC, None, Network::Bitcoin),
+            Err(TransactionError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            build_cpfp_psbt(&serialize_hex(&parent), wallet_utxos(), 1, "m/84'/0'/0'/1/0", destination, 500.0, TEST_MNEMONIC, None, Network::Bitcoin),
+            Err(TransactionError::InsufficientFunds { .. })
+        ));
+        assert!(matches!(
+            build_cpfp_psbt(&serialize_hex(&parent), wallet_utxos(), 1, "m/84'/0'/0'/1/0", destination, 1.0, TEST_MNEMONIC, None, Network::Bitcoin),
+            Err(TransactionError::InvalidFeeRate { .. })
+        ));
+    }
+
+    // BIP322 test vectors
+    const BIP322_PRIVATE_KEY: &str = "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k";
+    const BIP322_P2WPKH_ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const BIP322_P2TR_ADDRESS: &str = "bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3";
+
+    #[test]
+    fn test_verify_message_bip322_vectors() {
+        let empty = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let hello = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let taproot = "AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==";
+
+        assert!(verify_message(BIP322_P2WPKH_ADDRESS, "", empty).unwrap());
+        assert!(verify_message(BIP322_P2WPKH_ADDRESS, "Hello World", hello).unwrap());
+        assert!(verify_message(BIP322_P2TR_ADDRESS, "Hello World", taproot).unwrap());
+
+        assert!(!verify_message(BIP322_P2WPKH_ADDRESS, "Hello World", empty).unwrap());
+        assert!(!verify_message(BIP322_P2WPKH_ADDRESS, "", hello).unwrap());
+        assert!(!verify_message(BIP322_P2TR_ADDRESS, "Hello world", taproot).unwrap());
+        assert!(!verify_message(P2WPKH_ADDRESS, "Hello World", hello).unwrap());
+    }
+
+    #[test]
+    fn test_sign_message_bip322_with_key() {
+        let secp = Secp256k1::new();
+        let key = PrivateKey::from_wif(BIP322_PRIVATE_KEY).unwrap();
+
+        // Deterministic low-R ECDSA signatures reproduce the vector exactly
+        let signed = message::sign_message_with_key(&secp, &key, AddressType::P2WPKH, "Hello World", MessageSignatureFormat::Bip322Simple, Network::Bitcoin).unwrap();
+        assert_eq!(signed.address, BIP322_P2WPKH_ADDRESS);
+        assert_eq!(
+            signed.signature,
+            "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI="
+        );
+
+        let signed = message::sign_message_with_key(&secp, &key, AddressType::P2TR, "Hello World", MessageSignatureFormat::Bip322Simple, Network::Bitcoin).unwrap();
+        assert_eq!(signed.address, BIP322_P2TR_ADDRESS);
+        assert!(verify_message(&signed.address, "Hello World", &signed.signature).unwrap());
+    }
+
+    #[test]
+    fn test_sign_and_verify_message() {
+        let cases = [
+            ("m/44'/0'/0'/0/0", MessageSignatureFormat::Bip137, "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"),
+            ("m/49'/0'/0'/0/0", MessageSignatureFormat::Bip137, P2SH_ADDRESS),
+            ("m/84'/0'/0'/0/0", MessageSignatureFormat::Bip137, P2WPKH_ADDRESS),
+            ("m/84'/0'/0'/0/0", MessageSignatureFormat::Bip322Simple, P2WPKH_ADDRESS),
+            ("m/86'/0'/0'/0/0", MessageSignatureFormat::Bip322Simple, P2TR_ADDRESS),
+        ];
+
+        for (path, format, address) in cases {
+            let signed = sign_message(TEST_MNEMONIC, path, "Proof of reserves", format, None, Network::Bitcoin).unwrap();
+            assert_eq!(signed.address, address);
+            assert_eq!(signed.format, format);
+            assert!(verify_message(address, "Proof of reserves", &signed.signature).unwrap(), "{} {:?}", path, format);
+            assert!(!verify_message(address, "Proof of reserve", &signed.signature).unwrap(), "{} {:?}", path, format);
+        }
+
+        // BIP137 headers encode the address type
+        let signed = sign_message(TEST_MNEMONIC, "m/84'/0'/0'/0/0", "test", MessageSignatureFormat::Bip137, None, Network::Bitcoin).unwrap();
+        let header = BASE64.decode(&signed.signature).unwrap()[0];
+        assert!((39..=42).contains(&header));
+        assert!(!verify_message(P2SH_ADDRESS, "test", &signed.signature).unwrap());
+    }
+
+    #[test]
+    fn test_sign_message_errors() {
+        assert!(matches!(
+            sign_message(TEST_MNEMONIC, "m/86'/0'/0'/0/0", "test", MessageSignatureFormat::Bip137, None, Network::Bitcoin),
+            Err(MessageSigningError::UnsupportedFormat { .. })
+        ));
+        assert!(matches!(
+            sign_message(TEST_MNEMONIC, "m/44'/0'/0'/0/0", "test", MessageSignatureFormat::Bip322Simple, None, Network::Bitcoin),
+            Err(MessageSigningError::UnsupportedFormat { .. })
+        ));
+        assert!(matches!(
+            sign_message(TEST_MNEMONIC, "m/84'/1'/0'/0/0", "test", MessageSignatureFormat::Bip322Simple, None, Network::Bitcoin),
+            Err(MessageSigningError::KeyDerivationFailed { .. })
+        ));
+        assert!(matches!(
+            verify_message("not an address", "test", "AAAA"),
+            Err(MessageSigningError::InvalidAddress { .. })
+        ));
+        assert!(matches!(
+            verify_message(P2WPKH_ADDRESS, "test", "not base64!"),
+            Err(MessageSigningError::InvalidSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_transaction_bip143_vector() {
+        // Native P2WPKH example from BIP143, spending a P2PK and a P2WPKH output
+        let tx_hex = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+
+        let decoded = decode_transaction(tx_hex, Network::Bitcoin).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert_eq!(decoded.locktime, 17);
+        // The first input's 0xffffffee sequence signals replaceability
+        assert!(decoded.is_rbf);
+        assert!(!decoded.is_coinbase);
+        assert_eq!(decoded.size, tx_hex.len() as u64 / 2);
+        assert_ne!(decoded.txid, decoded.wtxid);
+        assert_eq!(decoded.vsize, decoded.weight.div_ceil(4));
+
+        assert_eq!(decoded.inputs.len(), 2);
+        assert_eq!(decoded.inputs[0].txid, "9f96ade4b41d5433f4eda31e1738ec2b36f6e7d1420d94a6af99801a88f7f7ff");
+        assert_eq!(decoded.inputs[0].vout, 0);
+        assert_eq!(decoded.inputs[0].sequence, 0xffffffee);
+        assert_eq!(decoded.inputs[0].witness_type, InputWitnessType::Legacy);
+        assert_eq!(decoded.inputs[1].vout, 1);
+        assert_eq!(decoded.inputs[1].witness_type, InputWitnessType::P2WPKH);
+        assert_eq!(decoded.inputs[1].witness.len(), 2);
+
+        assert_eq!(decoded.outputs.len(), 2);
+        assert_eq!(decoded.outputs[0].value, 112_340_000);
+        assert_eq!(decoded.outputs[0].script_type, OutputScriptType::P2PKH);
+        assert_eq!(decoded.outputs[0].script_pubkey, "76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac");
+        let expected = Address::from_script(&ScriptBuf::from_hex(&decoded.outputs[0].script_pubkey).unwrap(), Network::Bitcoin).unwrap();
+        assert_eq!(decoded.outputs[0].address, Some(expected.to_string()));
+        assert_eq!(decoded.outputs[1].value, 223_450_000);
+
+        // Addresses follow the requested network
+        let decoded = decode_transaction(tx_hex, Network::Testnet).unwrap();
+        assert!(decoded.outputs[0].address.as_ref().unwrap().starts_with(['m', 'n']));
+    }
+
+    #[test]
+    fn test_decode_transaction_witness_types() {
+        let tx = signed_wallet_transaction(true);
+        let decoded = decode_transaction(&serialize_hex(&tx), Network::Bitcoin).unwrap();
+
+        assert_eq!(decoded.txid, tx.compute_txid().to_string());
+        assert_eq!(decoded.wtxid, tx.compute_wtxid().to_string());
+        assert_eq!(decoded.version, 2);
+        assert_eq!(decoded.vsize, tx.vsize() as u64);
+        assert_eq!(decoded.weight, tx.weight().to_wu());
+        assert!(decoded.is_rbf);
+        let witness_types: Vec<InputWitnessType> = decoded.inputs.iter().map(|i| i.witness_type).collect();
+        assert_eq!(witness_types, vec![InputWitnessType::P2WPKH, InputWitnessType::NestedP2WPKH, InputWitnessType::TaprootKeyPath]);
+        assert_eq!(decoded.outputs[0].address.as_deref(), Some(P2WPKH_ADDRESS));
+        assert_eq!(decoded.outputs[1].address.as_deref(), Some(CHANGE_ADDRESS));
+        assert!(decoded.outputs.iter().all(|o| o.script_type == OutputScriptType::P2WPKH));
+
+        let tx = signed_wallet_transaction(false);
+        assert!(!decode_transaction(&serialize_hex(&tx), Network::Bitcoin).unwrap().is_rbf);
+    }
+
+    #[test]
+    fn test_decode_transaction_script_types() {
+        let secp = Secp256k1::new();
+        let public_key = test_key("m/0/0").public_key(&secp);
+        let mut tx = test_transaction(3, &[AddressType::P2SH, AddressType::P2WSH, AddressType::P2TR]);
+        tx.output.push(TxOut { value: Amount::ZERO, script_pubkey: ScriptBuf::new_op_return([1u8, 2, 3]) });
+        tx.output.push(TxOut { value: Amount::from_sat(1_000), script_pubkey: ScriptBuf::new_p2pk(&public_key) });
+
+        let multisig_script = ScriptBuilder::new().push_int(1).push_key(&public_key).push_int(1).push_opcode(OP_CHECKMULTISIG).into_script();
+        let control_block = [vec![0xc0], vec![0x02; 32]].concat();
+        tx.input[0].witness = Witness::from_slice(&[vec![], vec![0x30; 71], multisig_script.to_bytes()]);
+        tx.input[1].witness = Witness::from_slice(&[vec![0x01; 64], vec![0x20; 34], control_block]);
+        tx.input[2].witness = Witness::from_slice(&[vec![], vec![0x30; 71], multisig_script.to_bytes()]);
+        tx.input[2].script_sig = ScriptBuilder::new()
+            .push_slice(<&bitcoin::script::PushBytes>::try_from(ScriptBuf::new_p2wsh(&multisig_script.wscript_hash()).as_bytes()).unwrap())
+            .into_script();
+
+        let decoded = decode_transaction(&serialize_hex(&tx), Network::Bitcoin).unwrap();
+        let witness_types: Vec<InputWitnessType> = decoded.inputs.iter().map(|i| i.witness_type).collect();
+        assert_eq!(witness_types, vec![InputWitnessType::P2WSH, InputWitnessType::TaprootScriptPath, InputWitnessType::NestedP2WSH]);
+        let script_types: Vec<OutputScriptType> = decoded.outputs.iter().map(|o| o.script_type).collect();
+        assert_eq!(script_types, vec![
+            OutputScriptType::P2SH,
+            OutputScriptType::P2WSH,
+            OutputScriptType::P2TR,
+            OutputScriptType::OpReturn,
+            OutputScriptType::P2PK,
+        ]);
+        assert_eq!(decoded.outputs[3].address, None);
+        assert_eq!(decoded.outputs[3].script_pubkey, "6a03010203");
+
+        assert!(matches!(decode_transaction("zz", Network::Bitcoin), Err(TransactionError::InvalidTransaction { .. })));
+        assert!(matches!(decode_transaction("0100", Network::Bitcoin), Err(TransactionError::InvalidTransaction { .. })));
+    }
+
+    /// Master key of the BIP85 test vectors.
+    const BIP85_MASTER: &str = "xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb";
+
+    fn bip85_root() -> Xpriv {
+        Xpriv::from_str(BIP85_MASTER).unwrap()
+    }
+
+    #[test]
+    fn test_bip85_entropy_vectors() {
+        let root = bip85_root();
+        let entropy = bip85::derive_entropy(&root, &DerivationPath::from_str("m/83696968'/0'/0'").unwrap()).unwrap();
+        assert_eq!(
+            hex::encode(entropy),
+            "efecfbccffea313214232d29e71563d941229afb4338c21f9517c41aaa0d16f00b83d2a09ef747e7a64e8e2bd5a14869e693da66ce94ac2da570ab7ee48618f7"
+        );
+        let entropy = bip85::derive_entropy(&root, &DerivationPath::from_str("m/83696968'/0'/1'").unwrap()).unwrap();
+        assert_eq!(
+            hex::encode(entropy),
+            "70c6e3e8ebee8dc4c0dbba66076819bb8c09672527c4277ca8729532ad711872218f826919f6b67218adde99018a6df9095ab2b58d803b5b93ec9802085a690e"
+        );
+    }
+
+    #[test]
+    fn test_bip85_application_vectors() {
+        let root = bip85_root();
+        assert_eq!(
+            bip85::mnemonic_from_root(&root, WordCount::Words12, 0).unwrap(),
+            "girl mad pet galaxy egg matter matrix prison refuse sense ordinary nose"
+        );
+        assert_eq!(
+            bip85::mnemonic_from_root(&root, WordCount::Words18, 0).unwrap(),
+            "near account window bike charge season chef number sketch tomorrow excuse sniff circle vital hockey outdoor supply token"
+        );
+        assert_eq!(
+            bip85::mnemonic_from_root(&root, WordCount::Words24, 0).unwrap(),
+            "puppy ocean match cereal symbol another shed magic wrap hammer bulb intact gadget divorce twin tonight reason outdoor destroy simple truth cigar social volcano"
+        );
+        assert_eq!(
+            bip85::wif_from_root(&root, 0, Network::Bitcoin).unwrap(),
+            "Kzyv4uF39d4Jrw2W7UryTHwZr1zQVNk4dAFyqE6BuMrMh1Za7uhp"
+        );
+        assert_eq!(
+            bip85::xprv_from_root(&root, 0, Network::Bitcoin).unwrap(),
+            "xprv9s21ZrQH143K2srSbCSg4m4kLvPMzcWydgmKEnMmoZUurYuBuYG46c6P71UGXMzmriLzCCBvKQWBUv3vPB3m1SATMhp3uEjXHJ42jFg7myX"
+        );
+        assert_eq!(
+            bip85::hex_from_root(&root, 64, 0).unwrap(),
+            "492db4698cf3b73a5a24998aa3e9d7fa96275d85724a91e71aa2d645442f878555d078fd1f1f67e368976f04137b1f7a0d19232136ca50c44614af72b5582a5c"
+        );
+    }
+
+    #[test]
+    fn test_bip85_from_mnemonic() {
+        for (word_count, words) in [
+            (WordCount::Words12, 12),
+            (WordCount::Words15, 15),
+            (WordCount::Words18, 18),
+            (WordCount::Words21, 21),
+            (WordCount::Words24, 24),
+        ] {
+            let child = derive_bip85_mnemonic(TEST_MNEMONIC, None, word_count, 0).unwrap();
+            assert_eq!(child.split_whitespace().count(), words);
+            assert!(bip39::Mnemonic::parse(&child).is_ok());
+            assert_eq!(child, derive_bip85_mnemonic(TEST_MNEMONIC, None, word_count, 0).unwrap());
+        }
+
+        let first = derive_bip85_mnemonic(TEST_MNEMONIC, None, WordCount::Words12, 0).unwrap();
+        assert_ne!(first, derive_bip85_mnemonic(TEST_MNEMONIC, None, WordCount::Words12, 1).unwrap());
+        assert_ne!(first, derive_bip85_mnemonic(TEST_MNEMONIC, Some("TREZOR"), WordCount::Words12, 0).unwrap());
+
+        let wif = derive_bip85_wif(TEST_MNEMONIC, None, 0, Network::Testnet).unwrap();
+        assert_eq!(PrivateKey::from_wif(&wif).unwrap().network, bitcoin::NetworkKind::Test);
+        assert!(derive_bip85_xprv(TEST_MNEMONIC, None, 0, Network::Testnet).unwrap().starts_with("tprv"));
+        assert_eq!(derive_bip85_hex(TEST_MNEMONIC, None, 16, 0).unwrap().len(), 32);
+
+        assert!(matches!(derive_bip85_hex(TEST_MNEMONIC, None, 15, 0), Err(AddressError::InvalidLength)));
+        assert!(matches!(derive_bip85_hex(TEST_MNEMONIC, None, 65, 0), Err(AddressError::InvalidLength)));
+        assert!(matches!(derive_bip85_wif(TEST_MNEMONIC, None, 1 << 31, Network::Bitcoin), Err(AddressError::InvalidDerivationPath)));
+        assert!(matches!(derive_bip85_mnemonic("not a mnemonic", None, WordCount::Words12, 0), Err(AddressError::InvalidMnemonic)));
+    }
+
+    fn slip39_shares(shares: &[&str]) -> Vec<String> {
+        shares.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_slip39_vectors() {
+        let single = slip39_shares(&["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard"]);
+        assert_eq!(hex::encode(slip39::combine_master_secret(&single, Some("TREZOR")).unwrap()), "bb54aac4b89dc868ba37d9cc21b2cece");
+
+        let two_of_three = slip39_shares(&[
+            "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed",
+            "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking",
+        ]);
+        assert_eq!(hex::encode(slip39::combine_master_secret(&two_of_three, Some("TREZOR")).unwrap()), "b43ceb7e57a0ea8766221624d01b0864");
+
+        let long = slip39_shares(&["theory painting academic academic armed sweater year military elder discuss acne wildlife boring employer fused large satoshi bundle carbon diagnose anatomy hamster leaves tracks paces beyond phantom capital marvel lips brave detect luck"]);
+        assert_eq!(
+            hex::encode(slip39::combine_master_secret(&long, Some("TREZOR")).unwrap()),
+            "989baf9dcaad5b10ca33dfd8cc75e42477025dce88ae83e75a230086a0e00e92"
+        );
+
+        let extendable = slip39_shares(&["testify swimming academic academic column loyalty smear include exotic bedroom exotic wrist lobe cover grief golden smart junior estimate learn"]);
+        assert_eq!(hex::encode(slip39::combine_master_secret(&extendable, Some("TREZOR")).unwrap()), "1679b4516e0ee5954351d288a838f45e");
+        assert!(validate_slip39_share(&extendable[0]).unwrap().extendable);
+
+        // The recovered entropy is returned as a BIP39 mnemonic
+        let expected = bip39::Mnemonic::from_entropy(&hex::decode("bb54aac4b89dc868ba37d9cc21b2cece").unwrap()).unwrap();
+        assert_eq!(combine_slip39_shares(&single, Some("TREZOR")).unwrap(), expected.to_string());
+    }
+
+    #[test]
+    fn test_slip39_invalid_vectors() {
+        let bad_checksum = "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision kidney";
+        assert!(matches!(validate_slip39_share(bad_checksum), Err(Slip39Error::InvalidChecksum { .. })));
+        let bad_padding = "duckling enlarge academic academic email result length solution fridge kidney coal piece deal husband erode duke ajar music cargo fitness";
+        assert!(matches!(validate_slip39_share(bad_padding), Err(Slip39Error::InvalidShare { .. })));
+        assert!(matches!(validate_slip39_share("duckling enlarge academic"), Err(Slip39Error::InvalidShare { .. })));
+        assert!(matches!(validate_slip39_share("abandon ability able about above absent absorb abstract absurd abuse access accident account accuse achieve acid acoustic acquire across act"), Err(Slip39Error::InvalidShare { .. })));
+
+        let one_of_two = slip39_shares(&["shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed"]);
+        assert!(matches!(combine_slip39_shares(&one_of_two, Some("TREZOR")), Err(Slip39Error::InsufficientShares { .. })));
+        assert!(matches!(combine_slip39_shares(&[], None), Err(Slip39Error::InsufficientShares { .. })));
+
+        let mixed = slip39_shares(&[
+            "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed",
+            "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard",
+        ]);
+        assert!(matches!(combine_slip39_shares(&mixed, None), Err(Slip39Error::MismatchedShares { .. })));
+    }
+
+    #[test]
+    fn test_slip39_split_and_combine() {
+        let groups = vec![
+            Slip39Group { member_threshold: 1, member_count: 1 },
+            Slip39Group { member_threshold: 2, member_count: 3 },
+            Slip39Group { member_threshold: 3, member_count: 5 },
+        ];
+        let split = split_slip39_mnemonic(TEST_MNEMONIC, 2, &groups, Some("secret"), 0, true).unwrap();
+        assert_eq!(split.iter().map(|g| g.shares.len()).collect::<Vec<_>>(), vec![1, 3, 5]);
+
+        let info = validate_slip39_share(&split[2].shares[4]).unwrap();
+        assert_eq!((info.group_index, info.group_threshold, info.group_count), (2, 2, 3));
+        assert_eq!((info.member_index, info.member_threshold, info.word_count), (4, 3, 20));
+        assert!(split.iter().flat_map(|g| &g.shares).all(|s| validate_slip39_share(s).unwrap().identifier == info.identifier));
+
+        let recovered = [split[0].shares[0].clone(), split[1].shares[2].clone(), split[1].shares[0].clone()];
+        assert_eq!(combine_slip39_shares(&recovered, Some("secret")).unwrap(), TEST_MNEMONIC);
+        let recovered = [split[1].shares[1].clone(), split[2].shares[0].clone(), split[1].shares[2].clone(), split[2].shares[3].clone(), split[2].shares[4].clone()];
+        assert_eq!(combine_slip39_shares(&recovered, Some("secret")).unwrap(), TEST_MNEMONIC);
+        // A wrong passphrase decrypts to a different secret
+        assert_ne!(combine_slip39_shares(&recovered, Some("other")).unwrap(), TEST_MNEMONIC);
+
+        let incomplete = [split[0].shares[0].clone(), split[2].shares[0].clone(), split[2].shares[1].clone()];
+        match combine_slip39_shares(&incomplete, Some("secret")) {
+            Err(Slip39Error::InsufficientShares { error_details }) => assert!(error_details.contains("group 3 has 2 of 3")),
+            other => panic!("expected InsufficientShares, got {:?}", other),
+        }
+
+        let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+        let split = split_slip39_mnemonic(mnemonic_24, 1, &[Slip39Group { member_threshold: 2, member_count: 2 }], None, 0, false).unwrap();
+        assert_eq!(split[0].shares[0].split_whitespace().count(), 33);
+        assert_eq!(combine_slip39_shares(&split[0].shares, None).unwrap(), mnemonic_24);
+
+        let invalid = |threshold, groups: &[Slip39Group]| matches!(
+            split_slip39_mnemonic(TEST_MNEMONIC, threshold, groups, None, 0, true),
+            Err(Slip39Error::InvalidParameters { .. })
+        );
+        assert!(invalid(4, &groups));
+        assert!(invalid(0, &groups));
+        assert!(invalid(1, &[Slip39Group { member_threshold: 1, member_count: 2 }]));
+        assert!(invalid(1, &[Slip39Group { member_threshold: 3, member_count: 2 }]));
+        assert!(invalid(1, &[Slip39Group { member_threshold: 2, member_count: 17 }]));
+        assert!(matches!(
+            split_slip39_mnemonic("not a mnemonic", 1, &groups, None, 0, true),
+            Err(Slip39Error::InvalidMnemonic { .. })
+        ));
+    }
+
+    #[test]
+    fn test_seed_envelope_round_trip() {
+        let file = envelope::encrypt_with_cost(TEST_MNEMONIC, Some("TREZOR"), "12345678", crate::onchain::Network::Testnet, 10).unwrap();
+        assert!(!file.contains("abandon"));
+
+        let contents = decrypt_seed_envelope(&file, "12345678").unwrap();
+        assert_eq!(contents.mnemonic, TEST_MNEMONIC);
+        assert_eq!(contents.passphrase.as_deref(), Some("TREZOR"));
+        assert_eq!(contents.network, crate::onchain::Network::Testnet);
+        let master = keys::master_key(TEST_MNEMONIC, Some("TREZOR"), Network::Bitcoin).unwrap();
+        assert_eq!(contents.fingerprint, master.fingerprint(&Secp256k1::new()).to_string());
+        assert!(contents.created_at > 1_700_000_000);
+
+        // A fresh salt and nonce make every envelope different
+        let other = envelope::encrypt_with_cost(TEST_MNEMONIC, Some("TREZOR"), "12345678", crate::onchain::Network::Testnet, 10).unwrap();
+        assert_ne!(file, other);
+        assert!(decrypt_seed_envelope(&encrypt_seed_envelope(TEST_MNEMONIC, None, "correct horse", crate::onchain::Network::Bitcoin).unwrap(), "correct horse")
+            .unwrap()
+            .passphrase
+            .is_none());
+    }
+
+    #[test]
+    fn test_seed_envelope_rejects_bad_input() {
+        let file = envelope::encrypt_with_cost(TEST_MNEMONIC, None, "12345678", crate::onchain::Network::Bitcoin, 10).unwrap();
+        assert!(matches!(decrypt_seed_envelope(&file, "87654321"), Err(SeedEnvelopeError::DecryptionFailed)));
+
+        let mut json: serde_json::Value = serde_json::from_str(&file).unwrap();
+        let mut ciphertext = BASE64.decode(json["ciphertext"].as_str().unwrap()).unwrap();
+        ciphertext[0] ^= 1;
+        json["ciphertext"] = BASE64.encode(&ciphertext).into();
+        assert!(matches!(decrypt_seed_envelope(&json.to_string(), "12345678"), Err(SeedEnvelopeError::DecryptionFailed)));
+
+        let mut json: serde_json::Value = serde_json::from_str(&file).unwrap();
+        json["version"] = 2.into();
+        assert!(matches!(decrypt_seed_envelope(&json.to_string(), "12345678"), Err(SeedEnvelopeError::UnsupportedVersion { .. })));
+        json["version"] = 1.into();
+        // Anything past one step above the default cost is rejected before scrypt runs
+        for (log_n, r, p) in [(30, 8, 1), (20, 8, 1), (0, 8, 1), (10, 65536, 65536), (10, 64, 1), (10, 8, 8)] {
+            json["kdf"]["log_n"] = log_n.into();
+            json["kdf"]["r"] = r.into();
+            json["kdf"]["p"] = p.into();
+            let started = Instant::now();
+            assert!(matches!(decrypt_seed_envelope(&json.to_string(), "12345678"), Err(SeedEnvelopeError::InvalidEnvelope { .. })));
+            assert!(started.elapsed() < Duration::from_millis(100));
+        }
+        let mut json: serde_json::Value = serde_json::from_str(&file).unwrap();
+        json["kdf"]["salt"] = "".into();
+        assert!(matches!(decrypt_seed_envelope(&json.to_string(), "12345678"), Err(SeedEnvelopeError::InvalidEnvelope { .. })));
+
+        assert!(matches!(decrypt_seed_envelope("not json", "12345678"), Err(SeedEnvelopeError::InvalidEnvelope { .. })));
+        assert!(matches!(
+            envelope::encrypt_with_cost(TEST_MNEMONIC, None, "1234567", crate::onchain::Network::Bitcoin, 10),
+            Err(SeedEnvelopeError::InvalidPin { .. })
+        ));
+        assert!(matches!(
+            envelope::encrypt_with_cost("abandon abandon", None, "12345678", crate::onchain::Network::Bitcoin, 10),
+            Err(SeedEnvelopeError::InvalidMnemonic)
+        ));
+    }
+
+    #[test]
+    fn test_master_fingerprint_and_key_origin() {
+        assert_eq!(get_master_fingerprint(TEST_MNEMONIC, None).unwrap(), "73c5da0a");
+        assert_ne!(get_master_fingerprint(TEST_MNEMONIC, Some("TREZOR")).unwrap(), "73c5da0a");
+        assert!(matches!(get_master_fingerprint("abandon abandon", None), Err(AddressError::InvalidMnemonic)));
+
+        let origin = get_key_origin(TEST_MNEMONIC, None, "m/84'/0'/0'", Network::Bitcoin).unwrap();
+        assert_eq!(
+            origin,
+            "[73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V"
+        );
+        assert!(get_key_origin(TEST_MNEMONIC, None, "m", Network::Bitcoin).unwrap().starts_with("[73c5da0a]xpub661MyMwAqRbcF"));
+        assert!(get_key_origin(TEST_MNEMONIC, None, "m/84'/1'/0'", Network::Testnet).unwrap().starts_with("[73c5da0a/84'/1'/0']tpub"));
+        assert!(matches!(get_key_origin(TEST_MNEMONIC, None, "m/x", Network::Bitcoin), Err(AddressError::InvalidDerivationPath)));
+
+        // The output can be used as a descriptor key and parsed back
+        let key = parse_key_expression(&origin).unwrap();
+        let (fingerprint, path) = key.origin.unwrap();
+        assert_eq!(fingerprint.to_string(), "73c5da0a");
+        assert_eq!(path, DerivationPath::from_str("m/84'/0'/0'").unwrap());
+        assert_eq!(key.xpub.depth, 3);
+        assert!(matches!(
+            parse_key_expression("[73c5da0a/84'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V"),
+            Err(AddressError::DerivationPathMismatch)
+        ));
+        // Right depth, but the account 0' key under an account 1' origin
+        assert!(matches!(
+            parse_key_expression("[73c5da0a/84'/0'/1']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V"),
+            Err(AddressError::DerivationPathMismatch)
+        ));
+        assert!(matches!(
+            parse_key_expression("[73c5da0a/84'/0'/0]xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V"),
+            Err(AddressError::DerivationPathMismatch)
+        ));
+    }
+
+    const MULTISIG_PASSPHRASES: [&str; 3] = ["", "cosigner two", "cosigner three"];
+
+    fn multisig_keys() -> Vec<String> {
+        MULTISIG_PASSPHRASES
+            .iter()
+            .map(|passphrase| get_key_origin(TEST_MNEMONIC, Some(passphrase), "m/48'/0'/0'/2'", Network::Bitcoin).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_multisig_descriptors() {
+        let keys = multisig_keys();
+        let descriptors = create_multisig_descriptors(2, &keys, MultisigScriptType::P2WSH, Network::Bitcoin).unwrap();
+        let (body, checksum) = descriptors.receive_descriptor.split_once('#').unwrap();
+        assert_eq!(checksum, descriptor_checksum(body).unwrap());
+        assert_eq!(body, format!("wsh(sortedmulti(2,{}/0/*,{}/0/*,{}/0/*))", keys[0], keys[1], keys[2]));
+        assert!(descriptors.change_descriptor.starts_with(&format!("wsh(sortedmulti(2,{}/1/*,", keys[0])));
+
+        let nested = create_multisig_descriptors(2, &keys, MultisigScriptType::P2SHP2WSH, Network::Bitcoin).unwrap();
+        assert!(nested.receive_descriptor.starts_with("sh(wsh(sortedmulti(2,[73c5da0a/48'/0'/0'/2']xpub"));
+    }
+
+    #[test]
+    fn test_multisig_addresses() {
+        let keys = multisig_keys();
+        let addresses = derive_multisig_addresses(2, &keys, MultisigScriptType::P2WSH, Network::Bitcoin, false, 0, 3).unwrap();
+        assert_eq!(addresses.len(), 3);
+
+        for address in &addresses {
+            // Derive the cosigner keys independently from the private keys
+            let secp = Secp256k1::new();
+            let path = DerivationPath::from_str(&format!("m/48'/0'/0'/2'/0/{}", address.index)).unwrap();
+            let mut public_keys: Vec<CompressedPublicKey> = MULTISIG_PASSPHRASES
+                .iter()
+                .map(|passphrase| {
+                    let master = keys::master_key(TEST_MNEMONIC, Some(passphrase), Network::Bitcoin).unwrap();
+                    let xpriv = keys::derive_xpriv(&secp, &master, &path).unwrap();
+                    CompressedPublicKey(xpriv.private_key.public_key(&secp))
+                })
+                .collect();
+            public_keys.sort_by_key(|key| key.to_bytes());
+
+            let script = public_keys
+                .iter()
+                .fold(ScriptBuilder::new().push_int(2), |builder, key| builder.push_key(&bitcoin::PublicKey::from(*key)))
+                .push_int(3)
+                .push_opcode(OP_CHECKMULTISIG)
+                .into_script();
+            assert_eq!(address.witness_script, script.to_hex_string());
+            assert_eq!(address.address, Address::p2wsh(&script, Network::Bitcoin).to_string());
+            assert_eq!(address.public_keys, public_keys.iter().map(|key| hex::encode(key.to_bytes())).collect::<Vec<_>>());
+            assert!(address.address.starts_with("bc1q") && address.address.len() == 62);
+        }
+        assert_ne!(addresses[0].address, addresses[1].address);
+
+        // Key order does not change the wallet
+        let reversed: Vec<String> = keys.iter().rev().cloned().collect();
+        assert_eq!(derive_multisig_addresses(2, &reversed, MultisigScriptType::P2WSH, Network::Bitcoin, false, 0, 3).unwrap(), addresses);
+
+        let change = derive_multisig_addresses(2, &keys, MultisigScriptType::P2SHP2WSH, Network::Bitcoin, true, 5, 1).unwrap();
+        assert!(change[0].is_change && change[0].index == 5);
+        assert!(change[0].address.starts_with('3'));
+    }
+
+    #[test]
+    fn test_multisig_redeem_script() {
+        let keys = multisig_keys();
+        let redeem = get_multisig_redeem_script(2, &keys, MultisigScriptType::P2WSH, Network::Bitcoin, true, 4).unwrap();
+        let address = &derive_multisig_addresses(2, &keys, MultisigScriptType::P2WSH, Network::Bitcoin, true, 4, 1).unwrap()[0];
+
+        assert_eq!(redeem.m, 2);
+        assert_eq!(redeem.signatures, vec![String::new(); 3]);
+        assert_eq!(redeem.pubkeys_order, Some(1));
+        // Each node derives to the script's key at the same position
+        let secp = Secp256k1::verification_only();
+        for (pubkey, expected) in redeem.pubkeys.iter().zip(&address.public_keys) {
+            assert_eq!(pubkey.address_n, vec![1, 4]);
+            let node = match &pubkey.node {
+                crate::modules::trezor::HDNodeTypeOrString::Node(node) => node,
+                other => panic!("expected node, got {:?}", other),
+            };
+            let xpub = keys
+                .iter()
+                .map(|key| parse_key_expression(key).unwrap().xpub)
+                .find(|xpub| xpub.public_key.to_string() == node.public_key)
+                .unwrap();
+            let child = xpub.derive_pub(&secp, &[bitcoin::bip32::ChildNumber::from(1), bitcoin::bip32::ChildNumber::from(4)]).unwrap();
+            assert_eq!(&hex::encode(child.public_key.serialize()), expected);
+        }
+    }
+
+    #[test]
+    fn test_multisig_rejects_invalid_configurations() {
+        let keys = multisig_keys();
+        let invalid = |threshold, keys: &[String]| derive_multisig_addresses(threshold, keys, MultisigScriptType::P2WSH, Network::Bitcoin, false, 0, 1);
+        assert!(matches!(invalid(0, &keys), Err(AddressError::InvalidMultisig)));
+        assert!(matches!(invalid(4, &keys), Err(AddressError::InvalidMultisig)));
+        assert!(matches!(invalid(1, &[]), Err(AddressError::InvalidMultisig)));
+        assert!(matches!(invalid(2, &[keys[0].clone(), keys[0].clone()]), Err(AddressError::InvalidMultisig)));
+        assert!(matches!(invalid(2, &[keys[0].clone(), "xpub-invalid".to_string()]), Err(AddressError::InvalidExtendedKey)));
+        // A single-sig zpub does not belong in a multisig
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs".to_string();
+        assert!(matches!(invalid(1, &[keys[0].clone(), zpub]), Err(AddressError::InvalidMultisig)));
+        assert!(matches!(
+            derive_multisig_addresses(2, &keys, MultisigScriptType::P2WSH, Network::Testnet, false, 0, 1),
+            Err(AddressError::InvalidNetwork)
+        ));
+    }
+
+    const PAYJOIN_URL: &str = "https://example.com/pj";
+    const PAYEE_ADDRESS: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+
+    /// Stub receiver that answers with `respond` applied to the posted PSBT.
+    struct StubReceiver {
+        respond: Box<dyn Fn(Psbt) -> PayjoinResponse + Send + Sync>,
+        requests: Mutex<Vec<(String, Psbt)>>,
+    }
+
+    impl StubReceiver {
+        fn new(respond: impl Fn(Psbt) -> PayjoinResponse + Send + Sync + 'static) -> Self {
+            StubReceiver { respond: Box::new(respond), requests: Mutex::new(Vec::new()) }
+        }
+
+        /// Contributes a 100,000 sat input, adds it to the payment and takes `contribution` from the change.
+        fn contributing(contribution: u64, modify: impl Fn(&mut Psbt) + Send + Sync + 'static) -> Self {
+            Self::new(move |mut psbt| {
+                for input in &mut psbt.inputs {
+                    input.final_script_witness = None;
+                }
+                let payee_script = Address::from_str(PAYEE_ADDRESS).unwrap().assume_checked().script_pubkey();
+                psbt.unsigned_tx.input.push(TxIn {
+                    previous_output: OutPoint { txid: Txid::from_str(&"11".repeat(32)).unwrap(), vout: 0 },
+                    script_sig: ScriptBuf::new(),
+                    sequence: psbt.unsigned_tx.input[0].sequence,
+                    witness: Witness::new(),
+                });
+                psbt.inputs.push(bitcoin::psbt::Input {
+                    witness_utxo: Some(TxOut { value: Amount::from_sat(100_000), script_pubkey: payee_script.clone() }),
+                    final_script_witness: Some(Witness::from_slice(&[vec![0x30; 72], vec![0x02; 33]])),
+                    ..Default::default()
+                });
+                for output in &mut psbt.unsigned_tx.output {
+                    if output.script_pubkey == payee_script {
+                        output.value += Amount::from_sat(100_000);
+                    } else {
+                        output.value -= Amount::from_sat(contribution);
+                    }
+                }
+                modify(&mut psbt);
+                PayjoinResponse { status: 200, body: psbt.to_string() }
+            })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl PayjoinTransport for StubReceiver {
+        async fn post(&self, url: &str, body: String) -> Result<PayjoinResponse, PayjoinError> {
+            let psbt = Psbt::from_str(&body).unwrap();
+            self.requests.lock().unwrap().push((url.to_string(), psbt.clone()));
+            Ok((self.respond)(psbt))
+        }
+    }
+
+    /// Unsigned and signed one-input original paying 30,000 sats with 19,000 sats of change.
+    fn payjoin_original() -> (String, String) {
+        let outputs = vec![TargetOutput { address: PAYEE_ADDRESS.to_string(), amount: 30_000 }];
+        let unsigned = build_psbt(wallet_utxos()[..1].to_vec(), outputs, Some(wallet_change()), TEST_MNEMONIC, None, Network::Bitcoin, true, None).unwrap();
+        let signed = sign_psbt(&unsigned.to_string(), TEST_MNEMONIC, None, Network::Bitcoin).unwrap();
+        (unsigned.to_string(), signed.psbt)
+    }
+
+    fn fee_params(max_contribution: u64) -> PayjoinParams {
+        PayjoinParams {
+            additional_fee_output_index: Some(1),
+            max_additional_fee_contribution: Some(max_contribution),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_payjoin_proposal() {
+        let (unsigned, original) = payjoin_original();
+        let receiver = StubReceiver::contributing(200, |_| {});
+        let params = PayjoinParams { min_fee_rate: Some(1.0), ..fee_params(200) };
+
+        let proposal = send_payjoin_request(&receiver, &original, &unsigned, PAYJOIN_URL, PAYEE_ADDRESS, &params, Network::Bitcoin).await.unwrap();
+
+        let requests = receiver.requests.lock().unwrap();
+        let (url, sent) = &requests[0];
+        assert_eq!(url, "https://example.com/pj?v=1&additionalfeeoutputindex=1&maxadditionalfeecontribution=200&minfeerate=1");
+        // Change key paths are not revealed to the receiver
+        assert!(sent.outputs.iter().all(|o| o.bip32_derivation.is_empty()));
+        assert!(sent.inputs[0].final_script_witness.is_some());
+
+        assert_eq!(proposal.unsigned_tx.input.len(), 2);
+        assert_eq!(proposal.unsigned_tx.output[0].value.to_sat(), 130_000);
+        assert_eq!(proposal.unsigned_tx.output[1].value.to_sat(), 18_800);
+        assert_eq!(proposal.fee().unwrap().to_sat(), 1_200);
+        // Sender input and change data are restored for signing
+        assert!(proposal.inputs[0].witness_utxo.is_some());
+        assert!(proposal.inputs[0].final_script_witness.is_none());
+        let unsigned = Psbt::from_str(&unsigned).unwrap();
+        assert_eq!(proposal.inputs[0].bip32_derivation, unsigned.inputs[0].bip32_derivation);
+        assert_eq!(proposal.outputs[1].bip32_derivation, unsigned.outputs[1].bip32_derivation);
+
+        let signed = sign_psbt(&proposal.to_string(), TEST_MNEMONIC, None, Network::Bitcoin).unwrap();
+        assert_eq!(signed.signed_inputs, vec![0]);
+        assert!(signed.is_finalized);
+    }
+
+    #[tokio::test]
+    async fn test_payjoin_rejects_invalid_proposals() {
+        let (unsigned, original) = payjoin_original();
+        let send = |receiver: StubReceiver, params: PayjoinParams| {
+            let (unsigned, original) = (unsigned.clone(), original.clone());
+            async move { send_payjoin_request(&receiver, &original, &unsigned, PAYJOIN_URL, PAYEE_ADDRESS, &params, Network::Bitcoin).await }
+        };
+        let rejected = |result: Result<Psbt, PayjoinError>| matches!(result, Err(PayjoinError::InvalidProposal { .. }));
+
+        // More than the allowed contribution
+        assert!(rejected(send(StubReceiver::contributing(300, |_| {}), fee_params(200)).await));
+        // No contribution allowed at all
+        assert!(rejected(send(StubReceiver::contributing(200, |_| {}), PayjoinParams::default()).await));
+        // Within the maximum, but more than one P2WPKH input costs at the original fee rate
+        assert!(rejected(send(StubReceiver::contributing(1_000, |_| {}), fee_params(10_000)).await));
+        // Contribution that does not go to fees
+        let to_payee = |psbt: &mut Psbt| psbt.unsigned_tx.output[0].value += Amount::from_sat(200);
+        assert!(rejected(send(StubReceiver::contributing(200, to_payee), fee_params(200)).await));
+        // Receiver input left unsigned
+        let unsigned_input = |psbt: &mut Psbt| psbt.inputs[1].final_script_witness = None;
+        assert!(rejected(send(StubReceiver::contributing(0, unsigned_input), PayjoinParams::default()).await));
+        // Receiver input with a different sequence
+        let sequence = |psbt: &mut Psbt| psbt.unsigned_tx.input[1].sequence = Sequence::MAX;
+        assert!(rejected(send(StubReceiver::contributing(0, sequence), PayjoinParams::default()).await));
+        // Locktime changed
+        let locktime = |psbt: &mut Psbt| psbt.unsigned_tx.lock_time = bitcoin::absolute::LockTime::from_height(800_000).unwrap();
+        assert!(rejected(send(StubReceiver::contributing(0, locktime), PayjoinParams::default()).await));
+        // Sender input dropped
+        let drop_input = |psbt: &mut Psbt| {
+            psbt.unsigned_tx.input.remove(0);
+            psbt.inputs.remove(0);
+        };
+        assert!(rejected(send(StubReceiver::contributing(0, drop_input), PayjoinParams::default()).await));
+        // Payment output replaced although substitution is disabled
+        let substitute = |psbt: &mut Psbt| psbt.unsigned_tx.output[0].script_pubkey = ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::all_zeros());
+        let no_substitution = PayjoinParams { disable_output_substitution: true, ..Default::default() };
+        assert!(rejected(send(StubReceiver::contributing(0, substitute), no_substitution.clone()).await));
+        assert!(send(StubReceiver::contributing(0, |_| {}), no_substitution).await.is_ok());
+        // Fee rate below the minimum
+        let min_fee_rate = PayjoinParams { min_fee_rate: Some(10.0), ..Default::default() };
+        assert!(rejected(send(StubReceiver::contributing(0, |_| {}), min_fee_rate).await));
+
+        let not_psbt = StubReceiver::new(|_| PayjoinResponse { status: 200, body: "not a psbt".to_string() });
+        assert!(rejected(send(not_psbt, PayjoinParams::default()).await));
+    }
+
+    #[tokio::test]
+    async fn test_payjoin_request_errors() {
+        let (unsigned, original) = payjoin_original();
+        let echo = || StubReceiver::new(|psbt| PayjoinResponse { status: 200, body: psbt.to_string() });
+        let params = PayjoinParams::default();
+
+        let receiver = StubReceiver::new(|_| PayjoinResponse {
+            status: 400,
+            body: r#"{"errorCode": "not-enough-money", "message": "Not enough money"}"#.to_string(),
+        });
+        match send_payjoin_request(&receiver, &original, &unsigned, PAYJOIN_URL, PAYEE_ADDRESS, &params, Network::Bitcoin).await {
+            Err(PayjoinError::ReceiverError { error_details }) => assert_eq!(error_details, "not-enough-money: Not enough money"),
+            other => panic!("Expected a receiver error, got {:?}", other),
+        }
+
+        for url in ["http://example.com/pj", "not a url"] {
+            assert!(matches!(
+                send_payjoin_request(&echo(), &original, &unsigned, url, PAYEE_ADDRESS, &params, Network::Bitcoin).await,
+                Err(PayjoinError::InvalidUrl { .. })
+            ));
+        }
+        let onion = "http://pjxyz2iwaxkgxoxgyt3ky7l4cdxpxvs3nqt5wzfp2ooufh3ig5ujrtqd.onion/pj";
+        assert!(send_payjoin_request(&echo(), &original, &unsigned, onion, PAYEE_ADDRESS, &params, Network::Bitcoin).await.is_ok());
+
+        // The original must be signed
+        assert!(matches!(
+            send_payjoin_request(&echo(), &unsigned, &unsigned, PAYJOIN_URL, PAYEE_ADDRESS, &params, Network::Bitcoin).await,
+            Err(PayjoinError::InvalidPsbt { .. })
+        ));
+        assert!(matches!(
+            send_payjoin_request(&echo(), &original, &unsigned, PAYJOIN_URL, CHANGE_ADDRESS.replace("8c6f", "8c6e").as_str(), &params, Network::Bitcoin).await,
+            Err(PayjoinError::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            send_payjoin_request(&echo(), &original, &unsigned, PAYJOIN_URL, P2WPKH_ADDRESS, &params, Network::Bitcoin).await,
+            Err(PayjoinError::InvalidPsbt { .. })
+        ));
+        // The unsigned PSBT must be the same transaction
+        let other = build_psbt(wallet_utxos()[1..2].to_vec(), test_output(30_000), None, TEST_MNEMONIC, None, Network::Bitcoin, true, None).unwrap();
+        assert!(matches!(
+            send_payjoin_request(&echo(), &original, &other.to_string(), PAYJOIN_URL, PAYEE_ADDRESS, &params, Network::Bitcoin).await,
+            Err(PayjoinError::InvalidParameter { .. })
+        ));
+        // The payment output cannot pay the additional fee
+        let params = PayjoinParams { additional_fee_output_index: Some(0), max_additional_fee_contribution: Some(100), ..Default::default() };
+        assert!(matches!(
+            send_payjoin_request(&echo(), &original, &unsigned, PAYJOIN_URL, PAYEE_ADDRESS, &params, Network::Bitcoin).await,
+            Err(PayjoinError::InvalidParameter { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_http_payjoin_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/pj?v=1", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            let mut is_text = false;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let header = line.trim().to_ascii_lowercase();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some(length) = header.strip_prefix("content-length: ") {
+                    content_length = length.parse().unwrap();
+                }
+                is_text |= header == "content-type: text/plain";
+            }
+            let mut body = vec![0u8; content_length];
+            std::io::Read::read_exact(&mut reader, &mut body).unwrap();
+            let body = if is_text { String::from_utf8(body).unwrap() } else { String::new() };
+            let response = format!(
+                "HTTP/1.1 202 Accepted\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        let response = HttpPayjoinTransport::new().unwrap().post(&url, "cHNidP8=".to_string()).await.unwrap();
+        assert_eq!(response.status, 202);
+        assert_eq!(response.body, "cHNidP8=");
+    }
+}
@@ -0,0 +1,123 @@
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::hex::DisplayHex;
+use bitcoin::{Address, Network, Script, Transaction, TxIn, TxOut};
+use crate::onchain::TransactionError;
+use super::types::{DecodedInput, DecodedOutput, DecodedTransaction, InputWitnessType, OutputScriptType};
+
+/// Taproot annex prefix (BIP341).
+const TAPROOT_ANNEX_PREFIX: u8 = 0x50;
+
+/// Decodes a raw transaction, resolving output addresses for `network`.
+pub fn decode_transaction(tx_hex: &str, network: Network) -> Result<DecodedTransaction, TransactionError> {
+    let tx: Transaction = deserialize_hex(tx_hex.trim()).map_err(|e| TransactionError::InvalidTransaction {
+        error_details: e.to_string(),
+    })?;
+
+    let inputs = tx.input.iter().map(decode_input).collect();
+    let outputs = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(index, output)| decode_output(index as u32, output, network))
+        .collect();
+
+    Ok(DecodedTransaction {
+        txid: tx.compute_txid().to_string(),
+        wtxid: tx.compute_wtxid().to_string(),
+        version: tx.version.0,
+        locktime: tx.lock_time.to_consensus_u32(),
+        is_rbf: tx.is_explicitly_rbf(),
+        is_coinbase: tx.is_coinbase(),
+        size: tx.total_size() as u64,
+        vsize: tx.vsize() as u64,
+        weight: tx.weight().to_wu(),
+        inputs,
+        outputs,
+    })
+}
+
+fn decode_input(input: &TxIn) -> DecodedInput {
+    DecodedInput {
+        txid: input.previous_output.txid.to_string(),
+        vout: input.previous_output.vout,
+        sequence: input.sequence.0,
+        script_sig: input.script_sig.to_hex_string(),
+        witness: input.witness.iter().map(|item| item.to_lower_hex_string()).collect(),
+        witness_type: input_witness_type(input),
+    }
+}
+
+/// Infers how an input is spent from its scriptSig and witness.
+fn input_witness_type(input: &TxIn) -> InputWitnessType {
+    let witness = &input.witness;
+    if witness.is_empty() {
+        return InputWitnessType::Legacy;
+    }
+
+    // A nested segwit scriptSig is a single push of the witness program
+    let redeem_script = match input.script_sig.instructions().collect::<Result<Vec<_>, _>>() {
+        Ok(instructions) if instructions.is_empty() => None,
+        Ok(instructions) if instructions.len() == 1 => match instructions[0].push_bytes() {
+            Some(bytes) => Some(Script::from_bytes(bytes.as_bytes()).to_owned()),
+            None => return InputWitnessType::Unknown,
+        },
+        _ => return InputWitnessType::Unknown,
+    };
+
+    match redeem_script {
+        Some(script) if script.is_p2wpkh() => InputWitnessType::NestedP2WPKH,
+        Some(script) if script.is_p2wsh() => InputWitnessType::NestedP2WSH,
+        Some(_) => InputWitnessType::Unknown,
+        None => {
+            let items: Vec<&[u8]> = witness.iter().collect();
+            // The annex, if present, is the last item of a taproot witness with at least two items
+            let without_annex = match items.split_last() {
+                Some((last, rest)) if !rest.is_empty() && last.first() == Some(&TAPROOT_ANNEX_PREFIX) => rest,
+                _ => &items[..],
+            };
+            match without_annex {
+                [signature] if signature.len() == 64 || signature.len() == 65 => InputWitnessType::TaprootKeyPath,
+                [signature, public_key] if public_key.len() == 33 && (9..=73).contains(&signature.len()) => InputWitnessType::P2WPKH,
+                [.., script, control_block] if is_control_block(control_block) && !script.is_empty() => InputWitnessType::TaprootScriptPath,
+                [_, ..] => InputWitnessType::P2WSH,
+                [] => InputWitnessType::Unknown,
+            }
+        }
+    }
+}
+
+/// Whether `bytes` looks like a BIP341 control block.
+fn is_control_block(bytes: &[u8]) -> bool {
+    bytes.len() >= 33 && (bytes.len() - 33).is_multiple_of(32) && bytes.len() <= 33 + 128 * 32 && bytes[0] & 0xfe == 0xc0
+}
+
+fn decode_output(index: u32, output: &TxOut, network: Network) -> DecodedOutput {
+    let script = &output.script_pubkey;
+    let script_type = if script.is_p2pkh() {
+        OutputScriptType::P2PKH
+    } else if script.is_p2sh() {
+        OutputScriptType::P2SH
+    } else if script.is_p2wpkh() {
+        OutputScriptType::P2WPKH
+    } else if script.is_p2wsh() {
+        OutputScriptType::P2WSH
+    } else if script.is_p2tr() {
+        OutputScriptType::P2TR
+    } else if script.is_witness_program() {
+        OutputScriptType::WitnessUnknown
+    } else if script.is_op_return() {
+        OutputScriptType::OpReturn
+    } else if script.is_p2pk() {
+        OutputScriptType::P2PK
+    } else {
+        OutputScriptType::NonStandard
+    };
+
+    DecodedOutput {
+        index,
+        value: output.value.to_sat(),
+        script_pubkey: script.to_hex_string(),
+        script_type,
+        address: Address::from_script(script, network).ok().map(|a| a.to_string()),
+    }
+}
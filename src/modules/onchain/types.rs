@@ -5,6 +5,10 @@ use bitcoin_address_generator::{
     WordCount as ExternalWordCount
 };
 use bitcoin::Network as BitcoinNetwork;
+use bitcoin::{Address, Script};
+use std::str::FromStr;
+use crate::onchain::AddressError;
+use crate::modules::trezor::AccountUtxo;
 use uniffi::{Enum, Record};
 use serde::{Deserialize, Serialize};
 
@@ -121,7 +125,7 @@ impl From<Network> for BitcoinNetwork {
     }
 }
 
-#[derive(uniffi::Enum, Debug, PartialEq)]
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AddressType {
     P2PKH,    // Legacy
     P2SH,     // SegWit
@@ -142,6 +146,30 @@ impl AddressType {
             AddressType::Unknown => "Unknown",
         }
     }
+
+    /// Classifies an output script. Nested segwit cannot be told apart from
+    /// other P2SH scripts, so all P2SH outputs map to `P2SH`.
+    pub fn from_script(script: &Script) -> Self {
+        if script.is_p2pkh() {
+            AddressType::P2PKH
+        } else if script.is_p2sh() {
+            AddressType::P2SH
+        } else if script.is_p2wpkh() {
+            AddressType::P2WPKH
+        } else if script.is_p2wsh() {
+            AddressType::P2WSH
+        } else if script.is_p2tr() {
+            AddressType::P2TR
+        } else {
+            AddressType::Unknown
+        }
+    }
+
+    /// Classifies an address string of any network.
+    pub fn from_address(address: &str) -> Result<Self, AddressError> {
+        let address = Address::from_str(address).map_err(|_| AddressError::InvalidAddress)?;
+        Ok(Self::from_script(&address.assume_checked_ref().script_pubkey()))
+    }
 }
 
 #[derive(uniffi::Record, Debug)]
@@ -149,4 +177,362 @@ pub struct ValidationResult {
     pub address: String,
     pub network: NetworkType,
     pub address_type: AddressType,
-}
\ No newline at end of file
+}
+
+/// Backend used to look up address history during discovery
+#[derive(Debug, Clone, Enum)]
+pub enum ChainBackend {
+    /// Esplora REST API base URL, e.g. `https://blockstream.info/api`
+    Esplora { url: String },
+    /// Electrum server URL, e.g. `ssl://electrum.blockstream.info:50002`
+    Electrum { url: String },
+}
+
+/// History summary for a single address as reported by a chain source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressStats {
+    /// Number of confirmed and unconfirmed transactions touching the address
+    pub tx_count: u32,
+    /// Confirmed balance in satoshis
+    pub confirmed_balance: u64,
+    /// Net unconfirmed balance change in satoshis, negative when spending
+    pub unconfirmed_balance: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Record)]
+pub struct DiscoveredAddress {
+    /// The used address
+    pub address: String,
+    /// The derivation path of the address
+    pub path: String,
+    /// Whether the address is on the change chain
+    pub is_change: bool,
+    /// The address index on its chain
+    pub index: u32,
+    /// Number of transactions touching the address
+    pub tx_count: u32,
+    /// Confirmed balance in satoshis
+    pub confirmed_balance: u64,
+    /// Net unconfirmed balance change in satoshis
+    pub unconfirmed_balance: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Record)]
+pub struct AddressDiscoveryResult {
+    /// Addresses with on-chain history, receive chain first
+    pub used_addresses: Vec<DiscoveredAddress>,
+    /// First receive index after the last used receive address
+    pub next_receive_index: u32,
+    /// First change index after the last used change address
+    pub next_change_index: u32,
+    /// Total confirmed balance in satoshis
+    pub confirmed_balance: u64,
+    /// Total net unconfirmed balance change in satoshis
+    pub unconfirmed_balance: i64,
+}
+
+/// An unspent output owned by the wallet
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct Utxo {
+    /// Transaction ID of the output
+    pub txid: String,
+    /// Output index
+    pub vout: u32,
+    /// Value in satoshis
+    pub value: u64,
+    /// Address the output pays to
+    pub address: String,
+    /// Script type of the output
+    pub address_type: AddressType,
+    /// Derivation path of the key controlling the output, if known
+    pub derivation_path: Option<String>,
+    /// Number of confirmations, 0 if unconfirmed
+    pub confirmations: u32,
+}
+
+impl TryFrom<AccountUtxo> for Utxo {
+    type Error = AddressError;
+
+    fn try_from(utxo: AccountUtxo) -> Result<Self, Self::Error> {
+        let value = utxo.amount.parse::<u64>().map_err(|_| AddressError::InvalidAddress)?;
+        let address_type = AddressType::from_address(&utxo.address)?;
+
+        Ok(Utxo {
+            txid: utxo.txid,
+            vout: utxo.vout,
+            value,
+            address: utxo.address,
+            address_type,
+            derivation_path: Some(utxo.path),
+            confirmations: utxo.confirmations.unwrap_or(0),
+        })
+    }
+}
+
+/// A payment the transaction must make
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct TargetOutput {
+    /// Destination address
+    pub address: String,
+    /// Amount in satoshis
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum CoinSelectionStrategy {
+    /// Look for a changeless solution with minimal waste, falling back to largest-first
+    BranchAndBound,
+    /// Spend the largest UTXOs first
+    LargestFirst,
+    /// Only spend UTXOs of a single address type, using branch-and-bound within the type
+    SingleAddressType,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Record)]
+pub struct CoinSelectionResult {
+    /// UTXOs to spend
+    pub selected_utxos: Vec<Utxo>,
+    /// Change amount in satoshis, `None` when the transaction has no change output
+    pub change_amount: Option<u64>,
+    /// Absolute fee in satoshis
+    pub fee: u64,
+    /// Estimated virtual size in vbytes
+    pub vsize: u64,
+    /// Waste metric in satoshis, lower is better
+    pub waste: i64,
+}
+
+/// Change output of a PSBT, with the derivation path of its key if it belongs to the wallet
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct ChangeOutput {
+    /// Change address
+    pub address: String,
+    /// Amount in satoshis
+    pub amount: u64,
+    /// Derivation path of the change key, used to add BIP32 derivation info
+    pub derivation_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct SignPsbtResult {
+    /// Base64-encoded PSBT including the new signatures
+    pub psbt: String,
+    /// Indexes of the inputs signed with the mnemonic
+    pub signed_inputs: Vec<u32>,
+    /// Whether every input has been finalized
+    pub is_finalized: bool,
+    /// Hex-encoded network transaction, when every input is finalized
+    pub tx_hex: Option<String>,
+    /// Transaction ID, when every input is finalized
+    pub txid: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct FeeBumpResult {
+    /// Base64-encoded unsigned PSBT of the replacement or child transaction
+    pub psbt: String,
+    /// Absolute fee of the new transaction in satoshis
+    pub fee: u64,
+    /// Estimated virtual size of the new transaction in vbytes
+    pub vsize: u64,
+    /// Fee rate of the new transaction on its own in sat/vB
+    pub fee_rate: f64,
+    /// Fee rate of the package the miner evaluates in sat/vB: the replacement
+    /// itself for RBF, parent and child combined for CPFP
+    pub effective_fee_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum MessageSignatureFormat {
+    /// Legacy 65-byte recoverable signature (BIP137), for P2PKH, P2SH-P2WPKH and P2WPKH
+    Bip137,
+    /// BIP322 simple signature (base64 witness), for P2WPKH and P2TR
+    Bip322Simple,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct SignedMessage {
+    /// Address of the signing key
+    pub address: String,
+    /// Base64-encoded signature
+    pub signature: String,
+    /// Format of the signature
+    pub format: MessageSignatureFormat,
+}
+
+/// Member shares of one SLIP-39 group
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct Slip39Group {
+    /// Number of member shares needed to recover the group share
+    pub member_threshold: u8,
+    /// Number of member shares to create
+    pub member_count: u8,
+}
+
+/// Mnemonic shares created for one SLIP-39 group
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct Slip39GroupShares {
+    /// Number of these shares needed to recover the group share
+    pub member_threshold: u8,
+    /// SLIP-39 mnemonics, one per member
+    pub shares: Vec<String>,
+}
+
+/// Parameters encoded in a single SLIP-39 share
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct Slip39ShareInfo {
+    /// Random identifier shared by all shares of one backup
+    pub identifier: u16,
+    /// Whether the backup can be extended with new shares of the same secret
+    pub extendable: bool,
+    /// Passphrase stretching exponent, PBKDF2 runs 10000 × 2^e iterations
+    pub iteration_exponent: u8,
+    /// Index of this share's group
+    pub group_index: u8,
+    /// Number of groups needed to recover the secret
+    pub group_threshold: u8,
+    /// Total number of groups
+    pub group_count: u8,
+    /// Index of this share within its group
+    pub member_index: u8,
+    /// Number of shares of this group needed to recover the group share
+    pub member_threshold: u8,
+    /// Number of words in the share
+    pub word_count: u32,
+}
+
+/// Secrets and metadata stored in an encrypted seed envelope
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct SeedEnvelopeContents {
+    /// BIP39 mnemonic phrase
+    pub mnemonic: String,
+    /// BIP39 passphrase, if the wallet uses one
+    pub passphrase: Option<String>,
+    /// Network the wallet is used on
+    pub network: Network,
+    /// Master key fingerprint as 8 hex characters
+    pub fingerprint: String,
+    /// Unix timestamp of when the envelope was created
+    pub created_at: u64,
+}
+
+/// Script wrapping a sorted multisig
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum MultisigScriptType {
+    /// Native segwit, `wsh(sortedmulti(...))`
+    P2WSH,
+    /// Nested segwit, `sh(wsh(sortedmulti(...)))`
+    P2SHP2WSH,
+}
+
+/// Output descriptors of a multisig wallet, with checksums
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct MultisigDescriptors {
+    /// Descriptor of the receive chain (`/0/*`)
+    pub receive_descriptor: String,
+    /// Descriptor of the change chain (`/1/*`)
+    pub change_descriptor: String,
+}
+
+/// An address of a multisig wallet
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct MultisigAddress {
+    /// The address
+    pub address: String,
+    /// Index on the receive or change chain
+    pub index: u32,
+    /// Whether the address is on the change chain
+    pub is_change: bool,
+    /// Hex-encoded witness script
+    pub witness_script: String,
+    /// Hex-encoded cosigner public keys in script order
+    pub public_keys: Vec<String>,
+}
+
+/// How a transaction input is spent, inferred from its scriptSig and witness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum InputWitnessType {
+    /// No witness (P2PKH, P2SH, P2PK or a non-segwit transaction)
+    Legacy,
+    P2WPKH,
+    /// P2WPKH nested in P2SH
+    NestedP2WPKH,
+    P2WSH,
+    /// P2WSH nested in P2SH
+    NestedP2WSH,
+    TaprootKeyPath,
+    TaprootScriptPath,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum OutputScriptType {
+    P2PK,
+    P2PKH,
+    P2SH,
+    P2WPKH,
+    P2WSH,
+    P2TR,
+    /// Segwit output of an unknown witness version or program length
+    WitnessUnknown,
+    OpReturn,
+    NonStandard,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct DecodedInput {
+    /// Transaction ID of the spent output
+    pub txid: String,
+    /// Index of the spent output
+    pub vout: u32,
+    pub sequence: u32,
+    /// Hex-encoded scriptSig
+    pub script_sig: String,
+    /// Hex-encoded witness items
+    pub witness: Vec<String>,
+    pub witness_type: InputWitnessType,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct DecodedOutput {
+    pub index: u32,
+    /// Value in satoshis
+    pub value: u64,
+    /// Hex-encoded scriptPubKey
+    pub script_pubkey: String,
+    pub script_type: OutputScriptType,
+    /// Address for the requested network, if the script has one
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Record)]
+pub struct DecodedTransaction {
+    pub txid: String,
+    pub wtxid: String,
+    pub version: i32,
+    pub locktime: u32,
+    /// Whether any input signals BIP125 replaceability
+    pub is_rbf: bool,
+    pub is_coinbase: bool,
+    /// Serialized size in bytes
+    pub size: u64,
+    /// Virtual size in vbytes
+    pub vsize: u64,
+    /// Weight in weight units
+    pub weight: u64,
+    pub inputs: Vec<DecodedInput>,
+    pub outputs: Vec<DecodedOutput>,
+}
+
+/// Optional BIP78 parameters sent with a payjoin request
+#[derive(Debug, Clone, Default, PartialEq, Record)]
+pub struct PayjoinParams {
+    /// Index of the sender output the receiver may reduce to pay for its inputs
+    pub additional_fee_output_index: Option<u32>,
+    /// Most the receiver may take from that output, in satoshis
+    pub max_additional_fee_contribution: Option<u64>,
+    /// Lowest fee rate in sat/vB the proposal may pay
+    pub min_fee_rate: Option<f64>,
+    /// Forbids the receiver from replacing the payment output or lowering its value
+    pub disable_output_substitution: bool,
+}
@@ -68,6 +68,13 @@ impl From<AddressError> for DecodingError {
             AddressError::InvalidMnemonic => DecodingError::InvalidFormat,
             AddressError::MnemonicGenerationFailed => DecodingError::InvalidFormat,
             AddressError::AddressDerivationFailed => DecodingError::InvalidFormat,
+            AddressError::InvalidDescriptor => DecodingError::InvalidFormat,
+            AddressError::InvalidExtendedKey => DecodingError::InvalidFormat,
+            AddressError::InvalidDerivationPath => DecodingError::InvalidFormat,
+            AddressError::DerivationPathMismatch => DecodingError::InvalidFormat,
+            AddressError::UnsupportedAddressType => DecodingError::InvalidFormat,
+            AddressError::InvalidMultisig => DecodingError::InvalidFormat,
+            AddressError::InvalidLength => DecodingError::InvalidFormat,
         }
     }
 }
\ No newline at end of file
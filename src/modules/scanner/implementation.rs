@@ -323,12 +323,21 @@ impl Scanner {
         let message = params.get("message")
             .map(String::from);
 
+        // The endpoint is usually percent-encoded as it contains reserved characters
+        let payjoin_url = params.get("pj")
+            .and_then(|pj| {
+                url::form_urlencoded::parse(format!("pj={}", pj).as_bytes())
+                    .next()
+                    .map(|(_, pj)| pj.into_owned())
+            });
+
         Ok(Scanner::OnChain {
             invoice: OnChainInvoice {
                 address,
                 amount_satoshis,
                 label,
                 message,
+                payjoin_url,
                 params: Some(params),
             }
         })
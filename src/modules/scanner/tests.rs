@@ -37,6 +37,24 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_onchain_invoice_payjoin_endpoint() {
+        let invoice = "bitcoin:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?amount=0.001&pj=https%3A%2F%2Fexample.com%2Fpj%3Fid%3D1&pjos=0".to_string();
+        match Scanner::decode(invoice).await.unwrap() {
+            Scanner::OnChain { invoice } => {
+                assert_eq!(invoice.payjoin_url.as_deref(), Some("https://example.com/pj?id=1"));
+                assert_eq!(invoice.params.as_ref().unwrap().get("pjos").unwrap(), "0");
+            },
+            _ => panic!("Should be an OnChain invoice"),
+        }
+
+        let invoice = "bitcoin:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?amount=0.001".to_string();
+        match Scanner::decode(invoice).await.unwrap() {
+            Scanner::OnChain { invoice } => assert!(invoice.payjoin_url.is_none()),
+            _ => panic!("Should be an OnChain invoice"),
+        }
+    }
+
     #[tokio::test]
     async fn test_raw_address() {
         let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
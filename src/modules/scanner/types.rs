@@ -101,6 +101,8 @@ pub struct OnChainInvoice {
     pub amount_satoshis: u64,
     pub label: Option<String>,
     pub message: Option<String>,
+    /// BIP78 payjoin endpoint from the `pj` parameter
+    pub payjoin_url: Option<String>,
     pub params: Option<HashMap<String, String>>,
 }
 
@@ -32,6 +32,12 @@ pub enum TrezorConnectError {
     ClientError {
         error_details: String,
     },
+
+    #[error("Invalid key origin: {error_details}")]
+    /// A `[fingerprint/path]xpub` value could not be parsed
+    InvalidKeyOrigin {
+        error_details: String,
+    },
 }
 
 impl From<serde_json::Error> for TrezorConnectError {
@@ -73,7 +73,8 @@ impl TrezorConnectClient {
     }
 
     /// Get address for the specified path
-    pub fn get_address(&self, params: GetAddressParams, request_id: Option<String>) -> TrezorConnectResult<DeepLinkResult> {
+    pub fn get_address(&self, mut params: GetAddressParams, request_id: Option<String>) -> TrezorConnectResult<DeepLinkResult> {
+        params.multisig = params.multisig.map(|m| m.resolve_key_origins()).transpose()?;
         self.generate_deep_link("getAddress", params, request_id)
     }
 
@@ -131,7 +132,14 @@ impl TrezorConnectClient {
     ///
     /// This method asks the device to sign given inputs and outputs of a pre-composed transaction.
     /// The user is asked to confirm all transaction details on the Trezor device.
-    pub fn sign_transaction(&self, params: SignTransactionParams, request_id: Option<String>) -> TrezorConnectResult<DeepLinkResult> {
+    /// Multisig pubkeys may be given as `[fingerprint/path]xpub` key origins.
+    pub fn sign_transaction(&self, mut params: SignTransactionParams, request_id: Option<String>) -> TrezorConnectResult<DeepLinkResult> {
+        for input in &mut params.inputs {
+            input.multisig = input.multisig.take().map(|m| m.resolve_key_origins()).transpose()?;
+        }
+        for output in &mut params.outputs {
+            output.multisig = output.multisig.take().map(|m| m.resolve_key_origins()).transpose()?;
+        }
         self.generate_deep_link("signTransaction", params, request_id)
     }
 }
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
     use serde_json::json;
-    use crate::modules::trezor::{handle_deep_link, AccountAddresses, AccountInfoDetails, AccountUtxo, AddressInfo, ComposeAccount, ComposeOutput, ComposeTransactionParams, ComposeTransactionResponse, DefaultAccountType, FeeLevel, GetAccountInfoParams, GetAddressParams, GetPublicKeyParams, RefTransaction, RefTxInput, RefTxOutput, ScriptType, SignMessageParams, SignTransactionParams, TokenFilter, TrezorConnectClient, TrezorConnectError, TrezorEnvironment, TrezorResponsePayload, TxInputType, TxOutputType, VerifyMessageParams};
+    use crate::modules::trezor::{handle_deep_link, AccountAddresses, AccountInfoDetails, AccountUtxo, AddressInfo, ComposeAccount, ComposeOutput, ComposeTransactionParams, ComposeTransactionResponse, DefaultAccountType, FeeLevel, GetAccountInfoParams, GetAddressParams, GetPublicKeyParams, HDNodePathType, HDNodeType, HDNodeTypeOrString, MultisigRedeemScriptType, RefTransaction, RefTxInput, RefTxOutput, ScriptType, SignMessageParams, SignTransactionParams, TokenFilter, TrezorConnectClient, TrezorConnectError, TrezorEnvironment, TrezorResponsePayload, TxInputType, TxOutputType, VerifyMessageParams};
     use super::*;
 
     #[test]
@@ -1382,4 +1382,99 @@ mod tests {
             _ => panic!("Expected ComposeTransaction PrecomposedTransactions payload, but got something else"),
         }
     }
-}
\ No newline at end of file
+
+    const KEY_ORIGIN: &str = "[73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+
+    #[test]
+    fn test_hd_node_from_key_origin() {
+        let node = HDNodeType::from_key_origin(KEY_ORIGIN).unwrap();
+        assert_eq!(node.depth, 3);
+        assert_eq!(node.child_num, 0x80000000);
+        assert_eq!(node.address_n, Some(vec![84 | 0x80000000, 0x80000000, 0x80000000]));
+        assert_eq!(node.public_key.len(), 66);
+        assert_eq!(node.chain_code.len(), 64);
+        assert!(node.private_key.is_none());
+
+        assert!(node.matches_key_origin(KEY_ORIGIN).unwrap());
+        // A bare xpub has no path to compare
+        assert!(node.matches_key_origin(KEY_ORIGIN.split(']').nth(1).unwrap()).unwrap());
+        let other = "[73c5da0a/49'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+        assert!(!node.matches_key_origin(other).unwrap());
+        // An origin whose last step is not the key's child number cannot describe it
+        let inconsistent = "[73c5da0a/84'/0'/1']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+        assert!(matches!(node.matches_key_origin(inconsistent), Err(TrezorConnectError::InvalidKeyOrigin { .. })));
+        assert!(matches!(HDNodeType::from_key_origin("[73c5da0a/84'/0'/0']notakey"), Err(TrezorConnectError::InvalidKeyOrigin { .. })));
+    }
+
+    #[test]
+    fn test_sign_transaction_multisig_key_origins() {
+        let client = TrezorConnectClient::new(
+            TrezorEnvironment::Local,
+            "exampleapp://trezor-callback"
+        ).unwrap();
+
+        let multisig = MultisigRedeemScriptType {
+            pubkeys: vec![
+                HDNodePathType { node: HDNodeTypeOrString::String(KEY_ORIGIN.to_string()), address_n: vec![0, 0] },
+                HDNodePathType {
+                    node: HDNodeTypeOrString::String(KEY_ORIGIN.split(']').nth(1).unwrap().to_string()),
+                    address_n: vec![0, 0],
+                },
+            ],
+            signatures: vec![String::new(), String::new()],
+            m: 1,
+            nodes: None,
+            pubkeys_order: None,
+        };
+        let params = SignTransactionParams {
+            coin: "btc".to_string(),
+            inputs: vec![TxInputType {
+                prev_hash: "b035d89d4543ce5713c553d69431698116a822c57c03ddacf3f04b763d1999ac".to_string(),
+                prev_index: 0,
+                amount: 100000,
+                sequence: None,
+                address_n: Some(vec![48 | 0x80000000, 0x80000000, 0x80000000, 2 | 0x80000000, 0, 0]),
+                script_type: Some(ScriptType::SpendWitness),
+                multisig: Some(multisig),
+                script_pubkey: None,
+                script_sig: None,
+                witness: None,
+                ownership_proof: None,
+                commitment_data: None,
+                orig_hash: None,
+                orig_index: None,
+                coinjoin_flags: None,
+            }],
+            outputs: vec![],
+            refTxs: None,
+            paymentRequests: None,
+            locktime: None,
+            version: None,
+            expiry: None,
+            versionGroupId: None,
+            overwintered: None,
+            timestamp: None,
+            branchId: None,
+            push: None,
+            amountUnit: None,
+            unlockPath: None,
+            serialize: None,
+            chunkify: None,
+            common: None,
+        };
+
+        let result = client.sign_transaction(params, None).unwrap();
+        let url = url::Url::parse(&result.url).unwrap();
+        let params_json = url.query_pairs().find(|(key, _)| key == "params").unwrap().1;
+        let params: serde_json::Value = serde_json::from_str(&params_json).unwrap();
+        let pubkeys = &params["inputs"][0]["multisig"]["pubkeys"];
+
+        // Key origins and bare xpubs both become nodes
+        let public_key = HDNodeType::from_key_origin(KEY_ORIGIN).unwrap().public_key;
+        for pubkey in pubkeys.as_array().unwrap() {
+            assert_eq!(pubkey["depth"], 3);
+            assert_eq!(pubkey["address_n"], json!([0, 0]));
+            assert_eq!(pubkey["public_key"], public_key);
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 use url::Url;
+use bitcoin::bip32::Xpub;
+use bitcoin::hex::DisplayHex;
+use crate::modules::onchain;
 use crate::modules::trezor::TrezorConnectError;
 
 /// Result type for deep link generation, including the URL and the ID used
@@ -104,6 +107,64 @@ pub enum HDNodeTypeOrString {
     Node(HDNodeType),
 }
 
+impl HDNodeType {
+    /// Builds a node from a `[fingerprint/path]xpub` key origin, as returned by
+    /// `get_key_origin`, or from a bare extended public key. `address_n` is set
+    /// to the origin path when there is one.
+    pub fn from_key_origin(key_origin: &str) -> Result<Self, TrezorConnectError> {
+        let key = onchain::parse_key_expression(key_origin).map_err(|e| TrezorConnectError::InvalidKeyOrigin {
+            error_details: e.to_string(),
+        })?;
+        let mut node = Self::from_xpub(&key.xpub);
+        node.address_n = key.origin.map(|(_, path)| path.into_iter().map(|child| u32::from(*child)).collect());
+        Ok(node)
+    }
+
+    pub(crate) fn from_xpub(xpub: &Xpub) -> Self {
+        HDNodeType {
+            depth: xpub.depth as u32,
+            fingerprint: u32::from_be_bytes(xpub.parent_fingerprint.to_bytes()),
+            child_num: u32::from(xpub.child_number),
+            chain_code: xpub.chain_code.to_bytes().to_lower_hex_string(),
+            public_key: xpub.public_key.to_string(),
+            private_key: None,
+            address_n: None,
+        }
+    }
+
+    /// Whether this node is the key described by `key_origin`. Paths are only
+    /// compared when both sides have one.
+    pub fn matches_key_origin(&self, key_origin: &str) -> Result<bool, TrezorConnectError> {
+        let other = Self::from_key_origin(key_origin)?;
+        let same_key = self.depth == other.depth
+            && self.fingerprint == other.fingerprint
+            && self.child_num == other.child_num
+            && self.chain_code.eq_ignore_ascii_case(&other.chain_code)
+            && self.public_key.eq_ignore_ascii_case(&other.public_key);
+        let same_path = match (&self.address_n, &other.address_n) {
+            (Some(path), Some(other_path)) => path == other_path,
+            _ => true,
+        };
+        Ok(same_key && same_path)
+    }
+}
+
+impl HDNodeTypeOrString {
+    /// Converts an xpub or `[fingerprint/path]xpub` string into a node, since
+    /// `HDNodePathType` flattens its node and cannot serialize a string.
+    pub(crate) fn into_node(self) -> Result<Self, TrezorConnectError> {
+        match self {
+            HDNodeTypeOrString::String(key) => {
+                let mut node = HDNodeType::from_key_origin(&key)?;
+                // The path to the signing key is carried by HDNodePathType
+                node.address_n = None;
+                Ok(HDNodeTypeOrString::Node(node))
+            }
+            node => Ok(node),
+        }
+    }
+}
+
 /// Multisig Redeem Script Type
 #[derive(Serialize, Deserialize, Debug, Clone, uniffi::Record)]
 pub struct MultisigRedeemScriptType {
@@ -121,6 +182,15 @@ pub struct MultisigRedeemScriptType {
     pub pubkeys_order: Option<u8>,
 }
 
+impl MultisigRedeemScriptType {
+    pub(crate) fn resolve_key_origins(mut self) -> Result<Self, TrezorConnectError> {
+        for pubkey in &mut self.pubkeys {
+            pubkey.node = pubkey.node.clone().into_node()?;
+        }
+        Ok(self)
+    }
+}
+
 /// Unlock Path parameters
 #[derive(Serialize, Deserialize, Debug, Clone, uniffi::Record)]
 pub struct UnlockPath {